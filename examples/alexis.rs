@@ -1,4 +1,4 @@
-use crate::bible_lsp::BibleLSP;
+use bible_lsp::bible_lsp::BibleLSP;
 
 fn main() {
     let json_path = "/home/dgmastertemple/Development/rust/bible_api/esv.json";
@@ -6,6 +6,6 @@ fn main() {
     let contents = std::fs::read_to_string("/home/dgmastertemple/christian_commons.txt").unwrap();
     let references = lsp.find_book_references(&contents).unwrap();
     for r in references {
-        println!("{r}");
+        println!("{}", r.full_ref_label(&lsp.api));
     }
 }
@@ -0,0 +1,169 @@
+use crate::{
+    bible_api::BibleAPI,
+    book_reference_segment::{BookReferenceSegment, BookReferenceSegments},
+};
+
+/// Book id of Esther
+pub const ESTHER: usize = 17;
+/// Book id of Daniel
+pub const DANIEL: usize = 27;
+
+/**
+A deuterocanonical addition that translations with it print as extra chapters of another book
+
+- Cited as its own one-chapter book (`Susanna 1:45`), but the text is at `Daniel 13:45`
+- `verse_offset` is for additions that start partway through a chapter
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Addition {
+    /// lowercase, the first one is the full name
+    pub names: &'static [&'static str],
+    pub book_id: usize,
+    pub chapter: usize,
+    pub verse_offset: usize,
+}
+
+/// The Greek additions to Daniel, in the Vulgate numbering Catholic translations use
+pub const ADDITIONS: &[Addition] = &[
+    Addition {
+        names: &["susanna", "sus"],
+        book_id: DANIEL,
+        chapter: 13,
+        verse_offset: 0,
+    },
+    Addition {
+        names: &["bel and the dragon", "bel"],
+        book_id: DANIEL,
+        chapter: 14,
+        verse_offset: 0,
+    },
+    // `Prayer of Azariah 1:1` is `Daniel 3:24`, and the song goes on to `Daniel 3:90`
+    Addition {
+        names: &[
+            "prayer of azariah",
+            "pr azar",
+            "song of the three young men",
+            "song of the three holy children",
+            "song of three",
+        ],
+        book_id: DANIEL,
+        chapter: 3,
+        verse_offset: 23,
+    },
+];
+
+/**
+Names of the Greek additions to Esther
+
+- They already use Esther's own numbering (`Additions to Esther 11:2` is `Esther 11:2`), so they
+  are plain aliases
+*/
+const ESTHER_NAMES: &[&str] = &["additions to esther", "rest of esther", "add esth"];
+
+/**
+- Aliases for the additions the translation has, for the `additions` alias pack
+- Only when the book has the chapter, so translations without the additions don't detect them
+*/
+pub fn aliases(api: &BibleAPI) -> Vec<(String, usize)> {
+    let has_chapter = |book_id: usize, chapter: usize| {
+        api.get_book_chapter_count(book_id)
+            .is_some_and(|count| chapter <= count)
+    };
+    let mut aliases: Vec<(String, usize)> = ADDITIONS
+        .iter()
+        .filter(|addition| has_chapter(addition.book_id, addition.chapter))
+        // Daniel 3 is in every translation, so Azariah needs its verses too
+        .filter(|addition| {
+            api.get_chapter_verse_count(addition.book_id, addition.chapter)
+                .is_some_and(|count| count > addition.verse_offset + 1)
+        })
+        .flat_map(|addition| {
+            addition
+                .names
+                .iter()
+                .map(|name| (name.to_string(), addition.book_id))
+        })
+        .collect();
+    // the additions start at Esther 10:4, past where the Hebrew text ends
+    if has_chapter(ESTHER, 11) {
+        aliases.extend(ESTHER_NAMES.iter().map(|name| (name.to_string(), ESTHER)));
+    }
+    aliases
+}
+
+/// The addition a book name was written as, if it is one of them and resolved to `book_id`
+pub fn find(book_name: &str, book_id: usize) -> Option<&'static Addition> {
+    let name = book_name.trim_end_matches('.').to_lowercase();
+    ADDITIONS
+        .iter()
+        .find(|addition| addition.book_id == book_id && addition.names.contains(&name.as_str()))
+}
+
+/// - Segments cited in the addition's own numbering, in the numbering of the book it is part of
+/// - Every chapter is treated as its one chapter, since none of them have more
+pub fn retarget(addition: &Addition, segments: &BookReferenceSegments) -> BookReferenceSegments {
+    let map = |verse: usize| (addition.chapter, verse + addition.verse_offset);
+    BookReferenceSegments(
+        segments
+            .iter()
+            .map(|seg| {
+                BookReferenceSegment::from_span(
+                    map(seg.get_starting_verse()),
+                    map(seg.get_ending_verse()),
+                )
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bible_lsp::BibleLSP;
+
+    /// Daniel with the additions, and Esther without them
+    fn catholic_daniel() -> BibleLSP {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daniel.json");
+        let chapters = |name: &str, verses: &[usize]| -> Vec<Vec<String>> {
+            verses
+                .iter()
+                .enumerate()
+                .map(|(idx, count)| {
+                    (1..=*count)
+                        .map(|verse| format!("{name} {}:{verse}", idx + 1))
+                        .collect()
+                })
+                .collect()
+        };
+        let daniel = [21, 49, 100, 37, 31, 29, 28, 27, 27, 21, 45, 13, 64, 42];
+        let json = serde_json::json!({
+            "translation": { "name": "Test", "language": "English", "abbreviation": "TST" },
+            "bible": [
+                { "id": ESTHER, "book": "Esther", "abbreviations": [], "content": chapters("Esther", &[22, 23, 15, 17, 14, 14, 10, 17, 32, 3]) },
+                { "id": DANIEL, "book": "Daniel", "abbreviations": ["dan"], "content": chapters("Daniel", &daniel) },
+            ],
+        });
+        std::fs::write(&path, json.to_string()).unwrap();
+        let mut lsp = BibleLSP::new(path.to_str().unwrap());
+        let (aliases, _) = crate::alias_packs::aliases(&lsp.api, &[String::from("additions")]);
+        lsp.api.add_aliases(aliases);
+        lsp
+    }
+
+    #[test]
+    fn additions_are_found_in_the_book_they_belong_to() {
+        let lsp = catholic_daniel();
+        let labels: Vec<String> = lsp
+            .find_book_references("Susanna 1:45, Bel 1:3-5 and Pr Azar 1:1")
+            .unwrap()
+            .iter()
+            .map(|book_ref| book_ref.full_ref_label(&lsp.api))
+            .collect();
+        assert_eq!(labels, ["Daniel 13:45", "Daniel 14:3-5", "Daniel 3:24"]);
+        // this Esther ends at chapter 10
+        assert!(aliases(&lsp.api)
+            .iter()
+            .all(|(_, book_id)| *book_id == DANIEL));
+    }
+}
@@ -0,0 +1,263 @@
+use crate::bible_api::BookIdToName;
+
+/// Common English abbreviations by book id, mostly from the SBL handbook plus shorter forms
+const ENGLISH: &[(usize, &[&str])] = &[
+    (1, &["gen", "ge", "gn"]),
+    (2, &["exod", "ex", "exo"]),
+    (3, &["lev", "le", "lv"]),
+    (4, &["num", "nu", "nm", "nb"]),
+    (5, &["deut", "de", "dt"]),
+    (6, &["josh", "jos", "jsh"]),
+    (7, &["judg", "jdg", "jg", "jdgs"]),
+    (8, &["ruth", "rth", "ru"]),
+    (9, &["1 sam", "1 sm", "1 sa", "1sam"]),
+    (10, &["2 sam", "2 sm", "2 sa", "2sam"]),
+    (11, &["1 kgs", "1 ki", "1kgs"]),
+    (12, &["2 kgs", "2 ki", "2kgs"]),
+    (13, &["1 chr", "1 ch", "1chr"]),
+    (14, &["2 chr", "2 ch", "2chr"]),
+    (15, &["ezra", "ezr"]),
+    (16, &["neh", "ne"]),
+    (17, &["esth", "est", "es"]),
+    (18, &["job", "jb"]),
+    (19, &["ps", "pss", "psa", "psm"]),
+    (20, &["prov", "pr", "prv"]),
+    (21, &["eccl", "ecc", "qoh"]),
+    (22, &["song", "sos", "so"]),
+    (23, &["isa", "is"]),
+    (24, &["jer", "je", "jr"]),
+    (25, &["lam", "la"]),
+    (26, &["ezek", "eze", "ezk"]),
+    (27, &["dan", "da", "dn"]),
+    (28, &["hos", "ho"]),
+    (29, &["joel", "jl"]),
+    (30, &["amos", "am"]),
+    (31, &["obad", "ob"]),
+    (32, &["jonah", "jnh", "jon"]),
+    (33, &["mic", "mc"]),
+    (34, &["nah", "na"]),
+    (35, &["hab", "hb"]),
+    (36, &["zeph", "zep", "zp"]),
+    (37, &["hag", "hg"]),
+    (38, &["zech", "zec", "zc"]),
+    (39, &["mal", "ml"]),
+    (40, &["matt", "mt"]),
+    (41, &["mark", "mk", "mrk"]),
+    (42, &["luke", "lk"]),
+    (43, &["john", "jn", "jhn"]),
+    (44, &["acts", "ac"]),
+    (45, &["rom", "ro", "rm"]),
+    (46, &["1 cor", "1 co", "1cor"]),
+    (47, &["2 cor", "2 co", "2cor"]),
+    (48, &["gal", "ga"]),
+    (49, &["eph", "ephes"]),
+    (50, &["phil", "php", "pp"]),
+    (51, &["col", "co"]),
+    (52, &["1 thess", "1 th", "1thess"]),
+    (53, &["2 thess", "2 th", "2thess"]),
+    (54, &["1 tim", "1 ti", "1tim"]),
+    (55, &["2 tim", "2 ti", "2tim"]),
+    (56, &["titus", "tit", "ti"]),
+    (57, &["phlm", "phm", "pm"]),
+    (58, &["heb"]),
+    (59, &["jas", "jm"]),
+    (60, &["1 pet", "1 pe", "1 pt", "1pet"]),
+    (61, &["2 pet", "2 pe", "2 pt", "2pet"]),
+    (62, &["1 john", "1 jn", "1jn"]),
+    (63, &["2 john", "2 jn", "2jn"]),
+    (64, &["3 john", "3 jn", "3jn"]),
+    (65, &["jude", "jud", "jd"]),
+    (66, &["rev", "re"]),
+    (67, &["tob", "tb"]),
+    (68, &["jdt", "jdth"]),
+    (69, &["add esth", "gr esth"]),
+    (70, &["wis", "wisd"]),
+    (71, &["sir", "ecclus"]),
+    (72, &["bar"]),
+    (73, &["ep jer", "let jer"]),
+    (74, &["song thr", "pr azar"]),
+    (75, &["sus"]),
+    (76, &["bel"]),
+    (77, &["1 macc", "1 mac", "1macc"]),
+    (78, &["2 macc", "2 mac", "2macc"]),
+    (79, &["3 macc", "3 mac", "3macc"]),
+    (80, &["4 macc", "4 mac", "4macc"]),
+    (81, &["1 esd", "1esd"]),
+    (82, &["2 esd", "2esd"]),
+    (83, &["pr man", "prman"]),
+];
+
+/// Common Spanish abbreviations by book id
+const SPANISH: &[(usize, &[&str])] = &[
+    (1, &["gn", "gén"]),
+    (2, &["ex", "éx"]),
+    (3, &["lv", "lev"]),
+    (4, &["nm", "núm"]),
+    (5, &["dt", "deut"]),
+    (6, &["jos"]),
+    (7, &["jue", "jc"]),
+    (8, &["rt", "rut"]),
+    (9, &["1 s", "1 sam"]),
+    (10, &["2 s", "2 sam"]),
+    (11, &["1 r", "1 re"]),
+    (12, &["2 r", "2 re"]),
+    (13, &["1 cr", "1 cró"]),
+    (14, &["2 cr", "2 cró"]),
+    (15, &["esd"]),
+    (16, &["neh", "ne"]),
+    (17, &["est"]),
+    (18, &["job", "jb"]),
+    (19, &["sal", "sl"]),
+    (20, &["pr", "prov"]),
+    (21, &["ec", "ecl"]),
+    (22, &["cnt", "cant"]),
+    (23, &["is", "isa"]),
+    (24, &["jr", "jer"]),
+    (25, &["lm", "lam"]),
+    (26, &["ez", "eze"]),
+    (27, &["dn", "dan"]),
+    (28, &["os"]),
+    (29, &["jl"]),
+    (30, &["am"]),
+    (31, &["abd"]),
+    (32, &["jon"]),
+    (33, &["mi", "miq"]),
+    (34, &["nah"]),
+    (35, &["hab"]),
+    (36, &["sof"]),
+    (37, &["hag"]),
+    (38, &["zac"]),
+    (39, &["mal"]),
+    (40, &["mt", "mat"]),
+    (41, &["mr", "mc"]),
+    (42, &["lc", "luc"]),
+    (43, &["jn", "juan"]),
+    (44, &["hch", "hech"]),
+    (45, &["ro", "rom"]),
+    (46, &["1 co", "1 cor"]),
+    (47, &["2 co", "2 cor"]),
+    (48, &["gá", "gál", "gal"]),
+    (49, &["ef"]),
+    (50, &["flp", "fil"]),
+    (51, &["col"]),
+    (52, &["1 ts", "1 tes"]),
+    (53, &["2 ts", "2 tes"]),
+    (54, &["1 ti", "1 tim"]),
+    (55, &["2 ti", "2 tim"]),
+    (56, &["tit"]),
+    (57, &["flm"]),
+    (58, &["he", "heb"]),
+    (59, &["stg", "sant"]),
+    (60, &["1 p", "1 pe"]),
+    (61, &["2 p", "2 pe"]),
+    (62, &["1 jn"]),
+    (63, &["2 jn"]),
+    (64, &["3 jn"]),
+    (65, &["jud"]),
+    (66, &["ap", "apoc"]),
+];
+
+/// - The standard list for a translation's `language` (`English`, `en`, `Español`, ...)
+/// - `None` for languages without one, which only get [`prefixes`]
+fn standard_list(language: &str) -> Option<&'static [(usize, &'static [&'static str])]> {
+    let language = language.to_lowercase();
+    if language.starts_with("en") {
+        Some(ENGLISH)
+    } else if language.starts_with("es") || language.starts_with("spa") {
+        Some(SPANISH)
+    } else {
+        None
+    }
+}
+
+/// - The first 3 and 4 letters of the name, keeping a leading number (`1 cor`, `1cor`)
+/// - Only prefixes that no other book name starts with, so `Jud` isn't generated for either
+///   `Judges` or `Jude`
+fn prefixes(book_id: usize, names: &BookIdToName) -> Vec<String> {
+    let Some(name) = names.get(&book_id).map(|name| name.to_lowercase()) else {
+        return vec![];
+    };
+    let (number, rest) = match name.split_once(' ') {
+        Some((number, rest)) if number.chars().all(|c| c.is_ascii_digit()) => (number, rest),
+        _ => ("", name.as_str()),
+    };
+    let mut prefixes = vec![];
+    for length in [3, 4] {
+        let Some((end, _)) = rest.char_indices().nth(length) else {
+            // the whole name is already recognized
+            continue;
+        };
+        let prefix = format!(
+            "{number}{}{}",
+            if number.is_empty() { "" } else { " " },
+            &rest[..end]
+        );
+        let ambiguous = names.iter().any(|(other_id, other)| {
+            *other_id != book_id && other.to_lowercase().starts_with(&prefix)
+        });
+        if ambiguous {
+            continue;
+        }
+        if !number.is_empty() {
+            prefixes.push(prefix.replacen(' ', "", 1));
+        }
+        prefixes.push(prefix);
+    }
+    prefixes
+}
+
+/**
+Abbreviations for a book whose translation didn't list any
+
+- Imported texts (USFM, OSIS) usually only have book names, which means short citations like
+  `eph 1:1` wouldn't be detected at all
+- The standard list for the translation's language comes first, then [`prefixes`] of the name
+- Lowercase, and may repeat the book name or an abbreviation another book already has, which the
+  caller is expected to leave alone
+*/
+pub fn abbreviations(language: &str, book_id: usize, names: &BookIdToName) -> Vec<String> {
+    let mut abbreviations: Vec<String> = standard_list(language)
+        .and_then(|list| list.iter().find(|(id, _)| *id == book_id))
+        .map(|(_, list)| {
+            list.iter()
+                .map(|abbreviation| abbreviation.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    for prefix in prefixes(book_id, names) {
+        if !abbreviations.contains(&prefix) {
+            abbreviations.push(prefix);
+        }
+    }
+    abbreviations
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn prefixes_skip_ambiguous_names() {
+        let names: BookIdToName = [
+            (7, "Judges"),
+            (46, "1 Corinthians"),
+            (65, "Jude"),
+            (66, "Apocalipsis"),
+        ]
+        .into_iter()
+        .map(|(id, name)| (id, Arc::from(name)))
+        .collect();
+        assert_eq!(prefixes(7, &names), vec!["judg"]);
+        assert_eq!(prefixes(65, &names), Vec::<String>::new());
+        assert_eq!(
+            prefixes(46, &names),
+            vec!["1cor", "1 cor", "1cori", "1 cori"]
+        );
+        assert_eq!(
+            abbreviations("Español", 66, &names),
+            vec!["ap", "apoc", "apo"]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use crate::{additions, bible_api::BibleAPI};
+
+/// Spellings people actually type, which would otherwise not be detected at all
+const MISSPELLINGS: &[(&str, usize)] = &[
+    ("deuteronomey", 5),
+    ("ecclesiates", 21),
+    ("lamentation", 25),
+    ("habbakuk", 35),
+    ("habakuck", 35),
+    ("zephania", 36),
+    ("malachai", 39),
+    ("galations", 48),
+    ("ephesains", 49),
+    ("phillipians", 50),
+    ("philipians", 50),
+    ("phillippians", 50),
+    ("colosians", 51),
+    ("1 thesalonians", 52),
+    ("2 thesalonians", 53),
+    ("revelations", 66),
+];
+
+/// Other names for the same book
+const VARIANTS: &[(&str, usize)] = &[
+    ("psalm", 19),
+    ("qoheleth", 21),
+    ("song of songs", 22),
+    ("song of solomon", 22),
+    ("canticles", 22),
+    ("acts of the apostles", 44),
+    ("revelation of john", 66),
+];
+
+/// Older (mostly Douay-Rheims and KJV era) spellings
+const ARCHAIC: &[(&str, usize)] = &[
+    ("josue", 6),
+    ("1 paralipomenon", 13),
+    ("2 paralipomenon", 14),
+    ("canticle of canticles", 22),
+    ("esaias", 23),
+    ("isaias", 23),
+    ("jeremias", 24),
+    ("osee", 28),
+    ("abdias", 31),
+    ("jonas", 32),
+    ("micheas", 33),
+    ("sophonias", 36),
+    ("aggeus", 37),
+    ("aggaeus", 37),
+    ("zacharias", 38),
+    ("malachias", 39),
+    ("apocalypse", 66),
+];
+
+/// Every pack that can be enabled with the `aliasPacks` setting
+pub const PACKS: &[&str] = &[
+    "misspellings",
+    "variants",
+    "numbering",
+    "archaic",
+    "additions",
+];
+
+/// - `I John`, `First John`, and `1st John` for every numbered book
+/// - Generated from the translation's own names, so it works for any numbered book it has
+fn numbering(api: &BibleAPI) -> Vec<(String, usize)> {
+    let prefixes = [
+        ("1 ", ["i ", "first ", "1st "]),
+        ("2 ", ["ii ", "second ", "2nd "]),
+        ("3 ", ["iii ", "third ", "3rd "]),
+    ];
+    api.book_id_to_name
+        .iter()
+        .flat_map(|(book_id, name)| {
+            let name = name.to_lowercase();
+            prefixes
+                .iter()
+                .filter_map(|(number, _)| name.strip_prefix(number).map(|rest| (number, rest)))
+                .flat_map(|(number, rest)| {
+                    let alternatives = prefixes
+                        .iter()
+                        .find(|(other, _)| other == number)
+                        .map(|(_, alternatives)| alternatives.to_vec())
+                        .unwrap_or_default();
+                    alternatives
+                        .into_iter()
+                        .map(move |prefix| (format!("{prefix}{rest}"), *book_id))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/**
+- The aliases of every enabled pack, limited to books the translation has
+- Returns the aliases and any pack names that don't exist
+*/
+pub fn aliases(api: &BibleAPI, packs: &[String]) -> (Vec<(String, usize)>, Vec<String>) {
+    let mut aliases = vec![];
+    let mut unknown = vec![];
+    for pack in packs {
+        let pack_aliases = match pack.to_lowercase().as_str() {
+            "misspellings" => to_owned(MISSPELLINGS),
+            "variants" => to_owned(VARIANTS),
+            "numbering" => numbering(api),
+            "archaic" => to_owned(ARCHAIC),
+            "additions" => additions::aliases(api),
+            _ => {
+                unknown.push(pack.clone());
+                continue;
+            }
+        };
+        aliases.extend(
+            pack_aliases
+                .into_iter()
+                .filter(|(_, book_id)| api.get_book_name(*book_id).is_some()),
+        );
+    }
+    (aliases, unknown)
+}
+
+/**
+The user's own aliases from the `aliases` setting, like `{ "Gn": "Genesis" }`
+
+- The book can be a name, an abbreviation, or an id, as the translation knows it
+- Returns the aliases and any whose book isn't in the translation
+*/
+pub fn user_aliases(
+    api: &BibleAPI,
+    user: &BTreeMap<String, String>,
+) -> (Vec<(String, usize)>, Vec<String>) {
+    let mut aliases = vec![];
+    let mut unknown = vec![];
+    for (alias, book) in user {
+        let book_id = match book.trim().parse::<usize>() {
+            Ok(book_id) => Some(book_id),
+            Err(_) => api.get_book_id(book.trim()),
+        };
+        match book_id.filter(|book_id| api.get_book_name(*book_id).is_some()) {
+            Some(book_id) => aliases.push((alias.trim().to_string(), book_id)),
+            None => unknown.push(format!("{alias} ({book})")),
+        }
+    }
+    (aliases, unknown)
+}
+
+fn to_owned(pack: &[(&str, usize)]) -> Vec<(String, usize)> {
+    pack.iter()
+        .map(|(alias, book_id)| (alias.to_string(), *book_id))
+        .collect()
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bible_lsp::BibleLSP,
+    index_cache::FileStamp,
+    workspace_index::{self, IndexedReference},
+};
+
+/// How often `bible_lsp watch` checks for changes
+pub const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Appended to the source file's name, so `notes.md` gets `notes.md.refs.json`
+pub const SIDECAR_SUFFIX: &str = ".refs.json";
+
+/**
+What is written next to each file, for tools that don't speak LSP (like static site generators)
+
+- `references` are the same as in the workspace index, so ranges are UTF-16 line/character
+  positions
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sidecar {
+    /// the source file's name, without its folder
+    pub source: String,
+    /// the translation the labels use, ex: `ESV`
+    pub translation: String,
+    pub references: Vec<IndexedReference>,
+}
+
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// The file a sidecar belongs to, or `None` if it isn't a sidecar
+fn source_path(sidecar: &Path) -> Option<PathBuf> {
+    let name = sidecar.to_str()?.strip_suffix(SIDECAR_SUFFIX)?;
+    Some(PathBuf::from(name))
+}
+
+/// What a [`Watcher::poll`] did to a sidecar
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// written, with how many references it has
+    Written(PathBuf, usize),
+    /// removed because its file is gone
+    Removed(PathBuf),
+}
+
+/**
+Keeps a `.refs.json` sidecar next to every matching file in a folder
+
+- Polls modified times rather than using OS file events, the same way as
+  [`crate::templates::TemplateStore`]
+- Sidecars are only rewritten when their contents change, so a site generator watching them
+  doesn't rebuild for nothing
+*/
+#[derive(Debug, Default)]
+pub struct Watcher {
+    stamps: BTreeMap<PathBuf, FileStamp>,
+}
+
+impl Watcher {
+    /// - Updates the sidecar of every file that changed since the last call (every file, the
+    ///   first time)
+    /// - `write: false` reports what would change without touching anything
+    pub fn poll(
+        &mut self,
+        lsp: &BibleLSP,
+        root: &Path,
+        extensions: &[String],
+        write: bool,
+    ) -> io::Result<Vec<Change>> {
+        let mut files = vec![];
+        workspace_index::walk(root, extensions, &[], &mut files);
+        let mut changes = vec![];
+        let mut stamps = BTreeMap::new();
+        for path in files {
+            let Some(stamp) = FileStamp::of(&path) else {
+                continue;
+            };
+            stamps.insert(path.clone(), stamp);
+            if self.stamps.get(&path) == Some(&stamp) {
+                continue;
+            }
+            // deleted between walking and reading
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let sidecar = Sidecar {
+                source: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                translation: lsp.api.translation.abbreviation.clone(),
+                references: lsp
+                    .find_book_references(&text)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|book_ref| IndexedReference::new(lsp, book_ref))
+                    .collect(),
+            };
+            let json = serde_json::to_string_pretty(&sidecar)?;
+            let sidecar_path = sidecar_path(&path);
+            if fs::read_to_string(&sidecar_path).is_ok_and(|existing| existing == json) {
+                continue;
+            }
+            if write {
+                fs::write(&sidecar_path, json)?;
+            }
+            changes.push(Change::Written(sidecar_path, sidecar.references.len()));
+        }
+        self.stamps = stamps;
+
+        // sidecars left behind by deleted or renamed files
+        let mut sidecars = vec![];
+        workspace_index::walk(root, &[String::from("json")], &[], &mut sidecars);
+        for sidecar in sidecars {
+            let Some(source) = source_path(&sidecar) else {
+                continue;
+            };
+            if source.exists() {
+                continue;
+            }
+            if write {
+                fs::remove_file(&sidecar)?;
+            }
+            changes.push(Change::Removed(sidecar));
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_sit_next_to_their_file() {
+        let sidecar = sidecar_path(Path::new("notes/john.md"));
+        assert_eq!(sidecar, PathBuf::from("notes/john.md.refs.json"));
+        assert_eq!(source_path(&sidecar), Some(PathBuf::from("notes/john.md")));
+        assert_eq!(source_path(Path::new("package.json")), None);
+    }
+}
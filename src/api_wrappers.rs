@@ -34,6 +34,6 @@ impl<'a> APIBookReference<'a> {
 
     /// provides text for LSP diagnostic
     pub fn lsp_diagnostic(&self) -> Option<String> {
-        self.book_reference.format_diagnostic(&self.api)
+        self.book_reference.format_diagnostic(&self.api, None)
     }
 }
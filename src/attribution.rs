@@ -0,0 +1,151 @@
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{bible_api::BibleAPI, bible_lsp::BibleLSP, config::Config, document::LineIndex};
+
+/// The attribution block is wrapped in these so it can be found and updated later
+pub const START_MARKER: &str = "<!-- bible_lsp:attribution -->";
+pub const END_MARKER: &str = "<!-- /bible_lsp:attribution -->";
+
+/// - The config takes priority over the data file
+/// - Translations without either still get a plain "quotations are from" line
+pub fn attribution_text(api: &BibleAPI, config: &Config) -> String {
+    let translation = &api.translation;
+    let template = config
+        .attribution(&translation.abbreviation)
+        .cloned()
+        .or_else(|| translation.attribution.clone())
+        .unwrap_or_else(|| {
+            String::from("Scripture quotations are from the {name} ({abbreviation}).")
+        });
+    template
+        .replace("{name}", &translation.name)
+        .replace("{abbreviation}", &translation.abbreviation)
+}
+
+/**
+The translations quoted in a document, in the order they are first quoted
+
+- `cited` is the translation of each reference (`John 3:16 KJV`) or marked quote, and `None` is
+  the default one
+- Translations that aren't loaded are left out, since there is nothing to attribute them with
+*/
+pub fn quoted_translations<'a>(
+    lsp: &'a BibleLSP,
+    cited: impl IntoIterator<Item = Option<&'a str>>,
+) -> Vec<&'a BibleAPI> {
+    let mut translations: Vec<&BibleAPI> = vec![];
+    for translation in cited {
+        let Some(api) = lsp.translation(translation.unwrap_or(&lsp.api.translation.abbreviation))
+        else {
+            continue;
+        };
+        if !translations
+            .iter()
+            .any(|quoted| quoted.translation.abbreviation == api.translation.abbreviation)
+        {
+            translations.push(api);
+        }
+    }
+    translations
+}
+
+/// One paragraph per translation quoted, between the markers
+pub fn attribution_block(attributions: &[String]) -> String {
+    format!(
+        "{START_MARKER}\n{}\n{END_MARKER}",
+        attributions.join("\n\n")
+    )
+}
+
+/**
+- If the document already has an attribution block, it is replaced
+- Otherwise the block is appended to the end of the document
+
+Returns `None` when the existing block is already up to date, or has lost its end marker, since
+there is no telling where the notes after it start
+*/
+pub fn attribution_edit(text: &str, attributions: &[String]) -> Option<TextEdit> {
+    let block = attribution_block(attributions);
+    let line_index = LineIndex::new(text);
+
+    if let Some(start) = text.find(START_MARKER) {
+        let end = start + text[start..].find(END_MARKER)? + END_MARKER.len();
+        if text[start..end] == block {
+            return None;
+        }
+        return Some(TextEdit {
+            range: Range {
+                start: line_index.position(text, start),
+                end: line_index.position(text, end),
+            },
+            new_text: block,
+        });
+    }
+
+    let end: Position = line_index.position(text, text.len());
+    let separator = match text {
+        "" => "",
+        text if text.ends_with("\n\n") => "",
+        text if text.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    Some(TextEdit {
+        range: Range { start: end, end },
+        new_text: format!("{separator}{block}\n"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_replaced_between_the_markers() {
+        let attributions = [String::from("From the TST.")];
+        let text = format!("Notes\n\n{START_MARKER}\nOld.\n{END_MARKER}\nMore notes\n");
+        let edit = attribution_edit(&text, &attributions).unwrap();
+        assert_eq!(edit.new_text, attribution_block(&attributions));
+        assert_eq!(edit.range.start, Position::new(2, 0));
+        assert_eq!(edit.range.end, Position::new(4, END_MARKER.len() as u32));
+        let current = format!("Notes\n\n{}\n", attribution_block(&attributions));
+        assert_eq!(attribution_edit(&current, &attributions), None);
+        // without an end marker the notes after the start would be lost
+        let broken = format!("Notes\n{START_MARKER}\nOld.\nMore notes\n");
+        assert_eq!(attribution_edit(&broken, &attributions), None);
+        let appended = attribution_edit("Notes\n", &attributions).unwrap();
+        assert_eq!(
+            appended.new_text,
+            format!("\n{}\n", attribution_block(&attributions))
+        );
+    }
+
+    #[test]
+    fn each_quoted_translation_is_attributed_once() {
+        let mut lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let mut kjv = lsp.api.clone();
+        kjv.translation.name = String::from("King James Version");
+        kjv.translation.abbreviation = String::from("KJV");
+        lsp.add_translation(kjv);
+        let quoted =
+            quoted_translations(&lsp, [Some("kjv"), None, Some("NOPE"), Some("TST"), None]);
+        let config = Config::default();
+        let attributions: Vec<String> = quoted
+            .into_iter()
+            .map(|api| attribution_text(api, &config))
+            .collect();
+        assert_eq!(
+            attributions,
+            [
+                "Scripture quotations are from the King James Version (KJV).",
+                format!(
+                    "Scripture quotations are from the {} (TST).",
+                    lsp.api.translation.name
+                )
+                .as_str(),
+            ]
+        );
+    }
+}
@@ -1,6 +1,5 @@
 use std::fmt::Display;
 
-use cached::proc_macro::cached;
 use tower_lsp::lsp_types::CompletionItem;
 
 use crate::{
@@ -95,7 +94,7 @@ pub enum AutocompleteState {
 impl AutocompleteState {
     pub fn give_suggestions(&self, api: &BibleAPI) -> Vec<BibleCompletion> {
         match self.clone() {
-            AutocompleteState::BooksOnly => suggest_all_books(),
+            AutocompleteState::BooksOnly => suggest_all_books(api),
             AutocompleteState::ChaptersOnly { book_id } => {
                 let chapter_count = api.get_book_chapter_count(book_id).expect("Valid book id");
                 (1..=chapter_count)
@@ -263,7 +262,7 @@ impl BibleCompletion {
             BibleCompletion::BookName(BookNameCompletion { book_id }) => {
                 let book_name = api.get_book_name(book_id).unwrap();
                 // format!("{book_name}")
-                book_name
+                book_name.to_string()
             }
             BibleCompletion::Chapter(ChapterCompletion { book_id, chapter }) => {
                 let book_name = api.get_book_name(book_id).unwrap();
@@ -313,11 +312,12 @@ impl BibleCompletion {
                         }));
                     }
                 };
-                format!(
-                    "{} {}",
-                    api.get_book_name(book_id).unwrap(),
-                    segments.label()
-                )
+                let mut label = api.get_book_name(book_id).unwrap().to_string();
+                label.push(' ');
+                segments
+                    .write_label(&mut label)
+                    .expect("Writing to a String can't fail");
+                label
             }
         }
     }
@@ -411,12 +411,20 @@ impl BibleCompletion {
             }
         }
     }
-    pub fn lsp_sort(&self) -> String {
+    pub fn book_id(&self) -> usize {
+        match self {
+            BibleCompletion::BookName(BookNameCompletion { book_id }) => *book_id,
+            BibleCompletion::Chapter(ChapterCompletion { book_id, .. }) => *book_id,
+            BibleCompletion::Verse(VerseCompletion { book_id, .. }) => *book_id,
+        }
+    }
+
+    pub fn lsp_sort(&self, api: &BibleAPI) -> String {
         match self {
             // book's dont compete with chapters or verses
             BibleCompletion::BookName(book_name_completion) => {
                 // label.to_string()
-                format!("{:03}", book_name_completion.book_id)
+                format!("{:03}", api.canon_position(book_name_completion.book_id))
             }
             // prefixing with z so that verses show before chapters
             BibleCompletion::Chapter(chapter_completion) => {
@@ -432,11 +440,11 @@ impl BibleCompletion {
     }
 }
 
-/// It is probably more valuable to cache the one that actually formats everything, but oh well
-#[cached(size = 1)]
-pub fn suggest_all_books() -> Vec<BibleCompletion> {
-    (1..=66)
-        .map(|book_id| BibleCompletion::BookName(BookNameCompletion { book_id }))
+/// Every book the translation has, in its own order
+pub fn suggest_all_books(api: &BibleAPI) -> Vec<BibleCompletion> {
+    api.canon_order
+        .iter()
+        .map(|book_id| BibleCompletion::BookName(BookNameCompletion { book_id: *book_id }))
         .collect()
 }
 
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tower_lsp::lsp_types::{Range, Url};
+
+use crate::workspace_index::{IndexedReference, WorkspaceIndex};
+
+/// A reference in this document and one in another document that cite some of the same verses
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedPassage {
+    /// Ex: `Ephesians 2:8-10`
+    pub label: String,
+    pub range: Range,
+    /// Ex: `Ephesians 2:8`
+    pub other_label: String,
+    pub other_range: Range,
+}
+
+/// Another document citing a passage this one cites
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backlink {
+    pub uri: Url,
+    /// the path relative to its workspace folder
+    pub name: String,
+    pub shared: Vec<SharedPassage>,
+}
+
+/**
+Every other document that cites any passage `uri` cites, for a "related notes" sidebar
+
+- Passages are related when they share at least one verse, so `Ephesians 2:8` and
+  `Ephesians 2:1-10` are related
+- Documents sharing the most passages come first
+*/
+pub fn backlinks(index: &WorkspaceIndex, uri: &Url) -> Vec<Backlink> {
+    let mut backlinks = find(&index.files(), uri);
+    for backlink in backlinks.iter_mut() {
+        backlink.name = index.display_name(&backlink.uri);
+    }
+    backlinks
+}
+
+/// [`backlinks`] without the display names
+fn find(files: &BTreeMap<Url, Vec<IndexedReference>>, uri: &Url) -> Vec<Backlink> {
+    let Some(cited) = files.get(uri) else {
+        return vec![];
+    };
+    let mut backlinks: Vec<Backlink> = files
+        .iter()
+        .filter(|(other_uri, _)| *other_uri != uri)
+        .filter_map(|(other_uri, other_refs)| {
+            let shared: Vec<SharedPassage> = cited
+                .iter()
+                .flat_map(|indexed| {
+                    other_refs
+                        .iter()
+                        .filter(|other| indexed.overlaps(other))
+                        .map(|other| SharedPassage {
+                            label: indexed.label.clone(),
+                            range: indexed.range,
+                            other_label: other.label.clone(),
+                            other_range: other.range,
+                        })
+                })
+                .collect();
+            (!shared.is_empty()).then(|| Backlink {
+                uri: other_uri.clone(),
+                name: other_uri.to_string(),
+                shared,
+            })
+        })
+        .collect();
+    // stable, so documents sharing as many passages stay in path order
+    backlinks.sort_by_key(|backlink| std::cmp::Reverse(backlink.shared.len()));
+    backlinks
+}
+
+/**
+Every other place in the workspace citing a passage that overlaps `indexed`, for the citation
+count inlay hints
+
+- Other references in the same document count too, just not `indexed` itself (the reference at
+  the same range)
+*/
+pub fn citing(
+    files: &BTreeMap<Url, Vec<IndexedReference>>,
+    uri: &Url,
+    indexed: &IndexedReference,
+) -> Vec<(Url, IndexedReference)> {
+    files
+        .iter()
+        .flat_map(|(other_uri, other_refs)| {
+            other_refs
+                .iter()
+                .filter(move |other| {
+                    indexed.overlaps(other) && !(other_uri == uri && other.range == indexed.range)
+                })
+                .map(move |other| (other_uri.clone(), other.clone()))
+        })
+        .collect()
+}
+
+/// A front matter value naming a passage
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrontMatterPassage {
+    /// Ex: `passage`
+    pub field: String,
+    /// Ex: `John 15:1-12`
+    pub label: String,
+    pub range: Range,
+}
+
+/// A note whose front matter names a passage overlapping the one asked about
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteByPassage {
+    pub uri: Url,
+    /// the path relative to its workspace folder
+    pub name: String,
+    pub passages: Vec<FrontMatterPassage>,
+}
+
+/**
+Every note whose front matter names a passage that shares a verse with one of `passages`
+
+- Only the values of [`crate::config::Config::front_matter_fields`] count, so a note that only
+  mentions the passage in passing isn't listed, and `field` narrows it to one of them
+- Notes are sorted by name
+*/
+pub fn notes_by_passage(
+    index: &WorkspaceIndex,
+    passages: &[IndexedReference],
+    field: Option<&str>,
+) -> Vec<NoteByPassage> {
+    let mut notes: Vec<NoteByPassage> = index
+        .files()
+        .iter()
+        .filter_map(|(uri, refs)| {
+            let matching: Vec<FrontMatterPassage> = refs
+                .iter()
+                .filter(|indexed| match (&indexed.field, field) {
+                    (Some(indexed_field), Some(field)) => indexed_field.eq_ignore_ascii_case(field),
+                    (indexed_field, _) => indexed_field.is_some(),
+                })
+                .filter(|indexed| passages.iter().any(|passage| passage.overlaps(indexed)))
+                .map(|indexed| FrontMatterPassage {
+                    field: indexed.field.clone().unwrap_or_default(),
+                    label: indexed.label.clone(),
+                    range: indexed.range,
+                })
+                .collect();
+            (!matching.is_empty()).then(|| NoteByPassage {
+                uri: uri.clone(),
+                name: index.display_name(uri),
+                passages: matching,
+            })
+        })
+        .collect();
+    notes.sort_by(|a, b| a.name.cmp(&b.name));
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(book_id: usize, label: &str, spans: Vec<[(usize, usize); 2]>) -> IndexedReference {
+        IndexedReference {
+            range: Range::default(),
+            book_id,
+            label: label.to_string(),
+            id: String::new(),
+            spans,
+            field: None,
+            translation: None,
+            quoted: false,
+        }
+    }
+
+    #[test]
+    fn backlinks_share_verses() {
+        let notes = Url::parse("file:///notes/grace.md").unwrap();
+        let sermon = Url::parse("file:///notes/sermon.md").unwrap();
+        let unrelated = Url::parse("file:///notes/genesis.md").unwrap();
+        let files = BTreeMap::from([
+            (
+                notes.clone(),
+                vec![reference(49, "Ephesians 2:8-10", vec![[(2, 8), (2, 10)]])],
+            ),
+            (
+                sermon.clone(),
+                vec![
+                    reference(49, "Ephesians 2:1-9", vec![[(2, 1), (2, 9)]]),
+                    reference(49, "Ephesians 3:1", vec![[(3, 1), (3, 1)]]),
+                ],
+            ),
+            (
+                unrelated,
+                vec![reference(1, "Genesis 2:8", vec![[(2, 8), (2, 8)]])],
+            ),
+        ]);
+        assert_eq!(citing(&files, &notes, &files[&notes][0]).len(), 1);
+        let backlinks = find(&files, &notes);
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].uri, sermon);
+        assert_eq!(backlinks[0].shared[0].other_label, "Ephesians 2:1-9");
+    }
+
+    #[test]
+    fn notes_are_found_by_their_front_matter() {
+        let lsp = crate::bible_lsp::BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let dir = tempfile::tempdir().unwrap();
+        let index = WorkspaceIndex::default();
+        index.set_roots(vec![dir.path().to_path_buf()]);
+        index.set_front_matter_fields(vec![String::from("passage")]);
+        for (name, text) in [
+            ("abide.md", "---\npassage: John 3\n---\nSee Eph 2:8"),
+            ("mention.md", "Just John 3:16 in passing"),
+            ("grace.md", "---\npassage: [Eph 2:8-10, John 3:2]\n---\n"),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            assert!(index.index_file(&lsp, crate::paths::path_to_url(&path).unwrap()));
+        }
+        let (references, _) = crate::front_matter::value_references(&lsp, "John 3:16");
+        let passages: Vec<IndexedReference> = references
+            .iter()
+            .map(|book_ref| IndexedReference::new(&lsp, book_ref))
+            .collect();
+        let notes = notes_by_passage(&index, &passages, None);
+        let names: Vec<&str> = notes.iter().map(|note| note.name.as_str()).collect();
+        assert_eq!(names, ["abide.md"]);
+        assert_eq!(notes[0].passages[0].label, "John 3:1-36");
+        assert_eq!(notes[0].passages[0].field, "passage");
+
+        let (references, _) = crate::front_matter::value_references(&lsp, "Ephesians 2");
+        let passages: Vec<IndexedReference> = references
+            .iter()
+            .map(|book_ref| IndexedReference::new(&lsp, book_ref))
+            .collect();
+        let notes = notes_by_passage(&index, &passages, Some("passage"));
+        let names: Vec<&str> = notes.iter().map(|note| note.name.as_str()).collect();
+        assert_eq!(names, ["grace.md"]);
+        assert!(notes_by_passage(&index, &passages, Some("scripture")).is_empty());
+    }
+}
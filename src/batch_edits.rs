@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+/**
+Edits inserting text after the lines of several references at once, like for a selection with a
+reference on every line
+
+- One edit per line, so references sharing a line get their passages in one block instead of edits
+  at the same position, which clients apply in an unspecified order
+- Passages on the same line keep the order they were given in, and the edits are in document order
+- Each block starts with `\n`, the same as the single insert action, so it works on the last line
+*/
+pub fn insert_after_lines(passages: impl IntoIterator<Item = (u32, String)>) -> Vec<TextEdit> {
+    let mut lines: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for (line, passage) in passages {
+        lines.entry(line).or_default().push(passage);
+    }
+    lines
+        .into_iter()
+        .map(|(line, passages)| {
+            let end_of_line = Position {
+                line,
+                character: u32::MAX,
+            };
+            TextEdit {
+                range: Range {
+                    start: end_of_line,
+                    end: end_of_line,
+                },
+                new_text: format!("\n{}", passages.join("\n")),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_insert_per_line_in_order() {
+        let edits = insert_after_lines([
+            (4, String::from("Romans 8:28")),
+            (1, String::from("John 3:16")),
+            (4, String::from("Romans 8:29")),
+        ]);
+        let inserted: Vec<(u32, &str)> = edits
+            .iter()
+            .map(|edit| (edit.range.start.line, edit.new_text.as_str()))
+            .collect();
+        assert_eq!(
+            inserted,
+            vec![(1, "\nJohn 3:16"), (4, "\nRomans 8:28\nRomans 8:29")]
+        );
+    }
+}
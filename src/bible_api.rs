@@ -1,16 +1,25 @@
 use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::{collections::BTreeMap, sync::Mutex};
 
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use crate::bible_json::{JSONBible, JSONTranslation};
+use crate::alias_gen;
+use crate::bible_cache;
+use crate::bible_json::{self, JSONBible, JSONBook, JSONTranslation, VerseLayout};
+use crate::convert;
+use crate::error::{self, Error};
+use crate::versification::PsalmNumbering;
 
 /// map of abbreviations and actual name (all lowercase) to book id
 pub type AbbreviationsToBookId = BTreeMap<String, usize>;
 
-/// map of book id to book name
-pub type BookIdToName = BTreeMap<usize, String>;
+/// - map of book id to book name
+/// - `Arc<str>` so labels, completions, and symbols can share the name instead of copying it
+pub type BookIdToName = BTreeMap<usize, Arc<str>>;
 
 /// - 2D array to check if verse reference is valid
 ///   - each outer array corresponds to a book of the bible
@@ -24,10 +33,30 @@ pub type ReferenceArray = Vec<Vec<usize>>;
 ///   - each inner array corresponds to each verse of the chapter
 pub type BibleContents = Vec<Vec<Vec<String>>>;
 
+/// - Translator footnotes by `(book_id, chapter, verse)`, see [`JSONBook::footnotes`]
+/// - Most verses have none, so they aren't another array like [`BibleContents`]
+pub type Footnotes = BTreeMap<(usize, usize, usize), Vec<String>>;
+
+/// Section headings by the `(book_id, chapter, verse)` each section starts at, see
+/// [`JSONBook::headings`]
+pub type SectionHeadings = BTreeMap<(usize, usize, usize), String>;
+
+/// - The words of Christ by `(book_id, chapter, verse)`, see [`JSONBook::words_of_christ`]
+/// - `[start, end)` character offsets into the verse, in order
+pub type WordsOfChrist = BTreeMap<(usize, usize, usize), Vec<(usize, usize)>>;
+
+/// - How verses are laid out by `(book_id, chapter, verse)`, see [`JSONBook::layout`]
+/// - Lines are in order, and verses without an entry are prose
+pub type Layouts = BTreeMap<(usize, usize, usize), VerseLayout>;
+
+/// Translation abbreviation and [`BibleAPI::alias_generation`], so adding aliases rebuilds the regex
+type RegexCacheKey = (String, usize);
+
 /// - This is a cache used to store a dynamically generated RegEx for matching books of the Bible based on the abbreviations by translation
 /// - This **DOES NOT** match `1:1-4,5-7,2:2-3:4,6` in `eph 1:1-4,5-7,2:2-3:4,6`
 /// - This would match `eph` for `Ephesians`
-static BOOK_ABBREVIATION_REGEX_CACHE: Lazy<Mutex<Option<(String, Regex)>>> =
+/// - Keyed by [`RegexCacheKey`]
+static BOOK_ABBREVIATION_REGEX_CACHE: Lazy<Mutex<Option<(RegexCacheKey, Regex)>>> =
     Lazy::new(|| Mutex::new(None));
 
 /// - This is a cache used to store a dynamically generated RegEx for matching books of the Bible AND reference content based on the abbreviations by translation
@@ -36,9 +65,15 @@ static BOOK_ABBREVIATION_REGEX_CACHE: Lazy<Mutex<Option<(String, Regex)>>> =
 static BOOK_REFERENCE_REGEX_CACHE: Lazy<Mutex<Option<(String, Regex)>>> =
     Lazy::new(|| Mutex::new(None));
 
+/// Every load and every call to [`BibleAPI::add_aliases`] gets a new generation
+static ALIAS_GENERATION: AtomicUsize = AtomicUsize::new(1);
+
 #[derive(Clone, Debug)]
 pub struct BibleAPI {
     pub translation: JSONTranslation,
+    /// - Book ids in the translation's own order, see [`JSONBook::order`]
+    /// - Sorting anything shown to the user by book should use [`BibleAPI::canon_position`]
+    pub canon_order: Vec<usize>,
     /// map of abbreviations and actual name (all lowercase) to book id
     pub abbreviations_to_book_id: AbbreviationsToBookId,
     /// map of book id to book name
@@ -52,26 +87,88 @@ pub struct BibleAPI {
     ///   - each outer array corresponds to a book of the bible
     ///   - each middle array corresponds to each chapter of the book
     ///   - each inner array corresponds to each verse of the chapter
-    pub bible_contents: BibleContents,
+    /// - Shared between clones, so every client of `bible_lsp --daemon` reads the same text
+    pub bible_contents: Arc<BibleContents>,
+    /// see [`Footnotes`]
+    pub footnotes: Arc<Footnotes>,
+    /// see [`SectionHeadings`]
+    pub section_headings: Arc<SectionHeadings>,
+    /// see [`WordsOfChrist`]
+    pub words_of_christ: Arc<WordsOfChrist>,
+    /// see [`Layouts`]
+    pub layouts: Arc<Layouts>,
+    /// - New for every load and every call to [`BibleAPI::add_aliases`]
+    /// - Part of the regex cache key, since the aliases are part of the regex, and two files can
+    ///   have the same abbreviation
+    pub alias_generation: usize,
+    /// - How Psalms are numbered in citations, set from the config
+    /// - The data itself is always in the Hebrew numbering
+    pub psalm_numbering: PsalmNumbering,
 }
 
 impl BibleAPI {
     /// - This reads the JSON file and reformats it into optimized data structures to be used by
     /// the methods of this "API"
+    /// - Panics if the file can't be loaded, see [`BibleAPI::load`] for the error instead
     pub fn new(json_path: &str) -> Self {
-        let bible_json = std::fs::read_to_string(json_path)
-            .expect(format!("Couldn't find the Bible JSON file at {json_path:?}.").as_str());
-        let bible: JSONBible = serde_json::from_str(bible_json.as_str())
-            .expect("Bible JSON file improperly formatted.");
+        Self::load(json_path).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// - [`BibleAPI::new`], with what went wrong instead of a panic
+    /// - A directory is read as USFM files, see [`crate::bible_usfm::read_dir`]
+    /// - Files in other formats (`.xml`, `.SQLite3`, ...) are converted as they are read, see
+    ///   [`convert::Format::of_path`]
+    /// - Large files are only parsed once, and loaded from a binary cache after that, see
+    ///   [`bible_cache::read_through`]
+    pub fn load(json_path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = json_path.as_ref();
+        let bible: JSONBible = match (path.is_dir(), convert::Format::of_path(path)) {
+            #[cfg(feature = "usfm")]
+            (true, _) => crate::bible_usfm::read_dir(path)?,
+            (false, Some(format)) => bible_cache::read_through(path, || {
+                Ok(convert::merge(
+                    vec![convert::read_file(format, path)?],
+                    convert::fallback_translation(path),
+                ))
+            })?,
+            _ => bible_cache::read_through(path, || bible_json::read(path))?,
+        };
+        // the arrays below are indexed by `id - 1`
+        if let Some(book) = bible.bible.iter().find(|book| book.id == 0) {
+            return Err(Error::UnknownBook {
+                name: path.display().to_string(),
+                book: book.book.clone(),
+                id: book.id,
+            });
+        }
+        if let Some(problem) = bible_json::problems(&bible)
+            .into_iter()
+            .find(|problem| problem.fatal)
+        {
+            return Err(Error::invalid(path, problem));
+        }
 
         let mut abbreviations_to_book_id = AbbreviationsToBookId::new();
         let mut book_id_to_name = BookIdToName::new();
         let mut reference_array = ReferenceArray::new();
         let mut bible_contents = BibleContents::new();
+        let mut footnotes = Footnotes::new();
+        let mut section_headings = SectionHeadings::new();
+        let mut words_of_christ = WordsOfChrist::new();
+        let mut layouts = Layouts::new();
 
-        for book in bible.bible.iter() {
+        // the arrays are indexed by book id, whatever order the file lists the books in
+        let mut books_by_id: Vec<&JSONBook> = bible.bible.iter().collect();
+        books_by_id.sort_by_key(|book| book.id);
+        for book in books_by_id {
+            // ids can skip books (a New Testament, or deuterocanonical books numbered past 66), so
+            // the missing ones get no chapters rather than shifting every book after them
+            while reference_array.len() + 1 < book.id {
+                reference_array.push(vec![]);
+                bible_contents.push(vec![]);
+            }
             let mut book_contents: Vec<Vec<String>> = vec![];
-            book_id_to_name.insert(book.id, book.book.clone());
+            book_id_to_name.insert(book.id, Arc::from(book.book.as_str()));
             abbreviations_to_book_id.insert(book.book.clone().to_lowercase(), book.id);
             for abbreviation in book.abbreviations.iter().cloned() {
                 abbreviations_to_book_id.insert(abbreviation.to_lowercase(), book.id);
@@ -83,17 +180,132 @@ impl BibleAPI {
             }
             reference_array.push(chapter_array);
             bible_contents.push(book_contents);
+            // ones that aren't a verse were reported by `problems`
+            for (key, notes) in book.footnotes.iter() {
+                if let Some((chapter, verse)) = JSONBook::verse_key(key) {
+                    footnotes.insert((book.id, chapter, verse), notes.clone());
+                }
+            }
+            for (key, heading) in book.headings.iter() {
+                if let Some((chapter, verse)) = JSONBook::verse_key(key) {
+                    section_headings.insert((book.id, chapter, verse), heading.clone());
+                }
+            }
+            for (key, spans) in book.words_of_christ.iter() {
+                if let Some((chapter, verse)) = JSONBook::verse_key(key) {
+                    let mut spans: Vec<(usize, usize)> = spans
+                        .iter()
+                        .filter(|[start, end]| start < end)
+                        .map(|[start, end]| (*start, *end))
+                        .collect();
+                    spans.sort();
+                    words_of_christ.insert((book.id, chapter, verse), spans);
+                }
+            }
+            for (key, layout) in book.layout.iter() {
+                if let Some((chapter, verse)) = JSONBook::verse_key(key) {
+                    let mut layout = layout.clone();
+                    layout.lines.sort();
+                    layouts.insert((book.id, chapter, verse), layout);
+                }
+            }
         }
 
-        Self {
+        // imported texts often only have book names, so short forms wouldn't be detected at all
+        for book in bible
+            .bible
+            .iter()
+            .filter(|book| book.abbreviations.is_empty())
+        {
+            let generated =
+                alias_gen::abbreviations(&bible.translation.language, book.id, &book_id_to_name);
+            for abbreviation in generated {
+                abbreviations_to_book_id
+                    .entry(abbreviation)
+                    .or_insert(book.id);
+            }
+        }
+
+        let mut canon: Vec<(usize, usize)> = bible
+            .bible
+            .iter()
+            .enumerate()
+            .map(|(idx, book)| (book.order.unwrap_or(idx + 1), book.id))
+            .collect();
+        canon.sort();
+        let canon_order = canon.into_iter().map(|(_, book_id)| book_id).collect();
+
+        Ok(Self {
             translation: bible.translation,
+            canon_order,
             abbreviations_to_book_id,
             book_id_to_name,
             reference_array,
-            bible_contents,
+            bible_contents: Arc::new(bible_contents),
+            footnotes: Arc::new(footnotes),
+            section_headings: Arc::new(section_headings),
+            words_of_christ: Arc::new(words_of_christ),
+            layouts: Arc::new(layouts),
+            alias_generation: ALIAS_GENERATION.fetch_add(1, Ordering::Relaxed),
+            psalm_numbering: Default::default(),
+        })
+    }
+
+    /**
+    An API without a translation
+
+    - Nothing is detected in it, since it has no books
+    - What the server has until `initialize` says which translation to load, see
+      [`crate::config::Config::translation`]
+    */
+    pub fn empty() -> Self {
+        Self {
+            translation: JSONTranslation {
+                name: String::new(),
+                language: String::new(),
+                abbreviation: String::new(),
+                attribution: None,
+            },
+            canon_order: Vec::new(),
+            abbreviations_to_book_id: AbbreviationsToBookId::new(),
+            book_id_to_name: BookIdToName::new(),
+            reference_array: ReferenceArray::new(),
+            bible_contents: Default::default(),
+            footnotes: Default::default(),
+            section_headings: Default::default(),
+            words_of_christ: Default::default(),
+            layouts: Default::default(),
+            alias_generation: 0,
+            psalm_numbering: Default::default(),
         }
     }
 
+    /// See [`BibleAPI::empty`]
+    pub fn is_empty(&self) -> bool {
+        self.book_id_to_name.is_empty()
+    }
+
+    /// - Adds extra names for books on top of the ones in the data file
+    /// - Aliases that are already a name or abbreviation are left alone
+    pub fn add_aliases(&mut self, aliases: impl IntoIterator<Item = (String, usize)>) {
+        for (alias, book_id) in aliases {
+            self.abbreviations_to_book_id
+                .entry(alias.to_lowercase())
+                .or_insert(book_id);
+        }
+        self.alias_generation = ALIAS_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// - [`BibleAPI::add_aliases`], but these replace a name or abbreviation that is already there
+    /// - For aliases the user asked for, which should mean what they say
+    pub fn replace_aliases(&mut self, aliases: impl IntoIterator<Item = (String, usize)>) {
+        for (alias, book_id) in aliases {
+            self.abbreviations_to_book_id
+                .insert(alias.to_lowercase(), book_id);
+        }
+        self.alias_generation = ALIAS_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn is_valid_book_chapter(&self, book: usize, chapter: usize) -> bool {
         self.reference_array
             .get(book - 1)
@@ -101,23 +313,24 @@ impl BibleAPI {
     }
 
     pub fn is_valid_reference(&self, book: usize, chapter: usize, verse: usize) -> bool {
-        self.reference_array
-            .get(book - 1)
-            .and_then(|chapters| chapters.get(chapter - 1))
-            .is_some_and(|verse_count| verse <= *verse_count)
+        self.get_chapter_verse_count(book, chapter)
+            .is_some_and(|verse_count| verse <= verse_count)
     }
 
-    /// gets the number of chapters in a book
+    /// - gets the number of chapters in a book
+    /// - `None` for books the translation doesn't have, like the Old Testament in a New Testament,
+    ///   since ids it skips are still in [`BibleAPI::reference_array`]
     pub fn get_book_chapter_count(&self, book: usize) -> Option<usize> {
-        Some(self.reference_array.get(book - 1)?.len())
+        let chapters = self.reference_array.get(book.checked_sub(1)?)?;
+        (!chapters.is_empty()).then_some(chapters.len())
     }
 
     /// gets the number of verses in a chapter
     pub fn get_chapter_verse_count(&self, book: usize, chapter: usize) -> Option<usize> {
         Some(
             self.reference_array
-                .get(book - 1)?
-                .get(chapter - 1)?
+                .get(book.checked_sub(1)?)?
+                .get(chapter.checked_sub(1)?)?
                 .clone(),
         )
     }
@@ -161,6 +374,39 @@ impl BibleAPI {
         )
     }
 
+    /// The translator's footnotes on a verse, usually none
+    pub fn get_footnotes(&self, book: usize, chapter: usize, verse: usize) -> &[String] {
+        self.footnotes
+            .get(&(book, chapter, verse))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The heading of the section starting at a verse, if one does
+    pub fn get_section_heading(&self, book: usize, chapter: usize, verse: usize) -> Option<&str> {
+        self.section_headings
+            .get(&(book, chapter, verse))
+            .map(String::as_str)
+    }
+
+    /// Where the words of Christ are in a verse, see [`WordsOfChrist`]
+    pub fn get_words_of_christ(
+        &self,
+        book: usize,
+        chapter: usize,
+        verse: usize,
+    ) -> &[(usize, usize)] {
+        self.words_of_christ
+            .get(&(book, chapter, verse))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// How a verse is laid out, or `None` for prose, see [`Layouts`]
+    pub fn get_layout(&self, book: usize, chapter: usize, verse: usize) -> Option<&VerseLayout> {
+        self.layouts.get(&(book, chapter, verse))
+    }
+
     // this is actually wrong, because you must go to end of the chapter not end verse if there
     // is another chapter
     pub fn get_bible_range_contents(
@@ -189,7 +435,17 @@ impl BibleAPI {
             .cloned()
     }
 
-    pub fn get_book_name(&self, book: usize) -> Option<String> {
+    /// - Where the book is in [`BibleAPI::canon_order`], starting at 0
+    /// - Books the translation doesn't have go last
+    pub fn canon_position(&self, book_id: usize) -> usize {
+        self.canon_order
+            .iter()
+            .position(|id| *id == book_id)
+            .unwrap_or(self.canon_order.len() + book_id)
+    }
+
+    /// Cloning the `Arc` is cheap, so this doesn't allocate
+    pub fn get_book_name(&self, book: usize) -> Option<Arc<str>> {
         self.book_id_to_name.get(&book).cloned()
     }
 
@@ -197,10 +453,8 @@ impl BibleAPI {
     /// - The period is removed when calling [`BibleAPI::get_book_id`]
     pub fn book_abbreviation_regex(&self) -> Regex {
         let mut cache = BOOK_ABBREVIATION_REGEX_CACHE.lock().unwrap();
-        if cache
-            .as_ref()
-            .is_some_and(|(version, _)| *version == self.translation.abbreviation)
-        {
+        let key = (self.translation.abbreviation.clone(), self.alias_generation);
+        if cache.as_ref().is_some_and(|(version, _)| *version == key) {
             cache.as_ref().unwrap().clone().1
         } else {
             let books_pattern: String = self
@@ -211,10 +465,81 @@ impl BibleAPI {
                 .collect::<Vec<String>>()
                 .join("|");
             // I added the period so that people can use it in abbreviations
-            let pattern = Regex::new(format!(r"\b((?i){books_pattern})\b\.?").as_str())
-                .expect("Failed to compile book_abbreviation_regex.");
-            *cache = Some((self.translation.abbreviation.clone(), pattern.clone()));
+            let pattern = match self.is_empty() {
+                // an empty alternation would match everywhere, this matches nowhere
+                true => Regex::new(r"[^\s\S]"),
+                false => Regex::new(format!(r"\b((?i){books_pattern})\b\.?").as_str()),
+            }
+            .expect("Failed to compile book_abbreviation_regex.");
+            *cache = Some((key, pattern.clone()));
             pattern
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn books_keep_the_translations_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tanakh.json");
+        let book = |id: usize, name: &str, order: Option<usize>| {
+            serde_json::json!({
+                "id": id,
+                "book": name,
+                "abbreviations": [],
+                "order": order,
+                "content": [vec![format!("{name} 1:1")]],
+            })
+        };
+        let json = serde_json::json!({
+            "translation": { "name": "Test", "language": "English", "abbreviation": "TST" },
+            "bible": [
+                book(1, "Genesis", Some(9)),
+                book(3, "Leviticus", None),
+                book(2, "Exodus", None),
+            ],
+        });
+        std::fs::write(&path, json.to_string()).unwrap();
+        let api = BibleAPI::new(path.to_str().unwrap());
+        // listed order, except where a book says otherwise
+        assert_eq!(api.canon_order, vec![3, 2, 1]);
+        assert_eq!(api.canon_position(1), 2);
+        // contents are still found by id, not by where the book is in the file
+        assert_eq!(
+            api.get_bible_contents(2, 1, 1).as_deref(),
+            Some("Exodus 1:1")
+        );
+    }
+
+    #[test]
+    fn books_outside_the_protestant_canon_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("partial.json");
+        // no abbreviations, like a converted file, so they are generated
+        std::fs::write(
+            &path,
+            r#"{"translation": {"name": "", "language": "English", "abbreviation": ""},
+                "bible": [
+                    {"id": 40, "book": "Matthew", "abbreviations": [],
+                        "content": [["The book of the genealogy"]]},
+                    {"id": 67, "book": "Tobit", "abbreviations": [],
+                        "content": [["The book of the words of Tobit"]]}]}"#,
+        )
+        .unwrap();
+
+        let lsp = crate::bible_lsp::BibleLSP::new(path.to_str().unwrap());
+        let api = &lsp.api;
+        assert_eq!(api.canon_order, vec![40, 67]);
+        assert_eq!(crate::autocompletion::suggest_all_books(api).len(), 2);
+        assert_eq!(api.get_book_chapter_count(67), Some(1));
+        // the books a New Testament skips aren't there, rather than there without chapters
+        assert_eq!(api.get_book_chapter_count(1), None);
+        assert!(!api.is_valid_reference(1, 1, 1));
+        let found = lsp.find_book_references("Tob 1:1 and Gen 1:1").unwrap();
+        let books: Vec<usize> = found.iter().map(|book_ref| book_ref.book_id).collect();
+        assert_eq!(books, [67]);
+    }
+}
@@ -0,0 +1,359 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bible_json::{JSONBible, JSONBook, JSONTranslation, VerseLayout};
+use crate::error;
+use crate::{paths, trace};
+
+/// Files smaller than this parse about as fast as their cache loads, so they aren't cached
+pub const MIN_SIZE: u64 = 1024 * 1024;
+
+/// Marks a file as one of these caches
+const MAGIC: &[u8; 8] = b"BLSPBIBL";
+
+/// Bumped whenever the layout changes, so older caches are ignored rather than misread
+const FORMAT_VERSION: usize = 5;
+
+/// - Cache file names and keys have to be the same every run, which `DefaultHasher` doesn't
+///   promise
+/// - FNV-1a is tiny and good enough for a file name
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/**
+[`read`] for translation files, unless there is a current cache of them
+
+- Only for files of at least [`MIN_SIZE`], and what was read is cached for next time
+- A cache that can't be written is logged and otherwise ignored, since it only saves time
+*/
+pub fn read_through(
+    source: &Path,
+    read: impl FnOnce() -> error::Result<JSONBible>,
+) -> error::Result<JSONBible> {
+    let large = fs::metadata(source).is_ok_and(|meta| meta.is_file() && meta.len() >= MIN_SIZE);
+    let Some(contents) = large.then(|| fs::read(source).ok()).flatten() else {
+        return read();
+    };
+    let cache = BibleCache::of(source);
+    if let Some(bible) = cache.load(&contents) {
+        return Ok(bible);
+    }
+    let bible = read()?;
+    if let Err(err) = cache.save(&contents, &bible) {
+        trace::log(trace::Level::Warn, || {
+            format!("Couldn't cache {}: {err}", source.display())
+        });
+    }
+    Ok(bible)
+}
+
+/**
+A binary copy of a parsed translation file, so startup doesn't parse it again
+
+- One per translation file, next to it and named after it, like `.esv.json.cache` for `esv.json`
+- Under [`paths::cache_dir`] instead (named after the file's path) when the translation's
+  directory can't be written to, like a read-only install
+- Starts with a hash of the file's contents, and is stale (and ignored) once the file changes
+- The [`FORMAT_VERSION`] comes after the hash, and caches of any other version are ignored
+- Numbers are little-endian `u32`s, and strings and lists start with their length
+*/
+#[derive(Clone, Debug)]
+pub struct BibleCache {
+    path: PathBuf,
+    /// where it goes when `path` can't be written
+    fallback: Option<PathBuf>,
+}
+
+impl BibleCache {
+    pub fn of(source: &Path) -> Self {
+        let name = source
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        Self {
+            path: source.with_file_name(format!(".{name}.cache")),
+            fallback: Some(Self::in_dir(&paths::cache_dir().join("bibles"), source).path),
+        }
+    }
+
+    fn in_dir(dir: &Path, source: &Path) -> Self {
+        let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+        let key = fnv1a(source.to_string_lossy().as_bytes());
+        Self {
+            path: dir.join(format!("{key:016x}.bin")),
+            fallback: None,
+        }
+    }
+
+    /// The cached translation, if it was cached from `contents`
+    pub fn load(&self, contents: &[u8]) -> Option<JSONBible> {
+        std::iter::once(&self.path)
+            .chain(self.fallback.as_ref())
+            .find_map(|path| {
+                let bytes = fs::read(path).ok()?;
+                let mut decoder = Decoder(bytes.strip_prefix(MAGIC)?);
+                if decoder.hash()? != fnv1a(contents) || decoder.number()? != FORMAT_VERSION {
+                    return None;
+                }
+                decoder.bible()
+            })
+    }
+
+    /// - Written to a temporary file first, so a session starting meanwhile never reads half of it
+    /// - Only written to the fallback when it can't be written next to the translation
+    pub fn save(&self, contents: &[u8], bible: &JSONBible) -> io::Result<()> {
+        let mut encoder = Encoder(MAGIC.to_vec());
+        encoder.0.extend(fnv1a(contents).to_le_bytes());
+        encoder.number(FORMAT_VERSION);
+        encoder.bible(bible);
+        match (write(&self.path, &encoder.0), &self.fallback) {
+            (Err(_), Some(fallback)) => write(fallback, &encoder.0),
+            (written, _) => written,
+        }
+    }
+}
+
+fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    io::Write::write_all(&mut file, bytes)?;
+    file.persist(path)?;
+    Ok(())
+}
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn number(&mut self, number: usize) {
+        self.0.extend((number as u32).to_le_bytes());
+    }
+
+    fn text(&mut self, text: &str) {
+        self.number(text.len());
+        self.0.extend(text.as_bytes());
+    }
+
+    fn bible(&mut self, bible: &JSONBible) {
+        let translation = &bible.translation;
+        self.text(&translation.name);
+        self.text(&translation.language);
+        self.text(&translation.abbreviation);
+        // 0 for none, and 1 followed by it otherwise
+        match &translation.attribution {
+            Some(attribution) => {
+                self.number(1);
+                self.text(attribution);
+            }
+            None => self.number(0),
+        }
+        self.number(bible.bible.len());
+        for book in bible.bible.iter() {
+            self.number(book.id);
+            self.text(&book.book);
+            self.number(book.abbreviations.len());
+            for abbreviation in book.abbreviations.iter() {
+                self.text(abbreviation);
+            }
+            // 0 for none, and one more than it otherwise
+            self.number(book.order.map(|order| order + 1).unwrap_or(0));
+            self.number(book.content.len());
+            for verses in book.content.iter() {
+                self.number(verses.len());
+                for verse in verses.iter() {
+                    self.text(verse);
+                }
+            }
+            self.number(book.footnotes.len());
+            for (key, notes) in book.footnotes.iter() {
+                self.text(key);
+                self.number(notes.len());
+                for note in notes.iter() {
+                    self.text(note);
+                }
+            }
+            self.number(book.headings.len());
+            for (key, heading) in book.headings.iter() {
+                self.text(key);
+                self.text(heading);
+            }
+            self.number(book.words_of_christ.len());
+            for (key, spans) in book.words_of_christ.iter() {
+                self.text(key);
+                self.number(spans.len());
+                for [start, end] in spans.iter() {
+                    self.number(*start);
+                    self.number(*end);
+                }
+            }
+            self.number(book.layout.len());
+            for (key, layout) in book.layout.iter() {
+                self.text(key);
+                self.number(usize::from(layout.paragraph));
+                self.number(usize::from(layout.stanza));
+                self.number(layout.lines.len());
+                for [offset, indent] in layout.lines.iter() {
+                    self.number(*offset);
+                    self.number(*indent);
+                }
+            }
+        }
+    }
+}
+
+/// Reads what [`Encoder`] wrote, and `None` for anything cut short or malformed
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn hash(&mut self) -> Option<u64> {
+        let (hash, rest) = self.0.split_first_chunk::<8>()?;
+        self.0 = rest;
+        Some(u64::from_le_bytes(*hash))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let (number, rest) = self.0.split_first_chunk::<4>()?;
+        self.0 = rest;
+        Some(u32::from_le_bytes(*number) as usize)
+    }
+
+    fn text(&mut self) -> Option<String> {
+        let len = self.number()?;
+        if len > self.0.len() {
+            return None;
+        }
+        let (text, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(text.to_vec()).ok()
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.number()?;
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    fn bible(&mut self) -> Option<JSONBible> {
+        let translation = JSONTranslation {
+            name: self.text()?,
+            language: self.text()?,
+            abbreviation: self.text()?,
+            attribution: match self.number()? {
+                0 => None,
+                _ => Some(self.text()?),
+            },
+        };
+        let bible = self.list(|decoder| {
+            Some(JSONBook {
+                id: decoder.number()?,
+                book: decoder.text()?,
+                abbreviations: decoder.list(Self::text)?,
+                order: decoder.number()?.checked_sub(1),
+                content: decoder.list(|decoder| decoder.list(Self::text))?,
+                footnotes: decoder
+                    .list(|decoder| Some((decoder.text()?, decoder.list(Self::text)?)))?
+                    .into_iter()
+                    .collect(),
+                headings: decoder
+                    .list(|decoder| Some((decoder.text()?, decoder.text()?)))?
+                    .into_iter()
+                    .collect(),
+                words_of_christ: decoder
+                    .list(|decoder| {
+                        let key = decoder.text()?;
+                        let spans =
+                            decoder.list(|decoder| Some([decoder.number()?, decoder.number()?]))?;
+                        Some((key, spans))
+                    })?
+                    .into_iter()
+                    .collect(),
+                layout: decoder
+                    .list(|decoder| {
+                        let key = decoder.text()?;
+                        let layout = VerseLayout {
+                            paragraph: decoder.number()? != 0,
+                            stanza: decoder.number()? != 0,
+                            lines: decoder
+                                .list(|decoder| Some([decoder.number()?, decoder.number()?]))?,
+                        };
+                        Some((key, layout))
+                    })?
+                    .into_iter()
+                    .collect(),
+            })
+        })?;
+        // anything left over means it wasn't written by this version
+        self.0
+            .is_empty()
+            .then_some(JSONBible { translation, bible })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_are_only_used_while_the_file_is_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("tst.json");
+        let contents = br#"{"translation": {"name": "Test", "language": "English", "abbreviation": "TST"},
+            "bible": [{"id": 43, "book": "John", "abbreviations": ["jn"], "content": [["In the beginning", "was the Word"], []],
+            "footnotes": {"1:2": ["Or *the Message*"]}, "headings": {"1:1": "The Word"},
+            "wordsOfChrist": {"1:2": [[4, 8]]}, "layout": {"1:2": {"stanza": true, "lines": [[0, 0], [4, 1]]}}}]}"#;
+        fs::write(&source, contents).unwrap();
+        let bible = crate::bible_json::read(&source).unwrap();
+        let cache = BibleCache::in_dir(&dir.path().join("cache"), &source);
+        assert!(cache.load(contents).is_none());
+
+        cache.save(contents, &bible).unwrap();
+        let cached = cache.load(contents).unwrap();
+        assert_eq!(
+            serde_json::to_value(&cached).unwrap(),
+            serde_json::to_value(&bible).unwrap()
+        );
+        assert!(cache.load(b"{}").is_none());
+
+        let mut bytes = fs::read(&cache.path).unwrap();
+        fs::write(&cache.path, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(cache.load(contents).is_none());
+
+        // the version comes right after the magic number and the hash
+        bytes[MAGIC.len() + 8] += 1;
+        fs::write(&cache.path, &bytes).unwrap();
+        assert!(cache.load(contents).is_none());
+    }
+
+    #[test]
+    fn caches_go_next_to_the_translation_when_they_can() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("tst.json");
+        let contents = br#"{"translation": {"name": "Test", "language": "English", "abbreviation": "TST"},
+            "bible": [{"id": 43, "book": "John", "abbreviations": ["jn"], "content": [["In the beginning"]]}]}"#;
+        fs::write(&source, contents).unwrap();
+        let bible = crate::bible_json::read(&source).unwrap();
+        let fallback = dir.path().join("cache").join("tst.bin");
+
+        let cache = BibleCache {
+            fallback: Some(fallback.clone()),
+            ..BibleCache::of(&source)
+        };
+        cache.save(contents, &bible).unwrap();
+        assert!(dir.path().join(".tst.json.cache").is_file());
+        assert!(!fallback.exists());
+
+        // a file where the directory should be can't be written to, like a read-only one
+        let blocked = dir.path().join("blocked");
+        fs::write(&blocked, "").unwrap();
+        let cache = BibleCache {
+            path: blocked.join(".tst.json.cache"),
+            fallback: Some(fallback.clone()),
+        };
+        cache.save(contents, &bible).unwrap();
+        assert!(fallback.is_file());
+        assert!(cache.load(contents).is_some());
+    }
+}
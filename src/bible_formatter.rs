@@ -1,25 +1,125 @@
-use crate::book_reference_segment::BookReferenceSegment;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-struct PassageFormatter {
-    // can use book, chapter, verse, content
-    verse: String,
+use serde::{Deserialize, Serialize};
 
-    // the text that joins all verses together
-    join_verses: String,
+use crate::{
+    bible_api::BibleAPI, book_reference::BookReference,
+    book_reference_segment::BookReferenceSegment, footnotes::MarkdownFootnotes, poetry::Poetry,
+    red_letter::RedLetter, speech, versification,
+};
 
-    // can use verses
-    segment: String,
+/**
+Templates for turning a reference into text, from the innermost part out
+
+- Variables look like `{content}`, and `{{`/`}}` are literal braces
+- Sections only included when a flag is set look like `{?multi_verse}...{/}`, and `{!flag}...{/}`
+  is the opposite
+- Every template can use [`CONTEXT_VARIABLES`] and [`FLAGS`], on top of its own variables
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PassageFormatter {
+    /// can use chapter, verse, content
+    pub verse: String,
+
+    /// the text that joins all verses together
+    pub join_verses: String,
+
+    /// can use verses, segment, chapter
+    pub segment: String,
+
+    /// the text that joins all segments together
+    pub join_segment: String,
+
+    /// can use segments
+    pub text: String,
+
+    /// - Which code actions use this formatter, see [`CODE_ACTIONS`]
+    /// - Empty means just `insert`
+    pub code_actions: Vec<String>,
 
-    // the text that joins all segments together
-    join_segment: String,
+    /// how `{reference}`, `{segment}`, `{chapter}`, and `{verse}` are written
+    pub label: LabelStyle,
 
-    // can use book, label/reference, segments
-    text: String,
+    /// - Put between the verses wherever the chapter changes, like `— Chapter {chapter} —`, and
+    ///   joined to them with `join_verses`
+    /// - can use chapter
+    /// - Empty (the default for configured formatters) leaves the verses running together
+    #[serde(default)]
+    pub chapter_heading: String,
 
-    // insert, replace, all, ...
-    code_actions: Vec<String>,
+    /// - Puts each verse on exactly one line starting with this, like `> ` or two spaces, for
+    ///   editors that soft wrap long lines
+    /// - Line breaks inside a verse become spaces, `joinVerses` is ignored, and chapter headings
+    ///   get a line (and prefix) of their own
+    /// - Runs of lines starting with it can be folded, one per inserted passage
+    /// - `None` (the default) joins the verses with `joinVerses`
+    pub verse_per_line: Option<String>,
+
+    /// - The translation's footnotes as markdown footnotes: `[^John.3.16a]` at the end of
+    ///   `{content}`, and their text after the whole passage
+    /// - Off by default
+    #[serde(default)]
+    pub footnotes: bool,
+
+    /// - The words of Christ in `{content}` in bold, italics, or a color, see [`RedLetter`]
+    /// - `none` by default
+    #[serde(default)]
+    pub red_letter: RedLetter,
+
+    /// - Put between the verses wherever a section of the translation's headings starts, like
+    ///   `**{heading}**`, and joined to them with `joinVerses`
+    /// - can use heading, chapter, verse
+    /// - Never before the first verse, and empty (the default for configured formatters) leaves
+    ///   them out
+    #[serde(default)]
+    pub section_heading: String,
+
+    /// - Lines of poetry on lines of their own in `{content}`, and a blank line (joined with
+    ///   `joinVerses`) where a stanza or paragraph starts, see [`Poetry`]
+    /// - Ignored with `versePerLine`, and `null` (the default for configured formatters) leaves
+    ///   the verses as prose
+    #[serde(default)]
+    pub poetry: Option<Poetry>,
 }
 
+impl Default for PassageFormatter {
+    fn default() -> Self {
+        insert()
+    }
+}
+
+/// - `insert`: below the line with the reference
+/// - `replace`: in place of the line with the reference
+pub const CODE_ACTIONS: &[&str] = &["insert", "replace"];
+
+/// Variables every template can use
+pub const CONTEXT_VARIABLES: &[&str] = &[
+    "translation",
+    "translation_abbrev",
+    "date",
+    "file_name",
+    "book",
+    "reference",
+];
+const VERSE_VARIABLES: &[&str] = &["chapter", "verse", "content"];
+const SEGMENT_VARIABLES: &[&str] = &["verses", "segment", "chapter"];
+const TEXT_VARIABLES: &[&str] = &["segments"];
+const CHAPTER_HEADING_VARIABLES: &[&str] = &["chapter"];
+const SECTION_HEADING_VARIABLES: &[&str] = &["heading", "chapter", "verse"];
+
+/// - `multi_verse`, `multi_segment`, `multi_chapter` describe the whole reference
+/// - `first_verse` and `new_chapter` (a verse 1 that isn't the first verse) only make sense in
+///   the verse template
+pub const FLAGS: &[&str] = &[
+    "multi_verse",
+    "multi_segment",
+    "multi_chapter",
+    "first_verse",
+    "new_chapter",
+];
+
 fn literal_word() -> PassageFormatter {
     PassageFormatter {
         verse: "{content}".to_string(),
@@ -27,10 +127,627 @@ fn literal_word() -> PassageFormatter {
         segment: "{verses}".to_string(),
         join_segment: " ".to_string(),
         text: "> {segments}\n— {reference}".to_string(),
-        code_actions: vec![],
+        code_actions: vec![String::from("insert")],
+        label: LabelStyle::default(),
+        chapter_heading: String::new(),
+        verse_per_line: None,
+        footnotes: false,
+        red_letter: RedLetter::None,
+        section_heading: String::new(),
+        poetry: None,
+    }
+}
+
+/// `[1:1] Paul, an apostle...` with each verse on its own line, and a heading where the chapter
+/// or section changes
+fn insert() -> PassageFormatter {
+    PassageFormatter {
+        verse: "[{chapter}:{verse}] {content}".to_string(),
+        join_verses: "\n".to_string(),
+        segment: "{verses}".to_string(),
+        join_segment: "\n\n".to_string(),
+        text: "{segments}".to_string(),
+        code_actions: vec![String::from("insert")],
+        label: LabelStyle::default(),
+        chapter_heading: "— Chapter {chapter} —".to_string(),
+        verse_per_line: None,
+        footnotes: false,
+        red_letter: RedLetter::None,
+        section_heading: "**{heading}**".to_string(),
+        poetry: Some(Poetry::default()),
+    }
+}
+
+/// `> [1:1] Paul, an apostle... - Ephesians 1:1` on a single line
+fn replace() -> PassageFormatter {
+    PassageFormatter {
+        verse: "[{chapter}:{verse}] {content}".to_string(),
+        join_verses: " ".to_string(),
+        segment: "{verses}".to_string(),
+        join_segment: " ".to_string(),
+        text: "> {segments} - {reference}".to_string(),
+        code_actions: vec![String::from("replace")],
+        label: LabelStyle::default(),
+        chapter_heading: String::new(),
+        verse_per_line: None,
+        footnotes: false,
+        red_letter: RedLetter::None,
+        section_heading: String::new(),
+        poetry: None,
     }
 }
 
+/// An Obsidian callout with superscript verse numbers
+fn callout() -> PassageFormatter {
+    PassageFormatter {
+        verse: "<sup>{?new_chapter}{chapter}:{/}{verse}</sup>{content}".to_string(),
+        join_verses: "\n".to_string(),
+        segment: "{verses}".to_string(),
+        join_segment: "\n\n>".to_string(),
+        text: "> [!bible] {reference} {translation_abbrev}\n> {segments}".to_string(),
+        code_actions: vec![String::from("replace")],
+        label: LabelStyle::default(),
+        chapter_heading: String::new(),
+        verse_per_line: None,
+        footnotes: false,
+        red_letter: RedLetter::None,
+        section_heading: String::new(),
+        poetry: None,
+    }
+}
+
+/// `Ephesians chapter one, verses one through four.` and then the verses, for reading aloud
+fn speech() -> PassageFormatter {
+    PassageFormatter {
+        verse: "{content}".to_string(),
+        join_verses: " ".to_string(),
+        segment: "{verses}".to_string(),
+        join_segment: "\n\n".to_string(),
+        text: "{reference}.\n\n{segments}".to_string(),
+        code_actions: vec![String::from("insert")],
+        label: LabelStyle {
+            spoken: true,
+            ..Default::default()
+        },
+        chapter_heading: String::new(),
+        verse_per_line: None,
+        footnotes: false,
+        red_letter: RedLetter::None,
+        section_heading: String::new(),
+        poetry: None,
+    }
+}
+
+/// The formatters that exist without any configuration
+pub const BUILTIN_NAMES: &[&str] = &["callout", "insert", "replace", "blockquote", "speech"];
+
+pub fn builtin(name: &str) -> Option<PassageFormatter> {
+    match name {
+        "callout" => Some(callout()),
+        "insert" => Some(insert()),
+        "replace" => Some(replace()),
+        "blockquote" => Some(literal_word()),
+        "speech" => Some(speech()),
+        _ => None,
+    }
+}
+
+/// Digits other than `0-9` that chapter and verse numbers can be written with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Digits {
+    #[default]
+    Ascii,
+    /// `٠١٢٣٤٥٦٧٨٩`, used with Arabic
+    ArabicIndic,
+    /// `۰۱۲۳۴۵۶۷۸۹`, used with Persian and Urdu
+    Persian,
+    /// `०१२३४५६७८९`, used with Hindi
+    Devanagari,
+}
+
+impl Digits {
+    fn zero(&self) -> char {
+        match self {
+            Digits::Ascii => '0',
+            Digits::ArabicIndic => '\u{660}',
+            Digits::Persian => '\u{6f0}',
+            Digits::Devanagari => '\u{966}',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TextDirection {
+    /// right-to-left when the translation's language is, see [`is_rtl_language`]
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+/// Languages written right-to-left, by name or code (`Hebrew`, `he`, `ar`, ...)
+pub fn is_rtl_language(language: &str) -> bool {
+    const RTL: &[&str] = &[
+        "he", "hebrew", "ar", "arabic", "fa", "persian", "farsi", "ur", "urdu", "syc", "syriac",
+        "yi", "yiddish",
+    ];
+    let language = language.to_lowercase();
+    // `he-IL` and `ar_EG` are still Hebrew and Arabic
+    let code = language.split(['-', '_']).next().unwrap_or_default();
+    RTL.contains(&language.as_str()) || RTL.contains(&code)
+}
+
+/// Isolates text as left-to-right, so `3:16-18` isn't reordered inside right-to-left text
+const LRI: char = '\u{2066}';
+/// Isolates text as right-to-left, so a Hebrew label doesn't reorder the text around it
+const RLI: char = '\u{2067}';
+/// Ends an [`LRI`] or [`RLI`]
+const PDI: char = '\u{2069}';
+
+/**
+How reference labels and chapter/verse numbers are written
+
+- The default writes them as they are (`Psalm 23:1`)
+- Right-to-left labels are wrapped in bidi isolation marks: the whole label is isolated as
+  right-to-left and the numbers inside it as left-to-right, which is how Hebrew and Arabic texts
+  print them, and keeps the punctuation between numbers in order in mixed-direction documents
+*/
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LabelStyle {
+    /// - Pads chapter and verse numbers with zeros to this many digits (`Psalm 023:001`)
+    /// - `0` doesn't pad
+    pub pad: usize,
+    pub digits: Digits,
+    pub direction: TextDirection,
+    /// - Psalms with both numberings, like `Psalm 51(50):3`
+    /// - The configured numbering comes first, see [`crate::versification::PsalmNumbering`]
+    pub dual_psalms: bool,
+    /// - Labels and numbers in words for reading aloud, like `Ephesians chapter one, verses one
+    ///   through four`, see [`speech::citation`]
+    /// - English only, and the other options don't apply to them
+    pub spoken: bool,
+}
+
+impl LabelStyle {
+    pub fn is_rtl(&self, api: &BibleAPI) -> bool {
+        match self.direction {
+            TextDirection::Auto => is_rtl_language(&api.translation.language),
+            TextDirection::Ltr => false,
+            TextDirection::Rtl => true,
+        }
+    }
+
+    /// A chapter or verse number
+    pub fn number(&self, number: usize) -> String {
+        if self.spoken {
+            return speech::number(number);
+        }
+        self.localize(&format!("{number:0width$}", width = self.pad))
+    }
+
+    /// Every number in `text` padded and written with [`LabelStyle::digits`]
+    pub fn numbers(&self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            match rest[..end].parse::<usize>() {
+                Ok(number) => output.push_str(&self.number(number)),
+                Err(_) => output.push_str(&self.localize(&rest[..end])),
+            }
+            rest = &rest[end..];
+        }
+        output.push_str(rest);
+        output
+    }
+
+    fn localize(&self, digits: &str) -> String {
+        let zero = self.digits.zero();
+        digits
+            .chars()
+            .map(|c| match c.to_digit(10) {
+                Some(digit) => char::from_u32(zero as u32 + digit).unwrap_or(c),
+                None => c,
+            })
+            .collect()
+    }
+
+    /// A single segment of a reference written in this style, see [`LabelStyle::segment_label`]
+    pub fn segment(
+        &self,
+        api: &BibleAPI,
+        book_id: usize,
+        segment: &BookReferenceSegment,
+    ) -> String {
+        if self.spoken {
+            return speech::segments(api, book_id, std::slice::from_ref(segment));
+        }
+        let label =
+            crate::book_reference_segment::BookReferenceSegments(vec![segment.clone()]).label();
+        self.segment_label(api, &label)
+    }
+
+    /// A segment label like `1:1-4,6`, isolated as left-to-right for right-to-left translations
+    pub fn segment_label(&self, api: &BibleAPI, label: &str) -> String {
+        let numbers = self.numbers(label);
+        match self.is_rtl(api) {
+            true => format!("{LRI}{numbers}{PDI}"),
+            false => numbers,
+        }
+    }
+
+    /// [`BookReference::full_ref_label`] written in this style
+    pub fn label(&self, api: &BibleAPI, book_ref: &BookReference) -> String {
+        if self.spoken {
+            return speech::citation(api, book_ref.book_id, &book_ref.segments);
+        }
+        if *self == LabelStyle::default() && !self.is_rtl(api) {
+            return book_ref.full_ref_label(api);
+        }
+        let book = api.get_book_name(book_ref.book_id);
+        let book = book.as_deref().unwrap_or_default();
+        let mut segments = book_ref.segments.label();
+        if self.dual_psalms && book_ref.book_id == versification::PSALMS {
+            segments = versification::dual_label(&segments, api.psalm_numbering);
+        }
+        let segments = self.segment_label(api, &segments);
+        match self.is_rtl(api) {
+            true => format!("{RLI}{book} {segments}{PDI}"),
+            false => format!("{book} {segments}"),
+        }
+    }
+}
+
+/// Information about where the passage is going, rather than the passage itself
+#[derive(Clone, Debug, Default)]
+pub struct FormatContext {
+    /// ex: `English Standard Version`
+    pub translation: String,
+    /// ex: `ESV`
+    pub translation_abbrev: String,
+    /// `YYYY-MM-DD`
+    pub date: String,
+    /// ex: `romans-study.md`
+    pub file_name: String,
+}
+
+impl FormatContext {
+    pub fn new(api: &BibleAPI, file_name: impl Into<String>) -> Self {
+        Self {
+            translation: api.translation.name.clone(),
+            translation_abbrev: api.translation.abbreviation.clone(),
+            date: today(),
+            file_name: file_name.into(),
+        }
+    }
+}
+
+impl PassageFormatter {
+    pub fn format(
+        &self,
+        api: &BibleAPI,
+        book_ref: &BookReference,
+        context: &FormatContext,
+    ) -> String {
+        let book = api.get_book_name(book_ref.book_id);
+        let segments: Vec<(&BookReferenceSegment, Vec<(usize, usize)>)> = book_ref
+            .segments
+            .iter()
+            .map(|seg| (seg, seg.verses(api, book_ref.book_id)))
+            .collect();
+        let all_verses: Vec<&(usize, usize)> =
+            segments.iter().flat_map(|(_, verses)| verses).collect();
+
+        let mut variables: BTreeMap<&str, String> = BTreeMap::from([
+            ("translation", context.translation.clone()),
+            ("translation_abbrev", context.translation_abbrev.clone()),
+            ("date", context.date.clone()),
+            ("file_name", context.file_name.clone()),
+            ("book", book.as_deref().unwrap_or_default().to_owned()),
+            ("reference", self.label.label(api, book_ref)),
+        ]);
+        let mut flags: BTreeMap<&str, bool> = BTreeMap::from([
+            ("multi_verse", all_verses.len() > 1),
+            ("multi_segment", segments.len() > 1),
+            (
+                "multi_chapter",
+                all_verses
+                    .iter()
+                    .any(|(chapter, _)| Some(chapter) != all_verses.first().map(|(c, _)| c)),
+            ),
+            ("first_verse", false),
+            ("new_chapter", false),
+        ]);
+
+        let join_verses = match self.verse_per_line {
+            Some(_) => "\n",
+            None => self.join_verses.as_str(),
+        };
+        // across segments, so `1:9-10; 2:1` gets a heading before `2:1` too
+        let mut previous_chapter: Option<usize> = None;
+        let mut footnotes = MarkdownFootnotes::default();
+        let formatted_segments = segments
+            .iter()
+            .map(|(seg, verses)| {
+                let formatted_verses = verses
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, (chapter, verse))| {
+                        let content = api.get_bible_contents(book_ref.book_id, *chapter, *verse)?;
+                        let mark = |content: String, offset: usize| {
+                            self.red_letter.mark_from(
+                                api,
+                                book_ref.book_id,
+                                *chapter,
+                                *verse,
+                                content,
+                                offset,
+                            )
+                        };
+                        let poetry = self
+                            .poetry
+                            .as_ref()
+                            .filter(|_| self.verse_per_line.is_none());
+                        let mut content = match poetry {
+                            Some(poetry) => {
+                                poetry.lines(api, book_ref.book_id, *chapter, *verse, content, mark)
+                            }
+                            None => mark(content, 0),
+                        };
+                        if self.footnotes {
+                            content =
+                                footnotes.mark(api, book_ref.book_id, *chapter, *verse, content);
+                        }
+                        variables.insert("chapter", self.label.number(*chapter));
+                        variables.insert("verse", self.label.number(*verse));
+                        variables.insert("content", content);
+                        flags.insert("first_verse", idx == 0);
+                        flags.insert("new_chapter", idx > 0 && *verse == 1);
+                        let mut lines = vec![];
+                        if !self.chapter_heading.is_empty()
+                            && previous_chapter.is_some_and(|prev| prev != *chapter)
+                        {
+                            lines.push(self.line(render_template(
+                                &self.chapter_heading,
+                                &variables,
+                                &flags,
+                            )));
+                        }
+                        let section = api.get_section_heading(book_ref.book_id, *chapter, *verse);
+                        if let Some(section) = section.filter(|_| {
+                            !self.section_heading.is_empty() && previous_chapter.is_some()
+                        }) {
+                            variables.insert("heading", section.to_string());
+                            lines.push(self.line(render_template(
+                                &self.section_heading,
+                                &variables,
+                                &flags,
+                            )));
+                        }
+                        // a heading already sets the verse apart
+                        if idx > 0
+                            && lines.is_empty()
+                            && poetry.is_some_and(|poetry| {
+                                poetry.breaks_before(api, book_ref.book_id, *chapter, *verse)
+                            })
+                        {
+                            lines.push(String::new());
+                        }
+                        previous_chapter = Some(*chapter);
+                        lines.push(self.line(render_template(&self.verse, &variables, &flags)));
+                        Some(lines.join(join_verses))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(join_verses);
+                flags.insert("first_verse", false);
+                flags.insert("new_chapter", false);
+                variables.insert("chapter", self.label.number(seg.get_starting_chapter()));
+                variables.insert("segment", self.label.segment(api, book_ref.book_id, seg));
+                variables.insert("verses", formatted_verses);
+                render_template(&self.segment, &variables, &flags)
+            })
+            .collect::<Vec<_>>()
+            .join(&self.join_segment);
+
+        variables.insert("segments", formatted_segments);
+        let text = render_template(&self.text, &variables, &flags);
+        match footnotes.definitions() {
+            Some(definitions) => format!("{text}\n\n{definitions}"),
+            None => text,
+        }
+    }
+
+    /// A rendered verse or heading on one line with [`PassageFormatter::verse_per_line`]
+    fn line(&self, rendered: String) -> String {
+        match &self.verse_per_line {
+            Some(prefix) => {
+                let words: Vec<&str> = rendered.split_whitespace().collect();
+                format!("{prefix}{}", words.join(" "))
+            }
+            None => rendered,
+        }
+    }
+
+    pub fn code_actions(&self) -> Vec<&str> {
+        match self.code_actions.is_empty() {
+            true => vec!["insert"],
+            false => self.code_actions.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// - Every unknown variable or flag, and every unbalanced section
+    /// - Ex: `verse: unknown variable {contnet}`
+    pub fn lint(&self) -> Vec<String> {
+        [
+            ("verse", &self.verse, VERSE_VARIABLES),
+            ("segment", &self.segment, SEGMENT_VARIABLES),
+            ("text", &self.text, TEXT_VARIABLES),
+            (
+                "chapterHeading",
+                &self.chapter_heading,
+                CHAPTER_HEADING_VARIABLES,
+            ),
+            (
+                "sectionHeading",
+                &self.section_heading,
+                SECTION_HEADING_VARIABLES,
+            ),
+        ]
+        .into_iter()
+        .flat_map(|(field, template, own)| {
+            lint_template(template, own)
+                .into_iter()
+                .map(move |problem| format!("{field}: {problem}"))
+        })
+        .chain(
+            self.code_actions
+                .iter()
+                .filter(|action| !CODE_ACTIONS.contains(&action.as_str()))
+                .map(|action| format!("codeActions: unknown code action {action}")),
+        )
+        .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token<'a> {
+    Text(&'a str),
+    /// `{{` or `}}`
+    Brace(char),
+    Variable(&'a str),
+    /// `{?flag}` is `(flag, false)` and `{!flag}` is `(flag, true)`
+    Open(&'a str, bool),
+    Close,
+}
+
+fn tokenize(template: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut rest = template;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("{{") {
+            tokens.push(Token::Brace('{'));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("}}") {
+            tokens.push(Token::Brace('}'));
+            rest = after;
+        } else if let (true, Some(end)) = (rest.starts_with('{'), rest.find('}')) {
+            let inner = &rest[1..end];
+            tokens.push(if inner == "/" {
+                Token::Close
+            } else if let Some(flag) = inner.strip_prefix('?') {
+                Token::Open(flag, false)
+            } else if let Some(flag) = inner.strip_prefix('!') {
+                Token::Open(flag, true)
+            } else {
+                Token::Variable(inner)
+            });
+            rest = &rest[end + 1..];
+        } else {
+            // the first character can be more than a byte, like the `—` in `— {reference}`
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[first..]
+                .find(['{', '}'])
+                .map(|idx| idx + first)
+                .unwrap_or(rest.len());
+            tokens.push(Token::Text(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    tokens
+}
+
+/// - Unknown variables are left as they are, so mistakes are visible in the output
+/// - Unknown flags count as not set
+pub fn render_template(
+    template: &str,
+    variables: &BTreeMap<&str, String>,
+    flags: &BTreeMap<&str, bool>,
+) -> String {
+    let mut output = String::new();
+    // whether each open section is being included
+    let mut included: Vec<bool> = vec![];
+    for token in tokenize(template) {
+        let including = included.iter().all(|include| *include);
+        match token {
+            Token::Open(flag, negated) => {
+                included.push(flags.get(flag).copied().unwrap_or(false) != negated)
+            }
+            Token::Close => {
+                included.pop();
+            }
+            _ if !including => {}
+            Token::Text(text) => output.push_str(text),
+            Token::Brace(brace) => output.push(brace),
+            Token::Variable(name) => match variables.get(name) {
+                Some(value) => output.push_str(value),
+                None => output.push_str(&format!("{{{name}}}")),
+            },
+        }
+    }
+    output
+}
+
+fn lint_template(template: &str, own_variables: &[&str]) -> Vec<String> {
+    let mut problems = vec![];
+    let mut depth = 0;
+    for token in tokenize(template) {
+        match token {
+            Token::Variable(name)
+                if !own_variables.contains(&name) && !CONTEXT_VARIABLES.contains(&name) =>
+            {
+                problems.push(format!("unknown variable {{{name}}}"))
+            }
+            Token::Open(flag, _) => {
+                if !FLAGS.contains(&flag) {
+                    problems.push(format!("unknown flag {flag}"));
+                }
+                depth += 1;
+            }
+            Token::Close if depth == 0 => {
+                problems.push(String::from("{/} without a section to close"))
+            }
+            Token::Close => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        problems.push(format!("{depth} section(s) never closed with {{/}}"));
+    }
+    problems
+}
+
+/// - Today's date (UTC) as `YYYY-MM-DD`
+/// - Converted by hand since this is the only place a date is needed
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+    let (year, month, day) = civil_from_days(days);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Days since 1970-01-01 to a (year, month, day), see <https://howardhinnant.github.io/date_algorithms.html>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
 struct BibleFormatter {
     book_format: String,
     chapter_format: String,
@@ -118,3 +835,125 @@ impl BibleFormatter {
         String::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_numbers_are_padded_and_localized() {
+        let style = LabelStyle {
+            pad: 3,
+            ..Default::default()
+        };
+        assert_eq!(style.numbers("23:1-6"), "023:001-006");
+        let style = LabelStyle {
+            digits: Digits::ArabicIndic,
+            ..Default::default()
+        };
+        assert_eq!(style.numbers("3:16"), "\u{663}:\u{661}\u{666}");
+        assert!(is_rtl_language("he-IL"));
+        assert!(is_rtl_language("Arabic"));
+        assert!(!is_rtl_language("English"));
+    }
+
+    #[test]
+    fn templates_render_variables_and_sections() {
+        let variables = BTreeMap::from([("reference", String::from("John 3:16"))]);
+        let flags = BTreeMap::from([("multi_verse", false)]);
+        let template = "{reference}{?multi_verse} (passage){/}{!multi_verse} (verse){/} {{x}}";
+        assert_eq!(
+            render_template(template, &variables, &flags),
+            "John 3:16 (verse) {x}"
+        );
+        assert_eq!(
+            render_template("{missing}", &variables, &flags),
+            "{missing}"
+        );
+    }
+
+    #[test]
+    fn templates_can_start_with_any_character() {
+        let variables = BTreeMap::from([
+            ("content", String::from("For God so loved the world")),
+            ("reference", String::from("John 3:16")),
+        ]);
+        let flags = BTreeMap::new();
+        assert_eq!(
+            render_template("“{content}” — {reference}", &variables, &flags),
+            "“For God so loved the world” — John 3:16"
+        );
+        assert_eq!(
+            render_template("— {reference}", &variables, &flags),
+            "— John 3:16"
+        );
+        assert_eq!(render_template("«{", &variables, &flags), "«{");
+    }
+
+    #[test]
+    fn lint_reports_unknown_variables() {
+        let formatter = PassageFormatter {
+            verse: "{contnet}".to_string(),
+            text: "{?multi_verse}{segments}".to_string(),
+            ..insert()
+        };
+        assert_eq!(
+            formatter.lint(),
+            vec![
+                "verse: unknown variable {contnet}",
+                "text: 1 section(s) never closed with {/}"
+            ]
+        );
+        for name in BUILTIN_NAMES {
+            assert!(builtin(name).unwrap().lint().is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn chapter_headings_separate_chapters() {
+        let lsp = crate::bible_lsp::BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let book_ref = lsp.find_book_references("Gen 1:4-2:1").unwrap().remove(0);
+        let context = FormatContext::default();
+        assert_eq!(
+            insert().format(&lsp.api, &book_ref, &context),
+            "[1:4] Text of Genesis 1:4.\n[1:5] Text of Genesis 1:5.\n— Chapter 2 —\n[2:1] Text of Genesis 2:1."
+        );
+        assert_eq!(
+            replace().format(&lsp.api, &book_ref, &context),
+            "> [1:4] Text of Genesis 1:4. [1:5] Text of Genesis 1:5. [2:1] Text of Genesis 2:1. - Genesis 1:4-2:1"
+        );
+        assert_eq!(
+            book_ref.format_content(&lsp.api),
+            "[1:4] Text of Genesis 1:4.\n[1:5] Text of Genesis 1:5.\n[2:1] Text of Genesis 2:1."
+        );
+    }
+
+    #[test]
+    fn verse_per_line_puts_each_verse_on_one_line() {
+        let lsp = crate::bible_lsp::BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let book_ref = lsp.find_book_references("Gen 1:5-2:1").unwrap().remove(0);
+        let formatter = PassageFormatter {
+            verse: "[{chapter}:{verse}]\n  {content}".to_string(),
+            join_verses: " ".to_string(),
+            verse_per_line: Some(String::from("> ")),
+            ..insert()
+        };
+        assert_eq!(
+            formatter.format(&lsp.api, &book_ref, &FormatContext::default()),
+            "> [1:5] Text of Genesis 1:5.\n> — Chapter 2 —\n> [2:1] Text of Genesis 2:1."
+        );
+    }
+
+    #[test]
+    fn dates_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+}
@@ -1,11 +1,20 @@
 /// This is meant to be used only to create the initial data structure for reading in the JSON file
+use std::collections::BTreeMap;
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 
+use crate::error::{self, Error};
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct JSONTranslation {
     pub name: String,
     pub language: String,
     pub abbreviation: String,
+    /// - The copyright notice publishers ask for when quoting this translation
+    /// - `{name}` and `{abbreviation}` are replaced with the translation's own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -16,7 +25,60 @@ pub struct JSONBook {
     pub book: String,
     /// all abbreviations (any case), not necessarily including the book name
     pub abbreviations: Vec<String>,
+    /// - Where the book is in this translation's canon (Tanakh order puts Chronicles last)
+    /// - Defaults to where the book is in the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<usize>,
     pub content: Vec<Vec<String>>,
+    /// - Translator footnotes by the verse they are on, like `"3:16": ["Or *his only Son*"]`
+    /// - Markdown, and shown with the text when a formatter asks for them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub footnotes: BTreeMap<String, Vec<String>>,
+    /// - Section headings (pericope titles) by the verse each section starts at, like
+    ///   `"5:1": "The Sermon on the Mount"`
+    /// - Shown where a passage crosses into another section
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headings: BTreeMap<String, String>,
+    /// - The words of Christ by the verse they are in, as `[start, end)` character offsets into
+    ///   the verse, like `"3:16": [[0, 120]]`
+    /// - Shown in red letter (see [`crate::red_letter::RedLetter`]) when a formatter asks for it
+    #[serde(
+        default,
+        rename = "wordsOfChrist",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub words_of_christ: BTreeMap<String, Vec<[usize; 2]>>,
+    /// - Where the text isn't run-on prose, by verse, like
+    ///   `"23:1": {"stanza": true, "lines": [[0, 0], [25, 1]]}`
+    /// - Kept when a formatter asks for it (see [`crate::poetry::Poetry`]), so Psalms read as
+    ///   poems
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub layout: BTreeMap<String, VerseLayout>,
+}
+
+/// How a verse is laid out, see [`JSONBook::layout`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VerseLayout {
+    /// a paragraph starts at the verse
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub paragraph: bool,
+    /// a stanza of poetry starts at the verse
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stanza: bool,
+    /// - Where each line of poetry starts, as `[character offset, indent level]`
+    /// - Text before the first one is a line of its own, not indented
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<[usize; 2]>,
+}
+
+impl JSONBook {
+    /// `(chapter, verse)` of a [`JSONBook::footnotes`] (or any other verse keyed field) key like
+    /// `3:16`
+    pub fn verse_key(key: &str) -> Option<(usize, usize)> {
+        let (chapter, verse) = key.trim().split_once(':')?;
+        Some((chapter.parse().ok()?, verse.parse().ok()?))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -24,3 +86,277 @@ pub struct JSONBible {
     pub translation: JSONTranslation,
     pub bible: Vec<JSONBook>,
 }
+
+/// Something wrong with a Bible JSON file that its types can't catch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// where in the file, like `bible[39].content[2]`
+    pub at: String,
+    pub message: String,
+    /// the file can't be loaded at all, rather than loading with something missing
+    pub fatal: bool,
+}
+
+impl Problem {
+    fn new(at: String, message: impl Into<String>, fatal: bool) -> Self {
+        Self {
+            at,
+            message: message.into(),
+            fatal,
+        }
+    }
+}
+
+/**
+Reads a Bible JSON file, saying where in it any value has the wrong type
+
+- Ex: `bible[39].content[2]: invalid type: string "...", expected a sequence`
+- See [`problems`] for what is checked after it is read
+*/
+pub fn read(path: &Path) -> error::Result<JSONBible> {
+    let text = std::fs::read_to_string(path).map_err(|err| Error::read(path, err))?;
+    let deserializer = &mut serde_json::Deserializer::from_str(&text);
+    match serde_path_to_error::deserialize(deserializer) {
+        Ok(bible) => Ok(bible),
+        Err(err) => Err(Error::parse_at(path, text, err)),
+    }
+}
+
+/**
+Everything wrong with a file that parsed, for `bible_lsp validate`
+
+- Missing and duplicate book ids, and empty names, keep it from loading, since the books are
+  stored by id and every name is part of the detection regex
+- Books and chapters without any text still load, but nothing in them can be shown
+*/
+pub fn problems(bible: &JSONBible) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut seen: BTreeMap<usize, usize> = BTreeMap::new();
+    for (idx, book) in bible.bible.iter().enumerate() {
+        let at = format!("bible[{idx}]");
+        if book.id == 0 {
+            problems.push(Problem::new(
+                format!("{at}.id"),
+                "book ids start at 1 for Genesis",
+                true,
+            ));
+        } else if let Some(first) = seen.insert(book.id, idx) {
+            problems.push(Problem::new(
+                format!("{at}.id"),
+                format!("id {} is already used by bible[{first}]", book.id),
+                true,
+            ));
+        }
+        if book.book.trim().is_empty() {
+            problems.push(Problem::new(format!("{at}.book"), "empty book name", true));
+        }
+        for (abbreviation_idx, abbreviation) in book.abbreviations.iter().enumerate() {
+            if abbreviation.trim().is_empty() {
+                problems.push(Problem::new(
+                    format!("{at}.abbreviations[{abbreviation_idx}]"),
+                    "empty abbreviation",
+                    true,
+                ));
+            }
+        }
+        if book.content.is_empty() {
+            problems.push(Problem::new(
+                format!("{at}.content"),
+                format!("{} has no chapters", book.book),
+                false,
+            ));
+        }
+        for (chapter_idx, verses) in book.content.iter().enumerate() {
+            if verses.is_empty() {
+                problems.push(Problem::new(
+                    format!("{at}.content[{chapter_idx}]"),
+                    format!("{} {} has no verses", book.book, chapter_idx + 1),
+                    false,
+                ));
+            }
+        }
+        // footnotes, headings, words of Christ, and layouts nothing can show are left out
+        let keys = (book.footnotes.keys().map(|key| ("footnotes", key)))
+            .chain(book.headings.keys().map(|key| ("headings", key)))
+            .chain(
+                book.words_of_christ
+                    .keys()
+                    .map(|key| ("wordsOfChrist", key)),
+            )
+            .chain(book.layout.keys().map(|key| ("layout", key)));
+        for (field, key) in keys {
+            let message = match JSONBook::verse_key(key) {
+                None => format!("`{key}` isn't a chapter and verse like `3:16`"),
+                Some((chapter, verse))
+                    if chapter == 0
+                        || verse == 0
+                        || book
+                            .content
+                            .get(chapter - 1)
+                            .is_none_or(|verses| verses.len() < verse) =>
+                {
+                    format!("{} {key} isn't a verse", book.book)
+                }
+                Some(_) => continue,
+            };
+            problems.push(Problem::new(
+                format!("{at}.{field}[\"{key}\"]"),
+                message,
+                false,
+            ));
+        }
+        for (key, spans) in book.words_of_christ.iter() {
+            let Some(text) = verse_text(book, key) else {
+                continue;
+            };
+            let length = text.chars().count();
+            for (idx, [start, end]) in spans.iter().enumerate() {
+                if start < end && *end <= length {
+                    continue;
+                }
+                problems.push(Problem::new(
+                    format!("{at}.wordsOfChrist[\"{key}\"][{idx}]"),
+                    format!(
+                        "[{start}, {end}] isn't in {} {key}, which is {length} characters",
+                        book.book
+                    ),
+                    false,
+                ));
+            }
+        }
+        for (key, layout) in book.layout.iter() {
+            let Some(text) = verse_text(book, key) else {
+                continue;
+            };
+            let length = text.chars().count();
+            for (idx, [offset, _]) in layout.lines.iter().enumerate() {
+                if *offset < length {
+                    continue;
+                }
+                problems.push(Problem::new(
+                    format!("{at}.layout[\"{key}\"].lines[{idx}]"),
+                    format!(
+                        "{offset} isn't in {} {key}, which is {length} characters",
+                        book.book
+                    ),
+                    false,
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// The verse of a verse keyed field's key, when the book has it
+fn verse_text<'a>(book: &'a JSONBook, key: &str) -> Option<&'a String> {
+    let (chapter, verse) = JSONBook::verse_key(key)?;
+    book.content
+        .get(chapter.checked_sub(1)?)?
+        .get(verse.checked_sub(1)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_types_say_where_they_are() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tst.json");
+        std::fs::write(
+            &path,
+            r#"{"translation": {"name": "Test", "language": "English", "abbreviation": "TST"},
+                "bible": [{"id": 1, "book": "Genesis", "abbreviations": [], "content": [["1:1"], "1:2"]}]}"#,
+        )
+        .unwrap();
+        let err = read(&path).unwrap_err();
+        let summary = error::summary(&err);
+        assert!(
+            summary.contains(
+                ": bible[0].content[1]: invalid type: string \"1:2\", expected a sequence"
+            ),
+            "{summary}"
+        );
+    }
+
+    #[test]
+    fn duplicate_ids_and_empty_chapters_are_problems() {
+        let book = |id: usize, content: Vec<Vec<String>>| JSONBook {
+            id,
+            book: String::from("Genesis"),
+            abbreviations: vec![],
+            order: None,
+            content,
+            footnotes: BTreeMap::new(),
+            headings: BTreeMap::new(),
+            words_of_christ: BTreeMap::new(),
+            layout: BTreeMap::new(),
+        };
+        let mut bible = JSONBible {
+            translation: JSONTranslation {
+                name: String::from("Test"),
+                language: String::from("English"),
+                abbreviation: String::from("TST"),
+                attribution: None,
+            },
+            bible: vec![
+                book(1, vec![vec![String::from("1:1")], vec![]]),
+                book(1, vec![vec![String::from("1:1")]]),
+            ],
+        };
+        bible.bible[1].footnotes = BTreeMap::from([
+            (String::from("1:1"), vec![String::from("Or *heavens*")]),
+            (String::from("1:2"), vec![]),
+            (String::from("one"), vec![]),
+        ]);
+        bible.bible[1].headings = BTreeMap::from([(String::from("2:1"), String::from("Rest"))]);
+        bible.bible[1].words_of_christ = BTreeMap::from([(String::from("1:1"), vec![[0, 4]])]);
+        bible.bible[1].layout = BTreeMap::from([(
+            String::from("1:1"),
+            VerseLayout {
+                lines: vec![[0, 0], [3, 1]],
+                ..Default::default()
+            },
+        )]);
+        assert_eq!(
+            problems(&bible),
+            [
+                Problem::new(
+                    "bible[0].content[1]".into(),
+                    "Genesis 2 has no verses",
+                    false
+                ),
+                Problem::new(
+                    "bible[1].id".into(),
+                    "id 1 is already used by bible[0]",
+                    true
+                ),
+                Problem::new(
+                    "bible[1].footnotes[\"1:2\"]".into(),
+                    "Genesis 1:2 isn't a verse",
+                    false
+                ),
+                Problem::new(
+                    "bible[1].footnotes[\"one\"]".into(),
+                    "`one` isn't a chapter and verse like `3:16`",
+                    false
+                ),
+                Problem::new(
+                    "bible[1].headings[\"2:1\"]".into(),
+                    "Genesis 2:1 isn't a verse",
+                    false
+                ),
+                Problem::new(
+                    "bible[1].wordsOfChrist[\"1:1\"][0]".into(),
+                    "[0, 4] isn't in Genesis 1:1, which is 3 characters",
+                    false
+                ),
+                Problem::new(
+                    "bible[1].layout[\"1:1\"].lines[1]".into(),
+                    "3 isn't in Genesis 1:1, which is 3 characters",
+                    false
+                ),
+            ]
+        );
+    }
+}
@@ -1,12 +1,16 @@
+use std::collections::BTreeMap;
 use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
 use std::{
     fs::{self, OpenOptions},
     io,
 };
 
-use tower_lsp::lsp_types::{Position, Range};
+use tower_lsp::lsp_types::Range;
 
 use crate::{
+    additions,
     autocompletion::{
         suggest_all_books, AutocompleteState, AutocompletionEndingOperator, BibleCompletion,
         BookNameCompletion,
@@ -14,101 +18,26 @@ use crate::{
     bible_api::BibleAPI,
     book_reference::BookReference,
     book_reference_segment::{self, BookReferenceSegments},
-    re,
+    detection_context::{self, DetectionContext},
+    document::LineIndex,
+    paths, re, trace,
+    versification::{self, PsalmNumbering},
 };
 
 #[derive(Clone, Debug)]
 pub struct BibleLSP {
+    /// The default translation, whose book names references are found with
     pub api: BibleAPI,
-}
-
-fn calculate_position(newline_indexes: &Vec<usize>, start_index: usize, end_index: usize) -> Range {
-    // If there is one line or match is on the first line
-    if newline_indexes.len() == 0 || start_index < newline_indexes[0] {
-        return Range {
-            start: Position {
-                line: 0,
-                character: start_index as u32,
-            },
-            end: Position {
-                line: 0,
-                character: end_index as u32,
-            },
-        };
-    }
-
-    // If the match is on the last line
-    if *newline_indexes
-        .last()
-        .expect("Previous if statement guarantees len > 0")
-        < start_index
-    {
-        let line = newline_indexes.len() as u32;
-        let line_start_index = *newline_indexes
-            .last()
-            .expect("Previous if statement guarantees len > 0");
-        // im sure the off-by-one error is from cr-lf \r\n
-        let start_character = (start_index - 1 - line_start_index) as u32;
-        let end_character = (end_index - 1 - line_start_index) as u32;
-        return Range {
-            start: Position {
-                line,
-                character: start_character,
-            },
-            end: Position {
-                line,
-                character: end_character,
-            },
-        };
-    }
-
-    // With the above cases out of the way, at any given index (1..len()-1) I can just the
-    // adjacent one and it is guaranteed to be in bounds
-    let mut bottom = 1;
-    let mut top = newline_indexes.len() - 1;
-    let mut mid = top / bottom;
-
-    while top != bottom {
-        // dbg!(
-        //     top,
-        //     mid,
-        //     bottom,
-        //     newline_indexes.get(mid - 1),
-        //     newline_indexes.get(mid),
-        //     newline_indexes.get(mid + 1)
-        // );
-        // okay, maybe i want to just remove if the first one is it and then just always
-        // check left
-        // the below case may handle the end one, but i dont want to think about it right now so i
-        // will be content to let it handle it as its own case if it wants to
-        if newline_indexes[mid - 1] < start_index && start_index < newline_indexes[mid] {
-            break;
-        } else if start_index < newline_indexes[mid] {
-            top = mid;
-        } else {
-            if bottom == mid {
-                mid += 1;
-                break;
-            }
-            bottom = mid;
-        }
-        mid = bottom + ((top - bottom) / 2);
-    }
-
-    let line = mid as u32;
-    let line_start_index = newline_indexes[mid - 1];
-    let start_character = (start_index - 1 - line_start_index) as u32;
-    let end_character = (end_index - 1 - line_start_index) as u32;
-    return Range {
-        start: Position {
-            line,
-            character: start_character,
-        },
-        end: Position {
-            line,
-            character: end_character,
-        },
-    };
+    /// - Translations loaded alongside [`BibleLSP::api`], keyed by upper case abbreviation
+    /// - Only for their text, see [`BibleLSP::api_for`]
+    pub translations: BTreeMap<String, Arc<BibleAPI>>,
+    /// - Where references are recognized, see [`DetectionContext`]
+    /// - Empty (the default) for everywhere
+    pub contexts: Vec<DetectionContext>,
+    /// - Text that looks like a reference but isn't, like `Job 3:30` in a schedule, which
+    ///   [`BibleLSP::find_book_references`] skips wherever it is written exactly so
+    /// - See [`crate::ignored_phrases::IgnoredPhrases`]
+    pub ignored_phrases: Vec<String>,
 }
 
 const NOTHING: (Option<usize>, Option<usize>, Option<usize>) = (None, None, None);
@@ -138,18 +67,17 @@ fn parse_current_state(api: &BibleAPI, text_before_cursor: &str) -> Autocomplete
         return progress;
     }
 
-    // match segment characters
-    let Some(segment_match) = re::segment_characters().find(everything_after_book_name) else {
-        return progress;
+    // the segment characters have to run right up to the cursor, otherwise the user started
+    // typing something else, like `Ephesians 1:1 and jo`
+    let Some(segment_match) = re::segment_characters()
+        .find(everything_after_book_name)
+        .filter(|segment| {
+            segment.start() == 0 && segment.end() == everything_after_book_name.len()
+        })
+    else {
+        return AutocompleteState::BooksOnly;
     };
 
-    // if they segment characters ends before the end of the input, it means the user started
-    // typing something else
-    // maybe i need a -1
-    // if segment_match.end() < text_before_cursor.len() {
-    //     return progress;
-    // }
-
     // before parsing segments, must make sure they have at least 1 valid reference
     // segment parsing function assumes there is at least 1 valid segment, so a partial segment
     // like `1` or `1:` will return incorrect results
@@ -258,28 +186,91 @@ impl BibleLSP {
     pub fn new(json_path: &str) -> Self {
         BibleLSP {
             api: BibleAPI::new(json_path),
+            translations: BTreeMap::new(),
+            contexts: vec![],
+            ignored_phrases: vec![],
+        }
+    }
+
+    /// See [`BibleAPI::empty`]
+    pub fn empty() -> Self {
+        BibleLSP {
+            api: BibleAPI::empty(),
+            translations: BTreeMap::new(),
+            contexts: vec![],
+            ignored_phrases: vec![],
+        }
+    }
+
+    /// [`BibleLSP::new`], with what went wrong instead of a panic
+    pub fn load(json_path: impl AsRef<Path>) -> crate::error::Result<Self> {
+        Ok(BibleLSP {
+            api: BibleAPI::load(json_path)?,
+            translations: BTreeMap::new(),
+            contexts: vec![],
+            ignored_phrases: vec![],
+        })
+    }
+
+    /// - Loads `api` next to the default translation, replacing one with the same abbreviation
+    /// - The default one can't be replaced this way
+    pub fn add_translation(&mut self, api: BibleAPI) {
+        let abbreviation = api.translation.abbreviation.to_uppercase();
+        self.translations.insert(abbreviation, Arc::new(api));
+    }
+
+    /// A loaded translation by abbreviation, in any case, including the default one
+    pub fn translation(&self, abbreviation: &str) -> Option<&BibleAPI> {
+        if abbreviation.eq_ignore_ascii_case(&self.api.translation.abbreviation) {
+            return Some(&self.api);
         }
+        self.translations
+            .get(&abbreviation.to_uppercase())
+            .map(|api| api.as_ref())
+    }
+
+    /// - The translation to render text with, when a request asks for one
+    /// - The default one for `None`, or one that isn't loaded
+    pub fn api_for(&self, translation: Option<&str>) -> &BibleAPI {
+        translation
+            .and_then(|abbreviation| self.translation(abbreviation))
+            .unwrap_or(&self.api)
     }
 
+    /// The abbreviations of every loaded translation, the default one first
+    pub fn translation_names(&self) -> Vec<String> {
+        std::iter::once(&self.api)
+            .chain(self.translations.values().map(|api| api.as_ref()))
+            .map(|api| api.translation.abbreviation.clone())
+            .collect()
+    }
+
+    /// Every reference in `input`, in the [`BibleLSP::contexts`] and not one of the
+    /// [`BibleLSP::ignored_phrases`]
     pub fn find_book_references(&self, input: &str) -> Option<Vec<BookReference>> {
-        /*
-        Calculate the newline indexes so that I can convert the string index into line and column number for LSP (tower_lsp::Range)
-        */
-        let newline_indexes = input
-            // .char_indices()
-            .chars()
-            .filter(|ch| *ch != '\r')
-            .enumerate()
-            .filter(|(_, ch)| *ch == '\n')
-            .map(|(idx, _)| idx)
-            .collect::<Vec<usize>>();
-        // let char_offset: usize = input.chars().filter(|ch| !ch.is_ascii()).count();
-        // let char_offset = char_offset * 2;
-        let char_offsets: Vec<_> = input
-            .char_indices()
-            .filter(|(idx, ch)| !ch.is_ascii())
-            .map(|(idx, ch)| idx)
-            .collect();
+        self.find_references(input, &self.contexts, &self.ignored_phrases)
+    }
+
+    /// - [`BibleLSP::find_book_references`] in other contexts, like `&[]` for text that is known
+    ///   to be a passage
+    /// - Nothing is ignored, since the text was asked about
+    pub fn find_book_references_in(
+        &self,
+        input: &str,
+        contexts: &[DetectionContext],
+    ) -> Option<Vec<BookReference>> {
+        self.find_references(input, contexts, &[])
+    }
+
+    fn find_references(
+        &self,
+        input: &str,
+        contexts: &[DetectionContext],
+        ignored_phrases: &[String],
+    ) -> Option<Vec<BookReference>> {
+        // ranges are converted from byte offsets, so they are in UTF-16 like LSP expects no matter
+        // what characters come before them on the line
+        let line_index = LineIndex::new(input);
 
         /*
         Break the input into segments where each segment starts with a book of the Bible
@@ -287,31 +278,31 @@ impl BibleLSP {
         (which includes both the reference segments, such as `1:1-2:2` and everything after that up until the next book name)
         */
         let pat = self.api.book_abbreviation_regex();
-        let mut iter = pat.find_iter(input).peekable();
-        let mut prev: Option<usize> = None;
+        // with contexts, only books inside one count, and their segment stops where it ends
+        let spans = detection_context::allowed_spans(input, contexts);
+        let books: Vec<(regex::Match, usize)> = pat
+            .find_iter(input)
+            .filter_map(|cap| match &spans {
+                None => Some((cap, input.len())),
+                Some(spans) => spans
+                    .iter()
+                    .find(|span| span.start <= cap.start() && cap.end() <= span.end)
+                    .map(|span| (cap, span.end)),
+            })
+            .collect();
         let mut book_lens = vec![];
-        // saving the start index of the capture so I can get a slice of the input later and do
-        // only 1 .clone() at the end
+        // byte offsets of each book, for the ranges
         let mut start_indexes = vec![];
         // this is a vec of slices that correspond to the entire segment (start of one book or
         // abbreviation to right before the start of the next)
         let mut segment_matches = vec![];
-        while let Some(cap) = iter.next() {
-            let start = cap.start();
-            let char_offset = 2 * char_offsets.iter().filter(|o| o < &&start).count();
-            // let char_offset = char_offset + 2 - (cap.end() - cap.start());
-            start_indexes.push(cap.start() - char_offset);
+        for (idx, (cap, limit)) in books.iter().enumerate() {
+            start_indexes.push(cap.start());
             book_lens.push(cap.end() - cap.start());
-            // store the previous start up until the start of this book
-            // wait until the next iteration to store the segment of the current iteration
-            if let Some(prev_start) = prev {
-                segment_matches.push(&input[prev_start..cap.start()]);
-            }
-            prev = Some(cap.start());
-            // if at the last element, segment goes to the end
-            if iter.peek().is_none() {
-                segment_matches.push(&input[cap.start()..]);
-            }
+            let next_start = books
+                .get(idx + 1)
+                .map_or(input.len(), |(next, _)| next.start());
+            segment_matches.push(&input[cap.start()..next_start.min(*limit)]);
         }
         /*
         - Iterate together over the previous recorded data
@@ -327,18 +318,69 @@ impl BibleLSP {
             // dbg!(start_index, book_len, seg);
             // find the reference segments (`1:1-2:2,3:4`) in the text segment if it is right after
             // the book name/abbreviation
+            let book_name = &seg[0..book_len];
+            let book_id = self
+                .api
+                .get_book_id(&book_name)
+                .expect("The book_name slice already passed the RegEx of valid books.");
+            // `Psalm 51(50):3` is parsed as `Psalm 51:3`, and the range still covers the `(50)`
+            let dual = (book_id == versification::PSALMS)
+                .then(|| {
+                    versification::strip_dual_chapter(&seg[book_len..], self.api.psalm_numbering)
+                })
+                .flatten();
+            let (after_book, removed) = match &dual {
+                Some((stripped, removed)) => (stripped.as_str(), *removed),
+                None => (&seg[book_len..], 0),
+            };
             if let Some(segment_match) =
-                re::post_book_valid_reference_segment_characters().find(&seg[book_len..])
+                re::post_book_valid_reference_segment_characters().find(after_book)
             {
-                let book_name = &seg[0..book_len];
-                let book_id = self
-                    .api
-                    .get_book_id(&book_name)
-                    .expect("The book_name slice already passed the RegEx of valid books.");
                 let segment_chars = segment_match.as_str();
-                let end_index = start_index + book_name.len() + segment_chars.len();
-                let range = calculate_position(&newline_indexes, start_index, end_index);
-                let book_reference = BookReference::new(book_id, range, segment_chars);
+                let mut end_index = start_index + book_name.len() + segment_chars.len() + removed;
+                // `John 3:16 KJV`, when that translation is loaded
+                let translation = re::translation_suffix()
+                    .captures(&seg[end_index - start_index..])
+                    .and_then(|cap| {
+                        let api = self.translation(&cap[1])?;
+                        end_index += cap[0].len();
+                        Some(api.translation.abbreviation.clone())
+                    });
+                if ignored_phrases
+                    .iter()
+                    .any(|phrase| phrase == &input[start_index..end_index])
+                {
+                    continue;
+                }
+                let range = Range {
+                    start: line_index.position(input, start_index),
+                    end: line_index.position(input, end_index),
+                };
+                let mut book_reference = BookReference::new(book_id, range, segment_chars);
+                book_reference.translation = translation;
+                // a dual Psalm number moved the text, so only the whole reference is known
+                if dual.is_none() {
+                    let segment_start = start_index + book_len + segment_match.start();
+                    book_reference.segment_ranges =
+                        book_reference_segment::segment_spans(segment_chars)
+                            .into_iter()
+                            .map(|span| Range {
+                                start: line_index.position(input, segment_start + span.start),
+                                end: line_index.position(input, segment_start + span.end),
+                            })
+                            .collect();
+                }
+                if let Some(addition) = additions::find(book_name, book_id) {
+                    book_reference.segments =
+                        additions::retarget(addition, &book_reference.segments);
+                }
+                if book_id == versification::PSALMS
+                    && dual.is_none()
+                    && self.api.psalm_numbering == PsalmNumbering::Greek
+                {
+                    book_reference.segments =
+                        versification::segments_to_hebrew(&book_reference.segments);
+                }
 
                 // println!(
                 //     "{} {} at [{}:{}-{}:{}]",
@@ -433,7 +475,38 @@ impl BibleLSP {
     //     todo!()
     // }
     //
-    pub fn suggest_auto_completion(&self, line: &str) -> Vec<BibleCompletion> {
+    /**
+    Where the text a completion replaces starts, as a byte offset into `text_before_cursor`
+
+    - References are replaced from their book up to the cursor, since every suggestion is the
+      whole reference (`Ephesians 1:1-` becomes `Ephesians 1:1-14`)
+    - Book names only replace the name being typed, so a reference earlier on the line isn't
+      swallowed, and with nothing typed yet the book is inserted at the cursor
+    */
+    pub fn completion_start(&self, text_before_cursor: &str) -> usize {
+        let book_match = self
+            .api
+            .book_abbreviation_regex()
+            .find_iter(text_before_cursor)
+            .last();
+        match (
+            parse_current_state(&self.api, text_before_cursor),
+            book_match,
+        ) {
+            (AutocompleteState::BooksOnly, _) | (_, None) => re::partial_book_name()
+                .find(text_before_cursor)
+                .map_or(text_before_cursor.len(), |typed| typed.start()),
+            (_, Some(book_match)) => book_match.start(),
+        }
+    }
+
+    /// - At most `limit` suggestions, in the order they are generated
+    /// - Also returns whether any were left out
+    pub fn suggest_auto_completion(
+        &self,
+        line: &str,
+        limit: Option<usize>,
+    ) -> (Vec<BibleCompletion>, bool) {
         let state = parse_current_state(&self.api, line);
         // let mut file = OpenOptions::new()
         //     .write(true)
@@ -441,19 +514,29 @@ impl BibleLSP {
         //     .open("~/bible_lsp.log")
         //     .unwrap();
         // write!(file, format!("{:#?}", &state));
-        append_log(format!("{}\n{:#?}\n\n", line, &state));
+        trace::sampled("completion", trace::Level::Trace, || {
+            format!("{}\n{:#?}\n", line, &state)
+        });
         // format!("{:#?}", &state);
-        let result = state.give_suggestions(&self.api);
+        let mut result = state.give_suggestions(&self.api);
         // append_log(format!("result={:#?}\n\n", &result));
-        result
+        let truncated = limit.is_some_and(|limit| result.len() > limit);
+        if let Some(limit) = limit {
+            result.truncate(limit);
+        }
+        (result, truncated)
     }
 }
 
 pub fn append_log(content: impl AsRef<str>) {
-    _ = append_to_file("/home/dgmastertemple/bible_lsp.log", content.as_ref());
+    _ = append_to_file(paths::log_file(), content.as_ref());
 }
 
-pub fn append_to_file(filename: &str, content: &str) -> Result<(), io::Error> {
+pub fn append_to_file(filename: impl AsRef<Path>, content: &str) -> Result<(), io::Error> {
+    let filename = filename.as_ref();
+    if let Some(parent) = filename.parent() {
+        fs::create_dir_all(parent)?;
+    }
     // Open the file in append mode. Create it if it doesn't exist.
     let mut file = OpenOptions::new()
         .append(true)
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use crate::bible_json::{JSONBible, JSONTranslation};
+use crate::convert::{Reader, Usfm};
+use crate::error::{self, Error};
+
+/// Extensions of USFM files, in any case
+const EXTENSIONS: &[&str] = &["usfm", "sfm"];
+
+/// Optional, next to the books, for what USFM has no marker for
+pub const TRANSLATION_FILE: &str = "translation.json";
+
+/**
+Reads a directory of USFM files, one book each, as if it were a Bible JSON file
+
+- For loading translation sources (like the ones from eBible.org) straight from where they were
+  unzipped, without `bible_lsp convert` first
+- The translation is read from [`TRANSLATION_FILE`], like `{ "name": "World English Bible",
+  "language": "English", "abbreviation": "WEB" }`, and is otherwise named after the directory
+- Files are read in name order, and a book in two files is an error rather than one quietly
+  winning
+*/
+pub fn read_dir(dir: &Path) -> error::Result<JSONBible> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|err| Error::read(dir, err))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|extension| {
+                EXTENSIONS
+                    .iter()
+                    .any(|known| extension.eq_ignore_ascii_case(known))
+            })
+        })
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(Error::Convert {
+            name: dir.display().to_string(),
+            message: String::from("no .usfm or .sfm files in it"),
+        });
+    }
+
+    let mut bible = JSONBible {
+        translation: translation(dir)?,
+        bible: vec![],
+    };
+    for file in files {
+        let text = std::fs::read_to_string(&file).map_err(|err| Error::read(&file, err))?;
+        let read = Usfm.read(&text).map_err(|err| Error::convert(&file, err))?;
+        for book in read.bible {
+            if bible.bible.iter().any(|existing| existing.id == book.id) {
+                return Err(Error::Convert {
+                    name: file.display().to_string(),
+                    message: format!("{} is already in another file", book.book),
+                });
+            }
+            bible.bible.push(book);
+        }
+    }
+    // files are usually named by their place in the canon, but not always
+    bible.bible.sort_by_key(|book| book.id);
+    Ok(bible)
+}
+
+fn translation(dir: &Path) -> error::Result<JSONTranslation> {
+    let path = dir.join(TRANSLATION_FILE);
+    if path.is_file() {
+        let text = std::fs::read_to_string(&path).map_err(|err| Error::read(&path, err))?;
+        return serde_json::from_str(&text).map_err(|err| Error::parse(&path, text, err));
+    }
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(JSONTranslation {
+        abbreviation: name.to_uppercase(),
+        name,
+        language: String::new(),
+        attribution: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bible_api::BibleAPI;
+
+    #[test]
+    fn books_load_like_a_json_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("web");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join("02-JHN.usfm"),
+            "\\id JHN\n\\h John\n\\c 1\n\\v 1 In the beginning\n\\v 2 \\wj was\\wj* the Word\n\\c 2\n\\v 1 On the third day\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("01-GEN.SFM"),
+            "\\id GEN\n\\h Genesis\n\\c 1\n\\v 1 In the beginning God\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("readme.txt"), "not a book").unwrap();
+
+        let api = BibleAPI::load(&dir).unwrap();
+        assert_eq!(api.translation.abbreviation, "WEB");
+        assert_eq!(api.get_book_id("John"), Some(43));
+        assert_eq!(api.reference_array[42], [2, 1]);
+        assert_eq!(api.bible_contents[42][0][1], "was the Word");
+        assert_eq!(api.bible_contents[0][0][0], "In the beginning God");
+
+        std::fs::write(
+            dir.join(TRANSLATION_FILE),
+            r#"{ "name": "World English Bible", "language": "English", "abbreviation": "WEBBE" }"#,
+        )
+        .unwrap();
+        assert_eq!(read_dir(&dir).unwrap().translation.abbreviation, "WEBBE");
+
+        std::fs::write(dir.join("03-JHN.usfm"), "\\id JHN\n\\c 1\n\\v 1 Again\n").unwrap();
+        let err = error::summary(&read_dir(&dir).unwrap_err());
+        assert!(err.contains("John is already in another file"), "{err}");
+    }
+}
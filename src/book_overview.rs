@@ -0,0 +1,221 @@
+use std::ops::Range;
+
+use crate::{bible_api::BibleAPI, convert, headings::Headings};
+
+/**
+The book named at `cursor` (a byte offset in `line`), and where its name is in the line
+
+- For hovers on a name without a chapter, like `Ephesians` or `1 Cor.`
+- Only names written like one, starting with a capital or a number, since `acts`, `job` and
+  `numbers` are usually just words
+*/
+pub fn book_at(api: &BibleAPI, line: &str, cursor: usize) -> Option<(usize, Range<usize>)> {
+    let found = api
+        .book_abbreviation_regex()
+        .find_iter(line)
+        .find(|found| found.start() <= cursor && cursor <= found.end())?;
+    let first = found.as_str().chars().find(|ch| ch.is_alphabetic())?;
+    if !first.is_uppercase() {
+        return None;
+    }
+    Some((api.get_book_id(found.as_str())?, found.range()))
+}
+
+/**
+A compact overview of a book, lighter than rendering all of it
+
+```text
+### Ephesians
+
+6 chapters · 155 verses
+
+[1](...) 23 · [2](...) 22 · [3](...) 21 · [4](...) 32 · [5](...) 33 · [6](...) 24
+```
+
+- `chapter_link` is where each chapter number links to, like a command that opens it
+- Each chapter is followed by its number of verses
+*/
+pub fn format(
+    api: &BibleAPI,
+    book_id: usize,
+    chapter_link: impl Fn(usize) -> String,
+) -> Option<String> {
+    let name = api.get_book_name(book_id)?;
+    let chapters: Vec<(usize, usize)> = api
+        .get_all_chapters(book_id)?
+        .filter_map(|chapter| Some((chapter, api.get_chapter_verse_count(book_id, chapter)?)))
+        .collect();
+    let verses: usize = chapters.iter().map(|(_, verses)| verses).sum();
+    let plural = |count: usize, word: &str| match count {
+        1 => format!("1 {word}"),
+        count => format!("{count} {word}s"),
+    };
+    let list: Vec<String> = chapters
+        .iter()
+        .map(|(chapter, verses)| format!("[{chapter}]({}) {verses}", chapter_link(*chapter)))
+        .collect();
+    Some(format!(
+        "### {name}\n\n{} · {}\n\n{}",
+        plural(chapters.len(), "chapter"),
+        plural(verses, "verse"),
+        list.join(" · ")
+    ))
+}
+
+/// Which part of the canon a book is in, by its id
+fn testament(book_id: usize) -> &'static str {
+    match book_id {
+        1..=39 => "the Old Testament",
+        40..=66 => "the New Testament",
+        _ => "the Deuterocanon",
+    }
+}
+
+/**
+A book's introduction page, for `bible://{translation}/{book}/intro`
+
+```text
+# Ephesians
+
+- Book 49 of 66 in the English Standard Version (ESV), in the New Testament
+- Written `EPH` in USFM and `Eph` in OSIS
+
+## Outline
+
+- Spiritual Blessings in Christ (Ephesians 1:3-14)
+...
+
+## Chapters
+
+- [Ephesians 1](1.md): 23 verses
+...
+
+## Statistics
+
+- 6 chapters, 155 verses, 3013 words
+- 25.8 verses per chapter
+- Longest chapter: Ephesians 5 (33 verses)
+- Longest verse: Ephesians 1:21 (58 words)
+```
+
+- The outline is the section headings in the book (see [`crate::headings`]), and is left out
+  without any
+- The chapters link to the chapter documents next to it
+*/
+pub fn intro(api: &BibleAPI, book_id: usize, headings: &Headings) -> Option<String> {
+    let name = api.get_book_name(book_id)?;
+    let chapters: Vec<(usize, usize)> = api
+        .get_all_chapters(book_id)?
+        .filter_map(|chapter| Some((chapter, api.get_chapter_verse_count(book_id, chapter)?)))
+        .collect();
+    let last = chapters.last()?;
+
+    let translation = &api.translation;
+    let mut about = vec![format!(
+        "- Book {} of {} in the {} ({}), in {}",
+        api.canon_position(book_id) + 1,
+        api.canon_order.len(),
+        translation.name,
+        translation.abbreviation,
+        testament(book_id)
+    )];
+    if let Some((_, usfm, osis, _)) = convert::book_info(book_id) {
+        about.push(format!("- Written `{usfm}` in USFM and `{osis}` in OSIS"));
+    }
+
+    let mut sections = vec![format!("# {name}"), about.join("\n")];
+    let outline = crate::headings::outline(
+        api,
+        &headings.sections(api, book_id, (1, 1), (last.0, last.1)),
+    );
+    if let Some(outline) = outline {
+        sections.push(format!("## Outline\n\n{outline}"));
+    }
+
+    let list: Vec<String> = chapters
+        .iter()
+        .map(|(chapter, verses)| match verses {
+            1 => format!("- [{name} {chapter}]({chapter}.md): 1 verse"),
+            verses => format!("- [{name} {chapter}]({chapter}.md): {verses} verses"),
+        })
+        .collect();
+    sections.push(format!("## Chapters\n\n{}", list.join("\n")));
+
+    let verses: usize = chapters.iter().map(|(_, verses)| verses).sum();
+    // (words, chapter, verse) of the longest verse, the first one on a tie
+    let mut longest_verse = (0, 1, 1);
+    let mut words = 0;
+    for (chapter, verse_count) in chapters.iter() {
+        for verse in 1..=*verse_count {
+            let count = api
+                .get_bible_contents(book_id, *chapter, verse)
+                .map(|content| content.split_whitespace().count())
+                .unwrap_or_default();
+            words += count;
+            if count > longest_verse.0 {
+                longest_verse = (count, *chapter, verse);
+            }
+        }
+    }
+    let longest_chapter = chapters
+        .iter()
+        .rev()
+        .max_by_key(|(_, verses)| *verses)
+        .unwrap_or(last);
+    let statistics = [
+        format!(
+            "- {} {}, {verses} verses, {words} words",
+            chapters.len(),
+            match chapters.len() {
+                1 => "chapter",
+                _ => "chapters",
+            }
+        ),
+        format!(
+            "- {:.1} verses per chapter",
+            verses as f64 / chapters.len() as f64
+        ),
+        format!(
+            "- Longest chapter: {name} {} ({} verses)",
+            longest_chapter.0, longest_chapter.1
+        ),
+        format!(
+            "- Longest verse: {name} {}:{} ({} words)",
+            longest_verse.1, longest_verse.2, longest_verse.0
+        ),
+    ];
+    sections.push(format!("## Statistics\n\n{}", statistics.join("\n")));
+    Some(sections.join("\n\n") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api() -> BibleAPI {
+        BibleAPI::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ))
+    }
+
+    #[test]
+    fn only_capitalized_names_are_books() {
+        let api = api();
+        let line = "Read Gen. and genesis";
+        let (book_id, range) = book_at(&api, line, 6).unwrap();
+        assert_eq!(book_id, 1);
+        assert_eq!(&line[range], "Gen.");
+        assert_eq!(book_at(&api, line, 16), None);
+        assert_eq!(book_at(&api, line, 1), None);
+    }
+
+    #[test]
+    fn chapters_are_listed_with_their_verses() {
+        let api = api();
+        let overview = format(&api, 1, |chapter| format!("#{chapter}")).unwrap();
+        let chapters = api.get_book_chapter_count(1).unwrap();
+        assert!(overview.starts_with(&format!("### Genesis\n\n{chapters} chapters · ")));
+        assert!(overview.contains("[1](#1) 5 · [2](#2)"), "{overview}");
+    }
+}
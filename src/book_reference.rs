@@ -1,15 +1,81 @@
-use tower_lsp::lsp_types::Range;
+use std::fmt;
+
+use serde::Deserialize;
+use tower_lsp::lsp_types::{Position, Range};
 
 use crate::{
     api_wrappers::APIBookReference, bible_api::BibleAPI,
-    book_reference_segment::BookReferenceSegments,
+    book_reference_segment::BookReferenceSegments, footnotes::MarkdownFootnotes, poetry::Poetry,
+    red_letter::RedLetter, word_diff,
 };
 
+/**
+What [`BookReference::format`] shows, from the `hover` setting
+
+- The default is a heading with the reference, then every verse with its `[chapter:verse]`
+- Long passages can be cut to `max_verses`, with a line saying how many more there are
+*/
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormatOptions {
+    /// the `### Ephesians 1:1-4` line
+    pub heading: bool,
+    /// the `[1:1]` before each verse
+    pub verse_numbers: bool,
+    /// `null` for every verse
+    pub max_verses: Option<usize>,
+    /// a list of chapters for a book named without one, like `Ephesians`
+    pub books: bool,
+    /// - `See also: Romans 5:8; 1 John 4:9` after a single verse, from `crossReferencesFile`
+    /// - Only when the server is built with the `commentary` feature
+    pub cross_references: bool,
+    /// the most cross references listed, the ones with the most votes
+    pub max_cross_references: usize,
+    /// - The translation's footnotes as markdown footnotes after the verses
+    /// - Off by default, since most editors show `[^John.3.16a]` as it is in hovers
+    pub footnotes: bool,
+    /// - The translation's section headings, like `**The Sermon on the Mount**`, where the
+    ///   verses go into another section
+    /// - Not before the first verse, which is already under the reference
+    pub section_headings: bool,
+    /// the words of Christ in bold, italics, or a color, see [`RedLetter`]
+    pub red_letter: RedLetter,
+    /// - Lines of poetry on lines of their own, and a blank line where a stanza or paragraph
+    ///   starts, see [`Poetry`]
+    /// - `null` shows every verse as prose
+    pub poetry: Option<Poetry>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            heading: true,
+            verse_numbers: true,
+            max_verses: None,
+            books: true,
+            cross_references: true,
+            max_cross_references: 5,
+            footnotes: false,
+            section_headings: true,
+            red_letter: RedLetter::None,
+            poetry: Some(Poetry::default()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct BookReference {
     pub range: Range,
     pub book_id: usize,
     pub segments: BookReferenceSegments,
+    /// - The loaded translation named after it, like `KJV` in `John 3:16 KJV`, whose text it is
+    ///   shown in
+    /// - The name is part of `range`
+    pub translation: Option<String>,
+    /// - Where each of `segments` was written, in the same order, like `2:2-3:4` in
+    ///   `Eph 1:1-4,2:2-3:4`
+    /// - Empty for references that weren't read from text
+    pub segment_ranges: Vec<Range>,
 }
 
 impl<'a> BookReference {
@@ -31,15 +97,69 @@ impl BookReference {
             range,
             book_id,
             segments,
+            translation: None,
+            segment_ranges: vec![],
+        }
+    }
+
+    /// Moves `range` (and the segments' ranges) by `lines` and `characters`, for references
+    /// found in part of a document
+    pub fn offset(&mut self, lines: u32, characters: u32) {
+        for range in std::iter::once(&mut self.range).chain(self.segment_ranges.iter_mut()) {
+            range.start.line += lines;
+            range.end.line += lines;
+            range.start.character += characters;
+            range.end.character += characters;
+        }
+    }
+
+    /**
+    The segment `position` is on as a reference of its own, with the segment's range
+
+    - Only for references with more than one segment, since otherwise it is the whole reference
+    - `None` on the book name, or when it isn't known where the segments were written
+    */
+    pub fn segment_at(&self, position: Position) -> Option<BookReference> {
+        if self.segments.len() < 2 || self.segment_ranges.len() != self.segments.len() {
+            return None;
         }
+        let idx = self
+            .segment_ranges
+            .iter()
+            .position(|range| range.start <= position && position <= range.end)?;
+        Some(BookReference {
+            range: self.segment_ranges[idx],
+            book_id: self.book_id,
+            segments: BookReferenceSegments(vec![self.segments[idx].clone()]),
+            translation: self.translation.clone(),
+            segment_ranges: vec![self.segment_ranges[idx]],
+        })
+    }
+
+    /// ` KJV` for a reference with its own translation, to keep it when the reference is rewritten
+    pub fn translation_suffix(&self) -> String {
+        self.translation
+            .as_ref()
+            .map(|translation| format!(" {translation}"))
+            .unwrap_or_default()
     }
 
     /// Formats into something like `Ephesians 1:1-4, 5-7, 2:2-3:4, 6`
     pub fn full_ref_label(&self, api: &BibleAPI) -> String {
+        let mut label = String::new();
+        self.write_label(api, &mut label)
+            .expect("Writing to a String can't fail");
+        label
+    }
+
+    /// [`BookReference::full_ref_label`] written straight into `f`
+    pub fn write_label(&self, api: &BibleAPI, f: &mut impl fmt::Write) -> fmt::Result {
         let book_name = api
             .get_book_name(self.book_id)
             .expect("A BookReference struct should not be created if the book_id is invalid.");
-        format!("{} {}", book_name, self.segments.label())
+        f.write_str(&book_name)?;
+        f.write_char(' ')?;
+        self.segments.write_label(f)
     }
 
     /**
@@ -53,28 +173,90 @@ impl BookReference {
     ```
     */
     pub fn format_content(&self, api: &BibleAPI) -> String {
-        self.segments
-            .iter()
-            .map(|seg| {
-                let mut contents = vec![];
-                for chapter in seg.get_starting_chapter()..=seg.get_ending_chapter() {
-                    for verse in seg.get_starting_verse()..=seg.get_ending_verse() {
-                        if let Some(content) = api.get_bible_contents(self.book_id, chapter, verse)
+        self.format_content_with(api, &FormatOptions::default())
+    }
+
+    /// [`BookReference::format_content`] with the verse numbers and length from `options`
+    pub fn format_content_with(&self, api: &BibleAPI, options: &FormatOptions) -> String {
+        let max_verses = options.max_verses.unwrap_or(usize::MAX);
+        let mut shown = 0;
+        let mut left_out = 0;
+        let mut segments = vec![];
+        let mut footnotes = MarkdownFootnotes::default();
+        for seg in self.segments.iter() {
+            let mut contents = vec![];
+            // every verse of the chapters in between, not just the ones numbered between the
+            // first and last verse
+            for (chapter, verse) in seg.verses(api, self.book_id) {
+                let Some(content) = api.get_bible_contents(self.book_id, chapter, verse) else {
+                    continue;
+                };
+                if shown == max_verses {
+                    left_out += 1;
+                    continue;
+                }
+                let heading = api
+                    .get_section_heading(self.book_id, chapter, verse)
+                    .filter(|_| shown > 0 && options.section_headings);
+                if let Some(heading) = heading {
+                    contents.push(format!("\n**{heading}**\n"));
+                }
+                let mark = |content: String, offset: usize| {
+                    options
+                        .red_letter
+                        .mark_from(api, self.book_id, chapter, verse, content, offset)
+                };
+                let content = match &options.poetry {
+                    Some(poetry) => {
+                        // a heading already sets the verse apart
+                        if !contents.is_empty()
+                            && heading.is_none()
+                            && poetry.breaks_before(api, self.book_id, chapter, verse)
                         {
-                            contents.push(format!("[{}:{}] {}", chapter, verse, content));
+                            contents.push(String::new());
                         }
+                        poetry.lines(api, self.book_id, chapter, verse, content, mark)
                     }
-                }
-                contents.join("\n")
-            })
-            .collect::<Vec<String>>()
-            .join("\n\n")
+                    None => mark(content, 0),
+                };
+                shown += 1;
+                let content = match options.footnotes {
+                    true => footnotes.mark(api, self.book_id, chapter, verse, content),
+                    false => content,
+                };
+                contents.push(match options.verse_numbers {
+                    true => format!("[{}:{}] {}", chapter, verse, content),
+                    false => content,
+                });
+            }
+            if !contents.is_empty() {
+                segments.push(contents.join("\n"));
+            }
+        }
+        let mut content = segments.join("\n\n");
+        if left_out > 0 {
+            let verses = if left_out == 1 { "verse" } else { "verses" };
+            content.push_str(&format!("\n\n*… {left_out} more {verses}*"));
+        }
+        if let Some(definitions) = footnotes.definitions() {
+            content.push_str(&format!("\n\n{definitions}"));
+        }
+        content
     }
 
     pub fn format(&self, api: &BibleAPI) -> String {
+        self.format_with(api, &FormatOptions::default())
+    }
+
+    /// [`BookReference::format`], with what is shown from `options`
+    pub fn format_with(&self, api: &BibleAPI, options: &FormatOptions) -> String {
+        let content = self.format_content_with(api, options);
+        if !options.heading {
+            return content;
+        }
         let reference = self.full_ref_label(api);
-        let content = self.format_content(api);
-        format!("### {reference}\n\n{content}")
+        let translation = self.translation_suffix();
+        format!("### {reference}{translation}\n\n{content}")
     }
 
     pub fn format_callout(&self, api: &BibleAPI) -> String {
@@ -88,16 +270,12 @@ impl BookReference {
             .iter()
             .map(|seg| {
                 let mut contents = vec![];
-                for chapter in seg.get_starting_chapter()..=seg.get_ending_chapter() {
-                    for verse in seg.get_starting_verse()..=seg.get_ending_verse() {
-                        if let Some(content) = api.get_bible_contents(self.book_id, chapter, verse)
-                        {
-                            if verse == 1 && contents.len() > 0 {
-                                contents
-                                    .push(format!("<sup>{}:{}</sup>{}", chapter, verse, content));
-                            } else {
-                                contents.push(format!("<sup>{}</sup>{}", verse, content));
-                            }
+                for (chapter, verse) in seg.verses(api, self.book_id) {
+                    if let Some(content) = api.get_bible_contents(self.book_id, chapter, verse) {
+                        if verse == 1 && contents.len() > 0 {
+                            contents.push(format!("<sup>{}:{}</sup>{}", chapter, verse, content));
+                        } else {
+                            contents.push(format!("<sup>{}</sup>{}", verse, content));
                         }
                     }
                 }
@@ -122,7 +300,60 @@ impl BookReference {
         format!("> {content} - {reference}")
     }
 
-    pub fn format_diagnostic(&self, api: &BibleAPI) -> Option<String> {
+    /**
+    [`BookReference::format`] with the words `other` translates differently in bold, see
+    [`crate::word_diff::markdown`]
+
+    - Verses are matched by number, so versification differences between the two show up as
+      differences in wording
+    */
+    pub fn format_compared(&self, api: &BibleAPI, other: &BibleAPI) -> String {
+        let reference = self.full_ref_label(api);
+        let label = format!("{}: ", other.translation.abbreviation);
+        let content = self
+            .segments
+            .iter()
+            .map(|seg| {
+                let mut contents = vec![];
+                for (chapter, verse) in seg.verses(api, self.book_id) {
+                    let Some(content) = api.get_bible_contents(self.book_id, chapter, verse) else {
+                        continue;
+                    };
+                    let content = match other.get_bible_contents(self.book_id, chapter, verse) {
+                        Some(theirs) => word_diff::markdown(&content, &theirs, &label),
+                        None => content,
+                    };
+                    contents.push(format!("[{}:{}] {}", chapter, verse, content));
+                }
+                contents.join("\n")
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n");
+        format!(
+            "### {reference}\n\n{content}\n\n*Wording that differs from the {} in bold*",
+            other.translation.abbreviation
+        )
+    }
+
+    /// Every `(chapter, verse)` cited, across all segments
+    pub fn verses(&self, api: &BibleAPI) -> Vec<(usize, usize)> {
+        self.segments
+            .iter()
+            .flat_map(|seg| seg.verses(api, self.book_id))
+            .collect()
+    }
+
+    /**
+    The text of the first verse, for the diagnostic under the reference
+
+    - Cut to `preview_length` characters with an ellipsis, or the whole verse without one
+    - A length of `0` is just the reference, since a diagnostic needs some message
+    */
+    pub fn format_diagnostic(
+        &self,
+        api: &BibleAPI,
+        preview_length: Option<usize>,
+    ) -> Option<String> {
         let first_segment = self.segments.first()?;
         // .expect("This would not have matched as a book reference if there were not segments");
         let content = api.get_bible_contents(
@@ -130,6 +361,41 @@ impl BookReference {
             first_segment.get_starting_chapter(),
             first_segment.get_starting_verse(),
         )?;
-        Some(content)
+        Some(match preview_length {
+            Some(0) => self.full_ref_label(api),
+            Some(length) if content.chars().count() > length => {
+                let cut: String = content.chars().take(length).collect();
+                format!("{}…", cut.trim_end())
+            }
+            _ => content,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bible_lsp::BibleLSP;
+
+    #[test]
+    fn options_shape_the_passage() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let book_ref = lsp.find_book_references("Gen 1:1-3").unwrap().remove(0);
+        let options = FormatOptions {
+            heading: false,
+            verse_numbers: false,
+            max_verses: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            book_ref.format_with(&lsp.api, &options),
+            "Text of Genesis 1:1.\nText of Genesis 1:2.\n\n*… 1 more verse*"
+        );
+        assert!(book_ref
+            .format(&lsp.api)
+            .starts_with("### Genesis 1:1-3\n\n[1:1] Text of Genesis 1:1."));
     }
 }
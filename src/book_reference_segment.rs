@@ -1,9 +1,17 @@
+use std::fmt;
 use std::ops::{Deref, DerefMut};
 
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use tower_lsp::lsp_types::{Position, Range};
 
-use crate::{autocompletion::AutocompleteState, bible_api::BibleAPI, re};
+use crate::{
+    autocompletion::AutocompleteState,
+    bible_api::BibleAPI,
+    book_reference::BookReference,
+    re,
+    versification::{self, PsalmNumbering},
+};
 
 /// - This is a single chapter/verse reference
 /// - Ex: `1:2` in `John 1:2`
@@ -33,7 +41,7 @@ pub struct BookRange {
 }
 
 /// Remember, these correspond to
-/// ```
+/// ```text
 ///                `Ephesians 1:1-4,5-7,2:2-3:4,6`
 ///                          |     |   |       | |
 ///                ----------+     |   |       | |
@@ -74,68 +82,178 @@ impl BookReferenceSegments {
     }
 
     pub fn label(&self) -> String {
+        let mut label = String::new();
+        self.write_label(&mut label)
+            .expect("Writing to a String can't fail");
+        label
+    }
+
+    /// - Same as [`BookReferenceSegments::label`], but written straight into `f`
+    /// - Used where lots of labels are built at once (symbols, completions), so there is no
+    ///   `String` per segment
+    pub fn write_label(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        self.write_label_with(f, &LabelSeparators::default())
+    }
+
+    /// [`BookReferenceSegments::label`] with other separators
+    pub fn label_with(&self, separators: &LabelSeparators) -> String {
+        let mut label = String::new();
+        self.write_label_with(&mut label, separators)
+            .expect("Writing to a String can't fail");
+        label
+    }
+
+    /// [`BookReferenceSegments::write_label`] with other separators
+    pub fn write_label_with(
+        &self,
+        f: &mut impl fmt::Write,
+        separators: &LabelSeparators,
+    ) -> fmt::Result {
+        let dash = separators.dash();
         let mut previous_chapter: Option<usize> = None;
-        let mut label_segments: Vec<String> = vec![];
-        // let mut label_str = String::new();
         for seg in self.0.iter() {
-            let next_seg = match seg {
+            let ending_chapter = seg.get_ending_chapter();
+            if let Some(prev) = previous_chapter {
+                match prev == ending_chapter && separators.segments == SegmentSeparator::Comma {
+                    // if same chapter, add ','
+                    true => f.write_char(',')?,
+                    // if new chapter, add '; '
+                    false => f.write_str("; ")?,
+                }
+            }
+            // verses in the same chapter don't repeat it, unless every segment gets a `; `
+            let same_chapter = |chapter: usize| {
+                separators.segments == SegmentSeparator::Comma
+                    && previous_chapter.is_some_and(|prev| prev == chapter)
+            };
+            match seg {
                 BookReferenceSegment::ChapterVerse(chapter_verse) => {
-                    if previous_chapter.is_some_and(|prev| prev == chapter_verse.chapter) {
-                        format!("{}", chapter_verse.verse)
+                    if same_chapter(chapter_verse.chapter) {
+                        write!(f, "{}", chapter_verse.verse)?
                     } else {
-                        format!("{}:{}", chapter_verse.chapter, chapter_verse.verse)
+                        write!(f, "{}:{}", chapter_verse.chapter, chapter_verse.verse)?
                     }
                 }
                 BookReferenceSegment::ChapterRange(chapter_range) => {
-                    if previous_chapter.is_some_and(|prev| prev == chapter_range.chapter) {
-                        format!("{}-{}", chapter_range.start_verse, chapter_range.end_verse)
+                    if same_chapter(chapter_range.chapter) {
+                        write!(
+                            f,
+                            "{}{dash}{}",
+                            chapter_range.start_verse, chapter_range.end_verse
+                        )?
                     } else {
-                        format!(
-                            "{}:{}-{}",
+                        write!(
+                            f,
+                            "{}:{}{dash}{}",
                             chapter_range.chapter,
                             chapter_range.start_verse,
                             chapter_range.end_verse
-                        )
+                        )?
                     }
                 }
                 BookReferenceSegment::BookRange(book_range) => {
-                    if previous_chapter.is_some_and(|prev| prev == book_range.start_chapter) {
-                        format!(
-                            "{}-{}:{}",
+                    if same_chapter(book_range.start_chapter) {
+                        write!(
+                            f,
+                            "{}{dash}{}:{}",
                             book_range.start_verse, book_range.end_chapter, book_range.end_verse
-                        )
+                        )?
                     } else {
-                        format!(
-                            "{}:{}-{}:{}",
+                        write!(
+                            f,
+                            "{}:{}{dash}{}:{}",
                             book_range.start_chapter,
                             book_range.start_verse,
                             book_range.end_chapter,
                             book_range.end_verse
-                        )
+                        )?
                     }
                 }
             };
-            let ending_chapter = seg.get_ending_chapter();
-            // // if new chapter, add '; '
-            // if previous_chapter.is_some_and(|prev| prev != ending_chapter) {
-            //     label_segments.push(String::from("; "));
-            // }
-            // // if same chapter, add ','
-            // else {
-            //     label_segments.push(String::from(","));
-            // }
-            if let Some(prev) = previous_chapter {
-                match prev == ending_chapter {
-                    // if same chapter, add ','
-                    true => label_segments.push(String::from(",")),
-                    // if new chapter, add '; '
-                    false => label_segments.push(String::from("; ")),
-                }
-            }
-            label_segments.push(next_seg);
             previous_chapter = Some(ending_chapter);
         }
-        label_segments.join("")
+        Ok(())
+    }
+}
+
+/// What goes between the segments of a label
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SegmentSeparator {
+    /// `1:1-4,6; 2:1`, with `; ` only where the chapter changes
+    #[default]
+    Comma,
+    /// `1:1-4; 1:6; 2:1`, with every segment starting with its chapter
+    Semicolon,
+}
+
+/**
+How the separators in a label are written
+
+- The default is `1:1-4,6; 2:1`, whatever the reference looked like
+- `preserve` keeps the segments as they were written (`eph 1:1 – 4; 6` is
+  `Ephesians 1:1 – 4; 6`), where the original text is known, like in code action titles
+*/
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LabelSeparators {
+    pub preserve: bool,
+    pub segments: SegmentSeparator,
+    /// `1:1 - 4` instead of `1:1-4`
+    pub spaced_dashes: bool,
+    /// `1:1–4` instead of `1:1-4`
+    pub en_dash: bool,
+}
+
+impl LabelSeparators {
+    fn dash(&self) -> &'static str {
+        match (self.spaced_dashes, self.en_dash) {
+            (false, false) => "-",
+            (false, true) => "–",
+            (true, false) => " - ",
+            (true, true) => " – ",
+        }
+    }
+
+    /// `Ephesians 1:1–4`, the way references are cited in print, for the canonical inlay hints
+    pub fn canonical() -> Self {
+        Self {
+            en_dash: true,
+            ..Default::default()
+        }
+    }
+
+    /**
+    - The label of `book_ref`, like [`BookReference::full_ref_label`]
+    - `written` is the text the reference was found in, which is only used when preserving, and
+      only from the first chapter on, so the book name is always the translation's
+    - A translation written after it (`John 3:16 KJV`) is kept either way
+    - Psalms are written in the `psalmNumbering` they are cited in, not the Hebrew numbering they
+      were parsed into, so writing the label back doesn't move them
+    */
+    pub fn label(&self, api: &BibleAPI, book_ref: &BookReference, written: Option<&str>) -> String {
+        let book = api
+            .get_book_name(book_ref.book_id)
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        let preserved = written.filter(|_| self.preserve).and_then(|written| {
+            let after_book = &written[api.book_abbreviation_regex().find(written)?.end()..];
+            Some(after_book[after_book.find(|c: char| c.is_ascii_digit())?..].trim_end())
+        });
+        let segments = match book_ref.book_id == versification::PSALMS
+            && api.psalm_numbering == PsalmNumbering::Greek
+        {
+            true => versification::segments_to_greek(&book_ref.segments),
+            false => book_ref.segments.clone(),
+        };
+        match preserved {
+            Some(segments) => format!("{book} {segments}"),
+            None => format!(
+                "{book} {}{}",
+                segments.label_with(self),
+                book_ref.translation_suffix()
+            ),
+        }
     }
 }
 
@@ -187,6 +305,78 @@ impl BookReferenceSegment {
     }
 }
 
+impl BookReferenceSegment {
+    /// The simplest segment covering `start` through `end`
+    pub fn from_span(start: (usize, usize), end: (usize, usize)) -> Self {
+        if start == end {
+            BookReferenceSegment::ChapterVerse(ChapterVerse {
+                chapter: start.0,
+                verse: start.1,
+            })
+        } else if start.0 == end.0 {
+            BookReferenceSegment::ChapterRange(ChapterRange {
+                chapter: start.0,
+                start_verse: start.1,
+                end_verse: end.1,
+            })
+        } else {
+            BookReferenceSegment::BookRange(BookRange {
+                start_chapter: start.0,
+                end_chapter: end.0,
+                start_verse: start.1,
+                end_verse: end.1,
+            })
+        }
+    }
+
+    /// - Every `(chapter, verse)` covered by the segment, in order
+    /// - Ranges that cross chapters go to the end of each chapter before starting the next
+    /// - Verses that don't exist in the translation are skipped
+    pub fn verses(&self, api: &BibleAPI, book_id: usize) -> Vec<(usize, usize)> {
+        let start_chapter = self.get_starting_chapter();
+        let end_chapter = self.get_ending_chapter();
+        let mut verses = vec![];
+        for chapter in start_chapter..=end_chapter {
+            let Some(verse_count) = api.get_chapter_verse_count(book_id, chapter) else {
+                continue;
+            };
+            let first = match chapter == start_chapter {
+                true => self.get_starting_verse(),
+                false => 1,
+            };
+            let last = match chapter == end_chapter {
+                true => self.get_ending_verse().min(verse_count),
+                false => verse_count,
+            };
+            verses.extend((first..=last).map(|verse| (chapter, verse)));
+        }
+        verses
+    }
+}
+
+/**
+- The byte span of each segment in `segment_input`, in the order [`BookReferenceSegments::parse`]
+  gives them, without the spaces around them
+- Only for text that ends in a digit, like what
+  [`re::post_book_valid_reference_segment_characters`] matches, or the last segment is counted
+  twice
+*/
+pub fn segment_spans(segment_input: &str) -> Vec<std::ops::Range<usize>> {
+    let mut spans = vec![];
+    let mut start = 0;
+    let ends = segment_input
+        .match_indices([',', ';'])
+        .map(|(idx, _)| idx)
+        .chain(std::iter::once(segment_input.len()));
+    for end in ends {
+        let piece = &segment_input[start..end];
+        let leading = piece.len() - piece.trim_start().len();
+        spans.push(start + leading..start + piece.trim_end().len());
+        start = end + 1;
+    }
+    spans
+}
+
 const DIGITS_ONLY_MSG: &'static str =
     "Only digits in a capture group should always parse to an usize.";
 
@@ -194,7 +384,7 @@ const DIGITS_ONLY_MSG: &'static str =
 /// - Don't pass it anything else please :)
 /**
 Passing `1` will result in
-```no_run
+```text
 [src/main.rs:27:5] parse_reference_segments("1") = [
     ChapterVerse(
         ChapterVerse {
@@ -205,7 +395,7 @@ Passing `1` will result in
 ]
 ```
 Passing `1:` will result in
-```no_run
+```text
 [src/main.rs:28:5] parse_reference_segments("1:") = [
     ChapterVerse(
         ChapterVerse {
@@ -299,3 +489,60 @@ fn parse_reference_segments(segment_input: &str) -> BookReferenceSegments {
     }
     BookReferenceSegments(segments)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_keep_chapters_implicit() {
+        let segments = BookReferenceSegments::parse("1:1-4,5-7,2:2-3:4,6");
+        assert_eq!(segments.label(), "1:1-4,5-7; 2:2-3:4,6");
+        let mut written = String::from("Ephesians ");
+        segments.write_label(&mut written).unwrap();
+        assert_eq!(written, "Ephesians 1:1-4,5-7; 2:2-3:4,6");
+    }
+
+    #[test]
+    fn segments_know_where_they_were_written() {
+        let written = " 1:1-4, 5-7;2:2 – 3:4,6";
+        let spans: Vec<&str> = segment_spans(written)
+            .into_iter()
+            .map(|span| &written[span])
+            .collect();
+        assert_eq!(spans, ["1:1-4", "5-7", "2:2 – 3:4", "6"]);
+        assert_eq!(spans.len(), BookReferenceSegments::parse(written).len());
+    }
+
+    #[test]
+    fn labels_use_the_configured_separators() {
+        let segments = BookReferenceSegments::parse("1:1-4,6,2:2-3:4");
+        let semicolons = LabelSeparators {
+            segments: SegmentSeparator::Semicolon,
+            ..Default::default()
+        };
+        assert_eq!(segments.label_with(&semicolons), "1:1-4; 1:6; 2:2-3:4");
+        let dashes = LabelSeparators {
+            spaced_dashes: true,
+            en_dash: true,
+            ..Default::default()
+        };
+        assert_eq!(segments.label_with(&dashes), "1:1 – 4,6; 2:2 – 3:4");
+
+        let lsp = crate::bible_lsp::BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let written = "gen 1:1 – 4; 6";
+        let book_ref = lsp.find_book_references(written).unwrap().remove(0);
+        let preserve = LabelSeparators {
+            preserve: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            preserve.label(&lsp.api, &book_ref, Some(written)),
+            "Genesis 1:1 – 4; 6"
+        );
+        assert_eq!(preserve.label(&lsp.api, &book_ref, None), "Genesis 1:1-4,6");
+    }
+}
@@ -0,0 +1,167 @@
+use serde::Deserialize;
+use tower_lsp::lsp_types::{CodeActionKind, Position, Range, TextEdit};
+
+use crate::{bible_api::BibleAPI, book_reference::BookReference, document::LineIndex};
+
+/// - The kind of the code action, so editors can run it on save
+/// - Ex: `"editor.codeActionsOnSave": { "source.bibleBooks": "explicit" }` in VS Code
+pub const CODE_ACTION_KIND: &str = "source.bibleBooks";
+
+pub fn code_action_kind() -> CodeActionKind {
+    CodeActionKind::new(CODE_ACTION_KIND)
+}
+
+/**
+A front matter list of the books a note cites, like `bible-books: [Romans, Ephesians]`
+
+- For sorting notes by book in tools that read front matter (Obsidian, static site generators)
+- The code action is only offered when `enabled`, and `bible.updateBookTags` works either way
+*/
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BookTagsConfig {
+    pub enabled: bool,
+    pub field: String,
+}
+
+impl Default for BookTagsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            field: String::from("bible-books"),
+        }
+    }
+}
+
+/// The names of the books cited, once each and in canon order
+pub fn book_names<'a>(
+    api: &BibleAPI,
+    references: impl IntoIterator<Item = &'a BookReference>,
+) -> Vec<String> {
+    let mut book_ids: Vec<usize> = references
+        .into_iter()
+        .map(|book_ref| book_ref.book_id)
+        .collect();
+    book_ids.sort_by_key(|book_id| api.canon_position(*book_id));
+    book_ids.dedup();
+    book_ids
+        .into_iter()
+        .filter_map(|book_id| api.get_book_name(book_id))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// `[Romans, 1 Corinthians]`, quoting names YAML would read as something else
+fn flow_list(books: &[String]) -> String {
+    let items: Vec<String> = books
+        .iter()
+        .map(
+            |book| match book.contains([',', ':', '[', ']', '{', '}', '#', '"', '\'']) {
+                true => format!("\"{}\"", book.replace('"', "\\\"")),
+                false => book.clone(),
+            },
+        )
+        .collect();
+    format!("[{}]", items.join(", "))
+}
+
+/**
+The edit that makes the front matter's `field` list `books`
+
+- An existing field is replaced, along with the `- item` lines of a block list
+- Otherwise it is added at the end of the front matter, which is created if there is none
+- `None` when it is already up to date, or when there are no books and no field to empty
+*/
+pub fn tags_edit(text: &str, field: &str, books: &[String]) -> Option<TextEdit> {
+    let line_index = LineIndex::new(text);
+    let line = |number: usize| line_index.line(text, number).unwrap_or_default();
+    let new_line = format!("{field}: {}", flow_list(books));
+    let at = |line: u32, character: u32| Position { line, character };
+
+    let has_front_matter = line(0).trim_start_matches('\u{feff}').trim_end() == "---";
+    let closing = (1..line_index.line_count())
+        .find(|number| matches!(line(*number).trim_end(), "---" | "..."))
+        .filter(|_| has_front_matter);
+    let Some(closing) = closing else {
+        if books.is_empty() {
+            return None;
+        }
+        return Some(TextEdit {
+            range: Range::new(at(0, 0), at(0, 0)),
+            new_text: format!("---\n{new_line}\n---\n"),
+        });
+    };
+
+    let existing = (1..closing).find(|number| {
+        line(*number)
+            .split_once(':')
+            .is_some_and(|(key, _)| key.trim_end().trim_matches(['"', '\'']) == field)
+    });
+    let Some(start) = existing else {
+        if books.is_empty() {
+            return None;
+        }
+        return Some(TextEdit {
+            range: Range::new(at(closing as u32, 0), at(closing as u32, 0)),
+            new_text: format!("{new_line}\n"),
+        });
+    };
+    // the `- item` (or any indented) lines of a block list
+    let end = (start + 1..closing)
+        .find(|number| !line(*number).starts_with([' ', '\t', '-']))
+        .unwrap_or(closing)
+        - 1;
+    let old = (start..=end).map(line).collect::<Vec<_>>().join("\n");
+    if old == new_line {
+        return None;
+    }
+    let end_character = line(end).encode_utf16().count() as u32;
+    Some(TextEdit {
+        range: Range::new(at(start as u32, 0), at(end as u32, end_character)),
+        new_text: new_line,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(text: &str, books: &[&str]) -> Option<String> {
+        let books: Vec<String> = books.iter().map(|book| book.to_string()).collect();
+        let edit = tags_edit(text, "bible-books", &books)?;
+        let line_index = LineIndex::new(text);
+        let start = line_index.offset(text, edit.range.start).unwrap();
+        let end = line_index.offset(text, edit.range.end).unwrap();
+        Some(format!(
+            "{}{}{}",
+            &text[..start],
+            edit.new_text,
+            &text[end..]
+        ))
+    }
+
+    #[test]
+    fn tags_are_added_replaced_and_left_alone() {
+        assert_eq!(
+            apply("Rom 8:28\n", &["Romans"]).unwrap(),
+            "---\nbible-books: [Romans]\n---\nRom 8:28\n"
+        );
+        assert_eq!(
+            apply("---\ntitle: Grace\n---\nBody", &["Romans", "1 Corinthians"]).unwrap(),
+            "---\ntitle: Grace\nbible-books: [Romans, 1 Corinthians]\n---\nBody"
+        );
+        assert_eq!(
+            apply(
+                "---\nbible-books:\n  - Genesis\n  - Exodus\ntags: [x]\n---\n",
+                &["Romans"]
+            )
+            .unwrap(),
+            "---\nbible-books: [Romans]\ntags: [x]\n---\n"
+        );
+        assert_eq!(
+            apply("---\nbible-books: [Romans]\n---\n", &["Romans"]),
+            None
+        );
+        assert_eq!(apply("No references\n", &[]), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/**
+Chapters from licensed APIs kept on disk between sessions, see
+[`crate::config::RemoteConfig::disk_cache_chapters`]
+
+- One JSON file of verses per chapter, like `ESV/43.3.json`
+- Reading a chapter marks it as used, and once there are more than `capacity` (of every
+  translation together) the least recently used are dropped
+*/
+#[derive(Debug, Default)]
+pub struct ChapterCache {
+    dir: PathBuf,
+    capacity: usize,
+}
+
+impl ChapterCache {
+    pub fn new(dir: PathBuf, capacity: usize) -> Self {
+        Self { dir, capacity }
+    }
+
+    fn path(&self, translation: &str, book_id: usize, chapter: usize) -> PathBuf {
+        self.dir
+            .join(crate::paths::sanitize_file_name(
+                &translation.to_uppercase(),
+            ))
+            .join(format!("{book_id}.{chapter}.json"))
+    }
+
+    pub fn get(&self, translation: &str, book_id: usize, chapter: usize) -> Option<Vec<String>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let path = self.path(translation, book_id, chapter);
+        let verses = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        // when it was modified is when it was last used
+        _ = fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(verses)
+    }
+
+    pub fn put(
+        &self,
+        translation: &str,
+        book_id: usize,
+        chapter: usize,
+        verses: &[String],
+    ) -> io::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let path = self.path(translation, book_id, chapter);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string(verses)?)?;
+        self.evict()
+    }
+
+    fn evict(&self) -> io::Result<()> {
+        let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(&self.dir)?
+            .flatten()
+            .flat_map(|translation| fs::read_dir(translation.path()).into_iter().flatten())
+            .flatten()
+            .filter_map(|chapter| Some((chapter.metadata().ok()?.modified().ok()?, chapter.path())))
+            .collect();
+        if files.len() <= self.capacity {
+            return Ok(());
+        }
+        files.sort();
+        let excess = files.len() - self.capacity;
+        for (_, path) in files.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_least_recently_used_chapter_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ChapterCache::new(dir.path().to_path_buf(), 2);
+        let verses = |text: &str| vec![text.to_string()];
+        cache.put("esv", 43, 1, &verses("first")).unwrap();
+        cache.put("ESV", 43, 2, &verses("second")).unwrap();
+        assert_eq!(cache.get("esv", 43, 1), Some(verses("first")));
+        cache.put("KJV", 1, 1, &verses("third")).unwrap();
+        assert_eq!(cache.get("esv", 43, 2), None);
+        assert_eq!(cache.get("ESV", 43, 1), Some(verses("first")));
+        assert_eq!(cache.get("kjv", 1, 1), Some(verses("third")));
+
+        let off = ChapterCache::new(dir.path().to_path_buf(), 0);
+        assert_eq!(off.get("ESV", 43, 1), None);
+    }
+}
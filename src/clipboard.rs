@@ -0,0 +1,201 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crate::{
+    bible_formatter::{FormatContext, PassageFormatter},
+    bible_lsp::BibleLSP,
+};
+
+/// How often `bible_lsp clipboard` checks for copied text
+pub const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// What happens to copied text with references in it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// the copied text, then every passage after it
+    #[default]
+    Append,
+    /// just the passages
+    Replace,
+}
+
+/**
+- The copied text with the passages it cites, or `None` if it doesn't cite any
+- Each passage is formatted with `formatter`, and they are separated by blank lines
+*/
+pub fn expand(
+    lsp: &BibleLSP,
+    formatter: &PassageFormatter,
+    text: &str,
+    mode: Mode,
+) -> Option<String> {
+    let refs = lsp.find_book_references(text)?;
+    if refs.is_empty() {
+        return None;
+    }
+    let context = FormatContext::new(&lsp.api, "");
+    let passages = refs
+        .iter()
+        .map(|book_ref| formatter.format(&lsp.api, book_ref, &context))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Some(match mode {
+        Mode::Append => format!("{}\n\n{passages}", text.trim_end()),
+        Mode::Replace => passages,
+    })
+}
+
+/**
+The commands that read and write the clipboard on this platform
+
+- `pbpaste`/`pbcopy` on macOS, PowerShell on Windows, and `wl-paste`/`wl-copy` under Wayland or
+  `xclip` under X11 everywhere else
+- Companion tools rather than a clipboard library, like [`crate::extract`], so there is nothing
+  to link against
+*/
+fn tools() -> (Vec<&'static str>, Vec<&'static str>) {
+    if cfg!(target_os = "macos") {
+        (vec!["pbpaste"], vec!["pbcopy"])
+    } else if cfg!(windows) {
+        (
+            vec!["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"],
+            vec![
+                "powershell",
+                "-NoProfile",
+                "-Command",
+                "$input | Set-Clipboard",
+            ],
+        )
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        (vec!["wl-paste", "--no-newline"], vec!["wl-copy"])
+    } else {
+        (
+            vec!["xclip", "-selection", "clipboard", "-out"],
+            vec!["xclip", "-selection", "clipboard", "-in"],
+        )
+    }
+}
+
+fn spawn_error(program: &str, err: io::Error) -> io::Error {
+    io::Error::new(
+        err.kind(),
+        format!("Couldn't run {program} (is it installed?): {err}"),
+    )
+}
+
+pub fn read() -> io::Result<String> {
+    let (read, _) = tools();
+    let output = Command::new(read[0])
+        .args(&read[1..])
+        .output()
+        .map_err(|err| spawn_error(read[0], err))?;
+    // an empty clipboard is an error for some of the tools, so the status isn't checked
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn write(text: &str) -> io::Result<()> {
+    let (_, write) = tools();
+    let mut child = Command::new(write[0])
+        .args(&write[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|err| spawn_error(write[0], err))?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| io::Error::other("No stdin to write the clipboard to"))?
+        .write_all(text.as_bytes())?;
+    if !child.wait()?.success() {
+        return Err(io::Error::other(format!("{} failed", write[0])));
+    }
+    Ok(())
+}
+
+/**
+Expands references in whatever is copied, for writing outside the editor (like emails)
+
+- Polls the clipboard, since there is no portable way to be told when it changes
+- Text already in the clipboard when it starts is left alone, and so is what it wrote itself, so
+  it never expands the same text twice
+*/
+#[derive(Debug, Default)]
+pub struct Watcher {
+    /// the clipboard the last time it was checked
+    last: Option<String>,
+}
+
+impl Watcher {
+    /// The expanded text if the clipboard changed and cites something, after writing it back
+    pub fn poll(
+        &mut self,
+        lsp: &BibleLSP,
+        formatter: &PassageFormatter,
+        mode: Mode,
+    ) -> io::Result<Option<String>> {
+        let text = read()?;
+        let Some(expanded) = self.check(lsp, formatter, mode, text) else {
+            return Ok(None);
+        };
+        write(&expanded)?;
+        Ok(Some(expanded))
+    }
+
+    /// [`Watcher::poll`] without the clipboard, so it can be tested
+    fn check(
+        &mut self,
+        lsp: &BibleLSP,
+        formatter: &PassageFormatter,
+        mode: Mode,
+        text: String,
+    ) -> Option<String> {
+        let first = self.last.is_none();
+        // some tools add a line break when reading back what was written
+        if self
+            .last
+            .as_ref()
+            .is_some_and(|last| last.trim_end() == text.trim_end())
+        {
+            return None;
+        }
+        self.last = Some(text);
+        if first {
+            return None;
+        }
+        let expanded = expand(lsp, formatter, self.last.as_deref()?, mode)?;
+        self.last = Some(expanded.clone());
+        Some(expanded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copied_references_are_expanded_once() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let formatter = crate::bible_formatter::builtin("blockquote").unwrap();
+        let mut watcher = Watcher::default();
+        let check = |watcher: &mut Watcher, text: &str| {
+            watcher.check(&lsp, &formatter, Mode::Append, text.to_string())
+        };
+        // already copied before watching
+        assert_eq!(check(&mut watcher, "See Gen 1:1"), None);
+        assert_eq!(check(&mut watcher, "no references"), None);
+        let expanded = check(&mut watcher, "See Gen 1:2").unwrap();
+        assert_eq!(
+            expanded,
+            "See Gen 1:2\n\n> Text of Genesis 1:2.\n— Genesis 1:2"
+        );
+        // what it wrote itself
+        assert_eq!(check(&mut watcher, &format!("{expanded}\n")), None);
+        assert_eq!(
+            expand(&lsp, &formatter, "Gen 1:2", Mode::Replace).unwrap(),
+            "> Text of Genesis 1:2.\n— Genesis 1:2"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tower_lsp::jsonrpc;
+use tower_lsp::lsp_types::{
+    DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, TextDocumentEdit, TextEdit,
+    Url, WorkspaceEdit,
+};
+
+/// Inserts or updates the translation attribution block: `[uri]`
+pub const INSERT_ATTRIBUTION: &str = "bible.insertAttribution";
+
+/// Graph of documents and the passages they cite: `[format?]` where format is `"json"` (default)
+/// or `"dot"`
+pub const EXPORT_GRAPH: &str = "bible.exportGraph";
+
+/// Citation counts and percent cited per chapter and book: `[format?]` where format is `"json"`
+/// (default) or `"csv"`
+pub const COVERAGE: &str = "bible.coverage";
+
+/// Inserts or previews the verse after the reference at a position: `[uri, position, mode?]`
+/// where mode is `"insert"` (default) or `"preview"`
+pub const NEXT_VERSE: &str = "bible.nextVerse";
+
+/// Same as [`NEXT_VERSE`], but the verse before
+pub const PREV_VERSE: &str = "bible.prevVerse";
+
+/// Re-renders every quote wrapped in markers with the current translation and formatting: `[uri]`
+pub const REFRESH_QUOTES: &str = "bible.refreshQuotes";
+
+/**
+- Rewrites the Psalm citations in a document into another numbering, like after switching between
+  a translation numbered like the Hebrew and one numbered like the Greek: `[uri, to, from?]` where
+  `to` and `from` are `"hebrew"` or `"greek"`, and `from` defaults to the `psalmNumbering` setting
+- The edit needs confirmation, so clients that can preview it do, and the number of rewritten
+  citations is returned
+*/
+pub const RETARGET_REFERENCES: &str = "bible.retargetReferences";
+
+/**
+- Re-checks every quote wrapped in markers across the workspace against the current translation
+  text, like after installing a new edition: `[apply?]` where apply defaults to `false`
+- Only quotes of the loaded translation are compared, and the rest are counted as `skipped`
+- Returns `{ checked, skipped, changed: [{ uri, name, label, translation, range }], edit }`, where
+  `edit` refreshes every changed quote, and is applied too when `apply` is `true`
+*/
+pub const CHECK_QUOTES: &str = "bible.checkQuotes";
+
+/**
+- Brings the workspace along after switching translations: `[from?, apply?]` where from is the
+  abbreviation quotes were inserted in, and defaults to the translation loaded before the switch
+- Marked quotes in `from` are rendered again in the loaded translation, and attribution blocks
+  already in a document are updated, while quotes of any other translation are left alone
+- Returns `{ from, to, documents: [{ uri, name, quotes, attribution }], edit }`, and applies
+  `edit` unless `apply` is `false`
+*/
+pub const UPDATE_QUOTED_TRANSLATION: &str = "bible.updateQuotedTranslation";
+
+/// - Reports unknown variables and unclosed sections in formatter templates: `[formatter?]`
+///   where formatter is a formatter name or a whole formatter object
+/// - Without an argument, every configured formatter is checked
+/// - Returns problems keyed by formatter name, and formatters without problems are left out
+pub const LINT_TEMPLATE: &str = "bible.lintTemplate";
+
+/// - Converts between verse ids and references: `[text]` where text is a verse id like
+///   `ESV:49.1.3`, or text with references in it
+/// - Returns `{ id, reference }` for the id or every reference found
+pub const RESOLVE_ID: &str = "bible.resolveId";
+
+/// - Other workspace documents citing any passage a document cites: `[uri]`
+/// - Returns `{ uri, name, shared: [{ label, range, otherLabel, otherRange }] }` for each,
+///   documents sharing the most passages first
+pub const BACKLINKS: &str = "bible.backlinks";
+
+/// - Notes whose YAML front matter names a passage overlapping one in the text: `[text, field?]`
+///   where text is like `John 15` or `John 15:1-12`, and field narrows it to one front matter key
+/// - Returns `{ uri, name, passages: [{ field, label, range }] }` for each, sorted by name
+pub const NOTES_BY_PASSAGE: &str = "bible.notesByPassage";
+
+/// - Makes the front matter list of cited books current: `[uri]`
+/// - See [`crate::book_tags::BookTagsConfig`] for the field
+pub const UPDATE_BOOK_TAGS: &str = "bible.updateBookTags";
+
+/// - Inserts or updates the Scripture Index at the end of a document: `[uri]`
+/// - See [`crate::scripture_index`], and it is kept current on save after that
+pub const INSERT_SCRIPTURE_INDEX: &str = "bible.insertScriptureIndex";
+
+/**
+- Writes every chapter fetched from a remote translation to a Bible JSON file, so the passages
+  used most are still there offline: `[translation, path]`
+- Only for translations listed in `remote.allowExport`, and an earlier export at `path` is added
+  to rather than replaced
+- Refused like inserting when the translation's `quoteLimits` don't allow a full copy, or allow
+  fewer verses than the export would have
+- Returns `{ path, chapters }`, and the file can be loaded like any other translation
+*/
+pub const EXPORT_CACHE: &str = "bible.exportCache";
+
+/// - Loads another translation in place of the current one: `[path]` where path is a Bible JSON
+///   file
+/// - Lasts until the `translation` setting itself changes, and returns the new translation's
+///   `{ name, language, abbreviation }`
+pub const SET_TRANSLATION: &str = "bible.setTranslation";
+
+/**
+- Downloads a translation and loads it like [`SET_TRANSLATION`]: `[abbreviation]`, like `"KJV"`
+- It is converted and kept in the data directory, so it is only downloaded once, and is picked
+  up at startup when no `translation` is set
+- Returns the translation's `{ name, language, abbreviation }`
+*/
+pub const DOWNLOAD_TRANSLATION: &str = "bible.downloadTranslation";
+
+/// - Adds a passage to the end of the reading queue: `[label, uri?]` where uri is the document
+///   it came from
+/// - Attached to the "Add to reading queue" code action, and returns whether it wasn't queued
+///   already
+pub const QUEUE_PASSAGE: &str = "bible.queuePassage";
+
+/// - The reading queue, oldest first: `[]`
+/// - Returns `{ uri, passages: [{ label, source }] }`, where `uri` is the queue written out as a
+///   document, see [`crate::reading_queue::ReadingQueue::render`]
+pub const READING_QUEUE: &str = "bible.readingQueue";
+
+/// - Takes the oldest passage off the reading queue: `[]`
+/// - Returns `{ label, source }`, or `null` when the queue is empty
+pub const POP_READING_QUEUE: &str = "bible.popReadingQueue";
+
+/**
+- A bulleted outline of the section headings in a passage, to start a sermon or study outline
+  from: `[passage, uri?, position?]` where passage is like `Romans`, `John 3-4`, or `John 3:1-21`
+- Inserted at `position` in `uri` when they are given, see [`crate::headings::outline`]
+- Uses the translation's own headings when `headingsFile` has none
+- Returns `{ label, sections: [{ heading, label }], text }`
+*/
+pub const OUTLINE_PASSAGE: &str = "bible.outlinePassage";
+
+/**
+- Greek or Hebrew in SBL-style transliteration: `[text, uri?, range?]` where text is Greek or
+  Hebrew, or references whose verse text is, like `John 1:1 SBLGNT`
+- Replaces `range` in `uri` when they are given, which is what the "Transliterate selection"
+  code action does
+- Returns the transliterated text, see [`crate::transliterate::transliterate`]
+*/
+pub const TRANSLITERATE: &str = "bible.transliterate";
+
+/**
+- A passage written with a formatter, for the editor to put on the clipboard: `[passage,
+  formatter?]` where formatter is any formatter name and defaults to `insert`
+- `speech` writes it to be read aloud, like `Ephesians chapter one, verses one through four.`
+- Returns `{ label, text }`, with every reference in `passage` formatted and joined by blank lines
+- Refused when a translation's `quoteLimits` don't allow inserting that much of it
+*/
+pub const COPY_PASSAGE: &str = "bible.copyPassage";
+
+/// - Opens a chapter as a generated document: `[book_id, chapter]`
+/// - Linked from book hovers, and returns the chapter's URI
+pub const OPEN_CHAPTER: &str = "bible.openChapter";
+
+/// - Opens a book's introduction as a generated document: `[book_id]`, see
+///   [`crate::book_overview::intro`]
+/// - Linked from book hovers and book completions, and returns the introduction's URI
+pub const OPEN_BOOK_INTRO: &str = "bible.openBookIntro";
+
+/// - A snapshot of the server for debugging: `[]`
+/// - Returns `{ status, logLevel, logFile, roots, documents: [{ uri, version, languageId,
+///   detected, bytes, large, references }] }`, and writes it to the log file too
+pub const DUMP_STATE: &str = "bible.dumpState";
+
+/// - Attached to completion items so the server knows what was picked: `[book_id]`
+/// - Not meant to be run by hand
+pub const COMPLETION_ACCEPTED: &str = "bible.completionAccepted";
+
+/// - Stops detecting a phrase as a reference: `[phrase]`, see
+///   [`crate::ignored_phrases::IgnoredPhrases`]
+/// - Run by the "Ignore as a reference" code action, and returns whether it wasn't already ignored
+pub const IGNORE_REFERENCE: &str = "bible.ignoreReference";
+
+/// - The phrases that aren't detected as references: `[]`
+/// - Returns `{ path, phrases }`
+pub const IGNORED_REFERENCES: &str = "bible.ignoredReferences";
+
+/// - Detects ignored phrases as references again: `[phrase?]`, every one of them without a phrase
+/// - Returns how many were removed
+pub const UNIGNORE_REFERENCES: &str = "bible.unignoreReferences";
+
+/// Every command advertised in `executeCommandProvider`
+pub const ALL: &[&str] = &[
+    INSERT_ATTRIBUTION,
+    EXPORT_GRAPH,
+    COVERAGE,
+    COMPLETION_ACCEPTED,
+    NEXT_VERSE,
+    PREV_VERSE,
+    REFRESH_QUOTES,
+    CHECK_QUOTES,
+    UPDATE_QUOTED_TRANSLATION,
+    RETARGET_REFERENCES,
+    LINT_TEMPLATE,
+    RESOLVE_ID,
+    BACKLINKS,
+    NOTES_BY_PASSAGE,
+    UPDATE_BOOK_TAGS,
+    INSERT_SCRIPTURE_INDEX,
+    QUEUE_PASSAGE,
+    READING_QUEUE,
+    POP_READING_QUEUE,
+    OUTLINE_PASSAGE,
+    TRANSLITERATE,
+    COPY_PASSAGE,
+    SET_TRANSLATION,
+    DOWNLOAD_TRANSLATION,
+    EXPORT_CACHE,
+    DUMP_STATE,
+    OPEN_CHAPTER,
+    OPEN_BOOK_INTRO,
+    IGNORE_REFERENCE,
+    IGNORED_REFERENCES,
+    UNIGNORE_REFERENCES,
+];
+
+/// Deserializes the command argument at `index`, with an error the user can act on
+pub fn argument<T: DeserializeOwned>(arguments: &[Value], index: usize) -> jsonrpc::Result<T> {
+    let value = arguments.get(index).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value)
+        .map_err(|err| jsonrpc::Error::invalid_params(format!("Invalid argument {index}: {err}")))
+}
+
+/// Wraps edits to a single document the same way the code actions do
+pub fn document_edit(uri: Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    documents_edit(vec![(uri, edits)])
+}
+
+/// [`document_edit`] for several documents at once
+pub fn documents_edit(documents: Vec<(Url, Vec<TextEdit>)>) -> WorkspaceEdit {
+    WorkspaceEdit {
+        changes: None,
+        document_changes: Some(DocumentChanges::Edits(
+            documents
+                .into_iter()
+                .map(|(uri, edits)| TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+                    edits: edits.into_iter().map(OneOf::Left).collect(),
+                })
+                .collect(),
+        )),
+        change_annotations: None,
+    }
+}
+
+/// - A markdown link target that runs a command, like `command:bible.openChapter?%5B49%2C2%5D`
+/// - Editors only follow these in content they trust, like hovers from a language server
+pub fn command_uri(command: &str, arguments: &Value) -> String {
+    let encoded: String = arguments
+        .to_string()
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect();
+    format!("command:{command}?{encoded}")
+}
@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Mutex;
+
+use crate::{
+    autocompletion::{BibleCompletion, ChapterCompletion},
+    bible_api::BibleAPI,
+    book_reference::BookReference,
+};
+
+/// How many accepted completions are remembered
+const RECENT_CAPACITY: usize = 16;
+
+/// - Books the user recently picked from completion, most recent first
+/// - Recorded by the `bible.completionAccepted` command attached to every completion item
+#[derive(Debug, Default)]
+pub struct RecentBooks {
+    books: Mutex<VecDeque<usize>>,
+}
+
+impl RecentBooks {
+    pub fn record(&self, book_id: usize) {
+        let mut books = self.books.lock().unwrap();
+        books.retain(|recent| *recent != book_id);
+        books.push_front(book_id);
+        books.truncate(RECENT_CAPACITY);
+    }
+
+    pub fn books(&self) -> Vec<usize> {
+        self.books.lock().unwrap().iter().copied().collect()
+    }
+}
+
+/**
+What the user has been writing about, used to rank completions
+
+- Writing a Romans study should suggest Romans before Ruth once Romans has been cited a few times
+- Only the order changes (through `sort_text`), nothing is filtered out
+*/
+#[derive(Debug, Default)]
+pub struct CompletionContext {
+    /// book -> citations in the current document
+    cited_books: BTreeMap<usize, usize>,
+    cited_chapters: BTreeSet<(usize, usize)>,
+    recent_books: Vec<usize>,
+}
+
+impl CompletionContext {
+    pub fn new(refs: &[BookReference], recent_books: Vec<usize>) -> Self {
+        let mut context = Self {
+            recent_books,
+            ..Default::default()
+        };
+        for book_ref in refs {
+            *context.cited_books.entry(book_ref.book_id).or_default() += 1;
+            for seg in book_ref.segments.iter() {
+                for chapter in seg.get_starting_chapter()..=seg.get_ending_chapter() {
+                    context.cited_chapters.insert((book_ref.book_id, chapter));
+                }
+            }
+        }
+        context
+    }
+
+    /// - Books cited in the document and recently picked come first, then just cited, then just
+    ///   recent, then everything else in the translation's order
+    /// - Chapters already cited come before the rest, but still after verses
+    pub fn sort_text(&self, api: &BibleAPI, item: &BibleCompletion) -> String {
+        let sort_text = item.lsp_sort(api);
+        match item {
+            BibleCompletion::BookName(book) => {
+                let cited = self.cited_books.contains_key(&book.book_id);
+                let recent = self.recent_books.contains(&book.book_id);
+                let tier = match (cited, recent) {
+                    (true, true) => 'a',
+                    (true, false) => 'b',
+                    (false, true) => 'c',
+                    (false, false) => 'd',
+                };
+                format!("{tier}{sort_text}")
+            }
+            BibleCompletion::Chapter(ChapterCompletion { book_id, chapter }) => {
+                let tier = match self.cited_chapters.contains(&(*book_id, *chapter)) {
+                    true => 'a',
+                    false => 'b',
+                };
+                // keep the `z` prefix so verses still come first
+                format!("z{tier}{}", &sort_text[1..])
+            }
+            BibleCompletion::Verse(_) => sort_text,
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+use crate::{
+    bible_formatter::PassageFormatter, book_reference::FormatOptions,
+    book_reference_segment::LabelSeparators, book_tags::BookTagsConfig,
+    detection_context::DetectionContext, paths, trace, versification::PsalmNumbering,
+};
+
+/// - Settings sent by the client in `initializationOptions`
+/// - Every field has a default, so clients only need to send what they want to change
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    /// - The Bible JSON file to load, or a directory of USFM files (see [`crate::bible_usfm`])
+    /// - [`default_translation`] when it isn't set
+    pub translation: Option<PathBuf>,
+    /// - More Bible JSON files to load next to `translation`, which stays the default
+    /// - References are still recognized by the default translation's book names
+    pub translations: Vec<PathBuf>,
+    pub features: Features,
+    /// - Quotation limits keyed by translation abbreviation (`"ESV"`)
+    /// - Empty by default, so nothing is checked unless the user opts in
+    pub quote_limits: BTreeMap<String, QuoteLimit>,
+    /// Attribution text keyed by translation abbreviation, overriding the one in the data file
+    pub attributions: BTreeMap<String, String>,
+    pub index: IndexConfig,
+    /// - Extra book names to recognize, see [`crate::alias_packs::PACKS`]
+    /// - `archaic` is off by default since names like `Jonas` are also common words in some
+    ///   languages
+    /// - `misspellings` is off by default too, since it accepts `Galations 2:20` as it is instead
+    ///   of offering to fix the spelling (see [`crate::spelling`])
+    /// - `additions` (`Susanna`, `Bel and the Dragon`) only adds names for translations that have
+    ///   those chapters, see [`crate::additions`]
+    pub alias_packs: Vec<String>,
+    /// - Book abbreviations of your own, with the book's name, abbreviation, or id, like
+    ///   `{ "Gn": "Genesis", "Php": "Philippians" }`
+    /// - These win over the translation's names and the alias packs
+    pub aliases: BTreeMap<String, String>,
+    /// Wrap inserted quotes in HTML comments so `bible.refreshQuotes` can update them later
+    pub quote_markers: bool,
+    /// - Formatter templates by name, see [`PassageFormatter`]
+    /// - A formatter named like a built-in one (`callout`, `insert`, ...) or a template file (see
+    ///   [`crate::templates`]) replaces it
+    pub formatters: BTreeMap<String, PassageFormatter>,
+    /// - The order of the formatter code actions, and the first one is marked preferred
+    /// - Formatters that aren't listed come after, sorted by name
+    pub formatter_order: Vec<String>,
+    /// - A second Bible JSON file to compare hovers against, for spotting wording that is
+    ///   translation-sensitive while writing
+    /// - Hovers show the words it translates differently in bold, with its wording after them,
+    ///   instead of using `hover_formatter`
+    pub compare_translation: Option<PathBuf>,
+    /// - The formatter hovers are rendered with, instead of a heading and the verses
+    /// - Hovers are cached by reference, so `{file_name}` and `{date}` are from the first hover
+    pub hover_formatter: Option<String>,
+    /// What the default hover shows, when there is no `hover_formatter`
+    pub hover: FormatOptions,
+    pub limits: Limits,
+    pub timeouts: Timeouts,
+    /// - How Psalms are numbered in citations, see [`PsalmNumbering`]
+    /// - `Psalm 51(50)` style citations are read either way, with this numbering first
+    pub psalm_numbering: PsalmNumbering,
+    /// - Gitignore-style patterns of workspace files to leave out of the index, like
+    ///   `private/` or `*.draft.md`
+    /// - `.gitignore` and `.ignore` files are always honored, so this is for what they don't cover
+    pub ignore_globs: Vec<String>,
+    /// How references are written in code action titles, see [`LabelSeparators`]
+    pub label_separators: LabelSeparators,
+    /// - Megabytes the hover cache and workspace index may hold, see
+    ///   [`crate::memory_budget::MemoryBudget`]
+    /// - For low-memory machines, unlimited by default
+    pub memory_budget_mb: Option<usize>,
+    /// - Where in a document references are recognized, like only in `[[wikilinks]]`, see
+    ///   [`DetectionContext`]
+    /// - Empty (the default) for everywhere
+    pub contexts: Vec<DetectionContext>,
+    /// Which open documents references are detected in, see [`DocumentsConfig`]
+    pub documents: DocumentsConfig,
+    pub diagnostics: DiagnosticsConfig,
+    pub completion: CompletionConfig,
+    /// - YAML front matter keys whose values are passages, like `passage: John 15:1-12`
+    /// - Values are checked, can be whole chapters (`John 15`), and are what
+    ///   `bible.notesByPassage` searches, see [`crate::front_matter`]
+    pub front_matter_fields: Vec<String>,
+    /// A front matter list of the books each note cites, see [`BookTagsConfig`]
+    pub book_tags: BookTagsConfig,
+    /// - What goes into the log file, see [`trace::Level`]
+    /// - `bible.dumpState` is there for a snapshot without turning it up
+    pub log_level: trace::Level,
+    /// What the inlay hints after references show, when `features.inlayHints` is on
+    pub inlay_hints: InlayHintsConfig,
+    /// - Rewrites every reference the way `label_separators` writes them when a document is saved,
+    ///   like `eph 1:1 -4` to `Ephesians 1:1-4`
+    /// - Only for saves the user asked for, not autosaves after a delay or losing focus
+    /// - Off by default, and sent in `initialize`, so changing it needs a restart
+    pub autocorrect_on_save: bool,
+    /// - Keeps what was generated from the document current when it is saved: the Scripture Index
+    ///   (see [`crate::scripture_index`]) and quotes of the loaded translation wrapped in markers
+    /// - Only where they already are, and only for saves the user asked for
+    /// - Sent in `initialize`, so changing it needs a restart
+    pub refresh_on_save: bool,
+    /// Translations fetched from a web API, see [`RemoteConfig`]
+    pub remote: RemoteConfig,
+    /// - Where `bible.downloadTranslation` downloads translations from, with `{translation}` filled
+    ///   in, see [`crate::scaffold::install_translation`]
+    /// - bolls.life by default
+    pub download_source: String,
+    /// - Where the reading queue is kept, see [`crate::reading_queue::ReadingQueue`]
+    /// - `reading-queue.json` in the data directory by default, and a file in a synced folder
+    ///   shares it between machines
+    pub reading_queue_file: Option<PathBuf>,
+    /// - Section headings for `bible.outlinePassage` and book introductions, see
+    ///   [`crate::headings::Headings`]
+    /// - `headings.json` in the data directory by default, and the translation's own headings
+    ///   without one
+    pub headings_file: Option<PathBuf>,
+    /// - Cross references shown after single verses in hovers, see
+    ///   `crate::cross_references::CrossReferences` and [`FormatOptions::cross_references`]
+    /// - `cross-references.tsv` in the data directory by default, and nothing is shown without one
+    pub cross_references_file: Option<PathBuf>,
+    /// - Where phrases ignored as references are kept, see
+    ///   [`crate::ignored_phrases::IgnoredPhrases`]
+    /// - `.bible_lsp_ignored.json` in the first workspace folder by default
+    pub ignored_phrases_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            translation: None,
+            translations: vec![],
+            features: Default::default(),
+            quote_limits: Default::default(),
+            attributions: Default::default(),
+            index: Default::default(),
+            alias_packs: ["variants", "numbering", "additions"]
+                .map(String::from)
+                .to_vec(),
+            aliases: Default::default(),
+            quote_markers: false,
+            formatters: Default::default(),
+            formatter_order: ["callout", "insert", "replace"].map(String::from).to_vec(),
+            compare_translation: None,
+            hover_formatter: None,
+            hover: Default::default(),
+            limits: Default::default(),
+            timeouts: Default::default(),
+            psalm_numbering: Default::default(),
+            ignore_globs: Default::default(),
+            label_separators: Default::default(),
+            memory_budget_mb: None,
+            contexts: Default::default(),
+            documents: Default::default(),
+            diagnostics: Default::default(),
+            completion: Default::default(),
+            front_matter_fields: ["passage", "passages", "scripture"]
+                .map(String::from)
+                .to_vec(),
+            book_tags: Default::default(),
+            log_level: Default::default(),
+            inlay_hints: Default::default(),
+            autocorrect_on_save: false,
+            refresh_on_save: true,
+            remote: Default::default(),
+            download_source: crate::scaffold::DEFAULT_DOWNLOAD_SOURCE.to_string(),
+            reading_queue_file: None,
+            headings_file: None,
+            cross_references_file: None,
+            ignored_phrases_file: None,
+        }
+    }
+}
+
+/**
+The translation to load when the client doesn't set one
+
+- `$BIBLE_LSP_TRANSLATION`, then `translation` in the user's config file (see
+  [`crate::config_file::user_file`]), then the first JSON file in [`paths::translations_dir`],
+  which is where `bible_lsp init --download` puts them
+- The CLI subcommands use this too
+*/
+pub fn default_translation() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("BIBLE_LSP_TRANSLATION").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let settings = crate::config_file::load(&crate::config_file::user_file());
+    if let Some(path) = settings
+        .ok()
+        .flatten()
+        .and_then(|settings| Some(PathBuf::from(settings.get("translation")?.as_str()?)))
+    {
+        return Some(path);
+    }
+    let mut translations: Vec<PathBuf> = std::fs::read_dir(paths::translations_dir())
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    translations.sort();
+    translations.into_iter().next()
+}
+
+/// - Keeps huge files (like logs) from hanging every request
+/// - Past these, a warning diagnostic says what was left out
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Limits {
+    /// - In bytes
+    /// - Larger documents only have the lines hovers and code actions ask about parsed
+    pub max_document_size: usize,
+    /// references past this many are ignored
+    pub max_references: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_document_size: 2_000_000,
+            max_references: 5_000,
+        }
+    }
+}
+
+/**
+- Soft time limits in milliseconds, after which a handler returns what it has so far, see
+  [`crate::deadline::Deadline`]
+- Hovers show the passages rendered so far, and the others return the references found so far
+- A log message says when one is hit, and `0` turns a limit off
+*/
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Timeouts {
+    pub hover: u64,
+    pub diagnostics: u64,
+    pub document_symbols: u64,
+    pub inlay_hints: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            hover: 500,
+            diagnostics: 2_000,
+            document_symbols: 1_000,
+            inlay_hints: 500,
+        }
+    }
+}
+
+/**
+Translations whose text is fetched from a web API, a chapter at a time
+
+- Ex: `{ "translations": { "YLT": "https://bolls.life/get-text/YLT/{book}/{chapter}/" } }`
+- Cited like loaded ones, with the abbreviation after the reference (`John 3:16 YLT`), and their
+  references and verse counts are the default translation's
+- Needs a build with the `remote` feature, see [`crate::remote_source::RemoteSource`]
+*/
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RemoteConfig {
+    /// - URL templates by abbreviation, with `{book}` (the book id, Genesis is 1), `{chapter}`,
+    ///   and `{translation}` filled in
+    /// - The response is a JSON array of the chapter's verses, as strings or as objects with a
+    ///   `text`
+    pub translations: BTreeMap<String, String>,
+    /// - Requests allowed a day, for APIs with a quota
+    /// - An `X-RateLimit-Remaining` header in responses is respected either way
+    pub daily_limit: Option<usize>,
+    /// - Remote translations whose license allows keeping a copy, for `bible.exportCache`
+    /// - Empty by default, since most APIs only allow showing the text
+    pub allow_export: Vec<String>,
+    /// - Translations from APIs that need a key, by abbreviation, like `{ "ESV": { "api": "esv",
+    ///   "apiKey": "..." } }`
+    /// - Cited like the ones in `translations`, and counted against the same quota
+    pub licensed: BTreeMap<String, LicensedApi>,
+    /// - How many chapters from `licensed` APIs are kept on disk between sessions, dropping the
+    ///   least recently used first, see [`crate::chapter_cache::ChapterCache`]
+    /// - 15 by default, about 400 verses, since the ESV's terms allow keeping 500, and 0 keeps
+    ///   nothing
+    pub disk_cache_chapters: usize,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            translations: Default::default(),
+            daily_limit: None,
+            allow_export: vec![],
+            licensed: Default::default(),
+            disk_cache_chapters: 15,
+        }
+    }
+}
+
+/// An API that needs a key, for [`RemoteConfig::licensed`]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "api", rename_all = "camelCase")]
+pub enum LicensedApi {
+    /// The ESV from api.esv.org
+    #[serde(rename_all = "camelCase")]
+    Esv {
+        api_key: String,
+        /// in place of `https://api.esv.org`, like a proxy
+        endpoint: Option<String>,
+    },
+    /// Any of the translations on api.bible, by its id there
+    #[serde(rename_all = "camelCase")]
+    ApiBible {
+        api_key: String,
+        bible_id: String,
+        /// in place of `https://api.scripture.api.bible`, like a proxy
+        endpoint: Option<String>,
+    },
+}
+
+/// Which workspace files are scanned for references
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct IndexConfig {
+    /// file extensions without the dot
+    pub extensions: Vec<String>,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            extensions: ["md", "markdown", "txt"].map(String::from).to_vec(),
+        }
+    }
+}
+
+/// How references are completed as they are typed
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompletionConfig {
+    /// - Characters that ask for suggestions as they are typed
+    /// - Sent in `initialize`, so changing them needs a restart
+    pub trigger_characters: Vec<String>,
+    /// - The most suggestions sent at once, for lists like the 150 chapters of Psalms
+    /// - A cut list is marked incomplete, so the editor asks again as more is typed
+    /// - `null` (the default) sends them all
+    pub max_items: Option<usize>,
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        Self {
+            trigger_characters: [",", ";", "-", ":", " "].map(String::from).to_vec(),
+            max_items: None,
+        }
+    }
+}
+
+/**
+The diagnostic under every reference, with the text of its first verse
+
+- `features.diagnostics` turns off every diagnostic, and needs a restart
+- These only change the one under references, and misspellings and quote checks are still
+  reported with them off
+*/
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiagnosticsConfig {
+    pub enabled: bool,
+    pub severity: DiagnosticLevel,
+    /// - Characters of verse text to show, with an ellipsis when it is cut off
+    /// - `0` shows just the reference, and `null` (the default) the whole verse
+    pub preview_length: Option<usize>,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            severity: DiagnosticLevel::Information,
+            preview_length: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InlayHintsConfig {
+    pub mode: InlayHintMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InlayHintMode {
+    /// the text of the first verse
+    #[default]
+    Text,
+    /// - The reference written out in full, like `Ephesians 1:1–4` after `eph. 1.1-4`, see
+    ///   [`LabelSeparators::canonical`]
+    /// - Only after references not already written that way, and double-clicking it (in editors
+    ///   that can) rewrites the reference
+    Canonical,
+}
+
+/// - How references are marked, from least to most noticeable
+/// - Most editors only show hints as faint dots, which is less noisy in long notes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticLevel {
+    Hint,
+    #[default]
+    #[serde(alias = "info")]
+    Information,
+    Warning,
+}
+
+impl From<DiagnosticLevel> for DiagnosticSeverity {
+    fn from(level: DiagnosticLevel) -> Self {
+        match level {
+            DiagnosticLevel::Hint => DiagnosticSeverity::HINT,
+            DiagnosticLevel::Information => DiagnosticSeverity::INFORMATION,
+            DiagnosticLevel::Warning => DiagnosticSeverity::WARNING,
+        }
+    }
+}
+
+/// - Language IDs (`markdown`) or globs of paths (`**/*.md`), for people who only want references
+///   in their notes and not in source code
+/// - Ex: `{ "include": ["markdown", "plaintext"] }`
+/// - See [`crate::document_filter::DocumentFilter`]
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DocumentsConfig {
+    /// empty for every document
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// - Per-handler toggles
+/// - Disabled features are not advertised in `initialize`, so the client never asks for them
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Features {
+    pub hover: bool,
+    pub completion: bool,
+    pub diagnostics: bool,
+    pub definition: bool,
+    pub code_actions: bool,
+    pub document_symbols: bool,
+    /// off by default because showing verse text after every reference gets noisy
+    pub inlay_hints: bool,
+    /// - An inlay hint like `cited 4× in workspace` after references other places also cite
+    /// - Off by default for the same reason as `inlay_hints`
+    pub citation_counts: bool,
+    /// - Book names and chapter/verse numbers highlighted in the part of a document on screen
+    /// - Off by default since it recolors text the editor's own syntax highlighting already did
+    pub semantic_tokens: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            hover: true,
+            completion: true,
+            diagnostics: true,
+            definition: true,
+            code_actions: true,
+            document_symbols: true,
+            inlay_hints: false,
+            citation_counts: false,
+            semantic_tokens: false,
+        }
+    }
+}
+
+/// - Many translations only allow quoting so much without written permission
+/// - Ex: the ESV allows up to 500 verses, as long as that is less than half of any book
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct QuoteLimit {
+    /// - Total distinct verses quoted
+    /// - Only references followed by their text or in quote markers count, not bare citations
+    pub max_verses: Option<usize>,
+    /// percent (0-100) of any single book quoted
+    pub max_book_percent: Option<f64>,
+    /// - Whether the insert and replace code actions and `bible.copyPassage` can write out the
+    ///   translation's text at all, for ones that can only be cited
+    /// - `true` by default
+    pub allow_full_insert: bool,
+    /// most verses one passage can have to be inserted or copied
+    pub max_insert_verses: Option<usize>,
+    /// - Counts the verses quoted in every indexed document of the workspace towards
+    ///   `maxVerses` and `maxBookPercent`, not just the ones in the document being checked
+    /// - Off by default, and only with the `search` feature
+    pub workspace: bool,
+}
+
+impl Default for QuoteLimit {
+    fn default() -> Self {
+        Self {
+            max_verses: None,
+            max_book_percent: None,
+            allow_full_insert: true,
+            max_insert_verses: None,
+            workspace: false,
+        }
+    }
+}
+
+impl Config {
+    /// Translation abbreviations are matched case-insensitively
+    pub fn quote_limit(&self, translation: &str) -> Option<&QuoteLimit> {
+        self.quote_limits
+            .iter()
+            .find(|(abbreviation, _)| abbreviation.eq_ignore_ascii_case(translation))
+            .map(|(_, limit)| limit)
+    }
+
+    pub fn attribution(&self, translation: &str) -> Option<&String> {
+        self.attributions
+            .iter()
+            .find(|(abbreviation, _)| abbreviation.eq_ignore_ascii_case(translation))
+            .map(|(_, attribution)| attribution)
+    }
+
+    /// - Accepts either the settings object itself or one nested under a `bible` key (which is
+    ///   how most editors namespace settings)
+    /// - `null`/missing options give the default config
+    pub fn from_value(value: Option<Value>) -> serde_json::Result<Self> {
+        match value.map(unnest) {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value),
+        }
+    }
+}
+
+/// The settings object itself, when they are nested under a `bible` key
+pub fn unnest(value: Value) -> Value {
+    match value {
+        Value::Object(mut map) if map.contains_key("bible") => {
+            map.remove("bible").expect("Key was just checked")
+        }
+        value => value,
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::{
+    config::{self, Config},
+    error::{self, Error},
+    paths, scaffold,
+};
+
+/// Settings for every workspace, in [`paths::config_dir`]
+pub const USER_FILE: &str = "config.toml";
+
+/// - Settings for one workspace, at the root of its folder
+/// - `bible_lsp init` writes the second one, and the first one found wins
+pub const WORKSPACE_FILES: [&str; 2] = [".bible_lsp.toml", scaffold::CONFIG_FILE];
+
+/// Ex: `~/.config/bible_lsp/config.toml`
+pub fn user_file() -> PathBuf {
+    paths::config_dir().join(USER_FILE)
+}
+
+/// The config file of a workspace folder, if it has one
+pub fn workspace_file(root: &Path) -> Option<PathBuf> {
+    WORKSPACE_FILES
+        .iter()
+        .map(|name| root.join(name))
+        .find(|path| path.is_file())
+}
+
+/**
+The settings in a TOML config file, as if they were sent in `initializationOptions`
+
+- `None` if there is no file
+- Relative `translation` and `translations` paths are resolved against the file's folder, so a
+  vault can bring its own
+- Keys and types are checked against [`Config`] here, so mistakes point at the line in the file
+*/
+pub fn load(path: &Path) -> error::Result<Option<Value>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::read(path, err)),
+    };
+    if let Err(err) = toml::from_str::<Config>(&text) {
+        return Err(Error::config(path, text, err));
+    }
+    let table = match toml::from_str::<toml::Table>(&text) {
+        Ok(table) => table,
+        Err(err) => return Err(Error::config(path, text, err)),
+    };
+    let mut settings = serde_json::to_value(table).unwrap_or_default();
+    if let Some(dir) = path.parent() {
+        if let Some(translation) = settings.get_mut("translation") {
+            resolve(translation, dir);
+        }
+        if let Some(Value::Array(translations)) = settings.get_mut("translations") {
+            translations
+                .iter_mut()
+                .for_each(|translation| resolve(translation, dir));
+        }
+    }
+    Ok(Some(settings))
+}
+
+/// A relative path as one in `dir`
+fn resolve(path: &mut Value, dir: &Path) {
+    if let Some(relative) = path.as_str().filter(|path| Path::new(path).is_relative()) {
+        *path = Value::from(dir.join(relative).display().to_string());
+    }
+}
+
+/// - Copies `overlay` onto `base`, going into tables so one layer can change a single
+///   `[limits]` value without repeating the rest
+/// - Anything else (including arrays) is replaced whole
+/// - `null`s are skipped, since editors send them for settings that were left unset
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (_, Value::Null) => {}
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                if value.is_null() {
+                    continue;
+                }
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/**
+The settings for a session, with later layers overriding earlier ones
+
+1. the user's [`user_file`]
+2. the [`workspace_file`] of each workspace folder
+3. the `initializationOptions` from the editor
+
+- The same settings work in every editor this way, and editor settings still win
+- Files that can't be loaded are skipped, and returned so the user can be told
+*/
+pub fn layered(roots: &[PathBuf], options: Option<Value>) -> (Option<Value>, Vec<Error>) {
+    let mut files = vec![user_file()];
+    files.extend(roots.iter().filter_map(|root| workspace_file(root)));
+    let mut layers = vec![];
+    let mut problems = vec![];
+    for path in files {
+        match load(&path) {
+            Ok(layer) => layers.extend(layer),
+            Err(err) => problems.push(err),
+        }
+    }
+    layers.extend(options.map(config::unnest));
+    let settings = layers.into_iter().reduce(|mut settings, layer| {
+        merge(&mut settings, layer);
+        settings
+    });
+    (settings, problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn editor_settings_override_files() {
+        let vault = tempfile::tempdir().unwrap();
+        fs::write(
+            vault.path().join(".bible_lsp.toml"),
+            "translation = \"esv.json\"\ntranslations = [\"kjv.json\", \"/bibles/nasb.json\"]\nquoteMarkers = true\n\n[limits]\nmaxReferences = 10\n",
+        )
+        .unwrap();
+        let options = json!({ "bible": { "limits": { "maxDocumentSize": 100 } } });
+        let (settings, problems) = layered(&[vault.path().to_path_buf()], Some(options));
+        assert!(problems.is_empty());
+        let config = Config::from_value(settings).unwrap();
+        assert_eq!(config.translation, Some(vault.path().join("esv.json")));
+        assert_eq!(
+            config.translations,
+            [
+                vault.path().join("kjv.json"),
+                PathBuf::from("/bibles/nasb.json")
+            ]
+        );
+        assert!(config.quote_markers);
+        assert_eq!(config.limits.max_references, 10);
+        assert_eq!(config.limits.max_document_size, 100);
+    }
+
+    #[test]
+    fn unset_editor_settings_keep_the_files() {
+        let vault = tempfile::tempdir().unwrap();
+        fs::write(
+            vault.path().join(".bible_lsp.toml"),
+            "translation = \"esv.json\"\n\n[limits]\nmaxReferences = 10\n",
+        )
+        .unwrap();
+        let options = json!({ "translation": null, "limits": { "maxReferences": null }, "translations": null });
+        let (settings, problems) = layered(&[vault.path().to_path_buf()], Some(options));
+        assert!(problems.is_empty());
+        let config = Config::from_value(settings).unwrap();
+        assert_eq!(config.translation, Some(vault.path().join("esv.json")));
+        assert_eq!(config.limits.max_references, 10);
+    }
+
+    #[test]
+    fn mistakes_point_at_the_line() {
+        let vault = tempfile::tempdir().unwrap();
+        let path = vault.path().join(".bible_lsp.toml");
+        fs::write(
+            &path,
+            "quoteMarkers = true\n\n[limits]\nmaxReferences = \"many\"\n",
+        )
+        .unwrap();
+        let err = load(&path).unwrap_err();
+        let summary = error::summary(&err);
+        assert!(summary.ends_with("on line 4"), "{summary}");
+        assert_eq!(load(&vault.path().join("missing.toml")).unwrap(), None);
+    }
+}
@@ -0,0 +1,1629 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use cached::proc_macro::cached;
+use regex::Regex;
+
+use crate::bible_json::{JSONBible, JSONBook, JSONTranslation, VerseLayout};
+use crate::error::{self, Error};
+
+/**
+`(id, USFM code, OSIS id, English name)` of every book
+
+- The 66 books of the Protestant canon, then the deuterocanonical books numbered after them in
+  the order Paratext uses, so Catholic and Orthodox texts convert without losing books
+- Translations can have any of them, and the loaded one's books are what is detected, see
+  [`crate::bible_api::BibleAPI::canon_order`]
+*/
+pub const BOOKS: &[(usize, &str, &str, &str)] = &[
+    (1, "GEN", "Gen", "Genesis"),
+    (2, "EXO", "Exod", "Exodus"),
+    (3, "LEV", "Lev", "Leviticus"),
+    (4, "NUM", "Num", "Numbers"),
+    (5, "DEU", "Deut", "Deuteronomy"),
+    (6, "JOS", "Josh", "Joshua"),
+    (7, "JDG", "Judg", "Judges"),
+    (8, "RUT", "Ruth", "Ruth"),
+    (9, "1SA", "1Sam", "1 Samuel"),
+    (10, "2SA", "2Sam", "2 Samuel"),
+    (11, "1KI", "1Kgs", "1 Kings"),
+    (12, "2KI", "2Kgs", "2 Kings"),
+    (13, "1CH", "1Chr", "1 Chronicles"),
+    (14, "2CH", "2Chr", "2 Chronicles"),
+    (15, "EZR", "Ezra", "Ezra"),
+    (16, "NEH", "Neh", "Nehemiah"),
+    (17, "EST", "Esth", "Esther"),
+    (18, "JOB", "Job", "Job"),
+    (19, "PSA", "Ps", "Psalms"),
+    (20, "PRO", "Prov", "Proverbs"),
+    (21, "ECC", "Eccl", "Ecclesiastes"),
+    (22, "SNG", "Song", "Song of Solomon"),
+    (23, "ISA", "Isa", "Isaiah"),
+    (24, "JER", "Jer", "Jeremiah"),
+    (25, "LAM", "Lam", "Lamentations"),
+    (26, "EZK", "Ezek", "Ezekiel"),
+    (27, "DAN", "Dan", "Daniel"),
+    (28, "HOS", "Hos", "Hosea"),
+    (29, "JOL", "Joel", "Joel"),
+    (30, "AMO", "Amos", "Amos"),
+    (31, "OBA", "Obad", "Obadiah"),
+    (32, "JON", "Jonah", "Jonah"),
+    (33, "MIC", "Mic", "Micah"),
+    (34, "NAM", "Nah", "Nahum"),
+    (35, "HAB", "Hab", "Habakkuk"),
+    (36, "ZEP", "Zeph", "Zephaniah"),
+    (37, "HAG", "Hag", "Haggai"),
+    (38, "ZEC", "Zech", "Zechariah"),
+    (39, "MAL", "Mal", "Malachi"),
+    (40, "MAT", "Matt", "Matthew"),
+    (41, "MRK", "Mark", "Mark"),
+    (42, "LUK", "Luke", "Luke"),
+    (43, "JHN", "John", "John"),
+    (44, "ACT", "Acts", "Acts"),
+    (45, "ROM", "Rom", "Romans"),
+    (46, "1CO", "1Cor", "1 Corinthians"),
+    (47, "2CO", "2Cor", "2 Corinthians"),
+    (48, "GAL", "Gal", "Galatians"),
+    (49, "EPH", "Eph", "Ephesians"),
+    (50, "PHP", "Phil", "Philippians"),
+    (51, "COL", "Col", "Colossians"),
+    (52, "1TH", "1Thess", "1 Thessalonians"),
+    (53, "2TH", "2Thess", "2 Thessalonians"),
+    (54, "1TI", "1Tim", "1 Timothy"),
+    (55, "2TI", "2Tim", "2 Timothy"),
+    (56, "TIT", "Titus", "Titus"),
+    (57, "PHM", "Phlm", "Philemon"),
+    (58, "HEB", "Heb", "Hebrews"),
+    (59, "JAS", "Jas", "James"),
+    (60, "1PE", "1Pet", "1 Peter"),
+    (61, "2PE", "2Pet", "2 Peter"),
+    (62, "1JN", "1John", "1 John"),
+    (63, "2JN", "2John", "2 John"),
+    (64, "3JN", "3John", "3 John"),
+    (65, "JUD", "Jude", "Jude"),
+    (66, "REV", "Rev", "Revelation"),
+    (67, "TOB", "Tob", "Tobit"),
+    (68, "JDT", "Jdt", "Judith"),
+    (69, "ESG", "EsthGr", "Esther (Greek)"),
+    (70, "WIS", "Wis", "Wisdom of Solomon"),
+    (71, "SIR", "Sir", "Sirach"),
+    (72, "BAR", "Bar", "Baruch"),
+    (73, "LJE", "EpJer", "Letter of Jeremiah"),
+    (74, "S3Y", "PrAzar", "Song of the Three Young Men"),
+    (75, "SUS", "Sus", "Susanna"),
+    (76, "BEL", "Bel", "Bel and the Dragon"),
+    (77, "1MA", "1Macc", "1 Maccabees"),
+    (78, "2MA", "2Macc", "2 Maccabees"),
+    (79, "3MA", "3Macc", "3 Maccabees"),
+    (80, "4MA", "4Macc", "4 Maccabees"),
+    (81, "1ES", "1Esd", "1 Esdras"),
+    (82, "2ES", "2Esd", "2 Esdras"),
+    (83, "MAN", "PrMan", "Prayer of Manasseh"),
+    (84, "PS2", "AddPs", "Psalm 151"),
+];
+
+/// The book id of a USFM code, OSIS id, or English name, in any case
+pub fn book_id(book: &str) -> Option<usize> {
+    let book = book.trim();
+    BOOKS
+        .iter()
+        .find(|(_, usfm, osis, name)| {
+            [usfm, osis, name]
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(book))
+        })
+        .map(|(id, ..)| *id)
+}
+
+/// `(id, USFM code, OSIS id, English name)` from [`BOOKS`]
+pub fn book_info(
+    book_id: usize,
+) -> Option<&'static (usize, &'static str, &'static str, &'static str)> {
+    BOOKS.iter().find(|(id, ..)| *id == book_id)
+}
+
+/// What went wrong reading or writing, with the line of the input when there is one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConvertError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ConvertError {
+    fn at(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} on line {line}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// The formats `bible_lsp convert` knows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// the native format, see [`JSONBible`]
+    Json,
+    /// one book per file, from Paratext and most Bible societies
+    Usfm,
+    /// XML from the CrossWire and other digital libraries
+    Osis,
+    /// `book,chapter,verse,text` rows, with an optional `id` column
+    Csv,
+    /// `<XMLBIBLE>` XML, common for translations shared between Bible programs
+    Zefania,
+    /// `.SQLite3` modules from the MyBible app, which can only be read
+    MyBible,
+    /// a JSON list of `{ book, chapter, verse, text }`, like the downloads from bolls.life
+    Verses,
+}
+
+impl Format {
+    /**
+    The format of a translation file, by its extension
+
+    - `.xml` is Zefania when it has an `<XMLBIBLE>` and OSIS otherwise
+    - `None` for anything else, which is read as the native JSON
+    */
+    pub fn of_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "usfm" | "sfm" => Some(Self::Usfm),
+            "csv" => Some(Self::Csv),
+            "sqlite3" => Some(Self::MyBible),
+            "xml" => {
+                let text = std::fs::read_to_string(path).unwrap_or_default();
+                match text.contains("<XMLBIBLE") {
+                    true => Some(Self::Zefania),
+                    false => Some(Self::Osis),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "usfm" | "sfm" => Ok(Self::Usfm),
+            "osis" | "xml" => Ok(Self::Osis),
+            "csv" => Ok(Self::Csv),
+            "zefania" => Ok(Self::Zefania),
+            "mybible" | "sqlite3" => Ok(Self::MyBible),
+            "verses" => Ok(Self::Verses),
+            _ => Err(format!(
+                "Unknown format {s}, expected json, usfm, osis, csv, zefania, mybible, or verses"
+            )),
+        }
+    }
+}
+
+/**
+Reads one whole file into books
+
+- The translation is whatever the file says about itself, and empty where it doesn't say (USFM
+  and CSV never do)
+*/
+pub trait Reader {
+    fn read(&self, text: &str) -> Result<JSONBible, ConvertError>;
+}
+
+/// Writes books in a format, losing whatever it has no place for (like abbreviations in CSV)
+pub trait Writer {
+    fn write(&self, bible: &JSONBible) -> Result<String, ConvertError>;
+}
+
+/// `None` for formats this build wasn't compiled with
+pub fn reader(format: Format) -> Option<Box<dyn Reader>> {
+    match format {
+        Format::Json => Some(Box::new(Json)),
+        #[cfg(feature = "usfm")]
+        Format::Usfm => Some(Box::new(Usfm)),
+        #[cfg(not(feature = "usfm"))]
+        Format::Usfm => None,
+        Format::Osis => Some(Box::new(Osis)),
+        Format::Csv => Some(Box::new(Csv)),
+        Format::Zefania => Some(Box::new(Zefania)),
+        // a database rather than text, see [`read_file`]
+        Format::MyBible => None,
+        Format::Verses => Some(Box::new(Verses)),
+    }
+}
+
+/// `None` for formats this build wasn't compiled with, and ones that can only be read
+pub fn writer(format: Format) -> Option<Box<dyn Writer>> {
+    match format {
+        Format::Json => Some(Box::new(Json)),
+        #[cfg(feature = "usfm")]
+        Format::Usfm => Some(Box::new(Usfm)),
+        #[cfg(not(feature = "usfm"))]
+        Format::Usfm => None,
+        Format::Osis => Some(Box::new(Osis)),
+        Format::Csv => Some(Box::new(Csv)),
+        Format::Zefania => Some(Box::new(Zefania)),
+        Format::MyBible => None,
+        Format::Verses => Some(Box::new(Verses)),
+    }
+}
+
+/**
+Reads a file in `format`, for formats that aren't text too (MyBible)
+
+- The translation is whatever the file says about itself, like [`Reader::read`]
+*/
+pub fn read_file(format: Format, path: &Path) -> error::Result<JSONBible> {
+    if format == Format::MyBible {
+        #[cfg(feature = "mybible")]
+        return MyBible::read_path(path).map_err(|err| Error::convert(path, err));
+        #[cfg(not(feature = "mybible"))]
+        return Err(Error::Convert {
+            name: path.display().to_string(),
+            message: String::from(
+                "this build of bible_lsp was compiled without the \"mybible\" feature",
+            ),
+        });
+    }
+    let Some(reader) = reader(format) else {
+        return Err(Error::Convert {
+            name: path.display().to_string(),
+            message: String::from(
+                "this build of bible_lsp was compiled without the \"usfm\" feature",
+            ),
+        });
+    };
+    let text = std::fs::read_to_string(path).map_err(|err| Error::read(path, err))?;
+    reader.read(&text).map_err(|err| Error::convert(path, err))
+}
+
+/// - Verses as they are read, in whatever order the file has them
+/// - Missing verses and chapters become empty strings, since the native format is indexed by
+///   number
+#[derive(Debug, Default)]
+struct Books {
+    books: BTreeMap<usize, BookBuilder>,
+}
+
+#[derive(Debug, Default)]
+struct BookBuilder {
+    name: Option<String>,
+    abbreviations: Vec<String>,
+    chapters: BTreeMap<usize, BTreeMap<usize, String>>,
+    /// see [`JSONBook::headings`]
+    headings: BTreeMap<String, String>,
+    /// see [`JSONBook::layout`]
+    layout: BTreeMap<String, VerseLayout>,
+}
+
+impl Books {
+    fn book(&mut self, book_id: usize) -> &mut BookBuilder {
+        self.books.entry(book_id).or_default()
+    }
+
+    /// Returns where `text` starts in the verse, in characters once its whitespace is collapsed
+    fn verse(&mut self, book_id: usize, chapter: usize, verse: usize, text: &str) -> usize {
+        let verses = self.book(book_id).chapters.entry(chapter).or_default();
+        let existing = verses.entry(verse).or_default();
+        let offset = match collapse_whitespace(existing).chars().count() {
+            0 => 0,
+            length => length + 1,
+        };
+        if !existing.is_empty() && !text.is_empty() {
+            existing.push(' ');
+        }
+        existing.push_str(text);
+        offset
+    }
+
+    fn finish(self, translation: JSONTranslation) -> JSONBible {
+        let bible = self
+            .books
+            .into_iter()
+            .map(|(id, book)| {
+                let chapter_count = book.chapters.keys().max().copied().unwrap_or(0);
+                let content = (1..=chapter_count)
+                    .map(|chapter| {
+                        let verses = book.chapters.get(&chapter);
+                        let verse_count = verses
+                            .and_then(|verses| verses.keys().max().copied())
+                            .unwrap_or(0);
+                        (1..=verse_count)
+                            .map(|verse| {
+                                verses
+                                    .and_then(|verses| verses.get(&verse))
+                                    .map(|text| collapse_whitespace(text))
+                                    .unwrap_or_default()
+                            })
+                            .collect()
+                    })
+                    .collect();
+                JSONBook {
+                    id,
+                    book: book
+                        .name
+                        .or_else(|| book_info(id).map(|(.., name)| name.to_string()))
+                        .unwrap_or_default(),
+                    abbreviations: book.abbreviations,
+                    order: None,
+                    content,
+                    footnotes: Default::default(),
+                    headings: book.headings,
+                    words_of_christ: Default::default(),
+                    layout: book.layout,
+                }
+            })
+            .collect();
+        JSONBible { translation, bible }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn empty_translation() -> JSONTranslation {
+    JSONTranslation {
+        name: String::new(),
+        language: String::new(),
+        abbreviation: String::new(),
+        attribution: None,
+    }
+}
+
+/// The 1-based line `offset` is on
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+/// The leading number of `1`, `1-2`, or `1a`
+fn leading_number(text: &str) -> Option<usize> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    text[..end].parse().ok()
+}
+
+/// The native format, so it can be read from and written to like any other
+#[derive(Clone, Copy, Debug)]
+pub struct Json;
+
+impl Reader for Json {
+    fn read(&self, text: &str) -> Result<JSONBible, ConvertError> {
+        let deserializer = &mut serde_json::Deserializer::from_str(text);
+        serde_path_to_error::deserialize(deserializer).map_err(|err| {
+            let at = err.path().to_string();
+            let inner = err.into_inner();
+            ConvertError::at(inner.line(), format!("{at}: {inner}"))
+        })
+    }
+}
+
+impl Writer for Json {
+    fn write(&self, bible: &JSONBible) -> Result<String, ConvertError> {
+        serde_json::to_string(bible).map_err(|err| ConvertError::new(err.to_string()))
+    }
+}
+
+/// - USFM markers whose lines aren't verse text: identification, headings, titles, and intros
+/// - Prefixes, so `s` covers `s1` and `s2`
+#[cfg(feature = "usfm")]
+const USFM_NON_VERSE: &[&str] = &[
+    "id", "ide", "usfm", "sts", "rem", "h", "toc", "mt", "ms", "mr", "s", "r", "d", "cl", "cp",
+    "i", "periph",
+];
+
+/// What a paragraph marker says about how the verse after it is laid out
+#[cfg(feature = "usfm")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum UsfmBreak {
+    Paragraph,
+    Stanza,
+    /// a line of poetry, by its indent level
+    Line(usize),
+}
+
+#[cfg(feature = "usfm")]
+impl UsfmBreak {
+    /// `\p`, `\m`, and `\pi` are paragraphs, `\b` is a stanza break, and `\q2` or `\qm2` is a
+    /// line indented once
+    fn of(marker: &str) -> Option<Self> {
+        match marker {
+            "p" | "m" | "pmo" | "pi" | "pi1" | "pi2" | "pi3" => Some(Self::Paragraph),
+            "b" => Some(Self::Stanza),
+            _ => {
+                let level = marker
+                    .strip_prefix("qm")
+                    .or_else(|| marker.strip_prefix('q'))?;
+                match level {
+                    "" => Some(Self::Line(0)),
+                    level => Some(Self::Line(level.parse::<usize>().ok()?.saturating_sub(1))),
+                }
+            }
+        }
+    }
+
+    /// Adds the break to `layout`, where paragraphs and stanzas only start at the start of a
+    /// verse
+    fn apply(self, layout: &mut BTreeMap<String, VerseLayout>, key: String, offset: usize) {
+        match self {
+            Self::Paragraph if offset == 0 => layout.entry(key).or_default().paragraph = true,
+            Self::Stanza if offset == 0 => layout.entry(key).or_default().stanza = true,
+            Self::Line(level) => layout.entry(key).or_default().lines.push([offset, level]),
+            Self::Paragraph | Self::Stanza => {}
+        }
+    }
+}
+
+/// Footnotes and cross references, which aren't part of the verse
+#[cfg(feature = "usfm")]
+#[cached(size = 1)]
+fn usfm_notes() -> Regex {
+    Regex::new(r"(?s)\\(f|fe|x|ef|ex) .*?\\(f|fe|x|ef|ex)\*").unwrap()
+}
+
+/// The attributes of a word, like `|strong="H7225"` in `\w beginning|strong="H7225"\w*`
+#[cfg(feature = "usfm")]
+#[cached(size = 1)]
+fn usfm_attributes() -> Regex {
+    Regex::new(r"\|[^\\]*(\\\+?[a-z]+[0-9]*\*)").unwrap()
+}
+
+/// Any other marker, opening (with the space after it) or closing
+#[cfg(feature = "usfm")]
+#[cached(size = 1)]
+fn usfm_markers() -> Regex {
+    Regex::new(r"\\\+?[a-z]+[0-9]*(\*| ?)").unwrap()
+}
+
+/**
+Unified Standard Format Markers, one book per file
+
+- Verse text keeps words inside character markers (`\wj`, `\add`) and drops footnotes and cross
+  references
+- Section headings (`\s`, `\s1`, `\s2`, ...) are kept for the verse after them, the first one
+  when there are several
+- The book name is from `\h` (or `\toc2`, `\toc1`, `\mt1`), and `\toc3` is its abbreviation
+- Paragraphs (`\p`), stanza breaks (`\b`), and poetry lines (`\q1`, `\q2`, ...) are kept as the
+  verses' layout, see [`JSONBook::layout`]
+*/
+#[cfg(feature = "usfm")]
+#[derive(Clone, Copy, Debug)]
+pub struct Usfm;
+
+#[cfg(feature = "usfm")]
+impl Usfm {
+    fn clean(text: &str) -> String {
+        let text = usfm_notes().replace_all(text, "");
+        let text = usfm_attributes().replace_all(&text, "$1");
+        usfm_markers().replace_all(&text, "").to_string()
+    }
+}
+
+#[cfg(feature = "usfm")]
+impl Reader for Usfm {
+    fn read(&self, text: &str) -> Result<JSONBible, ConvertError> {
+        let mut books = Books::default();
+        let mut book: Option<usize> = None;
+        // `\h` wins over `\toc2`, which wins over `\toc1` and `\mt1`
+        let mut name_rank = usize::MAX;
+        let mut chapter: Option<usize> = None;
+        let mut verse: Option<usize> = None;
+        // the section heading waiting for the next verse
+        let mut heading: Option<String> = None;
+        // the paragraph and line breaks waiting for the next text
+        let mut breaks: Vec<UsfmBreak> = vec![];
+        for (idx, line) in text.lines().enumerate() {
+            let line_number = idx + 1;
+            let line = line.trim();
+            let marker = line
+                .strip_prefix('\\')
+                .map(|rest| rest.split_whitespace().next().unwrap_or_default())
+                .unwrap_or_default();
+            let rest = line
+                .strip_prefix('\\')
+                .map(|rest| rest[marker.len()..].trim())
+                .unwrap_or_default();
+            match marker {
+                "id" => {
+                    let code = rest.split_whitespace().next().unwrap_or_default();
+                    let id = book_id(code).ok_or_else(|| {
+                        ConvertError::at(line_number, format!("Unknown book code {code}"))
+                    })?;
+                    book = Some(id);
+                    books.book(id);
+                    (name_rank, chapter, verse, heading) = (usize::MAX, None, None, None);
+                    breaks.clear();
+                    continue;
+                }
+                "s" | "s1" | "s2" | "s3" | "s4" => {
+                    let text = Self::clean(rest).trim().to_string();
+                    if heading.is_none() && !text.is_empty() {
+                        heading = Some(text);
+                    }
+                    continue;
+                }
+                "h" | "toc2" | "toc1" | "mt1" | "mt" => {
+                    let rank = ["h", "toc2", "toc1", "mt1", "mt"]
+                        .iter()
+                        .position(|known| *known == marker)
+                        .unwrap_or(usize::MAX);
+                    if let Some(id) = book.filter(|_| rank < name_rank && !rest.is_empty()) {
+                        books.book(id).name = Some(Self::clean(rest).trim().to_string());
+                        name_rank = rank;
+                    }
+                    continue;
+                }
+                "toc3" => {
+                    if let Some(id) = book.filter(|_| !rest.is_empty()) {
+                        books.book(id).abbreviations = vec![rest.to_string()];
+                    }
+                    continue;
+                }
+                "c" => {
+                    chapter = Some(leading_number(rest).ok_or_else(|| {
+                        ConvertError::at(line_number, format!("Invalid chapter number {rest}"))
+                    })?);
+                    verse = None;
+                    continue;
+                }
+                _ if USFM_NON_VERSE
+                    .iter()
+                    .any(|prefix| marker.starts_with(prefix) && marker != "iex") =>
+                {
+                    continue
+                }
+                _ => {}
+            }
+            breaks.extend(UsfmBreak::of(marker));
+            // verses can start anywhere in a line, and text before the first continues the last
+            let mut pieces = line.split("\\v ");
+            let before = pieces.next().unwrap_or_default();
+            // the sections starting on this line, added once the verses are
+            let mut starts = vec![];
+            let mut add = |verse: Option<usize>, text: &str| -> Result<(), ConvertError> {
+                let text = Self::clean(text);
+                if text.trim().is_empty() {
+                    return Ok(());
+                }
+                let (Some(book), Some(chapter), Some(verse)) = (book, chapter, verse) else {
+                    return Err(ConvertError::at(
+                        line_number,
+                        "Text before the first \\id, \\c, or \\v",
+                    ));
+                };
+                let offset = books.verse(book, chapter, verse, &text);
+                let layout = &mut books.book(book).layout;
+                for brk in breaks.drain(..) {
+                    brk.apply(layout, format!("{chapter}:{verse}"), offset);
+                }
+                Ok(())
+            };
+            add(verse, before)?;
+            for piece in pieces {
+                let piece = piece.trim_start();
+                let number = piece.split_whitespace().next().unwrap_or_default();
+                verse = Some(leading_number(number).ok_or_else(|| {
+                    ConvertError::at(line_number, format!("Invalid verse number {number}"))
+                })?);
+                if let (Some(chapter), Some(verse)) = (chapter, verse) {
+                    starts.extend(heading.take().map(|heading| (chapter, verse, heading)));
+                }
+                add(verse, &piece[number.len()..])?;
+            }
+            for (chapter, verse, heading) in starts {
+                if let Some(book) = book {
+                    books
+                        .book(book)
+                        .headings
+                        .insert(format!("{chapter}:{verse}"), heading);
+                }
+            }
+        }
+        Ok(books.finish(empty_translation()))
+    }
+}
+
+#[cfg(feature = "usfm")]
+impl Writer for Usfm {
+    fn write(&self, bible: &JSONBible) -> Result<String, ConvertError> {
+        let mut usfm = String::new();
+        for book in bible.bible.iter() {
+            let Some((_, code, ..)) = book_info(book.id) else {
+                return Err(ConvertError::new(format!(
+                    "{} (id {}) has no USFM code",
+                    book.book, book.id
+                )));
+            };
+            usfm.push_str(&format!("\\id {code}\n\\usfm 3.0\n\\h {}\n", book.book));
+            usfm.push_str(&format!("\\toc1 {0}\n\\toc2 {0}\n", book.book));
+            if let Some(abbreviation) = book.abbreviations.first() {
+                usfm.push_str(&format!("\\toc3 {abbreviation}\n"));
+            }
+            usfm.push_str(&format!("\\mt1 {}\n", book.book));
+            for (chapter_idx, verses) in book.content.iter().enumerate() {
+                let chapter = chapter_idx + 1;
+                usfm.push_str(&format!("\\c {chapter}\n"));
+                for (verse_idx, text) in verses.iter().enumerate() {
+                    let verse = verse_idx + 1;
+                    // a new paragraph at each section, and at the start of the chapter
+                    match book.headings.get(&format!("{chapter}:{verse}")) {
+                        Some(heading) => usfm.push_str(&format!("\\s1 {heading}\n\\p\n")),
+                        None if verse == 1 => usfm.push_str("\\p\n"),
+                        None => {}
+                    }
+                    usfm.push_str(&format!("\\v {verse} {text}\n"));
+                }
+            }
+        }
+        Ok(usfm)
+    }
+}
+
+#[cached(size = 1)]
+fn osis_verse_tags() -> Regex {
+    Regex::new(r"<verse\b([^>]*?)(/?)>|</verse>").unwrap()
+}
+
+#[cached(size = 1)]
+fn osis_book_titles() -> Regex {
+    Regex::new(r#"(?s)<div\b[^>]*?\btype="book"[^>]*>\s*<title\b[^>]*>(.*?)</title>"#).unwrap()
+}
+
+#[cached(size = 1)]
+fn osis_skipped() -> Regex {
+    Regex::new(r"(?s)<note\b.*?</note>|<title\b.*?</title>").unwrap()
+}
+
+#[cached(size = 1)]
+fn xml_tags() -> Regex {
+    Regex::new(r"<[^>]*>").unwrap()
+}
+
+/// The value of `name="..."` in the attributes of a tag
+fn xml_attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let start = attributes.find(&format!("{name}=\""))? + name.len() + 2;
+    let end = attributes[start..].find('"')? + start;
+    Some(&attributes[start..end])
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/**
+Open Scripture Information Standard XML
+
+- Both container verses (`<verse osisID="Gen.1.1">...</verse>`) and milestones (`sID`/`eID`) are
+  read, and notes and titles inside them are dropped
+- The translation is from `osisIDWork`, the work's `<title>`, and `xml:lang`
+- Good enough for the files people actually have, not a validating XML parser
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Osis;
+
+impl Osis {
+    fn verse_text(fragment: &str) -> String {
+        let fragment = osis_skipped().replace_all(fragment, "");
+        xml_unescape(&xml_tags().replace_all(&fragment, " "))
+    }
+}
+
+impl Reader for Osis {
+    fn read(&self, text: &str) -> Result<JSONBible, ConvertError> {
+        let mut books = Books::default();
+        let mut translation = empty_translation();
+        if let Some(start) = text.find("<osisText") {
+            let attributes = &text[start
+                ..text[start..]
+                    .find('>')
+                    .map_or(text.len(), |end| start + end)];
+            if let Some(work) = xml_attribute(attributes, "osisIDWork") {
+                translation.abbreviation = xml_unescape(work);
+            }
+            if let Some(language) = xml_attribute(attributes, "xml:lang") {
+                translation.language = match language {
+                    "en" => String::from("English"),
+                    "es" => String::from("Español"),
+                    language => language.to_string(),
+                };
+            }
+        }
+        if let Some(work) = text.find("<work") {
+            let work_end = text[work..]
+                .find("</work>")
+                .map_or(text.len(), |end| work + end);
+            if let Some(title) = text[work..work_end].find("<title>") {
+                let title = &text[work + title + "<title>".len()..work_end];
+                translation.name = xml_unescape(&title[..title.find('<').unwrap_or(title.len())]);
+            }
+        }
+        for caps in osis_book_titles().captures_iter(text) {
+            let tag = caps.get(0).expect("Whole match");
+            let Some(id) = xml_attribute(tag.as_str(), "osisID").and_then(book_id) else {
+                continue;
+            };
+            books.book(id).name = Some(xml_unescape(&caps[1]).trim().to_string());
+        }
+
+        // the verse that is open, and where its text starts
+        let mut open: Option<(&str, usize, usize)> = None;
+        let mut close =
+            |open: &mut Option<(&str, usize, usize)>, end: usize| -> Result<(), ConvertError> {
+                let Some((osis_id, start, line)) = open.take() else {
+                    return Ok(());
+                };
+                let mut parts = osis_id.split('.');
+                let (Some(book), Some(chapter), Some(verse)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(ConvertError::at(
+                        line,
+                        format!("Invalid verse id {osis_id}"),
+                    ));
+                };
+                let Some(book) = book_id(book) else {
+                    return Err(ConvertError::at(line, format!("Unknown book {book}")));
+                };
+                let (Some(chapter), Some(verse)) = (leading_number(chapter), leading_number(verse))
+                else {
+                    return Err(ConvertError::at(
+                        line,
+                        format!("Invalid verse id {osis_id}"),
+                    ));
+                };
+                books.verse(book, chapter, verse, &Self::verse_text(&text[start..end]));
+                Ok(())
+            };
+        for caps in osis_verse_tags().captures_iter(text) {
+            let tag = caps.get(0).expect("Whole match");
+            let attributes = caps.get(1).map_or("", |attributes| attributes.as_str());
+            close(&mut open, tag.start())?;
+            if xml_attribute(attributes, "eID").is_some() || caps.get(1).is_none() {
+                continue;
+            }
+            if let Some(osis_id) = xml_attribute(attributes, "osisID") {
+                // a verse can list several ids when it spans them, and the text goes in the first
+                let first = osis_id.split_whitespace().next().unwrap_or(osis_id);
+                open = Some((first, tag.end(), line_of(text, tag.start())));
+            }
+        }
+        Ok(books.finish(translation))
+    }
+}
+
+impl Writer for Osis {
+    fn write(&self, bible: &JSONBible) -> Result<String, ConvertError> {
+        let work = xml_escape(&bible.translation.abbreviation);
+        let language = match bible.translation.language.as_str() {
+            "English" => "en",
+            "Español" => "es",
+            language => language,
+        };
+        let mut osis = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<osis xmlns=\"http://www.bibletechnologies.net/2003/OSIS/namespace\">\n<osisText osisIDWork=\"{work}\" xml:lang=\"{}\">\n<header><work osisWork=\"{work}\"><title>{}</title></work></header>\n",
+            xml_escape(language),
+            xml_escape(&bible.translation.name)
+        );
+        for book in bible.bible.iter() {
+            let Some((.., osis_id, _)) = book_info(book.id) else {
+                return Err(ConvertError::new(format!(
+                    "{} (id {}) has no OSIS id",
+                    book.book, book.id
+                )));
+            };
+            osis.push_str(&format!(
+                "<div type=\"book\" osisID=\"{osis_id}\"><title type=\"main\">{}</title>\n",
+                xml_escape(&book.book)
+            ));
+            for (chapter_idx, verses) in book.content.iter().enumerate() {
+                let chapter = chapter_idx + 1;
+                osis.push_str(&format!("<chapter osisID=\"{osis_id}.{chapter}\">\n"));
+                for (verse_idx, text) in verses.iter().enumerate() {
+                    osis.push_str(&format!(
+                        "<verse osisID=\"{osis_id}.{chapter}.{0}\">{1}</verse>\n",
+                        verse_idx + 1,
+                        xml_escape(text)
+                    ));
+                }
+                osis.push_str("</chapter>\n");
+            }
+            osis.push_str("</div>\n");
+        }
+        osis.push_str("</osisText>\n</osis>\n");
+        Ok(osis)
+    }
+}
+
+/// - The fields of every row, with quoted fields unquoted
+/// - Quoted fields can have commas, doubled quotes, and line breaks
+fn csv_rows(text: &str) -> Vec<(usize, Vec<String>)> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let (mut quoted, mut line, mut row_line) = (false, 1, 1);
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push((row_line, std::mem::take(&mut row)));
+                line += 1;
+                row_line = line;
+            }
+            ('\r', false) => {}
+            (ch, _) => {
+                if ch == '\n' {
+                    line += 1;
+                }
+                field.push(ch);
+            }
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push((row_line, row));
+    }
+    rows.retain(|(_, row)| row.iter().any(|field| !field.is_empty()));
+    rows
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/**
+One verse per row, with a header naming the columns
+
+- `book`, `chapter`, `verse`, and `text` are needed, in any order
+- `book` is a name, USFM code, or OSIS id, unless there is an `id` column with the book id
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Csv;
+
+impl Reader for Csv {
+    fn read(&self, text: &str) -> Result<JSONBible, ConvertError> {
+        let mut rows = csv_rows(text).into_iter();
+        let Some((_, header)) = rows.next() else {
+            return Err(ConvertError::new("The file is empty"));
+        };
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|column| column.trim().eq_ignore_ascii_case(name))
+        };
+        let required = |name: &str| {
+            column(name).ok_or_else(|| ConvertError::at(1, format!("No {name} column")))
+        };
+        let id_column = column("id").or_else(|| column("book_id"));
+        let (book_column, chapter_column, verse_column, text_column) = (
+            required("book")?,
+            required("chapter")?,
+            required("verse")?,
+            required("text")?,
+        );
+        let mut books = Books::default();
+        for (line, row) in rows {
+            let field = |idx: usize| row.get(idx).map(String::as_str).unwrap_or_default();
+            let number = |idx: usize, what: &str| {
+                field(idx)
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| ConvertError::at(line, format!("Invalid {what} {}", field(idx))))
+            };
+            let name = field(book_column).trim();
+            let id = match id_column {
+                Some(idx) => number(idx, "book id")?,
+                None => book_id(name).ok_or_else(|| {
+                    ConvertError::at(line, format!("Unknown book {name}, add an id column"))
+                })?,
+            };
+            if !name.is_empty() {
+                books.book(id).name.get_or_insert_with(|| name.to_string());
+            }
+            let (chapter, verse) = (
+                number(chapter_column, "chapter")?,
+                number(verse_column, "verse")?,
+            );
+            books.verse(id, chapter, verse, field(text_column));
+        }
+        Ok(books.finish(empty_translation()))
+    }
+}
+
+impl Writer for Csv {
+    fn write(&self, bible: &JSONBible) -> Result<String, ConvertError> {
+        let mut csv = String::from("id,book,chapter,verse,text\n");
+        for book in bible.bible.iter() {
+            for (chapter_idx, verses) in book.content.iter().enumerate() {
+                for (verse_idx, text) in verses.iter().enumerate() {
+                    csv.push_str(&format!(
+                        "{},{},{},{},{}\n",
+                        book.id,
+                        csv_field(&book.book),
+                        chapter_idx + 1,
+                        verse_idx + 1,
+                        csv_field(text)
+                    ));
+                }
+            }
+        }
+        Ok(csv)
+    }
+}
+
+#[cached(size = 1)]
+fn zefania_tags() -> Regex {
+    Regex::new(r"(?s)<BIBLEBOOK\b([^>]*)>|<CHAPTER\b([^>]*)>|<VERS\b([^>]*)>(.*?)</VERS>").unwrap()
+}
+
+#[cached(size = 1)]
+fn zefania_skipped() -> Regex {
+    Regex::new(r"(?s)<NOTE\b.*?</NOTE>|<DIV\b.*?</DIV>").unwrap()
+}
+
+/// The text of the first `<name>` element in `text`
+fn xml_element(text: &str, name: &str) -> Option<String> {
+    let start = text.find(&format!("<{name}>"))? + name.len() + 2;
+    let end = text[start..].find("</")? + start;
+    Some(xml_unescape(text[start..end].trim()))
+}
+
+/// `ENG` or `en` is `English`, like the names the alias lists are keyed by
+fn language_name(code: &str) -> String {
+    match code.to_lowercase().as_str() {
+        "en" | "eng" => String::from("English"),
+        "es" | "spa" => String::from("Español"),
+        _ => code.to_string(),
+    }
+}
+
+/**
+Zefania XML, one file for the whole translation
+
+- Books are numbered like here (`bnumber="1"` is Genesis), and `bsname` is the abbreviation
+- Notes are dropped, and words inside markup like `<gr>` or `<STYLE>` are kept
+- The translation is from `<INFORMATION>`, with `<identifier>` as the abbreviation
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Zefania;
+
+impl Zefania {
+    fn verse_text(fragment: &str) -> String {
+        let fragment = zefania_skipped().replace_all(fragment, "");
+        xml_unescape(&xml_tags().replace_all(&fragment, " "))
+    }
+}
+
+impl Reader for Zefania {
+    fn read(&self, text: &str) -> Result<JSONBible, ConvertError> {
+        if !text.contains("<XMLBIBLE") {
+            return Err(ConvertError::new("No <XMLBIBLE> element"));
+        }
+        let mut translation = empty_translation();
+        let information = text
+            .find("<INFORMATION")
+            .map(|start| {
+                &text[start
+                    ..text[start..]
+                        .find("</INFORMATION>")
+                        .map_or(text.len(), |end| start + end)]
+            })
+            .unwrap_or_default();
+        translation.name = xml_element(information, "title").unwrap_or_default();
+        translation.abbreviation = xml_element(information, "identifier").unwrap_or_default();
+        translation.language = xml_element(information, "language")
+            .map(|code| language_name(&code))
+            .unwrap_or_default();
+        if let Some(start) = text.find("<XMLBIBLE") {
+            let tag = &text[start
+                ..text[start..]
+                    .find('>')
+                    .map_or(text.len(), |end| start + end)];
+            if let Some(name) =
+                xml_attribute(tag, "biblename").filter(|_| translation.name.is_empty())
+            {
+                translation.name = xml_unescape(name);
+            }
+        }
+
+        let mut books = Books::default();
+        let (mut book, mut chapter): (Option<usize>, Option<usize>) = (None, None);
+        for caps in zefania_tags().captures_iter(text) {
+            let tag = caps.get(0).expect("Whole match");
+            let line = line_of(text, tag.start());
+            let number = |attributes: &str, name: &str| {
+                xml_attribute(attributes, name)
+                    .and_then(leading_number)
+                    .ok_or_else(|| ConvertError::at(line, format!("No {name}")))
+            };
+            if let Some(attributes) = caps.get(1).map(|found| found.as_str()) {
+                let id = number(attributes, "bnumber")?;
+                let builder = books.book(id);
+                if let Some(name) =
+                    xml_attribute(attributes, "bname").filter(|name| !name.is_empty())
+                {
+                    builder.name = Some(xml_unescape(name));
+                }
+                if let Some(short) =
+                    xml_attribute(attributes, "bsname").filter(|short| !short.is_empty())
+                {
+                    builder.abbreviations = vec![xml_unescape(short)];
+                }
+                (book, chapter) = (Some(id), None);
+            } else if let Some(attributes) = caps.get(2).map(|found| found.as_str()) {
+                chapter = Some(number(attributes, "cnumber")?);
+            } else if let Some(attributes) = caps.get(3).map(|found| found.as_str()) {
+                let verse = number(attributes, "vnumber")?;
+                let (Some(book), Some(chapter)) = (book, chapter) else {
+                    return Err(ConvertError::at(line, "A verse outside of a chapter"));
+                };
+                books.verse(book, chapter, verse, &Self::verse_text(&caps[4]));
+            }
+        }
+        Ok(books.finish(translation))
+    }
+}
+
+impl Writer for Zefania {
+    fn write(&self, bible: &JSONBible) -> Result<String, ConvertError> {
+        let translation = &bible.translation;
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<XMLBIBLE biblename=\"{}\">\n<INFORMATION>\n<title>{}</title>\n<identifier>{}</identifier>\n<language>{}</language>\n</INFORMATION>\n",
+            xml_escape(&translation.name),
+            xml_escape(&translation.name),
+            xml_escape(&translation.abbreviation),
+            xml_escape(&translation.language),
+        );
+        for book in bible.bible.iter() {
+            xml.push_str(&format!(
+                "<BIBLEBOOK bnumber=\"{}\" bname=\"{}\" bsname=\"{}\">\n",
+                book.id,
+                xml_escape(&book.book),
+                xml_escape(
+                    book.abbreviations
+                        .first()
+                        .map_or("", |short| short.as_str())
+                ),
+            ));
+            for (chapter_idx, verses) in book.content.iter().enumerate() {
+                xml.push_str(&format!("<CHAPTER cnumber=\"{}\">\n", chapter_idx + 1));
+                for (verse_idx, verse) in verses.iter().enumerate() {
+                    xml.push_str(&format!(
+                        "<VERS vnumber=\"{}\">{}</VERS>\n",
+                        verse_idx + 1,
+                        xml_escape(verse)
+                    ));
+                }
+                xml.push_str("</CHAPTER>\n");
+            }
+            xml.push_str("</BIBLEBOOK>\n");
+        }
+        xml.push_str("</XMLBIBLE>\n");
+        Ok(xml)
+    }
+}
+
+/// MyBible's book numbers, which go by tens and put the general epistles before Romans
+#[cfg(feature = "mybible")]
+const MYBIBLE_BOOKS: &[(usize, usize)] = &[
+    (10, 1),
+    (20, 2),
+    (30, 3),
+    (40, 4),
+    (50, 5),
+    (60, 6),
+    (70, 7),
+    (80, 8),
+    (90, 9),
+    (100, 10),
+    (110, 11),
+    (120, 12),
+    (130, 13),
+    (140, 14),
+    (150, 15),
+    (160, 16),
+    (190, 17),
+    (220, 18),
+    (230, 19),
+    (240, 20),
+    (250, 21),
+    (260, 22),
+    (290, 23),
+    (300, 24),
+    (310, 25),
+    (330, 26),
+    (340, 27),
+    (350, 28),
+    (360, 29),
+    (370, 30),
+    (380, 31),
+    (390, 32),
+    (400, 33),
+    (410, 34),
+    (420, 35),
+    (430, 36),
+    (440, 37),
+    (450, 38),
+    (460, 39),
+    (470, 40),
+    (480, 41),
+    (490, 42),
+    (500, 43),
+    (510, 44),
+    (520, 59),
+    (530, 60),
+    (540, 61),
+    (550, 62),
+    (560, 63),
+    (570, 64),
+    (580, 65),
+    (590, 45),
+    (600, 46),
+    (610, 47),
+    (620, 48),
+    (630, 49),
+    (640, 50),
+    (650, 51),
+    (660, 52),
+    (670, 53),
+    (680, 54),
+    (690, 55),
+    (700, 56),
+    (710, 57),
+    (720, 58),
+    (730, 66),
+];
+
+/// Strong's numbers, footnotes, and morphology, which aren't part of the verse
+#[cached(size = 1)]
+fn mybible_skipped() -> Regex {
+    Regex::new(r"(?s)<S>.*?</S>|<f>.*?</f>|<n>.*?</n>|<m>.*?</m>").unwrap()
+}
+
+/**
+MyBible `.SQLite3` modules
+
+- Verses are from the `verses` table, book names from `books`, and the translation from the
+  `description` and `language` in `info`
+- Deuterocanonical books, which MyBible numbers between the others, are left out
+- Only read when the server is built with the `mybible` feature
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct MyBible;
+
+impl MyBible {
+    #[cfg(feature = "mybible")]
+    fn read_path(path: &Path) -> Result<JSONBible, ConvertError> {
+        use rusqlite::{types::ValueRef, Connection, OpenFlags};
+        use serde_json::Value;
+
+        let failed =
+            |err: rusqlite::Error| ConvertError::new(format!("Couldn't read the module: {err}"));
+        let db =
+            Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(failed)?;
+        let query = |sql: &str| -> Result<Vec<serde_json::Map<String, Value>>, ConvertError> {
+            let mut statement = db.prepare(sql).map_err(failed)?;
+            let columns: Vec<String> = statement
+                .column_names()
+                .into_iter()
+                .map(String::from)
+                .collect();
+            let mut rows = statement.query([]).map_err(failed)?;
+            let mut read = vec![];
+            while let Some(row) = rows.next().map_err(failed)? {
+                let mut values = serde_json::Map::new();
+                for (idx, column) in columns.iter().enumerate() {
+                    let value = match row.get_ref(idx).map_err(failed)? {
+                        ValueRef::Integer(number) => Value::from(number),
+                        ValueRef::Real(number) => Value::from(number),
+                        ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text)),
+                        ValueRef::Null | ValueRef::Blob(_) => Value::Null,
+                    };
+                    values.insert(column.clone(), value);
+                }
+                read.push(values);
+            }
+            Ok(read)
+        };
+        let verses = query("SELECT book_number, chapter, verse, text FROM verses")?;
+        // older modules don't have these
+        let books = query("SELECT book_number, long_name FROM books").unwrap_or_default();
+        let info = query("SELECT name, value FROM info").unwrap_or_default();
+        Ok(Self::from_rows(&info, &books, &verses))
+    }
+
+    #[cfg(feature = "mybible")]
+    fn from_rows(
+        info: &[serde_json::Map<String, serde_json::Value>],
+        books: &[serde_json::Map<String, serde_json::Value>],
+        verses: &[serde_json::Map<String, serde_json::Value>],
+    ) -> JSONBible {
+        let number = |row: &serde_json::Map<String, serde_json::Value>, column: &str| {
+            row.get(column)
+                .and_then(|value| value.as_u64())
+                .map(|value| value as usize)
+        };
+        let text = |row: &serde_json::Map<String, serde_json::Value>, column: &str| {
+            row.get(column)
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let book_id = |book_number: Option<usize>| {
+            MYBIBLE_BOOKS
+                .iter()
+                .find(|(mybible, _)| Some(*mybible) == book_number)
+                .map(|(_, id)| *id)
+        };
+        let mut translation = empty_translation();
+        for row in info {
+            match text(row, "name").as_str() {
+                "description" => translation.name = text(row, "value"),
+                "language" => translation.language = language_name(&text(row, "value")),
+                _ => {}
+            }
+        }
+        let mut builder = Books::default();
+        for row in verses {
+            let (Some(id), Some(chapter), Some(verse)) = (
+                book_id(number(row, "book_number")),
+                number(row, "chapter"),
+                number(row, "verse"),
+            ) else {
+                continue;
+            };
+            builder.verse(id, chapter, verse, &strip_markup(&text(row, "text")));
+        }
+        for row in books {
+            if let Some(id) = book_id(number(row, "book_number")) {
+                if let Some(book) = builder.books.get_mut(&id) {
+                    book.name = Some(text(row, "long_name")).filter(|name| !name.is_empty());
+                }
+            }
+        }
+        builder.finish(translation)
+    }
+}
+
+/// Strips what [`MyBible`] and [`Verses`] texts mark up, like Strong's numbers and `<br/>`
+fn strip_markup(text: &str) -> String {
+    let cleaned = mybible_skipped().replace_all(text, "");
+    xml_unescape(&xml_tags().replace_all(&cleaned, " "))
+}
+
+/**
+A JSON list of verses, one object each, like the translations bolls.life has for download
+
+```json
+[{ "book": 43, "chapter": 3, "verse": 16, "text": "For God so loved the world, ..." }]
+```
+
+- `book` is a book id, or a name, USFM code, or OSIS id
+- Markup in the text is stripped like in [`MyBible`] modules, which use the same tags
+- Other fields (like `pk` and `translation`) are ignored
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Verses;
+
+impl Reader for Verses {
+    fn read(&self, text: &str) -> Result<JSONBible, ConvertError> {
+        let rows: Vec<serde_json::Value> = serde_json::from_str(text)
+            .map_err(|err| ConvertError::at(err.line(), format!("Not a list of verses: {err}")))?;
+        let mut books = Books::default();
+        for (idx, row) in rows.iter().enumerate() {
+            let invalid = |what: &str| ConvertError::new(format!("Verse {idx} has {what}"));
+            let number = |field: &str| {
+                row.get(field)
+                    .and_then(|value| value.as_u64())
+                    .map(|value| value as usize)
+            };
+            let id = match row.get("book") {
+                Some(serde_json::Value::String(name)) => book_id(name),
+                _ => number("book"),
+            }
+            .ok_or_else(|| invalid("no known book"))?;
+            let (Some(chapter), Some(verse)) = (number("chapter"), number("verse")) else {
+                return Err(invalid("no chapter or verse number"));
+            };
+            let text = row
+                .get("text")
+                .and_then(|text| text.as_str())
+                .ok_or_else(|| invalid("no text"))?;
+            books.verse(id, chapter, verse, &strip_markup(text));
+        }
+        Ok(books.finish(empty_translation()))
+    }
+}
+
+impl Writer for Verses {
+    fn write(&self, bible: &JSONBible) -> Result<String, ConvertError> {
+        let rows: Vec<serde_json::Value> = bible
+            .bible
+            .iter()
+            .flat_map(|book| {
+                book.content
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(chapter_idx, verses)| {
+                        verses.iter().enumerate().map(move |(verse_idx, text)| {
+                            serde_json::json!({
+                                "book": book.id,
+                                "chapter": chapter_idx + 1,
+                                "verse": verse_idx + 1,
+                                // read back as markup
+                                "text": xml_escape(text),
+                            })
+                        })
+                    })
+            })
+            .collect();
+        serde_json::to_string(&rows).map_err(|err| ConvertError::new(err.to_string()))
+    }
+}
+
+/// - For files that don't say which translation they are: named after the file, in English
+/// - Ex: `kjv.SQLite3` is `KJV`
+pub fn fallback_translation(path: &Path) -> JSONTranslation {
+    let abbreviation = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    JSONTranslation {
+        name: abbreviation.clone(),
+        language: String::from("English"),
+        abbreviation,
+        attribution: None,
+    }
+}
+
+/**
+Joins what was read from several files into one translation
+
+- Books from later files are added after the earlier ones, and a book in two files keeps the first
+- The translation is the first one that says anything, with empty fields from `fallback`
+*/
+pub fn merge(parts: Vec<JSONBible>, fallback: JSONTranslation) -> JSONBible {
+    let mut translation = parts
+        .iter()
+        .map(|part| part.translation.clone())
+        .find(|translation| !translation.name.is_empty() || !translation.abbreviation.is_empty())
+        .unwrap_or_else(empty_translation);
+    for (field, fallback) in [
+        (&mut translation.name, fallback.name),
+        (&mut translation.language, fallback.language),
+        (&mut translation.abbreviation, fallback.abbreviation),
+    ] {
+        if field.is_empty() {
+            *field = fallback;
+        }
+    }
+    translation.attribution = translation.attribution.or(fallback.attribution);
+    let mut bible: Vec<JSONBook> = vec![];
+    for book in parts.into_iter().flat_map(|part| part.bible) {
+        if !bible.iter().any(|existing| existing.id == book.id) {
+            bible.push(book);
+        }
+    }
+    JSONBible { translation, bible }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> JSONBible {
+        JSONBible {
+            translation: JSONTranslation {
+                name: String::from("Sample & Test"),
+                language: String::from("English"),
+                abbreviation: String::from("SMP"),
+                attribution: None,
+            },
+            bible: vec![JSONBook {
+                id: 57,
+                book: String::from("Philemon"),
+                abbreviations: vec![String::from("Phlm")],
+                order: None,
+                content: vec![vec![
+                    String::from("Paul, a prisoner for Christ Jesus, and Timothy our brother,"),
+                    String::from("To Apphia our sister, \"and\" Archippus <our> fellow soldier"),
+                    String::from("Grace to you, and peace."),
+                ]],
+                footnotes: Default::default(),
+                headings: Default::default(),
+                words_of_christ: Default::default(),
+                layout: Default::default(),
+            }],
+        }
+    }
+
+    /// What every format keeps
+    fn books(bible: &JSONBible) -> Vec<(usize, String, Vec<Vec<String>>)> {
+        bible
+            .bible
+            .iter()
+            .map(|book| (book.id, book.book.clone(), book.content.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let bible = sample();
+        for format in [
+            Format::Json,
+            Format::Usfm,
+            Format::Osis,
+            Format::Csv,
+            Format::Zefania,
+            Format::Verses,
+        ] {
+            let (Some(reader), Some(writer)) = (reader(format), writer(format)) else {
+                continue;
+            };
+            let written = writer.write(&bible).unwrap();
+            let read = reader.read(&written).unwrap();
+            assert_eq!(books(&read), books(&bible), "{format:?}");
+        }
+        let osis = Osis.read(&Osis.write(&bible).unwrap()).unwrap();
+        assert_eq!(osis.translation.name, "Sample & Test");
+        assert_eq!(osis.translation.abbreviation, "SMP");
+    }
+
+    #[test]
+    fn zefania_keeps_only_verse_text() {
+        let xml = r#"<?xml version="1.0"?>
+<XMLBIBLE biblename="Sample">
+<INFORMATION><title>Sample Bible</title><identifier>SMP</identifier><language>ENG</language></INFORMATION>
+<BIBLEBOOK bnumber="57" bname="Philemon" bsname="Phm">
+<CHAPTER cnumber="1">
+<VERS vnumber="1">Paul, a <gr str="1198">prisoner</gr> &amp; more<NOTE type="x-studynote">Or bondservant</NOTE></VERS>
+<VERS vnumber="2">To Apphia</VERS>
+</CHAPTER>
+</BIBLEBOOK>
+</XMLBIBLE>"#;
+        let bible = Zefania.read(xml).unwrap();
+        assert_eq!(bible.translation.abbreviation, "SMP");
+        assert_eq!(bible.translation.language, "English");
+        assert_eq!(bible.bible[0].abbreviations, ["Phm"]);
+        assert_eq!(
+            bible.bible[0].content[0],
+            ["Paul, a prisoner & more", "To Apphia"]
+        );
+        // and straight from the file at startup
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smp.xml");
+        std::fs::write(&path, xml).unwrap();
+        let api = crate::bible_api::BibleAPI::load(&path).unwrap();
+        assert_eq!(api.get_book_id("phm"), Some(57));
+        assert_eq!(api.translation.abbreviation, "SMP");
+    }
+
+    #[cfg(feature = "mybible")]
+    #[test]
+    fn mybible_modules_keep_only_verse_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("SMP.SQLite3");
+        let db = rusqlite::Connection::open(&path).unwrap();
+        db.execute_batch(
+            "CREATE TABLE info (name TEXT, value TEXT);
+            INSERT INTO info VALUES ('description', 'Sample Bible'), ('language', 'en');
+            CREATE TABLE books (book_number NUMERIC, long_name TEXT);
+            INSERT INTO books VALUES (710, 'Philemon');
+            CREATE TABLE verses (book_number INT, chapter INT, verse INT, text TEXT);
+            INSERT INTO verses VALUES
+                (710, 1, 1, 'Paul, a prisoner<S>1198</S> <f>[1]</f>of Christ'),
+                (171, 1, 1, 'Tobit');",
+        )
+        .unwrap();
+        drop(db);
+        let bible = read_file(Format::MyBible, &path).unwrap();
+        assert_eq!(bible.translation.name, "Sample Bible");
+        assert_eq!(bible.translation.language, "English");
+        assert_eq!(bible.bible.len(), 1);
+        assert_eq!(bible.bible[0].id, 57);
+        assert_eq!(bible.bible[0].content[0], ["Paul, a prisoner of Christ"]);
+    }
+
+    #[cfg(feature = "usfm")]
+    #[test]
+    fn usfm_keeps_only_verse_text() {
+        let usfm = concat!(
+            "\\id PHM Sample\n",
+            "\\h Philemon\n",
+            "\\s1 Greeting\n",
+            "\\c 1\n",
+            "\\p\n",
+            "\\v 1 Paul, a \\w prisoner|strong=\"G1198\"\\w* for Christ Jesus,\\f + \\ft Or bondservant\\f*\n",
+            "and Timothy \\add our\\add* brother, \\v 2 To Apphia\n",
+        );
+        let bible = Usfm.read(usfm).unwrap();
+        assert_eq!(
+            bible.bible[0].content[0],
+            [
+                "Paul, a prisoner for Christ Jesus, and Timothy our brother,",
+                "To Apphia"
+            ]
+        );
+        assert_eq!(bible.bible[0].headings["1:1"], "Greeting");
+        let written = Usfm.write(&bible).unwrap();
+        assert!(
+            written.contains("\\c 1\n\\s1 Greeting\n\\p\n\\v 1 Paul"),
+            "{written}"
+        );
+        assert_eq!(
+            Usfm.read(&written).unwrap().bible[0].headings,
+            bible.bible[0].headings
+        );
+        let err = Usfm.read("\\id XYZ\n").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown book code XYZ on line 1");
+    }
+
+    #[cfg(feature = "usfm")]
+    #[test]
+    fn usfm_keeps_poetry_lines() {
+        let usfm = concat!(
+            "\\id PSA\n",
+            "\\c 23\n",
+            "\\q1 \\v 1 The LORD is my shepherd;\n",
+            "\\q2 I shall not want.\n",
+            "\\b\n",
+            "\\q1 \\v 2 He makes me lie down\n",
+            "\\q2 in green pastures.\n",
+        );
+        let book = &Usfm.read(usfm).unwrap().bible[0];
+        assert_eq!(
+            book.content[22][0],
+            "The LORD is my shepherd; I shall not want."
+        );
+        assert_eq!(book.layout["23:1"].lines, [[0, 0], [25, 1]]);
+        assert_eq!(
+            book.layout["23:2"],
+            VerseLayout {
+                paragraph: false,
+                stanza: true,
+                lines: vec![[0, 0], [21, 1]],
+            }
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{bible_api::BibleAPI, workspace_index::WorkspaceIndex};
+
+#[derive(Clone, Copy, Debug)]
+pub enum CoverageFormat {
+    Json,
+    Csv,
+}
+
+/// How much of a chapter, book, or the whole Bible is cited
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageCount {
+    /// number of references that touch it
+    pub citations: usize,
+    /// distinct verses cited at least once
+    pub verses_cited: usize,
+    pub verses: usize,
+    /// `verses_cited / verses` as 0-100
+    pub percent: f64,
+}
+
+impl CoverageCount {
+    fn finish(&mut self) {
+        self.percent = match self.verses {
+            0 => 0.0,
+            verses => (self.verses_cited as f64 / verses as f64 * 1000.0).round() / 10.0,
+        };
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterCoverage {
+    pub chapter: usize,
+    #[serde(flatten)]
+    pub count: CoverageCount,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookCoverage {
+    pub book_id: usize,
+    pub book: Arc<str>,
+    #[serde(flatten)]
+    pub count: CoverageCount,
+    pub chapters: Vec<ChapterCoverage>,
+}
+
+/**
+- Which parts of the Bible the workspace cites, for rendering a heatmap
+- Every book and chapter of the translation is included (even with 0 citations) so the heatmap
+  has no holes
+*/
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Coverage {
+    #[serde(flatten)]
+    pub total: CoverageCount,
+    pub books: Vec<BookCoverage>,
+}
+
+impl Coverage {
+    pub fn build(api: &BibleAPI, index: &WorkspaceIndex) -> Self {
+        // (book, chapter) -> citations and verses cited
+        let mut citations: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        let mut cited: BTreeSet<(usize, usize, usize)> = BTreeSet::new();
+        for indexed in index.files().values().flatten() {
+            let verses = indexed.verses(api);
+            let chapters: BTreeSet<usize> = verses.iter().map(|(chapter, _)| *chapter).collect();
+            for chapter in chapters {
+                *citations.entry((indexed.book_id, chapter)).or_default() += 1;
+            }
+            cited.extend(
+                verses
+                    .into_iter()
+                    .map(|(chapter, verse)| (indexed.book_id, chapter, verse)),
+            );
+        }
+
+        let mut total = CoverageCount::default();
+        let mut books = vec![];
+        for book_id in api.canon_order.iter().copied() {
+            let Some(book) = api.get_book_name(book_id) else {
+                continue;
+            };
+            let mut book_count = CoverageCount::default();
+            let chapters = api
+                .get_all_chapters(book_id)
+                .into_iter()
+                .flatten()
+                .map(|chapter| {
+                    let mut count = CoverageCount {
+                        citations: citations.get(&(book_id, chapter)).copied().unwrap_or(0),
+                        verses_cited: cited
+                            .range((book_id, chapter, 0)..(book_id, chapter + 1, 0))
+                            .count(),
+                        verses: api.get_chapter_verse_count(book_id, chapter).unwrap_or(0),
+                        percent: 0.0,
+                    };
+                    count.finish();
+                    book_count.verses_cited += count.verses_cited;
+                    book_count.verses += count.verses;
+                    ChapterCoverage { chapter, count }
+                })
+                .collect();
+            // a reference spanning chapters is still one citation of the book
+            book_count.citations = index
+                .files()
+                .values()
+                .flatten()
+                .filter(|indexed| indexed.book_id == book_id)
+                .count();
+            book_count.finish();
+            total.citations += book_count.citations;
+            total.verses_cited += book_count.verses_cited;
+            total.verses += book_count.verses;
+            books.push(BookCoverage {
+                book_id,
+                book,
+                count: book_count,
+                chapters,
+            });
+        }
+        total.finish();
+        Self { total, books }
+    }
+
+    pub fn export(&self, format: CoverageFormat) -> Value {
+        match format {
+            CoverageFormat::Json => serde_json::to_value(self).unwrap_or_default(),
+            CoverageFormat::Csv => Value::String(self.to_csv()),
+        }
+    }
+
+    /// - One row per chapter, then a row for the whole book with `chapter` left empty
+    /// - The last row is the whole Bible
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("book,chapter,citations,verses_cited,verses,percent\n");
+        let mut row = |book: &str, chapter: Option<usize>, count: &CoverageCount| {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                csv_field(book),
+                chapter
+                    .map(|chapter| chapter.to_string())
+                    .unwrap_or_default(),
+                count.citations,
+                count.verses_cited,
+                count.verses,
+                count.percent
+            ));
+        };
+        for book in self.books.iter() {
+            for chapter in book.chapters.iter() {
+                row(&book.book, Some(chapter.chapter), &chapter.count);
+            }
+            row(&book.book, None, &book.count);
+        }
+        row("", None, &self.total);
+        csv
+    }
+}
+
+/// Book names don't have commas or quotes, but data files are user provided
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
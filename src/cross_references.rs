@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{bible_api::BibleAPI, convert, headings, paths};
+
+/// Where cross references are read from unless `crossReferencesFile` is set
+pub fn default_path() -> PathBuf {
+    paths::data_dir().join("cross-references.tsv")
+}
+
+/// A passage another verse points to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrossReference {
+    pub book_id: usize,
+    /// `(chapter, verse)`
+    pub start: (usize, usize),
+    /// `(chapter, verse)`, the same as `start` for a single verse
+    pub end: (usize, usize),
+    /// how many people found it helpful, where the dataset says
+    pub votes: i64,
+}
+
+/**
+Cross references by the verse they are from, like the Treasury of Scripture Knowledge
+
+```text
+From Verse    To Verse             Votes
+Gen.1.1       John.1.1-John.1.3    368
+John.3.16     Rom.5.8              520
+```
+
+- The tab-separated layout of the openbible.info download, with OSIS ids (see
+  [`convert::BOOKS`]), and the votes column can be left out
+- Lines that aren't a cross reference (the header, comments) are skipped
+- Each verse's cross references are kept most votes first, then in the order they are listed
+*/
+#[derive(Clone, Debug, Default)]
+pub struct CrossReferences {
+    by_verse: HashMap<(usize, usize, usize), Vec<CrossReference>>,
+}
+
+impl CrossReferences {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut by_verse: HashMap<(usize, usize, usize), Vec<CrossReference>> = HashMap::new();
+        for line in text.lines() {
+            let mut columns = line.split('\t');
+            let (Some(from), Some(to)) = (columns.next(), columns.next()) else {
+                continue;
+            };
+            let Some((book_id, chapter, verse)) = osis_verse(from) else {
+                continue;
+            };
+            let Some(target) = osis_passage(to) else {
+                continue;
+            };
+            let votes = columns
+                .next()
+                .and_then(|votes| votes.trim().parse().ok())
+                .unwrap_or(0);
+            by_verse
+                .entry((book_id, chapter, verse))
+                .or_default()
+                .push(CrossReference { votes, ..target });
+        }
+        for references in by_verse.values_mut() {
+            // stable, so ties keep the dataset's order
+            references.sort_by_key(|reference| std::cmp::Reverse(reference.votes));
+        }
+        Self { by_verse }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_verse.is_empty()
+    }
+
+    /// The cross references from a verse, most votes first
+    pub fn of(&self, book_id: usize, chapter: usize, verse: usize) -> &[CrossReference] {
+        self.by_verse
+            .get(&(book_id, chapter, verse))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// `John.3.16`
+fn osis_verse(id: &str) -> Option<(usize, usize, usize)> {
+    let mut parts = id.trim().split('.');
+    let book_id = convert::book_id(parts.next()?)?;
+    let chapter = parts.next()?.parse().ok()?;
+    let verse = parts.next()?.parse().ok()?;
+    Some((book_id, chapter, verse))
+}
+
+/// `John.1.1` or `John.1.1-John.1.3`, which can't go into another book
+fn osis_passage(id: &str) -> Option<CrossReference> {
+    let (start, end) = match id.split_once('-') {
+        Some((start, end)) => (osis_verse(start)?, osis_verse(end)?),
+        None => (osis_verse(id)?, osis_verse(id)?),
+    };
+    if start.0 != end.0 || (end.1, end.2) < (start.1, start.2) {
+        return None;
+    }
+    Some(CrossReference {
+        book_id: start.0,
+        start: (start.1, start.2),
+        end: (end.1, end.2),
+        votes: 0,
+    })
+}
+
+/**
+`See also: Romans 5:8; 1 John 4:9`, for the end of a single verse's hover
+
+- At most `max` of them, and only ones in books the translation has
+- `None` when there are none to show
+*/
+pub fn see_also(api: &BibleAPI, references: &[CrossReference], max: usize) -> Option<String> {
+    let labels: Vec<String> = references
+        .iter()
+        .filter(|reference| api.get_book_name(reference.book_id).is_some())
+        .take(max)
+        .map(|reference| {
+            headings::span_reference(reference.book_id, reference.start, reference.end)
+                .full_ref_label(api)
+        })
+        .collect();
+    (!labels.is_empty()).then(|| format!("See also: {}", labels.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_references_are_read_most_votes_first() {
+        let api = BibleAPI::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let references = CrossReferences::parse(
+            "From Verse\tTo Verse\tVotes\n\
+             John.3.16\tGen.1.2\t3\n\
+             John.3.16\tGen.2.1-Gen.2.3\t40\n\
+             John.3.16\tMatt.1.1\t12\n\
+             John.3.16\tNope.1.1\t99\n",
+        );
+        let from = references.of(43, 3, 16);
+        assert_eq!(from.len(), 3);
+        assert_eq!(from[0].votes, 40);
+        assert_eq!(references.of(43, 3, 17), []);
+        assert_eq!(
+            see_also(&api, from, 5).unwrap(),
+            "See also: Genesis 2:1-3; Matthew 1:1; Genesis 1:2"
+        );
+        assert_eq!(see_also(&api, from, 1).unwrap(), "See also: Genesis 2:1-3");
+    }
+}
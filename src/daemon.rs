@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[cfg(feature = "search")]
+use std::sync::Weak;
+
+#[cfg(unix)]
+use crate::paths::check_private;
+#[cfg(feature = "search")]
+use crate::workspace_index::WorkspaceIndex;
+use crate::{bible_lsp::BibleLSP, error};
+
+/// A loaded translation, with its file's modification time when it was loaded
+type LoadedTranslation = (Option<SystemTime>, Arc<BibleLSP>);
+
+/// The sorted workspace roots, and the fingerprint of the settings an index depends on
+#[cfg(feature = "search")]
+type IndexKey = (Vec<PathBuf>, u64);
+
+/**
+What the clients of one `bible_lsp --daemon` share
+
+- Each translation is loaded once, and its text is shared by every clone of the API (see
+  [`crate::bible_api::BibleAPI::bible_contents`])
+- Workspace indexes are shared by clients that opened the same folders with the same translation
+  and settings, so a second window on a vault doesn't scan and hold it again
+*/
+#[derive(Debug, Default)]
+pub struct Shared {
+    /// - Kept for as long as the daemon runs, since reloading is what it's there to avoid
+    /// - Loaded again once the file's modification time changes
+    translations: Mutex<HashMap<PathBuf, LoadedTranslation>>,
+    /// - Only shared by clients with the same key, so they'd build the same index
+    /// - Dropped once the last client using one leaves
+    #[cfg(feature = "search")]
+    indexes: Mutex<HashMap<IndexKey, Weak<WorkspaceIndex>>>,
+}
+
+impl Shared {
+    /**
+    The translation at `path`, loaded by the first client that asked for it
+
+    - Loaded again once the file has changed since
+    - Loading is on a blocking thread and outside the lock, so other clients aren't held up by
+      it, even if two that ask at once both end up loading it
+    */
+    pub async fn translation(&self, path: &Path) -> error::Result<BibleLSP> {
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if let Some((_, lsp)) = self
+            .translations
+            .lock()
+            .unwrap()
+            .get(path)
+            .filter(|(loaded, _)| modified.is_some() && *loaded == modified)
+        {
+            return Ok(BibleLSP::clone(lsp));
+        }
+        let lsp = tokio::task::spawn_blocking({
+            let path = path.to_path_buf();
+            move || BibleLSP::load(&path)
+        })
+        .await
+        .map_err(|err| error::Error::read(path, std::io::Error::other(err.to_string())))??;
+        self.translations
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (modified, Arc::new(lsp.clone())));
+        Ok(lsp)
+    }
+
+    /// - The index of the workspace with these roots, shared with any other client that has it
+    ///   with the same `settings` (see `Backend::index_settings`)
+    /// - Clients without a workspace each get their own
+    #[cfg(feature = "search")]
+    pub fn index(&self, roots: &[PathBuf], settings: u64) -> Arc<WorkspaceIndex> {
+        if roots.is_empty() {
+            return Default::default();
+        }
+        let mut roots = roots.to_vec();
+        roots.sort();
+        let key = (roots, settings);
+        let mut indexes = self.indexes.lock().unwrap();
+        indexes.retain(|_, index| index.strong_count() > 0);
+        if let Some(index) = indexes.get(&key).and_then(Weak::upgrade) {
+            return index;
+        }
+        let index = Arc::new(WorkspaceIndex::default());
+        indexes.insert(key, Arc::downgrade(&index));
+        index
+    }
+}
+
+/// Where the daemon listens unless it's given a path
+pub fn socket_path() -> PathBuf {
+    crate::paths::runtime_dir().join("bible_lsp.sock")
+}
+
+/**
+Serves every client that connects to the socket at `path`, until the process is killed
+
+- Translations are loaded by the clients' `initialize`, once for all of them instead of once per
+  editor window
+- Each client still gets its own documents and settings
+- A socket left behind by a daemon that didn't shut down cleanly is replaced, but a live one is
+  an error
+*/
+#[cfg(unix)]
+pub async fn serve(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    if tokio::net::UnixStream::connect(path).await.is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("A daemon is already listening on {}", path.display()),
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        // only the user should be able to reach their daemon
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+        check_private(parent)?;
+    }
+    _ = std::fs::remove_file(path);
+    let listener = tokio::net::UnixListener::bind(path)?;
+    let shared = Arc::new(Shared::default());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let (service, socket) =
+            crate::server::build_shared_service(BibleLSP::empty(), shared.clone());
+        tokio::spawn(async move {
+            let (read, write) = stream.into_split();
+            tower_lsp::Server::new(read, write, socket)
+                .serve(service)
+                .await;
+        });
+    }
+}
+
+/**
+Connects the editor on stdio to the daemon at `path`, starting one first if none is running
+
+- This is what editors should run, `bible_lsp --connect`, so they don't have to know about sockets
+- Returns when either side hangs up
+*/
+#[cfg(unix)]
+pub async fn connect(path: &Path) -> io::Result<()> {
+    // a missing folder is made by the daemon, which checks it then
+    if let Some(parent) = path.parent().filter(|parent| parent.exists()) {
+        check_private(parent)?;
+    }
+    let stream = match tokio::net::UnixStream::connect(path).await {
+        Ok(stream) => stream,
+        Err(_) => {
+            start(path)?;
+            wait_for(path).await?
+        }
+    };
+    let (mut read, mut write) = stream.into_split();
+    let (mut stdin, mut stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    tokio::select! {
+        copied = tokio::io::copy(&mut stdin, &mut write) => copied?,
+        copied = tokio::io::copy(&mut read, &mut stdout) => copied?,
+    };
+    Ok(())
+}
+
+/// - Starts `bible_lsp --daemon` in the background
+/// - In its own process group, so it outlives the editor that started it
+#[cfg(unix)]
+fn start(path: &Path) -> io::Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    Command::new(std::env::current_exe()?)
+        .arg("--daemon")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    Ok(())
+}
+
+/// Loading a translation takes a moment, so this retries for a few seconds
+#[cfg(unix)]
+async fn wait_for(path: &Path) -> io::Result<tokio::net::UnixStream> {
+    let mut attempts = 0;
+    loop {
+        match tokio::net::UnixStream::connect(path).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) if attempts == 50 => {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("The daemon didn't start on {}: {err}", path.display()),
+                ))
+            }
+            Err(_) => attempts += 1,
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_folders_have_to_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let private = dir.path().join("private");
+        std::fs::create_dir(&private).unwrap();
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(check_private(&private).is_ok());
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o777)).unwrap();
+        let err = check_private(&private).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&private, &link).unwrap();
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(check_private(&link).is_err());
+    }
+
+    #[tokio::test]
+    async fn translations_are_loaded_again_once_their_file_changes() {
+        let fixture = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ))
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bible.json");
+        std::fs::write(&path, &fixture).unwrap();
+        let shared = Shared::default();
+        let name = |lsp: BibleLSP| lsp.api.translation.name;
+        assert_eq!(name(shared.translation(&path).await.unwrap()), "Test Bible");
+        assert_eq!(name(shared.translation(&path).await.unwrap()), "Test Bible");
+
+        std::fs::write(&path, fixture.replace("Test Bible", "Edited Bible")).unwrap();
+        // the same time on file systems that only keep whole seconds otherwise
+        let later = SystemTime::now() + std::time::Duration::from_secs(2);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(
+            name(shared.translation(&path).await.unwrap()),
+            "Edited Bible"
+        );
+    }
+
+    #[cfg(feature = "search")]
+    #[test]
+    fn windows_on_the_same_workspace_share_an_index() {
+        let shared = Shared::default();
+        let (notes, sermons) = (PathBuf::from("/notes"), PathBuf::from("/sermons"));
+        let first = shared.index(&[notes.clone(), sermons.clone()], 1);
+        let second = shared.index(&[sermons.clone(), notes.clone()], 1);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(
+            &first,
+            &shared.index(std::slice::from_ref(&notes), 1)
+        ));
+        // another translation or other settings
+        assert!(!Arc::ptr_eq(
+            &first,
+            &shared.index(&[notes.clone(), sermons.clone()], 2)
+        ));
+        assert!(!Arc::ptr_eq(&shared.index(&[], 1), &shared.index(&[], 1)));
+
+        drop((first, second));
+        assert!(shared.indexes.lock().unwrap()[&(vec![notes, sermons], 1)]
+            .upgrade()
+            .is_none());
+    }
+}
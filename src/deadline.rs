@@ -0,0 +1,38 @@
+use std::time::{Duration, Instant};
+
+/**
+A soft time limit for a request handler, see [`crate::config::Timeouts`]
+
+- Handlers check it between pieces of work (a chunk of lines, a rendered passage) and return what
+  they have so far once it passes, instead of keeping the editor waiting
+- Nothing is interrupted, so a handler can run a little over
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    start: Instant,
+    /// `None` never expires
+    limit: Option<Duration>,
+}
+
+impl Deadline {
+    /// `0` means no limit
+    pub fn after_millis(millis: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            limit: (millis > 0).then(|| Duration::from_millis(millis)),
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    pub fn expired(&self) -> bool {
+        self.limit
+            .is_some_and(|limit| self.start.elapsed() >= limit)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
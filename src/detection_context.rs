@@ -0,0 +1,156 @@
+use std::ops::Range;
+
+use serde::Deserialize;
+
+/// The contexts that have names, for the `contexts` setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NamedContext {
+    /// `[[John 3:16]]`
+    Wikilink,
+    /// `` `John 3:16` ``, inline code on one line
+    Code,
+    /// `> John 3:16`, the rest of a line starting with a quote marker
+    Quote,
+}
+
+/**
+Somewhere references are recognized, when only some places should count
+
+- Ex: `["wikilink", "quote", { "open": "{{", "close": "}}" }]`
+- Prose like `Numbers 3` in a normal sentence is then left alone
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum DetectionContext {
+    Named(NamedContext),
+    /// between two delimiters of your own, which can span lines
+    Delimited {
+        open: String,
+        close: String,
+    },
+}
+
+/**
+The byte ranges of `text` references can be in, or `None` for everywhere (no contexts)
+
+- Ranges are the inside of the delimiters, and overlapping ones are left as they are since only
+  containment is checked
+- Parts of a document parsed on their own (the lines a hover or other range request is about,
+  or a chunk under a deadline) are widened to the delimiters around them first, see
+  [`multiline_spans`]
+*/
+pub fn allowed_spans(text: &str, contexts: &[DetectionContext]) -> Option<Vec<Range<usize>>> {
+    if contexts.is_empty() {
+        return None;
+    }
+    let mut spans = vec![];
+    for context in contexts {
+        match context {
+            DetectionContext::Named(NamedContext::Wikilink) => {
+                spans.extend(delimited(text, "[[", "]]", false))
+            }
+            DetectionContext::Named(NamedContext::Code) => {
+                spans.extend(delimited(text, "`", "`", false))
+            }
+            DetectionContext::Named(NamedContext::Quote) => spans.extend(quoted_lines(text)),
+            DetectionContext::Delimited { open, close } => {
+                spans.extend(delimited(text, open, close, true))
+            }
+        }
+    }
+    Some(spans)
+}
+
+/**
+The byte ranges of the [`DetectionContext::Delimited`] contexts in `text`, delimiters included
+
+- The only contexts that can span lines, so what a few lines of a document have to be widened to
+  for them to be parsed the same as the whole document
+- Empty when no delimited context is configured
+*/
+pub fn multiline_spans(text: &str, contexts: &[DetectionContext]) -> Vec<Range<usize>> {
+    let mut spans = vec![];
+    for context in contexts {
+        if let DetectionContext::Delimited { open, close } = context {
+            spans.extend(
+                delimited(text, open, close, true)
+                    .into_iter()
+                    .map(|span| span.start - open.len()..span.end + close.len()),
+            );
+        }
+    }
+    spans
+}
+
+/// Between each `open` and the `close` after it, skipping an `open` that is never closed
+fn delimited(text: &str, open: &str, close: &str, multiline: bool) -> Vec<Range<usize>> {
+    let mut spans = vec![];
+    if open.is_empty() || close.is_empty() {
+        return spans;
+    }
+    let mut from = 0;
+    while let Some(start) = text[from..].find(open).map(|idx| from + idx + open.len()) {
+        let Some(end) = text[start..].find(close).map(|idx| start + idx) else {
+            break;
+        };
+        if !multiline && text[start..end].contains('\n') {
+            // the next line might still open one of its own
+            from = start + text[start..end].find('\n').expect("Just checked");
+            continue;
+        }
+        spans.push(start..end);
+        from = end + close.len();
+    }
+    spans
+}
+
+/// The rest of every line starting with `>`, nested quotes too
+fn quoted_lines(text: &str) -> Vec<Range<usize>> {
+    let mut spans = vec![];
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('>') {
+            let start = line_start + (line.len() - trimmed.len()) + 1;
+            spans.push(start..line_start + line.trim_end_matches(['\n', '\r']).len());
+        }
+        line_start += line.len();
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_are_inside_the_delimiters() {
+        let text = "Numbers 3 and [[John 3:16]]\n> Rom 8:28\n`Eph 2:8` but `open\nline`";
+        let contexts: Vec<DetectionContext> =
+            serde_json::from_str(r#"["wikilink", "quote", "code"]"#).unwrap();
+        let inside: Vec<&str> = allowed_spans(text, &contexts)
+            .unwrap()
+            .into_iter()
+            .map(|span| &text[span])
+            .collect();
+        assert_eq!(inside, ["John 3:16", " Rom 8:28", "Eph 2:8"]);
+
+        let custom: Vec<DetectionContext> =
+            serde_json::from_str(r#"[{ "open": "{{", "close": "}}" }]"#).unwrap();
+        let text = "{{Gen 1:1,\nGen 1:2}} Gen 1:3";
+        let inside: Vec<&str> = allowed_spans(text, &custom)
+            .unwrap()
+            .into_iter()
+            .map(|span| &text[span])
+            .collect();
+        assert_eq!(inside, ["Gen 1:1,\nGen 1:2"]);
+        assert_eq!(allowed_spans(text, &[]), None);
+        let outside: Vec<&str> = multiline_spans(text, &custom)
+            .into_iter()
+            .map(|span| &text[span])
+            .collect();
+        assert_eq!(outside, ["{{Gen 1:1,\nGen 1:2}}"]);
+        assert!(multiline_spans(text, &contexts).is_empty());
+    }
+}
@@ -0,0 +1,412 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::OnceCell;
+use tower_lsp::lsp_types::{Position, Url};
+
+use crate::{
+    bible_lsp::BibleLSP, book_reference::BookReference, config::Limits, deadline::Deadline,
+    detection_context, document_filter::DocumentFilter,
+};
+
+/// How many lines [`DocumentSnapshot::references_before`] parses between deadline checks
+const CHUNK_LINES: u32 = 500;
+
+/// - Byte offsets of the start of every line in a document
+/// - Built once per document version so handlers don't rescan the text with `.lines().nth()`
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            text.bytes()
+                .enumerate()
+                .filter(|(_, byte)| *byte == b'\n')
+                .map(|(idx, _)| idx + 1),
+        );
+        Self { line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The byte range of the lines, including the line ending of the last one
+    pub fn line_range(&self, text: &str, lines: Range<u32>) -> Range<usize> {
+        let offset = |line: u32| {
+            self.line_starts
+                .get(line as usize)
+                .copied()
+                .unwrap_or(text.len())
+        };
+        offset(lines.start)..offset(lines.end).max(offset(lines.start))
+    }
+
+    /// - Converts a byte offset into an LSP position
+    /// - The character is counted in UTF-16 code units, which is what LSP uses by default
+    pub fn position(&self, text: &str, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        Position {
+            line: line as u32,
+            character: text[line_start..offset].encode_utf16().count() as u32,
+        }
+    }
+
+    /// - Converts an LSP position back into a byte offset
+    /// - Characters past the end of the line are clamped to the end of the line
+    pub fn offset(&self, text: &str, position: Position) -> Option<usize> {
+        let line_start = *self.line_starts.get(position.line as usize)?;
+        let line = self.line(text, position.line as usize)?;
+        let mut utf16 = 0;
+        for (idx, ch) in line.char_indices() {
+            if utf16 >= position.character as usize {
+                return Some(line_start + idx);
+            }
+            utf16 += ch.len_utf16();
+        }
+        Some(line_start + line.len())
+    }
+
+    /// Returns the line without its line ending (`\n` or `\r\n`)
+    pub fn line<'a>(&self, text: &'a str, line: usize) -> Option<&'a str> {
+        let start = *self.line_starts.get(line)?;
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map(|next| next - 1)
+            .unwrap_or(text.len());
+        Some(text[start..end].trim_end_matches('\r'))
+    }
+}
+
+/**
+An immutable view of a document at a specific version
+
+- Handlers grab an `Arc<DocumentSnapshot>` and release the store lock right away, so a
+  `did_change` that lands mid-request can't change the text out from under them
+- The parsed references are computed at most once per version, no matter how many hover,
+  diagnostic, and code action requests ask for them
+- Documents over [`Limits::max_document_size`] are never parsed whole, only the lines a request
+  is about
+*/
+#[derive(Debug)]
+pub struct DocumentSnapshot {
+    pub uri: Url,
+    pub version: i32,
+    pub text: String,
+    pub line_index: LineIndex,
+    /// from `didOpen`, empty for files read from disk
+    pub language_id: String,
+    /// - `false` for documents [`DocumentFilter`] leaves out, which have no references
+    /// - Files read from disk are always detected in, since they are only read for workspace
+    ///   commands
+    pub detected: bool,
+    limits: Limits,
+    references: OnceCell<Vec<BookReference>>,
+}
+
+impl DocumentSnapshot {
+    pub fn new(uri: Url, version: i32, text: String, limits: Limits) -> Self {
+        let line_index = LineIndex::new(&text);
+        Self {
+            uri,
+            version,
+            text,
+            line_index,
+            language_id: String::new(),
+            detected: true,
+            limits,
+            references: OnceCell::new(),
+        }
+    }
+
+    /// Too big to parse every reference in it
+    pub fn is_large(&self) -> bool {
+        self.text.len() > self.limits.max_document_size
+    }
+
+    /// - Why some references in the document are being left out, if any are
+    /// - Shown as a warning so it's clear why diagnostics stop partway
+    pub fn truncation_warning(&self, lsp: &BibleLSP) -> Option<String> {
+        if self.is_large() {
+            return Some(format!(
+                "This document is larger than {} bytes, so references are only found in hovers and code actions",
+                self.limits.max_document_size
+            ));
+        }
+        (self.references(lsp).len() >= self.limits.max_references).then(|| {
+            format!(
+                "Only the first {} references in this document are checked",
+                self.limits.max_references
+            )
+        })
+    }
+
+    pub fn line(&self, line: u32) -> Option<&str> {
+        self.line_index.line(&self.text, line as usize)
+    }
+
+    /// The text in `range`, like a reference as it was written
+    pub fn text_at(&self, range: tower_lsp::lsp_types::Range) -> Option<&str> {
+        let start = self.line_index.offset(&self.text, range.start)?;
+        let end = self.line_index.offset(&self.text, range.end)?;
+        self.text.get(start..end)
+    }
+
+    /// - All references in the document, parsed on first use
+    /// - At most [`Limits::max_references`], and none at all for large documents
+    pub fn references(&self, lsp: &BibleLSP) -> &[BookReference] {
+        self.references.get_or_init(|| {
+            if self.is_large() || !self.detected {
+                return vec![];
+            }
+            let mut refs = lsp.find_book_references(&self.text).unwrap_or_default();
+            refs.truncate(self.limits.max_references);
+            refs
+        })
+    }
+
+    /**
+    References that start on the lines
+
+    - Range requests (hovers, code actions, inlay hints for what is on screen) only need a few
+      lines, so only the text of those lines (and any delimited context around them) is parsed
+    - If the whole document was already parsed (or the lines are most of it anyway) the full list
+      is used instead
+    - Large documents only ever have the requested lines parsed
+    */
+    pub fn references_in_lines(&self, lsp: &BibleLSP, lines: Range<u32>) -> Vec<BookReference> {
+        let most_of_document = lines.len() * 2 > self.line_index.line_count();
+        if !self.is_large() && (self.references.get().is_some() || most_of_document) {
+            return self
+                .references(lsp)
+                .iter()
+                .filter(|book_ref| lines.contains(&book_ref.range.start.line))
+                .cloned()
+                .collect();
+        }
+        self.parse_lines(lsp, lines)
+    }
+
+    /**
+    Parses only the text of the lines, with ranges still relative to the whole document
+
+    - Widened to any delimited context the lines are part of, so a hover inside a block that
+      opened lines above finds the same references a full parse does
+    - Only references that start on the lines are kept
+    */
+    fn parse_lines(&self, lsp: &BibleLSP, lines: Range<u32>) -> Vec<BookReference> {
+        if !self.detected {
+            return vec![];
+        }
+        let parsed = self.widen_to_contexts(lsp, lines.clone());
+        let text = &self.text[self.line_index.line_range(&self.text, parsed.clone())];
+        let mut refs = lsp.find_book_references(text).unwrap_or_default();
+        for book_ref in refs.iter_mut() {
+            book_ref.offset(parsed.start, 0);
+        }
+        refs.retain(|book_ref| lines.contains(&book_ref.range.start.line));
+        refs.truncate(self.limits.max_references);
+        refs
+    }
+
+    /// The lines, plus the lines of every delimited context that starts or ends in them
+    fn widen_to_contexts(&self, lsp: &BibleLSP, lines: Range<u32>) -> Range<u32> {
+        let bytes = self.line_index.line_range(&self.text, lines.clone());
+        let line_of = |offset: usize| self.line_index.position(&self.text, offset).line;
+        detection_context::multiline_spans(&self.text, &lsp.contexts)
+            .into_iter()
+            .filter(|span| span.start < bytes.end && span.end > bytes.start)
+            .fold(lines, |widened, span| {
+                widened.start.min(line_of(span.start))..widened.end.max(line_of(span.end - 1) + 1)
+            })
+    }
+
+    /**
+    [`Self::references`], but gives up at the deadline
+
+    - Returns the references found so far, and whether that is all of them
+    - The document is parsed [`CHUNK_LINES`] lines at a time, so a reference split across a chunk
+      boundary can be missed, and an unlimited deadline parses it whole like before
+    - A complete list is kept, so later requests don't parse again
+    */
+    pub fn references_before(
+        &self,
+        lsp: &BibleLSP,
+        deadline: &Deadline,
+    ) -> (Cow<'_, [BookReference]>, bool) {
+        if !deadline.is_limited() || self.is_large() || self.references.get().is_some() {
+            return (Cow::Borrowed(self.references(lsp)), true);
+        }
+        let line_count = self.line_index.line_count() as u32;
+        let mut refs = vec![];
+        let mut line = 0;
+        while line < line_count && refs.len() < self.limits.max_references {
+            if deadline.expired() {
+                return (Cow::Owned(refs), false);
+            }
+            let end = (line + CHUNK_LINES).min(line_count);
+            refs.extend(self.parse_lines(lsp, line..end));
+            line = end;
+        }
+        refs.truncate(self.limits.max_references);
+        (Cow::Borrowed(self.references.get_or_init(|| refs)), true)
+    }
+
+    /// References that start on the given line
+    pub fn references_on_line(&self, lsp: &BibleLSP, line: u32) -> Vec<BookReference> {
+        self.references_in_lines(lsp, line..line.saturating_add(1))
+    }
+}
+
+/// All open documents, keyed by URI
+#[derive(Debug, Default)]
+pub struct DocumentStore {
+    documents: RwLock<BTreeMap<Url, Arc<DocumentSnapshot>>>,
+    /// applied to documents as they are opened or changed
+    limits: RwLock<Limits>,
+    filter: RwLock<DocumentFilter>,
+}
+
+impl DocumentStore {
+    pub fn set_limits(&self, limits: Limits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    /// - Applies to documents already open too, since they are only checked when they change
+    pub fn set_filter(&self, filter: DocumentFilter) {
+        *self.filter.write().unwrap() = filter;
+        self.reparse();
+    }
+
+    /// Drops the references parsed in every open document, like after another translation loads
+    pub fn reparse(&self) {
+        let mut documents = self.documents.write().unwrap();
+        for document in documents.values_mut() {
+            *document = self.snapshot(
+                document.uri.clone(),
+                document.language_id.clone(),
+                document.version,
+                document.text.clone(),
+            );
+        }
+    }
+
+    fn snapshot(
+        &self,
+        uri: Url,
+        language_id: String,
+        version: i32,
+        text: String,
+    ) -> Arc<DocumentSnapshot> {
+        let limits = self.limits.read().unwrap().clone();
+        let detected = self.filter.read().unwrap().allows(&uri, &language_id);
+        let mut snapshot = DocumentSnapshot::new(uri, version, text, limits);
+        snapshot.language_id = language_id;
+        snapshot.detected = detected;
+        Arc::new(snapshot)
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<Arc<DocumentSnapshot>> {
+        self.documents.read().unwrap().get(uri).cloned()
+    }
+
+    /// - The open document, or the file on disk when it isn't open
+    /// - `None` if it isn't open and can't be read
+    pub fn get_or_read(&self, uri: &Url) -> Option<Arc<DocumentSnapshot>> {
+        if let Some(snapshot) = self.get(uri) {
+            return Some(snapshot);
+        }
+        let text = std::fs::read_to_string(crate::paths::url_to_path(uri)?).ok()?;
+        let limits = self.limits.read().unwrap().clone();
+        Some(Arc::new(DocumentSnapshot::new(
+            uri.clone(),
+            0,
+            text,
+            limits,
+        )))
+    }
+
+    pub fn open(&self, uri: Url, language_id: String, version: i32, text: String) {
+        let snapshot = self.snapshot(uri.clone(), language_id, version, text);
+        self.documents.write().unwrap().insert(uri, snapshot);
+    }
+
+    /// - Replaces the document with a new version
+    /// - Out of order changes (an older version than what is stored) are ignored
+    pub fn update(&self, uri: Url, version: i32, text: String) {
+        let language_id = self
+            .get(&uri)
+            .map(|current| current.language_id.clone())
+            .unwrap_or_default();
+        let snapshot = self.snapshot(uri.clone(), language_id, version, text);
+        let mut documents = self.documents.write().unwrap();
+        if documents
+            .get(&uri)
+            .is_some_and(|current| current.version > version)
+        {
+            return;
+        }
+        documents.insert(uri, snapshot);
+    }
+
+    /// Every open document, in URI order
+    pub fn snapshots(&self) -> Vec<Arc<DocumentSnapshot>> {
+        self.documents.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn close(&self, uri: &Url) {
+        self.documents.write().unwrap().remove(uri);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_ranges_include_line_endings() {
+        let text = "one\r\ntwo\nthree";
+        let line_index = LineIndex::new(text);
+        assert_eq!(&text[line_index.line_range(text, 0..1)], "one\r\n");
+        assert_eq!(&text[line_index.line_range(text, 1..3)], "two\nthree");
+        assert_eq!(&text[line_index.line_range(text, 2..10)], "three");
+        assert_eq!(&text[line_index.line_range(text, 5..6)], "");
+    }
+
+    #[test]
+    fn references_stop_at_the_deadline() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let text = "see Gen 1:2 and John 3:16\n".repeat(600);
+        let uri = Url::parse("file:///notes/long.md").unwrap();
+        let snapshot = DocumentSnapshot::new(uri, 1, text, Limits::default());
+
+        let expired = Deadline::after_millis(1);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let (refs, complete) = snapshot.references_before(&lsp, &expired);
+        assert!(refs.is_empty() && !complete);
+
+        let (refs, complete) = snapshot.references_before(&lsp, &Deadline::after_millis(60_000));
+        assert!(complete);
+        assert_eq!(refs.len(), 1_200);
+        assert_eq!(refs[1_199].range.start.line, 599);
+    }
+}
@@ -0,0 +1,114 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tower_lsp::lsp_types::Url;
+
+use crate::config::DocumentsConfig;
+
+/// Language IDs and globs from one list in [`DocumentsConfig`]
+#[derive(Clone, Debug)]
+struct Patterns {
+    languages: Vec<String>,
+    globs: GlobSet,
+}
+
+impl Patterns {
+    /// - Entries with `*`, `?`, `[`, or `/` are globs, anything else is a language ID
+    /// - Globs that don't parse are left out and returned
+    fn new(entries: &[String]) -> (Self, Vec<globset::Error>) {
+        let mut languages = vec![];
+        let mut globs = GlobSetBuilder::new();
+        let mut errors = vec![];
+        for entry in entries {
+            if !entry.contains(['*', '?', '[', '/']) {
+                languages.push(entry.to_lowercase());
+                continue;
+            }
+            match Glob::new(entry) {
+                Ok(glob) => {
+                    globs.add(glob);
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+        let globs = globs.build().unwrap_or_else(|err| {
+            errors.push(err);
+            GlobSet::empty()
+        });
+        (Self { languages, globs }, errors)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.languages.is_empty() && self.globs.is_empty()
+    }
+
+    fn matches(&self, uri: &Url, language_id: &str) -> bool {
+        if self
+            .languages
+            .iter()
+            .any(|language| language.eq_ignore_ascii_case(language_id))
+        {
+            return true;
+        }
+        // untitled buffers and the like only have the path of their URI
+        match crate::paths::url_to_path(uri) {
+            Some(path) => self.globs.is_match(path),
+            None => self.globs.is_match(uri.path()),
+        }
+    }
+}
+
+/**
+Which open documents references are detected in, from `bible.documents`
+
+- Documents that are left out still open normally, but have no references, so hovers,
+  diagnostics, completion, and everything else built on them are skipped
+- An empty `include` means every document, and `exclude` wins over `include`
+*/
+#[derive(Clone, Debug)]
+pub struct DocumentFilter {
+    include: Patterns,
+    exclude: Patterns,
+}
+
+impl Default for DocumentFilter {
+    fn default() -> Self {
+        Self::new(&DocumentsConfig::default()).0
+    }
+}
+
+impl DocumentFilter {
+    /// Also returns the globs that don't parse, so the user can be told
+    pub fn new(config: &DocumentsConfig) -> (Self, Vec<globset::Error>) {
+        let (include, mut errors) = Patterns::new(&config.include);
+        let (exclude, exclude_errors) = Patterns::new(&config.exclude);
+        errors.extend(exclude_errors);
+        (Self { include, exclude }, errors)
+    }
+
+    pub fn allows(&self, uri: &Url, language_id: &str) -> bool {
+        (self.include.is_empty() || self.include.matches(uri, language_id))
+            && !self.exclude.matches(uri, language_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_and_globs_can_be_mixed() {
+        let config = DocumentsConfig {
+            include: ["markdown", "plaintext", "**/*.org"]
+                .map(String::from)
+                .to_vec(),
+            exclude: vec![String::from("**/drafts/**")],
+        };
+        let (filter, errors) = DocumentFilter::new(&config);
+        assert!(errors.is_empty());
+        let uri = |path: &str| Url::parse(&format!("file:///notes/{path}")).unwrap();
+        assert!(filter.allows(&uri("john.md"), "markdown"));
+        assert!(filter.allows(&uri("john.org"), "org"));
+        assert!(!filter.allows(&uri("main.rs"), "rust"));
+        assert!(!filter.allows(&uri("drafts/john.md"), "markdown"));
+        assert!(DocumentFilter::default().allows(&uri("main.rs"), "rust"));
+    }
+}
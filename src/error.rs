@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+/**
+What can go wrong outside of a request, like loading a translation
+
+- The CLI prints these with [`render`], which points at the line and column of bad JSON
+- The server shows these with [`summary`] when the configured translation can't be loaded
+*/
+#[derive(Debug, Error, Diagnostic)]
+pub enum Error {
+    #[error("Couldn't read {}", path.display())]
+    #[diagnostic(code(bible_lsp::read))]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{} isn't a Bible JSON file", source_code.name())]
+    #[diagnostic(
+        code(bible_lsp::parse),
+        help("The file needs a `translation` and a `bible` array of books")
+    )]
+    Parse {
+        #[source_code]
+        source_code: NamedSource<String>,
+        #[label("{message}")]
+        span: SourceSpan,
+        message: String,
+    },
+
+    #[error("Unknown book `{book}` in {name}")]
+    #[diagnostic(code(bible_lsp::unknown_book), help("Book ids start at 1 for Genesis"))]
+    UnknownBook {
+        name: String,
+        book: String,
+        id: usize,
+    },
+
+    #[error("{at} in {name}: {message}")]
+    #[diagnostic(
+        code(bible_lsp::invalid),
+        help("Run `bible_lsp validate` on the file to see every problem with it")
+    )]
+    Invalid {
+        name: String,
+        /// like `bible[3].id`
+        at: String,
+        message: String,
+    },
+
+    #[error("Couldn't convert {name}: {message}")]
+    #[diagnostic(
+        code(bible_lsp::convert),
+        help("`--from` needs to be the format the file is in: json, usfm, osis, or csv")
+    )]
+    Convert { name: String, message: String },
+
+    #[error("{} isn't a valid config file", source_code.name())]
+    #[diagnostic(
+        code(bible_lsp::config),
+        help("Keys are the same as the `initializationOptions`, like `translation` or `[limits]`")
+    )]
+    Config {
+        #[source_code]
+        source_code: NamedSource<String>,
+        #[label("{message}")]
+        span: SourceSpan,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn read(path: &Path, source: std::io::Error) -> Self {
+        Self::Read {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// A TOML error in `text`, see [`crate::config_file`]
+    pub fn config(path: &Path, text: String, err: toml::de::Error) -> Self {
+        Self::Config {
+            span: err.span().unwrap_or(0..0).into(),
+            source_code: NamedSource::new(path.display().to_string(), text),
+            message: err.message().to_string(),
+        }
+    }
+
+    /// A JSON error in `text`, labeled at its line and column
+    pub fn parse(path: &Path, text: String, err: serde_json::Error) -> Self {
+        let offset = text
+            .split_inclusive('\n')
+            .take(err.line().saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>()
+            + err.column().saturating_sub(1);
+        // serde_json's message ends with the position, which the label already shows
+        let message = err.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(end) => message[..end].to_string(),
+            None => message,
+        };
+        Self::Parse {
+            source_code: NamedSource::new(path.display().to_string(), text),
+            span: offset.into(),
+            message,
+        }
+    }
+
+    /// [`Error::parse`], with where in the document the value is, like `bible[39].content[2]`
+    pub fn parse_at(
+        path: &Path,
+        text: String,
+        err: serde_path_to_error::Error<serde_json::Error>,
+    ) -> Self {
+        let at = err.path().to_string();
+        let mut parse = Self::parse(path, text, err.into_inner());
+        if let Self::Parse { message, .. } = &mut parse {
+            // `.` is the whole document
+            if at != "." {
+                *message = format!("{at}: {message}");
+            }
+        }
+        parse
+    }
+
+    /// A problem from [`crate::bible_json::problems`] that keeps the file from loading
+    pub fn invalid(path: &Path, problem: crate::bible_json::Problem) -> Self {
+        Self::Invalid {
+            name: path.display().to_string(),
+            at: problem.at,
+            message: problem.message,
+        }
+    }
+
+    /// A file `bible_lsp convert` couldn't read or write
+    pub fn convert(path: &Path, err: crate::convert::ConvertError) -> Self {
+        Self::Convert {
+            name: path.display().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// - The error with its source lines and labels, for printing to a terminal
+/// - Colored when stderr supports it
+pub fn render(err: Error) -> String {
+    format!("{:?}", miette::Report::new(err))
+}
+
+/// - The error and its causes on one line, for `window/showMessage` where [`render`] won't fit
+/// - Parse errors say which line is wrong
+pub fn summary(err: &Error) -> String {
+    let mut summary = err.to_string();
+    if let Error::Parse {
+        source_code,
+        span,
+        message,
+    }
+    | Error::Config {
+        source_code,
+        span,
+        message,
+    } = err
+    {
+        let line = source_code.inner()[..span.offset()].matches('\n').count() + 1;
+        summary.push_str(&format!(": {message} on line {line}"));
+    }
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        summary.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let text = String::from("{\n  \"translation\": {\n    \"name\": 3\n  }\n}");
+        let err = serde_json::from_str::<crate::bible_json::JSONBible>(&text).unwrap_err();
+        let Error::Parse { span, message, .. } = Error::parse(Path::new("tst.json"), text, err)
+        else {
+            panic!("expected a parse error");
+        };
+        // at the `3`
+        assert_eq!(
+            span.offset(),
+            "{\n  \"translation\": {\n    \"name\": ".len()
+        );
+        assert_eq!(message, "invalid type: integer `3`, expected a string");
+    }
+
+    #[test]
+    fn summaries_include_the_cause() {
+        let path = Path::new("/missing/esv.json");
+        let err = crate::bible_api::BibleAPI::load(path).unwrap_err();
+        assert!(
+            summary(&err).starts_with("Couldn't read /missing/esv.json: "),
+            "{}",
+            summary(&err)
+        );
+        let text = String::from("{\n  \"translation\": 3\n}");
+        let json = serde_json::from_str::<crate::bible_json::JSONBible>(&text).unwrap_err();
+        assert_eq!(
+            summary(&Error::parse(path, text, json)),
+            "/missing/esv.json isn't a Bible JSON file: invalid type: integer `3`, expected struct JSONTranslation on line 2"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use cached::proc_macro::cached;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{bible_lsp::BibleLSP, workspace_index::IndexedReference};
+
+/// Where in the original file some text came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Location {
+    /// 1-based, for PDFs
+    Page(usize),
+    /// 1-based, for DOCX files
+    Paragraph(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Page(page) => write!(f, "page {page}"),
+            Location::Paragraph(paragraph) => write!(f, "paragraph {paragraph}"),
+        }
+    }
+}
+
+/// A reference found in a PDF or DOCX file
+#[derive(Clone, Debug, Serialize)]
+pub struct ExtractedReference {
+    pub location: Location,
+    /// same as the workspace index, with the range relative to the page or paragraph
+    #[serde(flatten)]
+    pub reference: IndexedReference,
+}
+
+/// - Every `<w:p>` paragraph in a DOCX `word/document.xml`
+/// - Empty ones are often `<w:p/>`, which count too so the numbers match the document's
+#[cached(size = 1)]
+fn docx_paragraph() -> Regex {
+    Regex::new(r"(?s)<w:p(?: [^>]*?)?/>|<w:p[ >].*?</w:p>").unwrap()
+}
+
+/// Text runs, tabs, and breaks inside a paragraph
+#[cached(size = 1)]
+fn docx_text() -> Regex {
+    Regex::new(r"(?s)<w:t(?: [^>]*)?>(.*?)</w:t>|<w:tab/>|<w:br/>").unwrap()
+}
+
+fn decode_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// - Runs a companion tool and returns what it printed
+/// - The error says which tool to install, since that is almost always the problem
+fn run(program: &str, args: &[&str]) -> io::Result<String> {
+    let output = Command::new(program).args(args).output().map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Couldn't run {program} (is it installed?): {err}"),
+        )
+    })?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Pages from `pdftotext` (poppler), which separates them with form feeds
+fn pdf_pages(path: &str) -> io::Result<Vec<(Location, String)>> {
+    let text = run("pdftotext", &["-layout", path, "-"])?;
+    Ok(text
+        .split('\u{c}')
+        .enumerate()
+        .map(|(idx, page)| (Location::Page(idx + 1), page.to_string()))
+        .collect())
+}
+
+/// Paragraphs from the document XML, since a DOCX is a zip file
+fn docx_paragraphs(path: &Path) -> io::Result<Vec<(Location, String)>> {
+    use std::io::Read;
+
+    let invalid = |err: zip::result::ZipError| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Couldn't read {} as a DOCX file: {err}", path.display()),
+        )
+    };
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?).map_err(invalid)?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(invalid)?
+        .read_to_string(&mut xml)?;
+    Ok(parse_docx_paragraphs(&xml))
+}
+
+fn parse_docx_paragraphs(xml: &str) -> Vec<(Location, String)> {
+    docx_paragraph()
+        .find_iter(xml)
+        .enumerate()
+        .map(|(idx, paragraph)| {
+            let text: String = docx_text()
+                .captures_iter(paragraph.as_str())
+                .map(|cap| match cap.get(1) {
+                    Some(run) => decode_xml(run.as_str()),
+                    None if &cap[0] == "<w:tab/>" => String::from("\t"),
+                    None => String::from("\n"),
+                })
+                .collect();
+            (Location::Paragraph(idx + 1), text)
+        })
+        .collect()
+}
+
+/**
+Every reference in a PDF or DOCX file, by page or paragraph
+
+- PDF text is extracted by `pdftotext` (from poppler) rather than parsing the format here
+- DOCX files are unzipped and their paragraphs read from the document XML
+*/
+pub fn extract_references(lsp: &BibleLSP, path: &Path) -> io::Result<Vec<ExtractedReference>> {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let path_str = path.to_string_lossy();
+    let chunks = match extension.as_str() {
+        "pdf" => pdf_pages(&path_str)?,
+        "docx" => docx_paragraphs(path)?,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Expected a .pdf or .docx file, got {}", path.display()),
+            ))
+        }
+    };
+    Ok(chunks
+        .into_iter()
+        .flat_map(|(location, text)| {
+            lsp.find_book_references(&text)
+                .unwrap_or_default()
+                .iter()
+                .map(|book_ref| ExtractedReference {
+                    location,
+                    reference: IndexedReference::new(lsp, book_ref),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn docx_paragraphs_keep_their_text() {
+        let xml = r#"<w:body><w:p w:rsidR="1"><w:r><w:t>Read Eph</w:t></w:r><w:r><w:t xml:space="preserve">esians 1:1 &amp; more</w:t></w:r></w:p><w:p/><w:p w:rsidR="2"/><w:p><w:r><w:tab/><w:t>John 3:16</w:t></w:r></w:p><w:pPr/></w:body>"#;
+        assert_eq!(
+            parse_docx_paragraphs(xml),
+            vec![
+                (
+                    Location::Paragraph(1),
+                    String::from("Read Ephesians 1:1 & more")
+                ),
+                (Location::Paragraph(2), String::new()),
+                (Location::Paragraph(3), String::new()),
+                (Location::Paragraph(4), String::from("\tJohn 3:16")),
+            ]
+        );
+    }
+
+    #[test]
+    fn docx_files_are_unzipped() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sermon.docx");
+        let mut zipped = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zipped
+            .start_file(
+                "word/document.xml",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        zipped
+            .write_all(br#"<w:body><w:p/><w:p><w:r><w:t>John 3:16</w:t></w:r></w:p></w:body>"#)
+            .unwrap();
+        zipped.finish().unwrap();
+        assert_eq!(
+            docx_paragraphs(&path).unwrap()[1],
+            (Location::Paragraph(2), String::from("John 3:16"))
+        );
+
+        std::fs::write(&path, "not a zip file").unwrap();
+        let err = docx_paragraphs(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,60 @@
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::{document::DocumentSnapshot, quote_markers};
+
+/**
+Folding ranges for inserted passages in a notes document
+
+- Every marked quote folds from its opening marker to its closing one, shown as its label
+- Every run of two or more lines starting with the same prefix from `prefixes` (see
+  [`crate::bible_formatter::PassageFormatter::verse_per_line`]) folds as one verse block, unless
+  a marked quote already folds exactly those lines
+*/
+pub fn folding_ranges(snapshot: &DocumentSnapshot, prefixes: &[String]) -> Vec<FoldingRange> {
+    let mut ranges: Vec<FoldingRange> = quote_markers::find_quotes(snapshot)
+        .into_iter()
+        .map(|quote| FoldingRange {
+            start_line: quote.range.start.line,
+            end_line: quote.range.end.line,
+            kind: Some(FoldingRangeKind::Region),
+            collapsed_text: Some(quote.label),
+            ..Default::default()
+        })
+        .collect();
+
+    let prefixes: Vec<&String> = prefixes
+        .iter()
+        .filter(|prefix| !prefix.is_empty())
+        .collect();
+    // the prefix of the block being read, and the line it started on
+    let mut block: Option<(&str, u32)> = None;
+    let line_count = snapshot.line_index.line_count() as u32;
+    for line in 0..=line_count {
+        let text = snapshot.line(line).unwrap_or_default();
+        if block.is_some_and(|(prefix, _)| text.starts_with(prefix)) {
+            continue;
+        }
+        if let Some((_, start)) = block.take() {
+            let end = line - 1;
+            let folded = ranges
+                .iter()
+                .any(|range| range.start_line + 1 == start && range.end_line == end + 1);
+            if end > start && !folded {
+                ranges.push(FoldingRange {
+                    start_line: start,
+                    end_line: end,
+                    kind: Some(FoldingRangeKind::Region),
+                    ..Default::default()
+                });
+            }
+        }
+        // longest first, so `>  ` isn't read as `> `
+        block = prefixes
+            .iter()
+            .filter(|prefix| text.starts_with(prefix.as_str()))
+            .max_by_key(|prefix| prefix.len())
+            .map(|prefix| (prefix.as_str(), line));
+    }
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
@@ -0,0 +1,65 @@
+use crate::{bible_api::BibleAPI, convert};
+
+/**
+Markdown footnotes for the verses of a passage, see [`crate::bible_json::JSONBook::footnotes`]
+
+```text
+[3:16] For God so loved the world, ... but have eternal life.[^John.3.16a]
+
+[^John.3.16a]: Or *his only begotten Son*
+```
+
+- Labels name the verse, so passages quoted in the same document don't share them
+- The marker goes at the end of the verse, since the data doesn't say which words a note is on
+*/
+#[derive(Clone, Debug, Default)]
+pub struct MarkdownFootnotes {
+    definitions: Vec<String>,
+}
+
+impl MarkdownFootnotes {
+    /// `content` with a marker for each of the verse's footnotes
+    pub fn mark(
+        &mut self,
+        api: &BibleAPI,
+        book_id: usize,
+        chapter: usize,
+        verse: usize,
+        content: String,
+    ) -> String {
+        let notes = api.get_footnotes(book_id, chapter, verse);
+        if notes.is_empty() {
+            return content;
+        }
+        let mut marked = content;
+        for (idx, note) in notes.iter().enumerate() {
+            let label = label(book_id, chapter, verse, idx);
+            marked.push_str(&format!("[^{label}]"));
+            let definition = format!("[^{label}]: {}", note.trim());
+            // a verse shown twice still has one definition
+            if !self.definitions.contains(&definition) {
+                self.definitions.push(definition);
+            }
+        }
+        marked
+    }
+
+    /// Every footnote marked so far, one per line, for after the passage
+    pub fn definitions(&self) -> Option<String> {
+        (!self.definitions.is_empty()).then(|| self.definitions.join("\n"))
+    }
+}
+
+/// `John.3.16a`, with the OSIS id (see [`convert::BOOKS`]) since labels can't have spaces
+fn label(book_id: usize, chapter: usize, verse: usize, idx: usize) -> String {
+    let book = match convert::book_info(book_id) {
+        Some((_, _, osis, _)) => osis.to_string(),
+        None => book_id.to_string(),
+    };
+    // `a` through `z`, and numbers past that
+    let letter = match u8::try_from(idx).ok().filter(|idx| *idx < 26) {
+        Some(idx) => char::from(b'a' + idx).to_string(),
+        None => (idx + 1).to_string(),
+    };
+    format!("{book}.{chapter}.{verse}{letter}")
+}
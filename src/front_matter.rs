@@ -0,0 +1,225 @@
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::{
+    bible_lsp::BibleLSP, book_reference::BookReference,
+    book_reference_segment::BookReferenceSegment, document::LineIndex,
+};
+
+/// A value in a note's YAML front matter, with where it is in the document
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub key: String,
+    /// without quotes
+    pub value: String,
+    pub range: Range,
+}
+
+/**
+Every value in the front matter at the top of a document, if it has any
+
+- Only what notes actually use: `key: value`, `key: [a, b]`, and `key:` followed by `- item`
+  lines, so each list item is its own [`Field`] with the list's key
+- Nested maps and multi-line strings are skipped rather than misread
+*/
+pub fn fields(text: &str) -> Vec<Field> {
+    let line_index = LineIndex::new(text);
+    let mut lines = (0..line_index.line_count()).filter_map(|line| {
+        let text = line_index.line(text, line)?;
+        Some((line as u32, text.trim_start_matches('\u{feff}')))
+    });
+    if lines.next().map(|(_, line)| line.trim_end()) != Some("---") {
+        return vec![];
+    }
+    let mut fields = vec![];
+    // the key of a `key:` line, for the `- item` lines under it
+    let mut list_key: Option<String> = None;
+    for (number, line) in lines {
+        if matches!(line.trim_end(), "---" | "...") {
+            return fields;
+        }
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if let (Some(key), Some(item)) = (&list_key, trimmed.strip_prefix("- ")) {
+            let start = line.len() - item.len();
+            fields.extend(scalar(key, line, number, start, line.len()));
+            continue;
+        }
+        list_key = None;
+        let Some((raw_key, value)) = line.split_once(':').filter(|_| indent == 0) else {
+            continue;
+        };
+        let key = raw_key.trim().trim_matches(['"', '\'']).to_string();
+        let (start, end) = (raw_key.len() + 1, line.len());
+        let value = value.trim();
+        if value.is_empty() {
+            list_key = Some(key);
+        } else if value.starts_with('[') && value.ends_with(']') {
+            let open = line.find('[').expect("Value starts with it");
+            let mut item_start = open + 1;
+            for item in line[open + 1..line.rfind(']').expect("Value ends with it")].split(',') {
+                fields.extend(scalar(
+                    &key,
+                    line,
+                    number,
+                    item_start,
+                    item_start + item.len(),
+                ));
+                item_start += item.len() + 1;
+            }
+        } else {
+            fields.extend(scalar(&key, line, number, start, end));
+        }
+    }
+    // never closed, so it wasn't front matter after all
+    vec![]
+}
+
+/// The value in `line[start..end]`, trimmed and unquoted
+fn scalar(key: &str, line: &str, number: u32, start: usize, end: usize) -> Option<Field> {
+    let raw = &line[start..end];
+    let mut start = start + (raw.len() - raw.trim_start().len());
+    let mut end = end - (raw.len() - raw.trim_end().len());
+    let trimmed = &line[start..end];
+    let quoted = trimmed.len() >= 2
+        && ((trimmed.starts_with('"') && trimmed.ends_with('"'))
+            || (trimmed.starts_with('\'') && trimmed.ends_with('\'')));
+    if quoted {
+        start += 1;
+        end -= 1;
+    }
+    let value = &line[start..end];
+    if value.is_empty() {
+        return None;
+    }
+    let character = |offset: usize| line[..offset].encode_utf16().count() as u32;
+    Some(Field {
+        key: key.to_string(),
+        value: value.to_string(),
+        range: Range {
+            start: Position {
+                line: number,
+                character: character(start),
+            },
+            end: Position {
+                line: number,
+                character: character(end),
+            },
+        },
+    })
+}
+
+/// A value of one of the configured front matter fields, and the passages in it
+#[derive(Clone, Debug)]
+pub struct Passage {
+    pub field: Field,
+    /// empty when the value isn't a passage, which is reported
+    pub references: Vec<BookReference>,
+    /// - A whole chapter like `John 15`, which is fine here since the value is known to be a
+    ///   passage
+    /// - References in the rest of the text need a verse, so this isn't found like the others
+    pub whole_chapter: bool,
+}
+
+/**
+The values of the fields in `keys` (like `passage`), with the references in them
+
+- Keys are matched case-insensitively
+- Ranges are in the document, so they line up with [`crate::document::DocumentSnapshot::references`]
+*/
+pub fn passages(lsp: &BibleLSP, text: &str, keys: &[String]) -> Vec<Passage> {
+    if keys.is_empty() {
+        return vec![];
+    }
+    fields(text)
+        .into_iter()
+        .filter(|field| keys.iter().any(|key| key.eq_ignore_ascii_case(&field.key)))
+        .map(|field| {
+            let (mut references, whole_chapter) = value_references(lsp, &field.value);
+            for book_ref in references.iter_mut() {
+                match whole_chapter {
+                    true => {
+                        book_ref.range = field.range;
+                        book_ref.segment_ranges.clear();
+                    }
+                    // the value is one line, so its references are all on line 0 of it
+                    false => book_ref.offset(field.range.start.line, field.range.start.character),
+                }
+            }
+            Passage {
+                field,
+                references,
+                whole_chapter,
+            }
+        })
+        .collect()
+}
+
+/**
+The references in a passage value, and whether it is a whole chapter
+
+- Ranges are in `value`, except a whole chapter's, which is left for the caller to set
+- Also for what `bible.notesByPassage` is asked about, so it takes the same values
+*/
+pub fn value_references(lsp: &BibleLSP, value: &str) -> (Vec<BookReference>, bool) {
+    // the value is already somewhere references belong, whatever the contexts are
+    let references = lsp.find_book_references_in(value, &[]).unwrap_or_default();
+    if !references.is_empty() || value.contains(':') {
+        return (references, false);
+    }
+    match whole_chapter_reference(lsp, value) {
+        Some(book_ref) => (vec![book_ref], true),
+        None => (vec![], false),
+    }
+}
+
+/// `John 15` as `John 15:1-27`
+fn whole_chapter_reference(lsp: &BibleLSP, value: &str) -> Option<BookReference> {
+    let value = value.trim();
+    let number_start = value.rfind(|c: char| !c.is_ascii_digit())? + 1;
+    let chapter: usize = value[number_start..].parse().ok()?;
+    let mut book_ref = lsp
+        .find_book_references_in(&format!("{value}:1"), &[])?
+        .into_iter()
+        .next()?;
+    let verse_count = lsp.api.get_chapter_verse_count(book_ref.book_id, chapter)?;
+    book_ref.segments.0 = vec![BookReferenceSegment::from_span(
+        (chapter, 1),
+        (chapter, verse_count),
+    )];
+    Some(book_ref)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_and_list_items_are_fields() {
+        let text = "---\ntitle: Abide\npassage: \"John 15:1-12\"\ntags: [sermon, john]\nscripture:\n  - Rom 8\n  - 'Eph 2:8'\n---\nBody";
+        let fields = fields(text);
+        let values: Vec<(&str, &str)> = fields
+            .iter()
+            .map(|field| (field.key.as_str(), field.value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("title", "Abide"),
+                ("passage", "John 15:1-12"),
+                ("tags", "sermon"),
+                ("tags", "john"),
+                ("scripture", "Rom 8"),
+                ("scripture", "Eph 2:8"),
+            ]
+        );
+        assert_eq!(
+            fields[1].range,
+            Range::new(Position::new(2, 10), Position::new(2, 22))
+        );
+        assert!(super::fields("passage: John 1:1\n").is_empty());
+        assert!(super::fields("---\npassage: John 1:1\n").is_empty());
+    }
+}
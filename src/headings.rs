@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    bible_api::BibleAPI,
+    bible_lsp::BibleLSP,
+    book_reference::BookReference,
+    book_reference_segment::{BookReferenceSegment, BookReferenceSegments},
+    paths,
+};
+
+/// Where the headings are read from unless `headingsFile` is set
+pub fn default_path() -> PathBuf {
+    paths::data_dir().join("headings.json")
+}
+
+/// A verse as `(book_id, chapter, verse)`, which sorts in reading order within a book
+type VerseKey = (usize, usize, usize);
+
+/**
+Section headings (pericope titles), by the verse each section starts at
+
+```json
+{
+  "John 3:1": "You Must Be Born Again",
+  "John 3:22": "John the Baptist Exalts Christ"
+}
+```
+
+- Any way of writing the verse works, since the keys are read like references in a document
+- Kept apart from translations, since the same headings usually work for any of them, but a
+  translation's own headings (see [`crate::bible_json::JSONBook::headings`]) are used without a
+  file
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Headings {
+    starts: BTreeMap<VerseKey, String>,
+}
+
+impl Headings {
+    /// A missing file has no headings
+    pub fn load(lsp: &BibleLSP, path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(lsp, &text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn parse(lsp: &BibleLSP, text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let entries: BTreeMap<String, String> =
+            serde_json::from_str(text).map_err(|err| invalid(err.to_string()))?;
+        let mut starts = BTreeMap::new();
+        for (key, heading) in entries {
+            let book_ref = lsp
+                .find_book_references_in(&key, &[])
+                .and_then(|references| references.into_iter().next())
+                .ok_or_else(|| invalid(format!("`{key}` isn't a verse")))?;
+            let Some(first) = book_ref.segments.first() else {
+                continue;
+            };
+            starts.insert(
+                (
+                    book_ref.book_id,
+                    first.get_starting_chapter(),
+                    first.get_starting_verse(),
+                ),
+                heading,
+            );
+        }
+        Ok(Self { starts })
+    }
+
+    /// The translation's own headings, see [`BibleAPI::section_headings`]
+    pub fn of_translation(api: &BibleAPI) -> Self {
+        Self {
+            starts: (*api.section_headings).clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /**
+    The sections overlapping `start` through `end` (`(chapter, verse)`s in `book_id`), in order
+
+    - A section runs until the verse before the next heading in the book, or the end of the book
+    - The first one can start before `start` when the range begins mid-section, and each is cut
+      to the range so the outline covers exactly what was asked for
+    */
+    pub fn sections(
+        &self,
+        api: &BibleAPI,
+        book_id: usize,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Vec<(String, BookReference)> {
+        let in_book: Vec<(VerseKey, &String)> = self
+            .starts
+            .range((book_id, 0, 0)..(book_id + 1, 0, 0))
+            .map(|(key, heading)| (*key, heading))
+            .collect();
+        let book_end = book_end(api, book_id).unwrap_or(end);
+        let mut sections = vec![];
+        for (idx, ((_, chapter, verse), heading)) in in_book.iter().enumerate() {
+            let section_end = match in_book.get(idx + 1) {
+                Some(((_, chapter, verse), _)) => {
+                    verse_before(api, book_id, (*chapter, *verse)).unwrap_or(book_end)
+                }
+                None => book_end,
+            };
+            if section_end < start || (*chapter, *verse) > end {
+                continue;
+            }
+            let from = (*chapter, *verse).max(start);
+            let to = section_end.min(end);
+            sections.push((heading.to_string(), span_reference(book_id, from, to)));
+        }
+        sections
+    }
+}
+
+/// `start` through `end` (`(chapter, verse)`s in `book_id`) as a reference, for its label
+pub fn span_reference(book_id: usize, start: (usize, usize), end: (usize, usize)) -> BookReference {
+    BookReference {
+        range: Default::default(),
+        book_id,
+        segments: BookReferenceSegments(vec![BookReferenceSegment::from_span(start, end)]),
+        translation: None,
+        segment_ranges: vec![],
+    }
+}
+
+/// The last `(chapter, verse)` of a book
+fn book_end(api: &BibleAPI, book_id: usize) -> Option<(usize, usize)> {
+    let chapter = api.get_book_chapter_count(book_id)?;
+    Some((chapter, api.get_chapter_verse_count(book_id, chapter)?))
+}
+
+/// The verse before `(chapter, verse)`, which is in the chapter before for a verse 1
+fn verse_before(
+    api: &BibleAPI,
+    book_id: usize,
+    (chapter, verse): (usize, usize),
+) -> Option<(usize, usize)> {
+    match verse {
+        0 | 1 if chapter > 1 => Some((
+            chapter - 1,
+            api.get_chapter_verse_count(book_id, chapter - 1)?,
+        )),
+        0 | 1 => None,
+        verse => Some((chapter, verse - 1)),
+    }
+}
+
+/**
+A bulleted outline of the sections in a passage, to start a sermon or study outline from
+
+```text
+- You Must Be Born Again (John 3:1-21)
+- John the Baptist Exalts Christ (John 3:22-36)
+```
+
+- `None` when no heading covers any of the passage
+*/
+pub fn outline(api: &BibleAPI, sections: &[(String, BookReference)]) -> Option<String> {
+    if sections.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = sections
+        .iter()
+        .map(|(heading, book_ref)| format!("- {heading} ({})", book_ref.full_ref_label(api)))
+        .collect();
+    Some(lines.join("\n"))
+}
+
+/// `(book_id, start, end)`, where `start` and `end` are `(chapter, verse)`s
+pub type Span = (usize, (usize, usize), (usize, usize));
+
+/**
+The book and `(chapter, verse)` span of what `bible.outlinePassage` is asked about
+
+- A book (`Romans`), chapters (`John 3` or `John 3-4`), or verses (`John 3:1-21`)
+- Verses spanning several segments (`John 3:1-5, 16`) are read from the first to the last
+*/
+pub fn passage_span(lsp: &BibleLSP, text: &str) -> Option<Span> {
+    let text = text.trim();
+    let api = &lsp.api;
+    if let Some(book_id) = api.get_book_id(text) {
+        return Some((book_id, (1, 1), book_end(api, book_id)?));
+    }
+    if !text.contains(':') {
+        // `John 3-4`, which is read like a reference as the verses `John 3:1-4`
+        let (book_and_first, last) = match text.rsplit_once('-') {
+            Some((rest, last)) => (rest.trim(), Some(last.trim().parse::<usize>().ok()?)),
+            None => (text, None),
+        };
+        let number_start = book_and_first.rfind(|c: char| !c.is_ascii_digit())? + 1;
+        let first: usize = book_and_first[number_start..].parse().ok()?;
+        let book_id = api.get_book_id(book_and_first[..number_start].trim())?;
+        let last = last.unwrap_or(first);
+        return Some((
+            book_id,
+            (first, 1),
+            (last, api.get_chapter_verse_count(book_id, last)?),
+        ));
+    }
+    let book_ref = lsp.find_book_references_in(text, &[])?.into_iter().next()?;
+    let first = book_ref.segments.first()?;
+    let last = book_ref.segments.last()?;
+    Some((
+        book_ref.book_id,
+        (first.get_starting_chapter(), first.get_starting_verse()),
+        (last.get_ending_chapter(), last.get_ending_verse()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_cut_to_the_passage() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let headings = Headings::parse(
+            &lsp,
+            r#"{ "Gen 1:1": "Creation", "Genesis 1:4": "Light", "Gen 2:1": "Rest", "Ex 1:1": "Egypt" }"#,
+        )
+        .unwrap();
+        let (book_id, start, end) = passage_span(&lsp, "Gen 1:2-2:1").unwrap();
+        let sections = headings.sections(&lsp.api, book_id, start, end);
+        let chapter_one = lsp.api.get_chapter_verse_count(1, 1).unwrap();
+        assert_eq!(
+            outline(&lsp.api, &sections).unwrap(),
+            format!(
+                "- Creation (Genesis 1:2-3)\n- Light (Genesis 1:4-{chapter_one})\n- Rest (Genesis 2:1)"
+            )
+        );
+
+        assert_eq!(
+            passage_span(&lsp, "Genesis"),
+            Some((1, (1, 1), book_end(&lsp.api, 1).unwrap()))
+        );
+        let (_, start, end) = passage_span(&lsp, "Gen 1-2").unwrap();
+        assert_eq!((start, end.0), ((1, 1), 2));
+        assert!(Headings::parse(&lsp, r#"{ "Nowhere 1:1": "?" }"#).is_err());
+    }
+}
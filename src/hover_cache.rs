@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use cached::{Cached, SizedCache};
+
+/// Plenty for the references in the notes someone is working on
+const CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HoverKey {
+    /// abbreviation, ex: `ESV`
+    pub translation: String,
+    /// the full label, so `eph 1:1` and `Ephesians 1:1` share an entry
+    pub reference: String,
+    /// the formatter the hover was rendered with
+    pub profile: String,
+}
+
+/**
+Rendered hover markdown for recently hovered references
+
+- People hover the same few references over and over while writing, and rendering means looking
+  up and formatting every verse each time
+- Least recently used entries are dropped once it is full, or past its share of
+  `bible.memoryBudgetMb` (see [`crate::memory_budget::MemoryBudget`])
+- Anything that changes how hovers render (settings, aliases, templates) has to [`HoverCache::clear`] it
+*/
+#[derive(Debug)]
+pub struct HoverCache {
+    entries: Mutex<SizedCache<HoverKey, String>>,
+    /// in bytes, see [`HoverCache::set_budget`]
+    budget: Mutex<Option<usize>>,
+    /// - Bumped by every [`HoverCache::clear`]
+    /// - Diagnostics depend on the same settings, so their result ids include it
+    generation: AtomicU64,
+}
+
+impl Default for HoverCache {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(SizedCache::with_size(CAPACITY)),
+            budget: Mutex::new(None),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl HoverCache {
+    /// The cached hover, or `render`'s (which is then cached)
+    pub fn get_or_render(&self, key: HoverKey, render: impl FnOnce() -> String) -> String {
+        if let Some(hover) = self.entries.lock().unwrap().cache_get(&key) {
+            return hover.clone();
+        }
+        // rendered without the lock, so other hovers aren't held up
+        let hover = render();
+        let mut entries = self.entries.lock().unwrap();
+        entries.cache_set(key, hover.clone());
+        trim(&mut entries, *self.budget.lock().unwrap());
+        hover
+    }
+
+    /// - Drops the least recently used hovers until the rest fit in `bytes`
+    /// - `None` leaves just the usual limit on the number of hovers
+    pub fn set_budget(&self, bytes: Option<usize>) {
+        *self.budget.lock().unwrap() = bytes;
+        trim(&mut self.entries.lock().unwrap(), bytes);
+    }
+
+    /// The number of hovers and roughly how many bytes they take
+    pub fn usage(&self) -> (usize, usize) {
+        let entries = self.entries.lock().unwrap();
+        (entries.cache_size(), bytes(&entries))
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().cache_clear();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+}
+
+fn entry_bytes(key: &HoverKey, hover: &str) -> usize {
+    key.translation.len() + key.reference.len() + key.profile.len() + hover.len()
+}
+
+fn bytes(entries: &SizedCache<HoverKey, String>) -> usize {
+    entries
+        .key_order()
+        .zip(entries.value_order())
+        .map(|(key, hover)| entry_bytes(key, hover))
+        .sum()
+}
+
+/// `key_order` goes from the most to the least recently used
+fn trim(entries: &mut SizedCache<HoverKey, String>, budget: Option<usize>) {
+    let Some(budget) = budget else {
+        return;
+    };
+    let mut total = bytes(entries);
+    while total > budget {
+        let Some(oldest) = entries.key_order().last().cloned() else {
+            break;
+        };
+        if let Some(hover) = entries.cache_remove(&oldest) {
+            total -= entry_bytes(&oldest, &hover);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(reference: &str) -> HoverKey {
+        HoverKey {
+            translation: String::from("TST"),
+            reference: reference.to_string(),
+            profile: String::new(),
+        }
+    }
+
+    #[test]
+    fn least_recently_used_hovers_go_first() {
+        let cache = HoverCache::default();
+        for reference in ["Genesis 1:1", "Genesis 1:2", "Genesis 1:3"] {
+            cache.get_or_render(key(reference), || "x".repeat(100));
+        }
+        // used again, so it's the newest
+        cache.get_or_render(key("Genesis 1:1"), || unreachable!());
+        cache.set_budget(Some(2 * entry_bytes(&key("Genesis 1:1"), &"x".repeat(100))));
+        assert_eq!(cache.usage().0, 2);
+        let rendered = cache.get_or_render(key("Genesis 1:2"), || String::from("again"));
+        assert_eq!(rendered, "again");
+        assert_eq!(
+            cache.get_or_render(key("Genesis 1:1"), || unreachable!()),
+            "x".repeat(100)
+        );
+    }
+}
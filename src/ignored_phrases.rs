@@ -0,0 +1,76 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+/// The file in a workspace folder that ignored phrases are kept in
+pub const FILE: &str = ".bible_lsp_ignored.json";
+
+/// Where ignored phrases are kept unless `ignoredPhrasesFile` is set: the first workspace
+/// folder, or the data directory without one
+pub fn default_path(roots: &[PathBuf]) -> PathBuf {
+    match roots.first() {
+        Some(root) => root.join(FILE),
+        None => paths::data_dir().join("ignored-phrases.json"),
+    }
+}
+
+/**
+Phrases that look like references but aren't, so they aren't detected
+
+```json
+{ "phrases": ["Job 3:30", "Mark 2:15 KJV"] }
+```
+
+- Filled from the "Ignore as a reference" code action, and read and cleared with
+  `bible.ignoredReferences` and `bible.unignoreReferences`
+- Matched exactly as written, so `job 3:30` is still a reference after `Job 3:30` is ignored
+- Per workspace, since what is a false positive depends on what the notes are about, and the
+  file can be committed with them
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnoredPhrases {
+    pub phrases: Vec<String>,
+}
+
+impl IgnoredPhrases {
+    /// A missing file has no phrases
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Adds a phrase, unless it is blank or already ignored
+    pub fn add(&mut self, phrase: &str) -> bool {
+        let phrase = phrase.trim();
+        if phrase.is_empty() || self.phrases.iter().any(|ignored| ignored == phrase) {
+            return false;
+        }
+        self.phrases.push(phrase.to_string());
+        true
+    }
+
+    /// Removes `phrase`, or every phrase for `None`, returning how many were removed
+    pub fn remove(&mut self, phrase: Option<&str>) -> usize {
+        let before = self.phrases.len();
+        match phrase {
+            Some(phrase) => self.phrases.retain(|ignored| ignored != phrase.trim()),
+            None => self.phrases.clear(),
+        }
+        before - self.phrases.len()
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Url;
+
+use crate::{paths, workspace_index::IndexedReference};
+
+/// Bump this whenever [`IndexedReference`] changes shape, so old caches are thrown away
+const CACHE_VERSION: u32 = 4;
+
+/// - Cheap way to tell if a file changed since it was indexed, without reading it
+/// - Editors and `git checkout` both update the modified time, and the size catches most
+///   changes that somehow keep it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    /// nanoseconds since the unix epoch
+    pub modified: u64,
+    pub size: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            modified: modified.as_nanos() as u64,
+            size: metadata.len(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedFile {
+    stamp: FileStamp,
+    references: Vec<IndexedReference>,
+}
+
+/**
+The workspace index as it was at the end of the last session
+
+- One cache file per set of workspace folders, under [`paths::cache_dir`]
+- Labels depend on the translation's book names, so a cache for another translation is ignored
+- So does which front matter fields are passages, and which phrases are ignored (see
+  [`crate::ignored_phrases`]), so a cache from other settings is ignored too
+- Entries are only reused when the file's [`FileStamp`] still matches
+*/
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IndexCache {
+    version: u32,
+    translation: String,
+    /// see [`crate::config::Config::front_matter_fields`]
+    front_matter_fields: Vec<String>,
+    /// see [`crate::bible_lsp::BibleLSP::ignored_phrases`]
+    #[serde(default)]
+    ignored_phrases: Vec<String>,
+    files: BTreeMap<Url, CachedFile>,
+}
+
+impl IndexCache {
+    pub fn new(
+        translation: &str,
+        front_matter_fields: &[String],
+        ignored_phrases: &[String],
+    ) -> Self {
+        Self {
+            version: CACHE_VERSION,
+            translation: translation.to_string(),
+            front_matter_fields: front_matter_fields.to_vec(),
+            ignored_phrases: ignored_phrases.to_vec(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Ex: `~/.cache/bible_lsp/index/5f3a9c0e12b4d678.json`
+    pub fn path(roots: &[PathBuf]) -> PathBuf {
+        let key = roots
+            .iter()
+            .map(|root| root.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("\n");
+        paths::cache_dir().join("index").join(format!(
+            "{:016x}.json",
+            crate::bible_cache::fnv1a(key.as_bytes())
+        ))
+    }
+
+    /// Anything missing, unreadable, outdated, or for another translation, other front matter
+    /// fields, or other ignored phrases gives an empty cache
+    pub fn load(
+        path: &Path,
+        translation: &str,
+        front_matter_fields: &[String],
+        ignored_phrases: &[String],
+    ) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Self>(&json).ok())
+            .filter(|cache| {
+                cache.version == CACHE_VERSION
+                    && cache.translation == translation
+                    && cache.front_matter_fields == front_matter_fields
+                    && cache.ignored_phrases == ignored_phrases
+            })
+            .unwrap_or_else(|| Self::new(translation, front_matter_fields, ignored_phrases))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// The cached references, but only if the file hasn't changed since
+    pub fn get(&self, uri: &Url, stamp: FileStamp) -> Option<&Vec<IndexedReference>> {
+        self.files
+            .get(uri)
+            .filter(|cached| cached.stamp == stamp)
+            .map(|cached| &cached.references)
+    }
+
+    pub fn insert(&mut self, uri: Url, stamp: FileStamp, references: Vec<IndexedReference>) {
+        self.files.insert(uri, CachedFile { stamp, references });
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    fn reference() -> IndexedReference {
+        IndexedReference {
+            range: Range::default(),
+            book_id: 49,
+            label: String::from("Ephesians 1:1-4"),
+            id: String::from("ESV:49.1.1-1.4"),
+            spans: vec![[(1, 1), (1, 4)]],
+            field: None,
+            translation: None,
+            quoted: false,
+        }
+    }
+
+    #[test]
+    fn cache_round_trips_and_checks_stamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        let uri = Url::parse("file:///notes/ephesians.md").unwrap();
+        let stamp = FileStamp {
+            modified: 1,
+            size: 10,
+        };
+
+        let fields = [String::from("passage")];
+        let mut cache = IndexCache::new("ESV", &fields, &[]);
+        cache.insert(uri.clone(), stamp, vec![reference()]);
+        cache.save(&path).unwrap();
+
+        let loaded = IndexCache::load(&path, "ESV", &fields, &[]);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.get(&uri, stamp).unwrap()[0].label, "Ephesians 1:1-4");
+        let changed = FileStamp { size: 11, ..stamp };
+        assert!(loaded.get(&uri, changed).is_none());
+
+        assert!(IndexCache::load(&path, "KJV", &fields, &[]).is_empty());
+        assert!(IndexCache::load(&path, "ESV", &[], &[]).is_empty());
+        let ignored = [String::from("Job 1:1")];
+        assert!(IndexCache::load(&path, "ESV", &fields, &ignored).is_empty());
+    }
+
+    #[test]
+    fn cache_path_depends_on_roots() {
+        let notes = IndexCache::path(&[PathBuf::from("/notes")]);
+        assert_eq!(notes, IndexCache::path(&[PathBuf::from("/notes")]));
+        assert_ne!(notes, IndexCache::path(&[PathBuf::from("/sermons")]));
+    }
+}
@@ -0,0 +1,85 @@
+pub mod additions;
+pub mod alias_gen;
+pub mod alias_packs;
+#[cfg(feature = "search")]
+pub mod annotations;
+pub mod api_wrappers;
+pub mod attribution;
+pub mod autocompletion;
+#[cfg(feature = "search")]
+pub mod backlinks;
+pub mod batch_edits;
+pub mod bible_api;
+pub mod bible_cache;
+pub mod bible_formatter;
+pub mod bible_json;
+pub mod bible_lsp;
+#[cfg(feature = "usfm")]
+pub mod bible_usfm;
+pub mod book_overview;
+pub mod book_reference;
+pub mod book_reference_segment;
+pub mod book_tags;
+#[cfg(feature = "remote")]
+pub mod chapter_cache;
+pub mod clipboard;
+pub mod commands;
+pub mod completion_ranking;
+pub mod config;
+pub mod config_file;
+pub mod convert;
+#[cfg(feature = "search")]
+pub mod coverage;
+#[cfg(feature = "commentary")]
+pub mod cross_references;
+pub mod daemon;
+pub mod deadline;
+pub mod detection_context;
+pub mod document;
+pub mod document_filter;
+pub mod error;
+#[cfg(feature = "search")]
+pub mod extract;
+pub mod folding;
+pub mod footnotes;
+pub mod front_matter;
+pub mod headings;
+pub mod hover_cache;
+pub mod ignored_phrases;
+#[cfg(feature = "search")]
+pub mod index_cache;
+pub mod memory_budget;
+pub mod paths;
+#[cfg(feature = "tui")]
+pub mod picker;
+pub mod poetry;
+pub mod progress;
+pub mod quote_limits;
+pub mod quote_markers;
+pub mod re;
+pub mod reading_queue;
+pub mod red_letter;
+#[cfg(feature = "search")]
+pub mod reference_graph;
+#[cfg(feature = "search")]
+pub mod reindex_queue;
+#[cfg(feature = "remote")]
+pub mod remote_source;
+pub mod scaffold;
+pub mod scripture_index;
+pub mod semantic_tokens;
+pub mod server;
+pub mod session_record;
+pub mod speech;
+pub mod spelling;
+pub mod status;
+pub mod templates;
+pub mod trace;
+pub mod transliterate;
+pub mod verse_id;
+pub mod verse_navigation;
+pub mod versification;
+pub mod virtual_document;
+pub mod word_diff;
+#[cfg(feature = "search")]
+pub mod workspace_index;
@@ -1,658 +1,497 @@
-use book_reference::BookReference;
-use book_reference_segment::{BookRange, BookReferenceSegment, BookReferenceSegments};
-use once_cell::sync::Lazy;
-use serde_json::Value;
-use std::borrow::Borrow;
-use std::collections::BTreeMap;
-use std::env;
-use std::fs::{self, read_to_string, File};
-use std::io::Write;
-use std::sync::{Arc, RwLock};
-use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::*;
-use tower_lsp::{Client, LanguageServer, LspService, Server};
-
-use bible_api::BibleAPI;
-use bible_lsp::{append_log, BibleLSP};
-use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range};
-
-pub mod api_wrappers;
-pub mod autocompletion;
-pub mod bible_api;
-pub mod bible_formatter;
-pub mod bible_json;
-pub mod bible_lsp;
-pub mod book_reference;
-pub mod book_reference_segment;
-pub mod re;
-
-/// Writes contents to a persistent temporary file and returns the file URI
-pub fn create_temp_file_in_memory(book_name: &str, contents: &str) -> std::io::Result<Url> {
-    // Create a temporary directory using the OS's temp dir
-    let temp_dir = env::temp_dir();
-
-    // Create a unique file name (e.g., definition_temp.txt)
-    let temp_file_path = temp_dir.join(format!("{book_name}.md"));
-
-    // Create and write to the file
-    let mut temp_file = File::create(&temp_file_path)?;
-    write!(temp_file, "{}", contents)?;
-
-    // Convert the file path to a URI (file://)
-    let uri = Url::from_file_path(&temp_file_path).map_err(|_| {
-        std::io::Error::new(std::io::ErrorKind::Other, "Failed to convert path to URI")
-    })?;
-
-    Ok(uri)
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use bible_lsp::{
+    bible_formatter::{self, PassageFormatter},
+    bible_json,
+    bible_lsp::BibleLSP,
+    config, error, server, session_record, templates,
+};
+use tower_lsp::Server;
+
+/// - The default translation for the subcommands, see [`config::default_translation`]
+/// - Or the exit code after printing why it couldn't be loaded
+fn load() -> Result<BibleLSP, ExitCode> {
+    let Some(path) = config::default_translation() else {
+        eprintln!(
+            "No translation found, set $BIBLE_LSP_TRANSLATION to a Bible JSON file or run `bible_lsp init --download <url>`"
+        );
+        return Err(ExitCode::FAILURE);
+    };
+    BibleLSP::load(path).map_err(|err| {
+        eprintln!("{}", error::render(err));
+        ExitCode::FAILURE
+    })
 }
 
-#[derive(Debug)]
-struct Backend {
-    client: Client,
-    lsp: BibleLSP,
+/// A built-in formatter, or a template the server would load
+fn formatter(name: &str) -> Option<PassageFormatter> {
+    let templates = templates::TemplateStore::default();
+    templates.reload(&templates::templates_dir());
+    bible_formatter::builtin(name).or_else(|| templates.get(name))
 }
 
-pub static documents: Lazy<Arc<RwLock<BTreeMap<Url, String>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(BTreeMap::new())));
-
-#[tower_lsp::async_trait]
-impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
-        Ok(InitializeResult {
-            capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                hover_provider: Some(HoverProviderCapability::Simple(true)),
-                definition_provider: Some(OneOf::Left(true)),
-                completion_provider: Some(CompletionOptions {
-                    trigger_characters: Some(
-                        vec![",", ";", "-", ":", " "]
-                            .into_iter()
-                            .map(|ch| ch.to_string())
-                            .collect(),
-                    ),
-                    completion_item: Some(CompletionOptionsCompletionItem {
-                        label_details_support: Some(true),
-                    }),
-                    ..CompletionOptions::default()
-                }),
-                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
-                    DiagnosticOptions {
-                        identifier: Some(String::from("bible_lsp")),
-                        ..Default::default()
-                    },
-                )),
-                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
-                // inline_value_provider: Some(OneOf::Left(true)),
-                // inlay_hint_provider: Some(OneOf::Left(true)),
-                // code_lens_provider: Some(CodeLensOptions {
-                //     resolve_provider: Some(true),
-                // }),
-                document_symbol_provider: Some(OneOf::Left(true)),
-                ..Default::default()
-            },
-            server_info: Some(ServerInfo {
-                name: String::from("Bible LSP"),
-                version: Some(String::from("0.0.1α")),
-            }),
-        })
-    }
-
-    async fn initialized(&self, _: InitializedParams) {
-        self.client
-            .log_message(MessageType::INFO, "server initialized!")
-            .await;
-    }
-
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        let TextDocumentItem { text, uri, .. } = params.text_document;
-        documents.write().unwrap().insert(uri, text);
-    }
-
-    async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let uri = params.text_document.uri;
-        for change in params.content_changes {
-            documents.write().unwrap().insert(uri.clone(), change.text);
+/// - `bible_lsp`: serve over stdio
+/// - `bible_lsp --record <file>`: serve over stdio, recording every message to the file
+/// - `bible_lsp --daemon [socket]`: serve every editor that connects to the socket, sharing one
+///   translation and the workspace indexes between them
+/// - `bible_lsp --connect [socket]`: serve over stdio through the daemon, starting it if needed
+/// - `bible_lsp replay <file>`: re-run a recording and print the responses that changed
+/// - `bible_lsp extract <file.pdf|file.docx> [--json]`: print the references in a document
+/// - `bible_lsp watch <dir> [--write-annotations]`: keep a `.refs.json` file next to every
+///   document in the folder, or just print what would change without the flag
+/// - `bible_lsp init [dir] [--download <url>]`: set up a vault with a config file and example
+///   templates, downloading a translation first with the flag
+/// - `bible_lsp pick [--formatter <name>]`: pick a passage in the terminal and print it formatted
+///   (with the `tui` feature)
+/// - `bible_lsp clipboard [--replace] [--formatter <name>]`: add the passages cited in copied text
+///   to the clipboard, or put them in place of it with `--replace`
+/// - `bible_lsp validate <file>`: check a Bible JSON file, printing every problem with where it is
+/// - `bible_lsp convert --from <format> --to <format> <file>... [--output <file>]`: turn USFM,
+///   OSIS, or CSV files into a Bible JSON file (or the other way), printing it without `--output`.
+///   `--name`, `--abbreviation`, and `--language` fill in what the files don't say
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => {
+            // the translation is loaded once `initialize` says which one
+            let (service, socket) = server::build_service(BibleLSP::empty());
+            Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+                .serve(service)
+                .await;
         }
-    }
-
-    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        let doc = params.text_document_position_params.text_document;
-        let text = documents
-            .read()
-            .unwrap()
-            .get(&doc.uri)
-            .cloned()
-            .expect("It should be in the map");
-        let pos = params.text_document_position_params.position;
-        let Some(refs) = self.lsp.find_book_references(&text) else {
-            return Ok(None);
-        };
-
-        let refs = refs
-            .into_iter()
-            .filter(|book_ref| book_ref.range.start.line == pos.line)
-            .collect::<Vec<_>>();
-
-        if refs.len() == 1 {
-            let book_ref = refs.first().unwrap();
-            let hover_contents = book_ref.format(&self.lsp.api);
-            return Ok(Some(Hover {
-                contents: HoverContents::Scalar(MarkedString::from_markdown(hover_contents)),
-                range: Some(book_ref.range),
-            }));
+        ["--record", file] => {
+            let recorder = match session_record::Recorder::create(&PathBuf::from(file)) {
+                Ok(recorder) => recorder,
+                Err(err) => {
+                    eprintln!("Couldn't create {file}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let stdin = session_record::RecordingReader::new(tokio::io::stdin(), recorder.clone());
+            let stdout = session_record::RecordingWriter::new(tokio::io::stdout(), recorder);
+            let (service, socket) = server::build_service(BibleLSP::empty());
+            Server::new(stdin, stdout, socket).serve(service).await;
         }
-
-        // i could just use the one under the cursor, but i dont want to do that right now
-        let hover_contents = refs
-            .into_iter()
-            .map(|book_ref| book_ref.format(&self.lsp.api))
-            .collect::<Vec<String>>()
-            .join("\n\n---\n");
-        Ok(Some(Hover {
-            contents: HoverContents::Scalar(MarkedString::from_markdown(hover_contents)),
-            range: None,
-        }))
-    }
-
-    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        let doc = params.text_document_position.text_document;
-        let text = documents
-            .read()
-            .unwrap()
-            .get(&doc.uri)
-            .cloned()
-            .expect("It should be in the map");
-        let pos = params.text_document_position.position;
-        let line = text
-            .lines()
-            .nth(pos.line as usize)
-            .expect("LSP gave bad index")
-            .to_string();
-
-        // append_log(format!("{:?}\n{:#?}", &line, pos));
-        // neovim panics here
-        // let text_before_cursor = &line[..(pos.character as usize)];
-        let text_before_cursor = &line[..(std::cmp::min(pos.character as usize, line.len()))];
-        let suggestions = self.lsp.suggest_auto_completion(text_before_cursor);
-        // let mut completion_items: Vec<CompletionItem> = vec![];
-        // completion_items.push(CompletionItem {
-        //     ..Default::default()
-        // });
-        let book_match = self
-            .lsp
-            .api
-            .book_abbreviation_regex()
-            .find_iter(text_before_cursor)
-            .last();
-        let completion_items: Vec<CompletionItem> = suggestions
-            .into_iter()
-            .map(|item| {
-                let label = item.label(&self.lsp.api);
-                // append_log(format!("{:#?}", label));
-                // append_log(format!("{:#?}\n", item));
-                let text_edit = match book_match {
-                    Some(m) => {
-                        let start = m.start() as u32;
-                        let end = start + label.len() as u32;
-                        Some(CompletionTextEdit::Edit(TextEdit {
-                            range: Range {
-                                start: Position {
-                                    line: pos.line,
-                                    character: start,
-                                },
-                                end: Position {
-                                    line: pos.line,
-                                    character: end,
-                                },
-                            },
-                            new_text: label.clone(),
-                        }))
+        ["--daemon", rest @ ..] | ["--connect", rest @ ..] => {
+            let path = match rest {
+                [] => bible_lsp::daemon::socket_path(),
+                [path] => PathBuf::from(path),
+                _ => {
+                    eprintln!("Usage: bible_lsp --daemon|--connect [socket]");
+                    return ExitCode::FAILURE;
+                }
+            };
+            #[cfg(unix)]
+            {
+                let served = match args[0].as_str() {
+                    "--daemon" => bible_lsp::daemon::serve(&path).await,
+                    _ => bible_lsp::daemon::connect(&path).await,
+                };
+                if let Err(err) = served {
+                    eprintln!("{err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+            #[cfg(not(unix))]
+            {
+                _ = path;
+                eprintln!("The bible_lsp daemon needs Unix sockets");
+                return ExitCode::FAILURE;
+            }
+        }
+        ["replay", file] => {
+            let lsp = match load() {
+                Ok(lsp) => lsp,
+                Err(code) => return code,
+            };
+            match session_record::replay(&PathBuf::from(file), lsp).await {
+                Ok(differences) if differences.is_empty() => {
+                    println!("Every response matched the recording");
+                }
+                Ok(differences) => {
+                    for difference in differences.iter() {
+                        println!("{} (id {}) changed", difference.method, difference.id);
+                        println!("  recorded: {}", difference.recorded);
+                        println!("  replayed: {}", difference.replayed);
                     }
-                    None => None,
+                    return ExitCode::FAILURE;
+                }
+                Err(err) => {
+                    eprintln!("Couldn't replay {file}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        ["extract", file, rest @ ..] => {
+            #[cfg(feature = "search")]
+            {
+                let lsp = match load() {
+                    Ok(lsp) => lsp,
+                    Err(code) => return code,
                 };
-
-                // match item {
-                //
-                // };
-                let doc_content = item.lsp_preview(&self.lsp.api);
-                let sort_text = item.lsp_sort();
-                CompletionItem {
-                    label,
-                    documentation: Some(Documentation::MarkupContent(MarkupContent {
-                        kind: MarkupKind::Markdown,
-                        value: doc_content,
-                    })),
-                    text_edit,
-                    kind: Some(CompletionItemKind::REFERENCE),
-                    sort_text: Some(sort_text),
-                    ..Default::default()
+                let references =
+                    match bible_lsp::extract::extract_references(&lsp, &PathBuf::from(file)) {
+                        Ok(references) => references,
+                        Err(err) => {
+                            eprintln!("Couldn't extract references from {file}: {err}");
+                            return ExitCode::FAILURE;
+                        }
+                    };
+                if rest.contains(&"--json") {
+                    match serde_json::to_string_pretty(&references) {
+                        Ok(json) => println!("{json}"),
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                } else {
+                    for extracted in references {
+                        println!("{}: {}", extracted.location, extracted.reference.label);
+                    }
                 }
-            })
-            .collect();
-        Ok(Some(CompletionResponse::Array(completion_items)))
-    }
-
-    async fn diagnostic(
-        &self,
-        params: DocumentDiagnosticParams,
-    ) -> Result<DocumentDiagnosticReportResult> {
-        let doc = params.text_document;
-        let text = documents
-            .read()
-            .unwrap()
-            .get(&doc.uri)
-            .cloned()
-            .expect("It should be in the map");
-
-        let mut diagnostics: Vec<Diagnostic> = Vec::new();
-
-        if let Some(refs) = self.lsp.find_book_references(&text) {
-            for book_ref in refs.iter() {
-                let Some(message) = book_ref.format_diagnostic(&self.lsp.api) else {
-                    continue;
+            }
+            #[cfg(not(feature = "search"))]
+            {
+                _ = (file, rest);
+                eprintln!("This build of bible_lsp was compiled without the \"search\" feature");
+                return ExitCode::FAILURE;
+            }
+        }
+        ["watch", dir, rest @ ..] => {
+            #[cfg(feature = "search")]
+            {
+                use bible_lsp::annotations::{self, Change};
+                let lsp = match load() {
+                    Ok(lsp) => lsp,
+                    Err(code) => return code,
                 };
-                diagnostics.push(Diagnostic {
-                    range: book_ref.range,
-                    severity: Some(DiagnosticSeverity::INFORMATION),
-                    // severity: Some(DiagnosticSeverity::HINT),
-                    message,
-                    code: Some(NumberOrString::String(
-                        book_ref.full_ref_label(&self.lsp.api),
-                    )),
-                    // code_description: Some(CodeDescription { href: () }),
-                    // source: todo!(),
-                    // related_information: Some(vec![
-                    //     DiagnosticRelatedInformation
-                    // ]),
-                    // tags: Some(vec![DiagnosticTag::UNNECESSARY]),
-                    // data: todo!(),
-                    ..Default::default()
-                });
+                let write = rest.contains(&"--write-annotations");
+                let extensions = bible_lsp::config::IndexConfig::default().extensions;
+                let mut watcher = annotations::Watcher::default();
+                loop {
+                    match watcher.poll(&lsp, &PathBuf::from(dir), &extensions, write) {
+                        Ok(changes) => {
+                            for change in changes {
+                                match change {
+                                    Change::Written(path, count) => {
+                                        println!("{}: {count} references", path.display())
+                                    }
+                                    Change::Removed(path) => {
+                                        println!("{}: removed", path.display())
+                                    }
+                                }
+                            }
+                        }
+                        Err(err) => eprintln!("Couldn't update annotations: {err}"),
+                    }
+                    tokio::time::sleep(annotations::POLL_INTERVAL).await;
+                }
+            }
+            #[cfg(not(feature = "search"))]
+            {
+                _ = (dir, rest);
+                eprintln!("This build of bible_lsp was compiled without the \"search\" feature");
+                return ExitCode::FAILURE;
             }
         }
-
-        Ok(DocumentDiagnosticReportResult::Report(
-            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
-                related_documents: None,
-                full_document_diagnostic_report: FullDocumentDiagnosticReport {
-                    result_id: None,
-                    items: diagnostics,
-                },
-            }),
-        ))
-    }
-
-    // see /home/dgmastertemple/Development/rust/scripture_lsp/src/main.rs line 233
-    async fn goto_definition(
-        &self,
-        params: GotoDefinitionParams,
-    ) -> Result<Option<GotoDefinitionResponse>> {
-        let doc = params.text_document_position_params.text_document;
-        let text = documents
-            .read()
-            .unwrap()
-            .get(&doc.uri)
-            .cloned()
-            .expect("It should be in the map");
-        let pos = params.text_document_position_params.position;
-        let Some(refs) = self.lsp.find_book_references(&text) else {
-            return Ok(None);
-        };
-
-        let refs = refs
-            .into_iter()
-            .filter(|book_ref| book_ref.range.start.line == pos.line)
-            .collect::<Vec<_>>();
-        let cursor = params.text_document_position_params.position.character;
-        // let book_ref = if refs.first().is_some_and(|found| found.range) {
-        //
-        // } else {};
-        let Some(book_ref) = refs
-            .into_iter()
-            .find(|r| r.range.start.character <= cursor && cursor <= r.range.end.character)
-        else {
-            return Ok(None);
-        };
-        let book_id = book_ref.book_id;
-        let end_chapter = self
-            .lsp
-            .api
-            .get_book_chapter_count(book_id)
-            .expect("This is a valid book id");
-        let end_verse = self
-            .lsp
-            .api
-            .get_chapter_verse_count(book_id, end_chapter)
-            .expect("This is a valid book and chapter");
-        let whole_book = BookReference {
-            book_id,
-            range: book_ref.range,
-            segments: BookReferenceSegments(vec![BookReferenceSegment::BookRange(BookRange {
-                start_chapter: 1,
-                end_chapter,
-                start_verse: 1,
-                end_verse,
-            })]),
-        };
-
-        let book_name = self.lsp.api.get_book_name(book_id).expect("It is valid");
-        let content = whole_book.format_content(&self.lsp.api);
-        let file_contents = format!("### {}\n\n{}", book_name, content);
-        let Some((chapter, verse)) = book_ref
-            .segments
-            .first()
-            .map(|seg| (seg.get_starting_chapter(), seg.get_starting_verse()))
-        else {
-            return Ok(None);
-        };
-        // this would have to change when i change templating
-        // let the_match = format!("[{}:{}]", chapter, verse).as_str();
-        let Some(the_match) = file_contents.find(format!("[{}:{}]", chapter, verse).as_str())
-        else {
-            return Ok(None);
-        };
-        let line_number = file_contents[..=the_match]
-            .chars()
-            .filter(|c| *c == '\n')
-            .count();
-
-        match create_temp_file_in_memory(&book_name, file_contents.as_str()) {
-            Ok(uri) => Ok(Some(GotoDefinitionResponse::Scalar(Location {
-                uri,
-                range: Range {
-                    start: Position {
-                        line: line_number as u32,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: line_number as u32,
-                        character: 0,
+        ["init", rest @ ..] => {
+            use bible_lsp::scaffold::{self, Scaffolded};
+            let (dir, url) = match rest {
+                [] => (".", None),
+                ["--download", url] => (".", Some(*url)),
+                [dir] => (*dir, None),
+                [dir, "--download", url] => (*dir, Some(*url)),
+                _ => {
+                    eprintln!("Usage: bible_lsp init [dir] [--download <url>]");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let translation: Option<PathBuf> = match url {
+                #[cfg(feature = "remote")]
+                Some(url) => {
+                    let dir = bible_lsp::paths::translations_dir();
+                    match scaffold::download_translation(url, &dir) {
+                        Ok(path) => {
+                            println!("Downloaded {}", path.display());
+                            Some(path)
+                        }
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                }
+                #[cfg(not(feature = "remote"))]
+                Some(_) => {
+                    eprintln!(
+                        "This build of bible_lsp was compiled without the \"remote\" feature"
+                    );
+                    return ExitCode::FAILURE;
+                }
+                None => None,
+            };
+            let templates_dir = bible_lsp::templates::templates_dir();
+            match scaffold::init(&PathBuf::from(dir), &templates_dir, translation.as_deref()) {
+                Ok(scaffolded) => {
+                    for each in scaffolded.iter() {
+                        match each {
+                            Scaffolded::Created(path) => println!("Created {}", path.display()),
+                            Scaffolded::Skipped(path) => {
+                                println!("Kept the existing {}", path.display())
+                            }
+                        }
+                    }
+                    // the existing config doesn't know about the download
+                    if let (Some(Scaffolded::Skipped(config)), Some(translation)) =
+                        (scaffolded.first(), &translation)
+                    {
+                        println!(
+                            "Set translation = {:?} in {} to use the download",
+                            translation.display().to_string(),
+                            config.display()
+                        );
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Couldn't set up {dir}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        ["pick", rest @ ..] => {
+            #[cfg(feature = "tui")]
+            {
+                let name = match rest {
+                    [] => "insert",
+                    ["--formatter", name] => *name,
+                    _ => {
+                        eprintln!("Usage: bible_lsp pick [--formatter <name>]");
+                        return ExitCode::FAILURE;
+                    }
+                };
+                let Some(formatter) = formatter(name) else {
+                    eprintln!("Unknown formatter {name}");
+                    return ExitCode::FAILURE;
+                };
+                let lsp = match load() {
+                    Ok(lsp) => lsp,
+                    Err(code) => return code,
+                };
+                match bible_lsp::picker::run(&lsp) {
+                    Ok(Some(book_ref)) => {
+                        let context = bible_formatter::FormatContext::new(&lsp.api, "");
+                        println!("{}", formatter.format(&lsp.api, &book_ref, &context));
+                    }
+                    // cancelled, like fzf
+                    Ok(None) => return ExitCode::from(130),
+                    Err(err) => {
+                        eprintln!("{err}");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            #[cfg(not(feature = "tui"))]
+            {
+                _ = rest;
+                eprintln!("This build of bible_lsp was compiled without the \"tui\" feature");
+                return ExitCode::FAILURE;
+            }
+        }
+        ["clipboard", rest @ ..] => {
+            use bible_lsp::clipboard::{self, Mode};
+            let mut mode = Mode::Append;
+            let mut name = "blockquote";
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                match (*arg, rest.as_slice().first()) {
+                    ("--replace", _) => mode = Mode::Replace,
+                    ("--formatter", Some(value)) => {
+                        name = value;
+                        rest.next();
+                    }
+                    _ => {
+                        eprintln!("Usage: bible_lsp clipboard [--replace] [--formatter <name>]");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            let Some(formatter) = formatter(name) else {
+                eprintln!("Unknown formatter {name}");
+                return ExitCode::FAILURE;
+            };
+            let lsp = match load() {
+                Ok(lsp) => lsp,
+                Err(code) => return code,
+            };
+            let mut watcher = clipboard::Watcher::default();
+            eprintln!("Watching the clipboard, press ctrl-c to stop");
+            loop {
+                match watcher.poll(&lsp, &formatter, mode) {
+                    Ok(Some(expanded)) => {
+                        println!("{expanded}\n");
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        eprintln!("{err}");
+                        return ExitCode::FAILURE;
+                    }
+                }
+                tokio::time::sleep(clipboard::POLL_INTERVAL).await;
+            }
+        }
+        ["validate", file] => {
+            let path = PathBuf::from(file);
+            let bible = match bible_json::read(&path) {
+                Ok(bible) => bible,
+                Err(err) => {
+                    eprintln!("{}", error::render(err));
+                    return ExitCode::FAILURE;
+                }
+            };
+            let problems = bible_json::problems(&bible);
+            for problem in problems.iter() {
+                let severity = if problem.fatal { "error" } else { "warning" };
+                println!("{severity}: {}: {}", problem.at, problem.message);
+            }
+            if problems.iter().any(|problem| problem.fatal) {
+                return ExitCode::FAILURE;
+            }
+            let chapters = bible.bible.iter().map(|book| book.content.len());
+            let verses = bible
+                .bible
+                .iter()
+                .flat_map(|book| &book.content)
+                .map(Vec::len);
+            println!(
+                "{file} can be loaded: {} books, {} chapters, {} verses",
+                bible.bible.len(),
+                chapters.sum::<usize>(),
+                verses.sum::<usize>()
+            );
+        }
+        ["convert", rest @ ..] => {
+            use bible_lsp::bible_json::JSONTranslation;
+            use bible_lsp::convert::{self, Format};
+            const USAGE: &str = "Usage: bible_lsp convert --from <format> --to <format> <file>... [--output <file>] [--name <name>] [--abbreviation <abbreviation>] [--language <language>]";
+            let (mut from, mut to, mut output) = (None, None, None);
+            let mut fallback = JSONTranslation {
+                name: String::new(),
+                language: String::from("English"),
+                abbreviation: String::new(),
+                attribution: None,
+            };
+            let mut inputs = vec![];
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                let value = match (*arg, rest.as_slice().first()) {
+                    (flag, Some(value)) if flag.starts_with("--") => {
+                        rest.next();
+                        value.to_string()
+                    }
+                    (flag, None) if flag.starts_with("--") => {
+                        eprintln!("{USAGE}");
+                        return ExitCode::FAILURE;
+                    }
+                    (file, _) => {
+                        inputs.push(PathBuf::from(file));
+                        continue;
+                    }
+                };
+                match *arg {
+                    "--from" | "--to" => match value.parse::<Format>() {
+                        Ok(format) if *arg == "--from" => from = Some(format),
+                        Ok(format) => to = Some(format),
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return ExitCode::FAILURE;
+                        }
                     },
-                },
-            }))),
-            Err(_) => Ok(None),
+                    "--output" => output = Some(PathBuf::from(value)),
+                    "--name" => fallback.name = value,
+                    "--abbreviation" => fallback.abbreviation = value,
+                    "--language" => fallback.language = value,
+                    _ => {
+                        eprintln!("{USAGE}");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            let (Some(from), Some(to), false) = (from, to, inputs.is_empty()) else {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            };
+            let Some(writer) = convert::writer(to) else {
+                match to {
+                    Format::MyBible => eprintln!("MyBible modules can only be read"),
+                    _ => eprintln!(
+                        "This build of bible_lsp was compiled without the \"usfm\" feature"
+                    ),
+                }
+                return ExitCode::FAILURE;
+            };
+            let mut parts = vec![];
+            for path in inputs.iter() {
+                match convert::read_file(from, path) {
+                    Ok(part) => parts.push(part),
+                    Err(err) => {
+                        eprintln!("{}", error::render(err));
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            if fallback.abbreviation.is_empty() {
+                fallback.abbreviation = inputs[0]
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_uppercase())
+                    .unwrap_or_default();
+            }
+            if fallback.name.is_empty() {
+                fallback.name = fallback.abbreviation.clone();
+            }
+            let bible = convert::merge(parts, fallback);
+            let written = match writer.write(&bible) {
+                Ok(written) => written,
+                Err(err) => {
+                    let name = output.clone().unwrap_or_else(|| PathBuf::from("-"));
+                    eprintln!("{}", error::render(error::Error::convert(&name, err)));
+                    return ExitCode::FAILURE;
+                }
+            };
+            match output {
+                Some(path) => {
+                    if let Err(err) = std::fs::write(&path, written) {
+                        eprintln!("{}", error::render(error::Error::read(&path, err)));
+                        return ExitCode::FAILURE;
+                    }
+                    let problems = bible_json::problems(&bible);
+                    for problem in problems.iter() {
+                        let severity = if problem.fatal { "error" } else { "warning" };
+                        eprintln!("{severity}: {}: {}", problem.at, problem.message);
+                    }
+                    eprintln!("Wrote {} books to {}", bible.bible.len(), path.display());
+                }
+                None => print!("{written}"),
+            }
         }
-    }
-
-    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
-        // params.text_document.uri
-        let doc = params.text_document;
-        let uri = doc.uri.clone();
-        let text = documents
-            .read()
-            .unwrap()
-            .get(&doc.uri)
-            .cloned()
-            .expect("It should be in the map");
-        let pos = params.range.start;
-        let Some(refs) = self.lsp.find_book_references(&text) else {
-            return Ok(None);
-        };
-
-        let refs = refs
-            .into_iter()
-            .filter(|book_ref| book_ref.range.start.line == pos.line)
-            .collect::<Vec<_>>();
-        // append_log(format!("{:#?}", refs));
-        let mut res = CodeActionResponse::new();
-        for each in refs {
-            res.push(CodeActionOrCommand::CodeAction(CodeAction {
-                title: format!("Insert Callout {}", each.full_ref_label(&self.lsp.api)),
-                kind: None,
-                diagnostics: None,
-                edit: Some(WorkspaceEdit {
-                    changes: None,
-                    document_changes: Some(DocumentChanges::Edits(vec![
-                        // TextDocumentEdit::new()
-                        TextDocumentEdit {
-                            text_document: OptionalVersionedTextDocumentIdentifier {
-                                uri: uri.clone(),
-                                version: None,
-                            },
-                            // prefix inserted content with \n so that way it works when
-                            // i try inserting on the next line when i am on the last line
-                            edits: vec![OneOf::Left(TextEdit {
-                                range: Range {
-                                    start: Position {
-                                        line: pos.line,
-                                        character: 0,
-                                    },
-                                    end: Position {
-                                        line: pos.line,
-                                        character: u32::MAX,
-                                    },
-                                },
-                                new_text: each.format_callout(&self.lsp.api),
-                            })],
-                        },
-                    ])),
-                    change_annotations: None,
-                }),
-                command: None,
-                is_preferred: None,
-                disabled: None,
-                data: None,
-                ..Default::default()
-            }));
-
-            res.push(CodeActionOrCommand::CodeAction(CodeAction {
-                title: format!("Insert {}", each.full_ref_label(&self.lsp.api)),
-                kind: None,
-                diagnostics: None,
-                edit: Some(WorkspaceEdit {
-                    changes: None,
-                    document_changes: Some(DocumentChanges::Edits(vec![
-                        // TextDocumentEdit::new()
-                        TextDocumentEdit {
-                            text_document: OptionalVersionedTextDocumentIdentifier {
-                                uri: uri.clone(),
-                                version: None,
-                            },
-                            // prefix inserted content with \n so that way it works when
-                            // i try inserting on the next line when i am on the last line
-                            edits: vec![OneOf::Left(TextEdit {
-                                range: Range {
-                                    start: Position {
-                                        line: pos.line,
-                                        character: u32::MAX,
-                                    },
-                                    end: Position {
-                                        line: pos.line,
-                                        character: u32::MAX,
-                                    },
-                                },
-                                new_text: each.format_insert(&self.lsp.api),
-                            })],
-                        },
-                    ])),
-                    change_annotations: None,
-                }),
-                command: None,
-                is_preferred: None,
-                disabled: None,
-                data: None,
-                ..Default::default()
-            }));
-
-            res.push(CodeActionOrCommand::CodeAction(CodeAction {
-                title: format!("Replace {}", each.full_ref_label(&self.lsp.api)),
-                kind: None,
-                diagnostics: None,
-                edit: Some(WorkspaceEdit {
-                    changes: None,
-                    document_changes: Some(DocumentChanges::Edits(vec![
-                        // TextDocumentEdit::new()
-                        TextDocumentEdit {
-                            text_document: OptionalVersionedTextDocumentIdentifier {
-                                uri: uri.clone(),
-                                version: None,
-                            },
-                            // this doesn't work if i am on last line
-                            edits: vec![OneOf::Left(TextEdit {
-                                range: Range {
-                                    start: Position {
-                                        line: pos.line,
-                                        character: 0,
-                                    },
-                                    end: Position {
-                                        line: pos.line,
-                                        character: u32::MAX,
-                                    },
-                                },
-                                new_text: each.format_replace(&self.lsp.api),
-                            })],
-                        },
-                    ])),
-                    change_annotations: None,
-                }),
-                command: None,
-                is_preferred: None,
-                disabled: None,
-                data: None,
-                ..Default::default()
-            }));
+        _ => {
+            eprintln!("Usage: bible_lsp [--record <file>] | bible_lsp --daemon|--connect [socket] | bible_lsp replay <file> | bible_lsp extract <file> [--json] | bible_lsp watch <dir> [--write-annotations] | bible_lsp init [dir] [--download <url>] | bible_lsp pick [--formatter <name>] | bible_lsp clipboard [--replace] [--formatter <name>] | bible_lsp validate <file> | bible_lsp convert --from <format> --to <format> <file>... [--output <file>]");
+            return ExitCode::FAILURE;
         }
-
-        Ok(Some(res))
-        // Ok(None)
-    }
-
-    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
-        Ok(Some(vec![CodeLens {
-            range: Range {
-                start: Position {
-                    line: 1,
-                    character: 0,
-                },
-                end: Position {
-                    line: 1,
-                    character: 0,
-                },
-            },
-            command: Some(Command {
-                title: "Code Lens Title".to_string(),
-                command: "command".to_string(),
-                arguments: Some(vec![Value::String(String::from("arg 1"))]),
-            }),
-            data: None,
-        }]))
-    }
-
-    async fn inline_value(&self, params: InlineValueParams) -> Result<Option<Vec<InlineValue>>> {
-        Ok(Some(vec![InlineValue::Text(InlineValueText {
-            range: Range {
-                start: Position {
-                    line: 1,
-                    character: 0,
-                },
-                end: Position {
-                    line: 1,
-                    character: u32::MAX,
-                },
-            },
-            text: "Inline Value".to_string(),
-        })]))
-    }
-
-    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
-        Ok(Some(vec![
-            InlayHint {
-                position: Position {
-                    line: 1,
-                    character: u32::MAX,
-                },
-                // label: InlayHintLabel::String(String::from("Ephesians 1:1")),
-                label: InlayHintLabel::String(String::from("Paul, an apostle of Christ Jesus by the will of God, To the saints who are in Ephesus, and are faithful in Christ Jesus:")),
-                kind: None,
-                text_edits: None,
-                tooltip: Some(InlayHintTooltip::MarkupContent(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: String::from("### Ephesians 1:1
-
-[1:1] Paul, an apostle of Christ Jesus by the will of God, To the saints who are in Ephesus, and are faithful in Christ Jesus:
-"),
-                })),
-                padding_left: Some(true),
-                padding_right: Some(true),
-                data: None,
-            },
-//             InlayHint {
-//                 position: Position {
-//                     line: 1,
-//                     character: u32::MAX,
-//                 },
-//                 // label: InlayHintLabel::String(String::from("John 1:1")),
-//                 label: InlayHintLabel::String(String::from("In the beginning was the Word, and the Word was with God, and the Word was God.")),
-//                 kind: None,
-//                 text_edits: None,
-//                 tooltip: Some(InlayHintTooltip::MarkupContent(MarkupContent {
-//                     kind: MarkupKind::Markdown,
-//                     value: String::from(
-//                         "### John 1:1
-//
-// [1:1] In the beginning was the Word, and the Word was with God, and the Word was God.",
-//                     ),
-//                 })),
-//                 padding_left: Some(true),
-//                 padding_right: Some(true),
-//                 data: None,
-//             },
-        ]))
     }
-
-    async fn document_symbol(
-        &self,
-        params: DocumentSymbolParams,
-    ) -> Result<Option<DocumentSymbolResponse>> {
-        let doc = params.text_document;
-        let text = documents
-            .read()
-            .unwrap()
-            .get(&doc.uri)
-            .cloned()
-            .expect("It should be in the map");
-
-        // let mut symbols: Vec<Diagnostic> = Vec::new();
-        let Some(refs) = self.lsp.find_book_references(&text) else {
-            return Ok(None);
-        };
-        let symbols = refs
-            .into_iter()
-            .map(|book_ref| SymbolInformation {
-                name: book_ref.full_ref_label(&self.lsp.api),
-                kind: SymbolKind::KEY,
-                location: Location {
-                    uri: doc.uri.clone(),
-                    range: book_ref.range,
-                },
-                tags: None,
-                deprecated: None,
-                container_name: None,
-            })
-            .collect::<Vec<_>>();
-        Ok(Some(DocumentSymbolResponse::Flat(symbols)))
-    }
-
-    async fn shutdown(&self) -> Result<()> {
-        Ok(())
-    }
-}
-
-#[tokio::main]
-async fn main() {
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
-    let json_path = "/home/dgmastertemple/Development/rust/bible_api/esv.json";
-    let lsp = BibleLSP::new(json_path);
-    let (service, socket) = LspService::new(|client| Backend { client, lsp });
-    Server::new(stdin, stdout, socket).serve(service).await;
+    ExitCode::SUCCESS
 }
 
 // fn main() {
 //     let json_path = "/home/dgmastertemple/Development/rust/bible_api/esv.json";
-//     let lsp = BibleLSP::new(json_path);
+//     let lsp = RwLock::new(Arc::new(BibleLSP::new(json_path)));
 //     let contents = std::fs::read_to_string("/home/dgmastertemple/christian_commons.txt").unwrap();
 //     let references = lsp.find_book_references(&contents).unwrap();
 //     // for BookReference {
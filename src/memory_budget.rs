@@ -0,0 +1,55 @@
+use serde::Serialize;
+
+/// The hover cache gets a quarter of the budget, and the workspace index the rest
+const HOVER_CACHE_SHARE: usize = 4;
+
+/**
+How much the caches may hold, from `bible.memoryBudgetMb`
+
+- Split between the hover cache and the in-memory workspace index, the two things that grow
+  with use
+- Sizes are estimates from string lengths, not what the allocator actually hands out, so it's a
+  ceiling to aim for rather than a guarantee
+- No budget (the default) means nothing is evicted beyond the hover cache's usual size
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryBudget {
+    bytes: Option<usize>,
+}
+
+impl MemoryBudget {
+    pub fn from_mb(mb: Option<usize>) -> Self {
+        Self {
+            bytes: mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+        }
+    }
+
+    pub fn bytes(&self) -> Option<usize> {
+        self.bytes
+    }
+
+    /// see [`crate::hover_cache::HoverCache::set_budget`]
+    pub fn hover_cache(&self) -> Option<usize> {
+        self.bytes.map(|bytes| bytes / HOVER_CACHE_SHARE)
+    }
+
+    /// see [`crate::workspace_index::WorkspaceIndex::set_budget`]
+    pub fn index(&self) -> Option<usize> {
+        self.bytes.map(|bytes| bytes - bytes / HOVER_CACHE_SHARE)
+    }
+}
+
+/// What the caches hold right now, reported by `bible/status`
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryUsage {
+    /// `null` without a budget
+    pub budget_bytes: Option<usize>,
+    pub hover_cache_entries: usize,
+    pub hover_cache_bytes: usize,
+    pub index_files: usize,
+    pub index_bytes: usize,
+    /// - Workspace files dropped from the index to stay in the budget
+    /// - Backlinks and coverage leave them out until they are indexed again
+    pub evicted_files: usize,
+}
@@ -0,0 +1,265 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::lsp_types::Url;
+
+/// The folder name used under each of the platform directories
+const APP_NAME: &str = "bible_lsp";
+
+fn env_path(key: &str) -> Option<PathBuf> {
+    env::var_os(key)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env_path("USERPROFILE")
+    } else {
+        env_path("HOME")
+    }
+}
+
+/// - If there is no home directory (like in some containers), things go in the temp dir
+///   instead of failing
+fn home_or_temp() -> PathBuf {
+    home_dir().unwrap_or_else(env::temp_dir)
+}
+
+/// - Linux: `$XDG_CONFIG_HOME/bible_lsp` or `~/.config/bible_lsp`
+/// - macOS: `~/Library/Application Support/bible_lsp`
+/// - Windows: `%APPDATA%\bible_lsp`
+pub fn config_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        env_path("APPDATA").unwrap_or_else(|| home_or_temp().join("AppData").join("Roaming"))
+    } else if cfg!(target_os = "macos") {
+        home_or_temp().join("Library").join("Application Support")
+    } else {
+        env_path("XDG_CONFIG_HOME").unwrap_or_else(|| home_or_temp().join(".config"))
+    };
+    base.join(APP_NAME)
+}
+
+/// - Linux: `$XDG_CACHE_HOME/bible_lsp` or `~/.cache/bible_lsp`
+/// - macOS: `~/Library/Caches/bible_lsp`
+/// - Windows: `%LOCALAPPDATA%\bible_lsp\cache`
+pub fn cache_dir() -> PathBuf {
+    if cfg!(windows) {
+        local_app_data().join(APP_NAME).join("cache")
+    } else if cfg!(target_os = "macos") {
+        home_or_temp().join("Library").join("Caches").join(APP_NAME)
+    } else {
+        env_path("XDG_CACHE_HOME")
+            .unwrap_or_else(|| home_or_temp().join(".cache"))
+            .join(APP_NAME)
+    }
+}
+
+/// - Linux: `$XDG_DATA_HOME/bible_lsp` or `~/.local/share/bible_lsp`
+/// - macOS: `~/Library/Application Support/bible_lsp`
+/// - Windows: `%LOCALAPPDATA%\bible_lsp\data`
+pub fn data_dir() -> PathBuf {
+    if cfg!(windows) {
+        local_app_data().join(APP_NAME).join("data")
+    } else if cfg!(target_os = "macos") {
+        home_or_temp()
+            .join("Library")
+            .join("Application Support")
+            .join(APP_NAME)
+    } else {
+        env_path("XDG_DATA_HOME")
+            .unwrap_or_else(|| home_or_temp().join(".local").join("share"))
+            .join(APP_NAME)
+    }
+}
+
+/// Where `bible_lsp init --download` puts translations, and where the server looks for one
+pub fn translations_dir() -> PathBuf {
+    data_dir().join("translations")
+}
+
+/// - Linux: `$XDG_STATE_HOME/bible_lsp` or `~/.local/state/bible_lsp`
+/// - macOS: `~/Library/Logs/bible_lsp`
+/// - Windows: `%LOCALAPPDATA%\bible_lsp\logs`
+pub fn log_dir() -> PathBuf {
+    if cfg!(windows) {
+        local_app_data().join(APP_NAME).join("logs")
+    } else if cfg!(target_os = "macos") {
+        home_or_temp().join("Library").join("Logs").join(APP_NAME)
+    } else {
+        env_path("XDG_STATE_HOME")
+            .unwrap_or_else(|| home_or_temp().join(".local").join("state"))
+            .join(APP_NAME)
+    }
+}
+
+pub fn log_file() -> PathBuf {
+    log_dir().join("bible_lsp.log")
+}
+
+/// - Where `bible_lsp --daemon` puts its socket
+/// - `$XDG_RUNTIME_DIR/bible_lsp` when there is one, since only the user can get in there
+/// - Otherwise a folder in the temp dir with the user name in it, so users don't share a daemon,
+///   which the daemon refuses unless it's the user's own and private
+pub fn runtime_dir() -> PathBuf {
+    match env_path("XDG_RUNTIME_DIR") {
+        Some(dir) => dir.join(APP_NAME),
+        None => user_temp_dir(),
+    }
+}
+
+/// `bible_lsp-<user>` in the temp dir
+fn user_temp_dir() -> PathBuf {
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_default();
+    env::temp_dir().join(sanitize_file_name(&format!("{APP_NAME}-{user}")))
+}
+
+fn local_app_data() -> PathBuf {
+    env_path("LOCALAPPDATA").unwrap_or_else(|| home_or_temp().join("AppData").join("Local"))
+}
+
+/// - Where generated documents (like the chapters opened by goto-definition) are written
+/// - In the user's own folder in the temp dir, like [`runtime_dir`] without `$XDG_RUNTIME_DIR`,
+///   so other users can't read them or put their own there, see [`create_temp_dir`]
+pub fn temp_dir() -> PathBuf {
+    user_temp_dir().join("documents")
+}
+
+/**
+Makes [`temp_dir`] before anything is written there, and returns it
+
+- The user's folder it's in is made with mode `0700`, and refused unless it's private (see
+  [`check_private`]), since another user could have made it first
+*/
+pub fn create_temp_dir() -> io::Result<PathBuf> {
+    let dir = temp_dir();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+
+        let private = user_temp_dir();
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&private)?;
+        check_private(&private)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/**
+Refuses a folder someone else could get into, like one another user made first in the temp dir
+under this user's name
+
+- It has to be a real folder (not a link) owned by this user, with mode `0700`
+- Creating it with that mode isn't enough, since an existing folder keeps its owner and mode
+*/
+#[cfg(unix)]
+pub(crate) fn check_private(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::symlink_metadata(dir)?;
+    // SAFETY: `geteuid` has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+    if metadata.is_dir() && metadata.uid() == uid && metadata.mode() & 0o777 == 0o700 {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "{} has to be a folder only this user can get into (mode 0700), so it won't be used",
+            dir.display()
+        ),
+    ))
+}
+
+/// - Makes a string safe to use as a file name on every platform
+/// - Windows is the strictest: no `<>:"/\|?*` and no trailing dots or spaces
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|ch| match ch {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        })
+        .collect();
+    sanitized.trim_end_matches(['.', ' ']).to_string()
+}
+
+/// - `Url::from_file_path` only accepts absolute paths, so relative ones are resolved against
+///   the current directory first
+pub fn path_to_url(path: &Path) -> Option<Url> {
+    if path.is_absolute() {
+        Url::from_file_path(path).ok()
+    } else {
+        Url::from_file_path(env::current_dir().ok()?.join(path)).ok()
+    }
+}
+
+/// Only `file://` URIs have a path
+pub fn url_to_path(url: &Url) -> Option<PathBuf> {
+    if url.scheme() != "file" {
+        return None;
+    }
+    url.to_file_path().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_paths_round_trip_through_url() {
+        for name in ["Ephesians", "1 Corinthians", "Ésaïe", "Song of Songs"] {
+            let path = temp_dir().join(format!("{}.md", sanitize_file_name(name)));
+            let url = path_to_url(&path).expect("Temp dir is absolute");
+            assert_eq!(url.scheme(), "file");
+            assert_eq!(url_to_path(&url), Some(path));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn generated_documents_are_in_a_private_folder() {
+        let dir = create_temp_dir().unwrap();
+        assert!(dir.is_dir());
+        assert!(dir.starts_with(user_temp_dir()));
+        assert!(user_temp_dir()
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(APP_NAME)));
+        assert!(check_private(&user_temp_dir()).is_ok());
+    }
+
+    #[test]
+    fn relative_paths_become_absolute_urls() {
+        let url = path_to_url(Path::new("notes/romans.md")).unwrap();
+        let path = url_to_path(&url).unwrap();
+        assert!(path.is_absolute());
+        assert!(path.ends_with("notes/romans.md"));
+    }
+
+    #[test]
+    fn non_file_urls_have_no_path() {
+        let url = Url::parse("untitled:Untitled-1").unwrap();
+        assert_eq!(url_to_path(&url), None);
+    }
+
+    #[test]
+    fn file_names_are_sanitized() {
+        assert_eq!(sanitize_file_name("Ephesians 1:1"), "Ephesians 1_1");
+        assert_eq!(sanitize_file_name("What?."), "What_");
+        assert_eq!(sanitize_file_name("a/b\\c"), "a_b_c");
+    }
+
+    #[test]
+    fn platform_dirs_are_namespaced() {
+        for dir in [config_dir(), cache_dir(), data_dir(), log_dir()] {
+            assert!(dir.components().any(|c| c.as_os_str() == APP_NAME));
+        }
+    }
+}
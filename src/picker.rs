@@ -0,0 +1,378 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+
+use crate::{
+    bible_api::BibleAPI,
+    bible_lsp::BibleLSP,
+    book_reference::BookReference,
+    book_reference_segment::{BookReferenceSegment, BookReferenceSegments},
+};
+
+/// At most this many candidates are listed
+const MAX_CANDIDATES: usize = 50;
+
+/// A line in the picker's list
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// Ex: `Ephesians 2:8`
+    pub label: String,
+    /// `None` for a book on its own, which is picked by typing a chapter after it
+    pub book_ref: Option<BookReference>,
+}
+
+/**
+- How well `query` matches `name` when its characters appear in order, like `eph` in `Ephesians`
+  or `1jn` in `1 John`
+- Lower is better, and `None` means it doesn't match at all
+- Gaps between the matched characters cost the most, then starting late in the name
+*/
+fn fuzzy_score(query: &str, name: &str) -> Option<usize> {
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let mut position = 0;
+    let mut first = None;
+    let mut gaps = 0;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = name[position..].iter().position(|n| *n == c)?;
+        match first {
+            // spaces in the name don't count against the query, so `1jn` matches the `1 jn`
+            // abbreviation perfectly
+            Some(_) => {
+                gaps += name[position..position + found]
+                    .iter()
+                    .filter(|n| !n.is_whitespace())
+                    .count()
+            }
+            None => first = Some(position + found),
+        }
+        position += found + 1;
+    }
+    Some(gaps * 10 + first.unwrap_or(0))
+}
+
+/// The whole chapter, from its first verse to its last
+fn whole_chapter(api: &BibleAPI, book_id: usize, chapter: usize) -> Option<BookReference> {
+    let verse_count = api.get_chapter_verse_count(book_id, chapter)?;
+    Some(BookReference {
+        range: Default::default(),
+        book_id,
+        segments: BookReferenceSegments(vec![BookReferenceSegment::from_span(
+            (chapter, 1),
+            (chapter, verse_count),
+        )]),
+        translation: None,
+        segment_ranges: vec![],
+    })
+}
+
+/**
+What `query` could mean, best first
+
+- The book part is matched fuzzily against every name and abbreviation (`eph`, `1jn`)
+- `eph` lists books, `eph 2` lists the whole chapter and then each of its verses, and `eph 2:8-10`
+  is just that passage
+*/
+pub fn candidates(lsp: &BibleLSP, query: &str) -> Vec<Candidate> {
+    let api = &lsp.api;
+    // the numbers are whatever comes after the last letter, since `1 John` starts with one
+    let split = query
+        .char_indices()
+        .rfind(|(_, c)| c.is_alphabetic())
+        .map_or(0, |(idx, c)| idx + c.len_utf8());
+    let (book_query, numbers) = (query[..split].trim(), query[split..].trim());
+    if book_query.is_empty() {
+        return vec![];
+    }
+
+    let mut books: Vec<(usize, usize)> = api
+        .abbreviations_to_book_id
+        .iter()
+        .filter_map(|(name, book_id)| Some((fuzzy_score(book_query, name)?, *book_id)))
+        .collect();
+    books.sort_by_key(|(score, book_id)| (*score, api.canon_position(*book_id)));
+    // a book matched by several abbreviations is only listed once, at its best score
+    let mut seen = vec![];
+    books.retain(|(_, book_id)| {
+        let new = !seen.contains(book_id);
+        seen.push(*book_id);
+        new
+    });
+
+    let mut candidates = vec![];
+    for (_, book_id) in books {
+        let Some(name) = api.get_book_name(book_id) else {
+            continue;
+        };
+        if numbers.is_empty() {
+            candidates.push(Candidate {
+                label: name.to_string(),
+                book_ref: None,
+            });
+        } else if let Ok(chapter) = numbers.parse::<usize>() {
+            let Some(verse_count) = api.get_chapter_verse_count(book_id, chapter) else {
+                continue;
+            };
+            candidates.push(Candidate {
+                label: format!("{name} {chapter}"),
+                book_ref: whole_chapter(api, book_id, chapter),
+            });
+            candidates.extend((1..=verse_count).map(|verse| Candidate {
+                label: format!("{name} {chapter}:{verse}"),
+                book_ref: Some(BookReference {
+                    range: Default::default(),
+                    book_id,
+                    segments: BookReferenceSegments(vec![BookReferenceSegment::from_span(
+                        (chapter, verse),
+                        (chapter, verse),
+                    )]),
+                    translation: None,
+                    segment_ranges: vec![],
+                }),
+            }));
+        } else if let Some(book_ref) = lsp
+            .find_book_references(&format!("{name} {numbers}"))
+            .and_then(|refs| refs.into_iter().next())
+            .filter(|book_ref| {
+                book_ref.segments.iter().all(|seg| {
+                    api.is_valid_reference(
+                        book_id,
+                        seg.get_starting_chapter(),
+                        seg.get_starting_verse(),
+                    )
+                })
+            })
+        {
+            candidates.push(Candidate {
+                label: book_ref.full_ref_label(api),
+                book_ref: Some(book_ref),
+            });
+        }
+        if candidates.len() >= MAX_CANDIDATES {
+            break;
+        }
+    }
+    candidates.truncate(MAX_CANDIDATES);
+    candidates
+}
+
+/// What a key press did
+#[derive(Debug)]
+pub enum Action {
+    Continue,
+    Pick(BookReference),
+    Cancel,
+}
+
+#[derive(Debug, Default)]
+pub struct Picker {
+    pub query: String,
+    pub selected: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Picker {
+    fn update(&mut self, lsp: &BibleLSP) {
+        self.candidates = candidates(lsp, &self.query);
+        self.selected = 0;
+    }
+
+    fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.candidates.len().saturating_sub(1));
+    }
+
+    /// Puts the selected candidate in the query, so a book can be followed by a chapter
+    fn complete(&mut self, lsp: &BibleLSP) {
+        let Some(candidate) = self.candidates.get(self.selected) else {
+            return;
+        };
+        self.query = match candidate.book_ref {
+            Some(_) => candidate.label.clone(),
+            None => format!("{} ", candidate.label),
+        };
+        self.update(lsp);
+    }
+
+    /**
+    - Typing filters, up/down (or ctrl-p/ctrl-n) move the selection, and tab completes it
+    - Enter picks a passage, or completes a book
+    - Escape and ctrl-c cancel
+    */
+    pub fn handle(&mut self, lsp: &BibleLSP, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Action::Cancel,
+            KeyCode::Char('c') if ctrl => return Action::Cancel,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.select_next(),
+            KeyCode::Char('n') if ctrl => self.select_next(),
+            KeyCode::Tab => self.complete(lsp),
+            KeyCode::Enter => match self.candidates.get(self.selected) {
+                Some(Candidate {
+                    book_ref: Some(book_ref),
+                    ..
+                }) => return Action::Pick(book_ref.clone()),
+                _ => self.complete(lsp),
+            },
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.update(lsp);
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.update(lsp);
+            }
+            _ => {}
+        }
+        Action::Continue
+    }
+}
+
+/// Leaves the terminal how it was found, even when drawing fails part way
+struct RawMode;
+
+impl RawMode {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), terminal::EnterAlternateScreen)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        _ = execute!(io::stderr(), terminal::LeaveAlternateScreen);
+        _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The query, the candidates, and a preview of the selected one's first verses
+fn draw(out: &mut impl Write, lsp: &BibleLSP, picker: &Picker) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (width as usize, height as usize);
+    let list_height = (height.saturating_sub(2) / 2).max(1);
+    let fit = |text: &str| text.chars().take(width).collect::<String>();
+    queue!(
+        out,
+        terminal::Clear(ClearType::All),
+        cursor::MoveTo(0, 0),
+        Print(fit(&format!("> {}", picker.query)))
+    )?;
+    // keep the selection in view
+    let first = picker.selected.saturating_sub(list_height - 1);
+    for (row, (idx, candidate)) in picker
+        .candidates
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(list_height)
+        .enumerate()
+    {
+        queue!(out, cursor::MoveTo(0, row as u16 + 1))?;
+        if idx == picker.selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(fit(&candidate.label)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(fit(&candidate.label)))?;
+        }
+    }
+    if let Some(book_ref) = picker
+        .candidates
+        .get(picker.selected)
+        .and_then(|candidate| candidate.book_ref.as_ref())
+    {
+        let preview_top = list_height + 2;
+        let lines = book_ref
+            .verses(&lsp.api)
+            .into_iter()
+            .filter_map(|(chapter, verse)| {
+                let content = lsp
+                    .api
+                    .get_bible_contents(book_ref.book_id, chapter, verse)?;
+                Some(format!("[{chapter}:{verse}] {content}"))
+            })
+            .take(height.saturating_sub(preview_top));
+        for (row, line) in lines.enumerate() {
+            queue!(
+                out,
+                cursor::MoveTo(0, (preview_top + row) as u16),
+                Print(fit(&line))
+            )?;
+        }
+    }
+    let query_width = format!("> {}", picker.query).chars().count();
+    queue!(out, cursor::MoveTo(query_width.min(width) as u16, 0))?;
+    out.flush()
+}
+
+/**
+Runs the picker until a passage is picked, or `None` if it was cancelled
+
+- Drawn on stderr, so only the passage goes to stdout and `bible_lsp pick | pbcopy` works
+*/
+pub fn run(lsp: &BibleLSP) -> io::Result<Option<BookReference>> {
+    let _raw = RawMode::enter()?;
+    let mut picker = Picker::default();
+    let mut stderr = io::stderr();
+    loop {
+        draw(&mut stderr, lsp, &picker)?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match picker.handle(lsp, key) {
+            Action::Continue => {}
+            Action::Pick(book_ref) => return Ok(Some(book_ref)),
+            Action::Cancel => return Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picking_drills_down_from_books() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        assert_eq!(fuzzy_score("1jn", "1 jn"), Some(0));
+        assert!(fuzzy_score("eph", "ephesians") < fuzzy_score("eph", "zephaniah"));
+        assert!(fuzzy_score("jn1", "1 john").is_none());
+
+        let mut picker = Picker::default();
+        for c in "gnss".chars() {
+            picker.handle(&lsp, KeyEvent::from(KeyCode::Char(c)));
+        }
+        assert_eq!(picker.candidates[0].label, "Genesis");
+        picker.handle(&lsp, KeyEvent::from(KeyCode::Enter));
+        assert_eq!(picker.query, "Genesis ");
+        picker.handle(&lsp, KeyEvent::from(KeyCode::Char('2')));
+        let labels: Vec<&str> = picker
+            .candidates
+            .iter()
+            .map(|candidate| candidate.label.as_str())
+            .collect();
+        assert_eq!(labels[..3], ["Genesis 2", "Genesis 2:1", "Genesis 2:2"]);
+        picker.handle(&lsp, KeyEvent::from(KeyCode::Down));
+        let Action::Pick(book_ref) = picker.handle(&lsp, KeyEvent::from(KeyCode::Enter)) else {
+            panic!("expected a passage");
+        };
+        assert_eq!(book_ref.full_ref_label(&lsp.api), "Genesis 2:1");
+        assert_eq!(candidates(&lsp, "gen 1:2-4")[0].label, "Genesis 1:2-4");
+    }
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bible_api::BibleAPI;
+
+/**
+How verses laid out as poetry or paragraphs are shown, see [`crate::bible_json::JSONBook::layout`]
+
+```json
+"poetry": { "indent": "    ", "stanzaBreaks": true, "paragraphBreaks": false }
+```
+
+```text
+[23:1] The LORD is my shepherd;
+  I shall not want.
+
+[23:2] He makes me lie down in green pastures.
+```
+
+- Each line of poetry goes on a line of its own, after `indent` once for each level it is
+  indented, except the first, which follows the verse number
+- Stanza and paragraph breaks are a blank line before the verse, but never before the first one
+- Verses without a layout are prose, and stay on one line
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Poetry {
+    /// two spaces by default
+    pub indent: String,
+    /// a blank line where a stanza starts
+    pub stanza_breaks: bool,
+    /// a blank line where a paragraph starts
+    pub paragraph_breaks: bool,
+}
+
+impl Default for Poetry {
+    fn default() -> Self {
+        Self {
+            indent: String::from("  "),
+            stanza_breaks: true,
+            paragraph_breaks: true,
+        }
+    }
+}
+
+impl Poetry {
+    /// `content` (the verse's text) with its lines of poetry on lines of their own, each passed
+    /// through `mark` with where it starts in the verse
+    pub fn lines(
+        &self,
+        api: &BibleAPI,
+        book_id: usize,
+        chapter: usize,
+        verse: usize,
+        content: String,
+        mut mark: impl FnMut(String, usize) -> String,
+    ) -> String {
+        let starts = match api.get_layout(book_id, chapter, verse) {
+            Some(layout) if !layout.lines.is_empty() => &layout.lines,
+            _ => return mark(content, 0),
+        };
+        let chars: Vec<char> = content.chars().collect();
+        // text before the first line is a line of its own
+        let mut breaks: Vec<(usize, usize)> = vec![(0, 0)];
+        breaks.extend(starts.iter().map(|[offset, level]| (*offset, *level)));
+        let mut lines = vec![];
+        for (idx, (start, level)) in breaks.iter().copied().enumerate() {
+            let end = breaks
+                .get(idx + 1)
+                .map_or(chars.len(), |(offset, _)| *offset)
+                .min(chars.len());
+            if start >= end {
+                continue;
+            }
+            let line: String = chars[start..end].iter().collect();
+            let leading = line.chars().take_while(|c| c.is_whitespace()).count();
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let indent = match lines.is_empty() {
+                true => String::new(),
+                false => self.indent.repeat(level),
+            };
+            lines.push(format!(
+                "{indent}{}",
+                mark(line.to_string(), start + leading)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Whether a blank line goes before a verse that isn't the first one shown
+    pub fn breaks_before(
+        &self,
+        api: &BibleAPI,
+        book_id: usize,
+        chapter: usize,
+        verse: usize,
+    ) -> bool {
+        api.get_layout(book_id, chapter, verse)
+            .is_some_and(|layout| {
+                (layout.stanza && self.stanza_breaks) || (layout.paragraph && self.paragraph_breaks)
+            })
+    }
+}
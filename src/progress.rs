@@ -0,0 +1,72 @@
+use tower_lsp::lsp_types::notification::Progress as ProgressNotification;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use tower_lsp::Client;
+
+/**
+A `$/progress` indicator in the editor (the spinner in the status bar)
+
+- Server initiated, so the client has to accept `window/workDoneProgress/create` first
+- Clients that don't support it just get nothing
+*/
+pub struct Progress {
+    client: Client,
+    token: NumberOrString,
+}
+
+impl Progress {
+    pub async fn begin(client: &Client, token: &str, title: &str) -> Option<Self> {
+        let token = NumberOrString::String(token.to_string());
+        client
+            .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .ok()?;
+        let progress = Self {
+            client: client.clone(),
+            token,
+        };
+        progress
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                message: None,
+                percentage: Some(0),
+            }))
+            .await;
+        Some(progress)
+    }
+
+    pub async fn report(&self, message: String, done: usize, total: usize) {
+        let percentage = match total {
+            0 => 100,
+            total => (done * 100 / total).min(100) as u32,
+        };
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(message),
+            percentage: Some(percentage),
+        }))
+        .await;
+    }
+
+    pub async fn end(self, message: String) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message),
+        }))
+        .await;
+    }
+
+    async fn send(&self, value: WorkDoneProgress) {
+        self.client
+            .send_notification::<ProgressNotification>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+            .await;
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+
+use crate::{
+    bible_api::BibleAPI, bible_lsp::BibleLSP, book_reference::BookReference, config::Config,
+    config::QuoteLimit, quote_markers,
+};
+
+/// How many of a verse's first words have to be in a document for it to count as quoted
+const QUOTED_WORDS: usize = 4;
+
+/// The `(book, chapter, verse)`s quoted from each translation, by abbreviation
+pub type QuotedVerses = BTreeMap<String, BTreeSet<(usize, usize, usize)>>;
+
+/**
+The references in `refs` whose text is in the document, which are the ones quote limits count
+
+- A bare citation like `see John 3:16` quotes nothing, so it doesn't count
+- A reference counts when the first words of its first verse are anywhere in `text`, like after
+  the insert and replace code actions, ignoring case, punctuation, and markdown emphasis
+- The label of a marked quote (see [`quote_markers`]) always counts, in the translation the
+  marker names
+*/
+pub fn quoted_references(lsp: &BibleLSP, text: &str, refs: &[BookReference]) -> Vec<BookReference> {
+    let mut quoted = vec![];
+    let mut marker_lines = HashSet::new();
+    for (line, line_text) in text.lines().enumerate() {
+        let Some((label, translation)) = quote_markers::marker_label(line_text) else {
+            continue;
+        };
+        let line = line as u32;
+        marker_lines.insert(line);
+        let on_line: Vec<BookReference> = refs
+            .iter()
+            .filter(|book_ref| book_ref.range.start.line == line)
+            .cloned()
+            .collect();
+        // detection can leave the marker out, like with `contexts`
+        let labelled = match on_line.is_empty() {
+            false => on_line,
+            true => lsp
+                .find_book_references_in(&label, &[])
+                .unwrap_or_default()
+                .into_iter()
+                .map(|book_ref| BookReference {
+                    range: Range::new(
+                        Position::new(line, 0),
+                        Position::new(line, line_text.encode_utf16().count() as u32),
+                    ),
+                    segment_ranges: vec![],
+                    ..book_ref
+                })
+                .collect(),
+        };
+        quoted.extend(labelled.into_iter().map(|book_ref| BookReference {
+            translation: Some(translation.clone()),
+            ..book_ref
+        }));
+    }
+
+    let words = plain_words(text);
+    let windows: HashSet<&[String]> = words.windows(QUOTED_WORDS).collect();
+    quoted.extend(
+        refs.iter()
+            .filter(|book_ref| !marker_lines.contains(&book_ref.range.start.line))
+            .filter(|book_ref| {
+                let api = lsp.api_for(book_ref.translation.as_deref());
+                let Some((chapter, verse)) = book_ref.verses(api).first().copied() else {
+                    return false;
+                };
+                let verse_text = api
+                    .get_bible_contents(book_ref.book_id, chapter, verse)
+                    .unwrap_or_default();
+                let first_words: Vec<String> = plain_words(&verse_text)
+                    .into_iter()
+                    .take(QUOTED_WORDS)
+                    .collect();
+                match first_words.len() {
+                    0 => false,
+                    QUOTED_WORDS => windows.contains(first_words.as_slice()),
+                    len => words
+                        .windows(len)
+                        .any(|window| window == first_words.as_slice()),
+                }
+            })
+            .cloned(),
+    );
+    quoted.sort_by_key(|book_ref| (book_ref.range.start.line, book_ref.range.start.character));
+    quoted
+}
+
+/// Lower case words without punctuation or markup, so `**For God** so loved,` is `for god so loved`
+fn plain_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|ch| ch.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/**
+- Sums the distinct verses quoted in the order they appear, see [`quoted_references`], and warns on every reference
+  that adds verses once a limit has been passed
+- Each reference counts towards the limit of its own translation, like `KJV` in `John 3:16 KJV`
+- Verses cited more than once only count once
+- `elsewhere` are the verses quoted in the rest of the workspace, which only count for
+  translations whose limit has [`QuoteLimit::workspace`] on
+*/
+pub fn quote_limit_diagnostics(
+    lsp: &BibleLSP,
+    config: &Config,
+    refs: &[BookReference],
+    elsewhere: &QuotedVerses,
+) -> Vec<Diagnostic> {
+    let mut quoted = QuotedVerses::new();
+    let mut diagnostics = vec![];
+
+    for book_ref in refs {
+        let api = lsp.api_for(book_ref.translation.as_deref());
+        let translation = &api.translation.abbreviation;
+        let Some(limit) = config.quote_limit(translation) else {
+            continue;
+        };
+        let quoted = quoted
+            .entry(translation.clone())
+            .or_insert_with(|| match limit.workspace {
+                true => elsewhere.get(translation).cloned().unwrap_or_default(),
+                false => BTreeSet::new(),
+            });
+        let mut added = false;
+        for (chapter, verse) in book_ref.verses(api) {
+            added |= quoted.insert((book_ref.book_id, chapter, verse));
+        }
+        if !added {
+            continue;
+        }
+
+        let scope = match limit.workspace {
+            true => " in the workspace",
+            false => "",
+        };
+        let mut messages = vec![];
+        if let Some(max_verses) = limit.max_verses {
+            if quoted.len() > max_verses {
+                messages.push(format!(
+                    "{} verses of the {translation} are quoted{scope}, but the limit is {max_verses}",
+                    quoted.len()
+                ));
+            }
+        }
+        if let Some(max_book_percent) = limit.max_book_percent {
+            let book_id = book_ref.book_id;
+            let book_quoted = quoted.range((book_id, 0, 0)..(book_id + 1, 0, 0)).count();
+            let book_total = book_verse_count(api, book_id);
+            let percent = 100.0 * book_quoted as f64 / book_total.max(1) as f64;
+            if percent > max_book_percent {
+                let book_name = api.get_book_name(book_id).unwrap_or_default();
+                messages.push(format!(
+                    "{percent:.0}% of {book_name} is quoted from the {translation}{scope}, but the limit is {max_book_percent}%"
+                ));
+            }
+        }
+        if messages.is_empty() {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            range: book_ref.range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(String::from("quote-limit"))),
+            source: Some(String::from("bible_lsp")),
+            message: messages.join("\n"),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
+
+/**
+Why `book_ref` can't be written out from the translation, when `limit` doesn't allow it
+
+- For the insert and replace code actions and `bible.copyPassage`, which refuse rather than
+  leave the user to find out from [`quote_limit_diagnostics`] afterwards
+- Each passage is counted on its own, since the whole document is what the diagnostics are for
+*/
+pub fn insert_refusal(
+    api: &BibleAPI,
+    limit: &QuoteLimit,
+    book_ref: &BookReference,
+) -> Option<String> {
+    let translation = &api.translation.abbreviation;
+    if !limit.allow_full_insert {
+        return Some(format!(
+            "The {translation} can only be cited, not inserted (see `quoteLimits`)"
+        ));
+    }
+    let max_insert_verses = limit.max_insert_verses?;
+    let verses = book_ref.verses(api).len();
+    (verses > max_insert_verses).then(|| {
+        format!(
+            "{} is {verses} verses, but at most {max_insert_verses} of the {translation} can be inserted at once",
+            book_ref.full_ref_label(api)
+        )
+    })
+}
+
+fn book_verse_count(api: &BibleAPI, book_id: usize) -> usize {
+    api.get_all_chapters(book_id)
+        .into_iter()
+        .flatten()
+        .filter_map(|chapter| api.get_chapter_verse_count(book_id, chapter))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lsp() -> BibleLSP {
+        let mut lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let mut kjv = lsp.api.clone();
+        kjv.translation.abbreviation = String::from("KJV");
+        lsp.add_translation(kjv);
+        lsp
+    }
+
+    fn config(translation: &str, limit: QuoteLimit) -> Config {
+        Config {
+            quote_limits: BTreeMap::from([(translation.to_string(), limit)]),
+            ..Default::default()
+        }
+    }
+
+    fn messages(
+        lsp: &BibleLSP,
+        config: &Config,
+        text: &str,
+        elsewhere: &QuotedVerses,
+    ) -> Vec<String> {
+        let refs = lsp.find_book_references(text).unwrap_or_default();
+        quote_limit_diagnostics(lsp, config, &refs, elsewhere)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn only_quoted_references_count() {
+        let lsp = lsp();
+        let labels = |text: &str| {
+            let refs = lsp.find_book_references(text).unwrap_or_default();
+            quoted_references(&lsp, text, &refs)
+                .into_iter()
+                .map(|book_ref| {
+                    let label = book_ref.full_ref_label(&lsp.api);
+                    format!("{label} {}", book_ref.translation.unwrap_or_default())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(labels("See Genesis 1:1 and Genesis 1:2").is_empty());
+        // the fixture's verses cite themselves, so they count as well
+        assert_eq!(
+            labels("Genesis 1:1-2\n> 1 *Text of Genesis 1:1.* 2 Text of Genesis 1:2.\nGenesis 1:3"),
+            ["Genesis 1:1-2 ", "Genesis 1:1 ", "Genesis 1:2 "]
+        );
+        let marked =
+            "<!-- bible:quote Genesis 1:3 | KJV | insert -->\nquoted elsewhere\n<!-- bible:end -->";
+        assert_eq!(labels(marked), ["Genesis 1:3 KJV"]);
+    }
+
+    #[test]
+    fn verses_past_the_limit_are_warned_about() {
+        let lsp = lsp();
+        let config = config(
+            "TST",
+            QuoteLimit {
+                max_verses: Some(3),
+                ..Default::default()
+            },
+        );
+        let no_quotes = QuotedVerses::new();
+        // repeated verses only count once
+        let text = "Genesis 1:1-2 and Genesis 1:2-3 and Exodus 1:1";
+        assert_eq!(
+            messages(&lsp, &config, text, &no_quotes),
+            ["4 verses of the TST are quoted, but the limit is 3"]
+        );
+        assert!(messages(&lsp, &config, "Genesis 1:1-3 and Genesis 1:2", &no_quotes).is_empty());
+    }
+
+    #[test]
+    fn books_past_the_percent_are_warned_about() {
+        let lsp = lsp();
+        let config = config(
+            "TST",
+            QuoteLimit {
+                max_book_percent: Some(20.0),
+                ..Default::default()
+            },
+        );
+        let no_quotes = QuotedVerses::new();
+        // Genesis has 15 verses, so 3 are exactly 20%
+        assert!(messages(&lsp, &config, "Genesis 1:1-3 and Exodus 1:1-3", &no_quotes).is_empty());
+        assert_eq!(
+            messages(&lsp, &config, "Genesis 1:1-3 and Genesis 2:1", &no_quotes),
+            ["27% of Genesis is quoted from the TST, but the limit is 20%"]
+        );
+    }
+
+    #[test]
+    fn each_translation_has_its_own_limit() {
+        let lsp = lsp();
+        let limit = QuoteLimit {
+            max_verses: Some(2),
+            ..Default::default()
+        };
+        let no_quotes = QuotedVerses::new();
+        let text = "Genesis 1:1-2 KJV and Genesis 1:3-4";
+        assert_eq!(
+            messages(&lsp, &config("KJV", limit.clone()), text, &no_quotes),
+            Vec::<String>::new()
+        );
+        let text = "Genesis 1:1-2 KJV and Genesis 1:3 KJV and Genesis 1:4-5";
+        assert_eq!(
+            messages(&lsp, &config("kjv", limit.clone()), text, &no_quotes),
+            ["3 verses of the KJV are quoted, but the limit is 2"]
+        );
+        assert!(messages(&lsp, &config("TST", limit), text, &no_quotes).is_empty());
+    }
+
+    #[test]
+    fn workspace_limits_count_other_documents() {
+        let lsp = lsp();
+        let elsewhere =
+            QuotedVerses::from([(String::from("TST"), BTreeSet::from([(1, 1, 1), (1, 1, 2)]))]);
+        let mut limit = QuoteLimit {
+            max_verses: Some(2),
+            ..Default::default()
+        };
+        assert!(messages(
+            &lsp,
+            &config("TST", limit.clone()),
+            "Genesis 1:3",
+            &elsewhere
+        )
+        .is_empty());
+        limit.workspace = true;
+        assert_eq!(
+            messages(
+                &lsp,
+                &config("TST", limit.clone()),
+                "Genesis 1:3",
+                &elsewhere
+            ),
+            ["3 verses of the TST are quoted in the workspace, but the limit is 2"]
+        );
+        // verses already quoted elsewhere add nothing
+        assert!(messages(&lsp, &config("TST", limit), "Genesis 1:1-2", &elsewhere).is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+use cached::proc_macro::cached;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, TextEdit,
+};
+
+use crate::{
+    bible_lsp::BibleLSP, book_reference::BookReference, document::DocumentSnapshot,
+    verse_id::VerseId,
+};
+
+const END_MARKER: &str = "<!-- /bible:quote -->";
+
+/// Matches the opening marker, like `<!-- bible:quote Ephesians 1:1-4 | ESV | callout -->`
+#[cached(size = 1)]
+fn start_marker() -> Regex {
+    Regex::new(r"^<!-- bible:quote (.+?) \| (.+?) \| ([\w-]+) -->$").unwrap()
+}
+
+/**
+Wraps a quote in HTML comments recording what it is, so it can be re-rendered later
+
+```text
+<!-- bible:quote Ephesians 1:1-2 | ESV | insert -->
+[1:1] Paul, an apostle of Christ Jesus by the will of God...
+[1:2] Grace to you and peace from God our Father and the Lord Jesus Christ.
+<!-- /bible:quote -->
+```
+
+- Markdown renderers hide the comments, so they don't show up in the notes
+- `translation` is the abbreviation of the one the quote is in, which isn't always the default
+*/
+pub fn wrap(
+    lsp: &BibleLSP,
+    translation: &str,
+    book_ref: &BookReference,
+    formatter: &str,
+    quote: &str,
+) -> String {
+    format!(
+        "<!-- bible:quote {} | {translation} | {formatter} -->\n{quote}\n{END_MARKER}",
+        book_ref.full_ref_label(&lsp.api),
+    )
+}
+
+/// The label and translation on an opening marker line, like `Ephesians 1:1-4` and `ESV`
+pub fn marker_label(line: &str) -> Option<(String, String)> {
+    let cap = start_marker().captures(line.trim())?;
+    Some((cap[1].to_string(), cap[2].to_string()))
+}
+
+/// A quote found between markers
+#[derive(Clone, Debug)]
+pub struct MarkedQuote {
+    /// from the start of the opening marker to the end of the closing marker
+    pub range: Range,
+    pub label: String,
+    pub translation: String,
+    /// the name of the formatter it was rendered with
+    pub formatter: String,
+}
+
+pub fn find_quotes(snapshot: &DocumentSnapshot) -> Vec<MarkedQuote> {
+    let mut quotes = vec![];
+    let mut open: Option<(u32, String, String, String)> = None;
+    for line in 0..snapshot.line_index.line_count() as u32 {
+        let Some(text) = snapshot.line(line) else {
+            continue;
+        };
+        let text = text.trim();
+        if let Some(cap) = start_marker().captures(text) {
+            open = Some((
+                line,
+                cap[1].to_string(),
+                cap[2].to_string(),
+                cap[3].to_string(),
+            ));
+        } else if text == END_MARKER {
+            let Some((start_line, label, translation, formatter)) = open.take() else {
+                continue;
+            };
+            quotes.push(MarkedQuote {
+                range: Range {
+                    start: Position {
+                        line: start_line,
+                        character: 0,
+                    },
+                    end: Position {
+                        line,
+                        character: snapshot
+                            .line(line)
+                            .map(|text| text.encode_utf16().count() as u32)
+                            .unwrap_or(0),
+                    },
+                },
+                label,
+                translation,
+                formatter,
+            });
+        }
+    }
+    quotes
+}
+
+/// A marked quote that no longer matches what it renders to now
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StaleQuote {
+    pub label: String,
+    pub translation: String,
+    /// replaces the whole quote, markers included
+    pub edit: TextEdit,
+}
+
+/// `code` of [`StaleQuote::diagnostic`]
+const STALE_QUOTE: &str = "stale-quote";
+
+impl StaleQuote {
+    /// - Only on the opening marker, since the whole quote would be a lot of squiggles
+    /// - The refresh edit is in `data` so the quick fix doesn't have to render it again
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            range: Range {
+                start: self.edit.range.start,
+                end: Position {
+                    line: self.edit.range.start.line,
+                    character: u32::MAX,
+                },
+            },
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(STALE_QUOTE.to_string())),
+            source: Some(String::from("bible_lsp")),
+            message: format!(
+                "The quote of {} no longer matches the {} text",
+                self.label, self.translation
+            ),
+            data: serde_json::to_value(self).ok(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_diagnostic(diagnostic: &Diagnostic) -> Option<Self> {
+        if diagnostic.code != Some(NumberOrString::String(STALE_QUOTE.to_string())) {
+            return None;
+        }
+        serde_json::from_value(diagnostic.data.clone().unwrap_or(Value::Null)).ok()
+    }
+}
+
+/// - Every marked quote that re-renders differently with the current translation and formatting
+/// - `render` formats a reference with the named formatter, and quotes whose formatter no
+///   longer exists are left alone
+pub fn stale_quotes(
+    lsp: &BibleLSP,
+    snapshot: &DocumentSnapshot,
+    render: impl Fn(&BookReference, &str) -> Option<String>,
+) -> Vec<StaleQuote> {
+    find_quotes(snapshot)
+        .into_iter()
+        .filter_map(|quote| {
+            // markers written by hand or by other tools can use a verse id instead of a label
+            let book_ref = match quote.label.parse::<VerseId>() {
+                Ok(id) => id.to_book_reference(&lsp.api)?,
+                Err(_) => lsp.find_book_references(&quote.label)?.into_iter().next()?,
+            };
+            let rendered = render(&book_ref, &quote.formatter)?;
+            let new_text = wrap(
+                lsp,
+                &lsp.api.translation.abbreviation,
+                &book_ref,
+                &quote.formatter,
+                &rendered,
+            );
+            let start = snapshot
+                .line_index
+                .offset(&snapshot.text, quote.range.start)?;
+            let end = snapshot
+                .line_index
+                .offset(&snapshot.text, quote.range.end)?;
+            let current = snapshot.text[start..end].replace("\r\n", "\n");
+            (current != new_text).then_some(StaleQuote {
+                label: book_ref.full_ref_label(&lsp.api),
+                translation: quote.translation,
+                edit: TextEdit {
+                    range: quote.range,
+                    new_text,
+                },
+            })
+        })
+        .collect()
+}
+
+/**
+Quotes of the loaded translation whose wording changed, like after installing a new edition
+
+- Quotes of other translations are left out, since they would all differ
+- Same as [`stale_quotes`] otherwise, so a changed formatter also counts
+*/
+pub fn changed_quotes(
+    lsp: &BibleLSP,
+    snapshot: &DocumentSnapshot,
+    render: impl Fn(&BookReference, &str) -> Option<String>,
+) -> Vec<StaleQuote> {
+    stale_quotes(lsp, snapshot, render)
+        .into_iter()
+        .filter(|stale| {
+            stale
+                .translation
+                .eq_ignore_ascii_case(&lsp.api.translation.abbreviation)
+        })
+        .collect()
+}
+
+/// - Re-renders every marked quote with the current translation and formatting
+/// - Quotes that are already current get no edit
+pub fn refresh_edits(
+    lsp: &BibleLSP,
+    snapshot: &DocumentSnapshot,
+    render: impl Fn(&BookReference, &str) -> Option<String>,
+) -> Vec<TextEdit> {
+    stale_quotes(lsp, snapshot, render)
+        .into_iter()
+        .map(|stale| stale.edit)
+        .collect()
+}
@@ -1,6 +1,13 @@
 use cached::proc_macro::cached;
 use regex::Regex;
 
+/// - A translation abbreviation right after a reference, like ` KJV` in `John 3:16 KJV`
+/// - Only capitals and numbers (`NASB95`), so words after a reference aren't mistaken for one
+#[cached(size = 1)]
+pub fn translation_suffix() -> Regex {
+    Regex::new(r"^ +(\p{Lu}[\p{Lu}\d]*)\b").unwrap()
+}
+
 /// - This matches reference segments if they are at the start of the String
 /// - The purpose is so that only what is right after a book name is matched
 /// - This is designed to be used in segments that start with a book and go to the next
@@ -30,6 +37,19 @@ pub fn post_book_valid_reference_segment_characters() -> Regex {
     Regex::new(r"^ *\d+:\d+( *[,:;\-–] *\d+)*").unwrap()
 }
 
+/// - A Psalm chapter with the other numbering in parentheses, like `51(50)` in `Psalm 51(50):3`
+/// - See [`crate::versification::strip_dual_chapter`]
+#[cached(size = 1)]
+pub fn dual_psalm_chapter() -> Regex {
+    Regex::new(r"^( *)(\d+) ?\((\d+)\)").unwrap()
+}
+
+/// The chapter numbers in a segment label like `1:1-4,5-7; 2:2-3:4,6`
+#[cached(size = 1)]
+pub fn label_chapter() -> Regex {
+    Regex::new(r"(\d+):").unwrap()
+}
+
 #[cached(size = 1)]
 pub fn segment_characters() -> Regex {
     Regex::new(r"\.?[ \d,:;\-–]+").unwrap()
@@ -111,3 +131,10 @@ pub fn at_least_one_segment() -> Regex {
 pub fn non_segment_state() -> Regex {
     Regex::new(r"^ *(\d+)?(:)?(\d+)?$").unwrap()
 }
+
+/// - The book name being typed right before the cursor, like `eph` or `1 jo`
+/// - Used so book completions only replace that and not the rest of the line
+#[cached(size = 1)]
+pub fn partial_book_name() -> Regex {
+    Regex::new(r"(?:\d ?)?\p{L}[\p{L}.]*$").unwrap()
+}
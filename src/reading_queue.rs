@@ -0,0 +1,154 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Url;
+
+use crate::paths;
+
+/// Where the queue is kept unless `readingQueueFile` is set
+pub fn default_path() -> PathBuf {
+    paths::data_dir().join("reading-queue.json")
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedPassage {
+    /// written out in full, like `John 3:16-18`
+    pub label: String,
+    /// the document it was queued from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Url>,
+}
+
+/**
+Passages put aside to study later, oldest first
+
+- Filled from the "Add to reading queue" code action, and read with `bible.readingQueue` and
+  `bible.popReadingQueue`
+- One per user rather than per workspace, since it is about what to read next
+- The file is read and written for every change, so editors (and `bible_lsp --daemon` clients)
+  sharing it see each other's changes
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadingQueue {
+    pub passages: Vec<QueuedPassage>,
+}
+
+impl ReadingQueue {
+    /// A missing file is an empty queue
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Adds a passage to the end, unless it is already queued
+    pub fn push(&mut self, passage: QueuedPassage) -> bool {
+        if self
+            .passages
+            .iter()
+            .any(|queued| queued.label == passage.label)
+        {
+            return false;
+        }
+        self.passages.push(passage);
+        true
+    }
+
+    /// Takes the passage queued first
+    pub fn pop(&mut self) -> Option<QueuedPassage> {
+        (!self.passages.is_empty()).then(|| self.passages.remove(0))
+    }
+
+    /**
+    The queue as markdown, so the passages in it can be hovered and opened like anywhere else
+
+    ```text
+    # Reading Queue
+
+    1. John 3:16-18 (from [notes.md](file:///...))
+    2. Romans 8
+    ```
+    */
+    pub fn render(&self) -> String {
+        if self.passages.is_empty() {
+            return String::from(
+                "# Reading Queue\n\nNothing to read, add passages with the \"Add to reading queue\" code action\n",
+            );
+        }
+        let list: Vec<String> = self
+            .passages
+            .iter()
+            .enumerate()
+            .map(|(idx, passage)| {
+                let source = passage.source.as_ref().map(|source| {
+                    let name = source
+                        .path_segments()
+                        .and_then(|mut segments| segments.next_back())
+                        .unwrap_or_default();
+                    format!(" (from [{name}]({source}))")
+                });
+                format!(
+                    "{}. {}{}",
+                    idx + 1,
+                    passage.label,
+                    source.unwrap_or_default()
+                )
+            })
+            .collect();
+        format!("# Reading Queue\n\n{}\n", list.join("\n"))
+    }
+
+    /// - Writes [`ReadingQueue::render`] next to the generated chapters, see
+    ///   [`crate::virtual_document::VirtualDocument`], and returns its `file://` URI
+    /// - Rewritten only when it changed, like the chapters
+    pub fn materialize(&self) -> io::Result<Url> {
+        let path = paths::create_temp_dir()?.join("reading-queue.md");
+        let contents = self.render();
+        if fs::read_to_string(&path).ok().as_ref() != Some(&contents) {
+            fs::write(&path, contents)?;
+        }
+        paths::path_to_url(&path).ok_or_else(|| io::Error::other("Failed to convert path to URI"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passages_are_read_in_the_order_they_were_queued() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.json");
+        let passage = |label: &str| QueuedPassage {
+            label: label.to_string(),
+            source: None,
+        };
+        let mut queue = ReadingQueue::load(&path).unwrap();
+        assert!(queue.push(passage("John 3:16")));
+        assert!(queue.push(passage("Romans 8")));
+        assert!(!queue.push(passage("John 3:16")));
+        queue.save(&path).unwrap();
+
+        let mut queue = ReadingQueue::load(&path).unwrap();
+        assert_eq!(
+            queue.render(),
+            "# Reading Queue\n\n1. John 3:16\n2. Romans 8\n"
+        );
+        assert_eq!(queue.pop(), Some(passage("John 3:16")));
+        assert_eq!(queue.pop(), Some(passage("Romans 8")));
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bible_api::BibleAPI;
+
+/**
+How the words of Christ are marked in a verse, see [`crate::bible_json::JSONBook::words_of_christ`]
+
+```json
+"redLetter": "bold"
+"redLetter": { "color": "#c00" }
+```
+
+- `color` is an HTML `<span>`, since markdown has no colors, and not every editor shows it
+- `none` (the default) leaves the verse as it is
+*/
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RedLetter {
+    #[default]
+    None,
+    Bold,
+    Italic,
+    Color(String),
+}
+
+impl RedLetter {
+    /// `content` (the verse's text) with its words of Christ marked
+    pub fn mark(
+        &self,
+        api: &BibleAPI,
+        book_id: usize,
+        chapter: usize,
+        verse: usize,
+        content: String,
+    ) -> String {
+        self.mark_from(api, book_id, chapter, verse, content, 0)
+    }
+
+    /// [`RedLetter::mark`] for part of a verse, like a line of poetry, that starts `offset`
+    /// characters in
+    pub fn mark_from(
+        &self,
+        api: &BibleAPI,
+        book_id: usize,
+        chapter: usize,
+        verse: usize,
+        content: String,
+        offset: usize,
+    ) -> String {
+        let (open, close) = match self {
+            Self::None => return content,
+            Self::Bold => (String::from("**"), String::from("**")),
+            Self::Italic => (String::from("*"), String::from("*")),
+            Self::Color(color) => (
+                format!("<span style=\"color: {color}\">"),
+                String::from("</span>"),
+            ),
+        };
+        let spans = api.get_words_of_christ(book_id, chapter, verse);
+        if spans.is_empty() {
+            return content;
+        }
+        let chars: Vec<char> = content.chars().collect();
+        let mut marked = String::new();
+        let mut at = 0;
+        for (start, end) in spans.iter().copied() {
+            let (start, end) = (start.saturating_sub(offset), end.saturating_sub(offset));
+            // overlapping spans and ones past the end, which `problems` reports
+            let (start, end) = (start.max(at), end.min(chars.len()));
+            if start >= end {
+                continue;
+            }
+            marked.extend(&chars[at..start]);
+            let words: String = chars[start..end].iter().collect();
+            at = end;
+            // markdown emphasis can't start or end on a space
+            let trimmed = words.trim();
+            if trimmed.is_empty() {
+                marked.push_str(&words);
+                continue;
+            }
+            marked.push_str(&words[..words.len() - words.trim_start().len()]);
+            marked.push_str(&format!("{open}{trimmed}{close}"));
+            marked.push_str(&words[words.trim_end().len()..]);
+        }
+        marked.extend(&chars[at..]);
+        marked
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::workspace_index::WorkspaceIndex;
+
+#[derive(Clone, Copy, Debug)]
+pub enum GraphFormat {
+    /// Graphviz
+    Dot,
+    Json,
+}
+
+/**
+- Documents are connected to the passages they cite (weighted by how many times)
+- Passages are connected to each other when they are cited in the same document (weighted by
+  how many documents they share)
+- Passages are identified by their canonical label, so `eph 1:1` and `Ephesians 1:1` are the
+  same node
+*/
+#[derive(Debug, Default)]
+pub struct ReferenceGraph {
+    /// document -> passage -> times cited
+    pub citations: BTreeMap<String, BTreeMap<String, usize>>,
+    /// (passage, passage) -> documents in common
+    pub co_citations: BTreeMap<(String, String), usize>,
+}
+
+impl ReferenceGraph {
+    pub fn build(index: &WorkspaceIndex) -> Self {
+        let mut graph = Self::default();
+        for (uri, refs) in index.files().iter() {
+            if refs.is_empty() {
+                continue;
+            }
+            let cited = graph.citations.entry(index.display_name(uri)).or_default();
+            for indexed in refs {
+                *cited.entry(indexed.label.clone()).or_default() += 1;
+            }
+            let passages: Vec<&String> = cited.keys().collect();
+            for (idx, left) in passages.iter().enumerate() {
+                for right in &passages[idx + 1..] {
+                    *graph
+                        .co_citations
+                        .entry(((*left).clone(), (*right).clone()))
+                        .or_default() += 1;
+                }
+            }
+        }
+        graph
+    }
+
+    pub fn export(&self, format: GraphFormat) -> Value {
+        match format {
+            GraphFormat::Dot => Value::String(self.to_dot()),
+            GraphFormat::Json => self.to_json(),
+        }
+    }
+
+    fn passages(&self) -> Vec<&String> {
+        let mut passages: Vec<&String> = self
+            .citations
+            .values()
+            .flat_map(|cited| cited.keys())
+            .collect();
+        passages.sort();
+        passages.dedup();
+        passages
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("graph bible_references {\n");
+        for document in self.citations.keys() {
+            dot.push_str(&format!("  {} [shape=box];\n", quote(document)));
+        }
+        for passage in self.passages() {
+            dot.push_str(&format!("  {} [shape=ellipse];\n", quote(passage)));
+        }
+        for (document, cited) in self.citations.iter() {
+            for (passage, count) in cited {
+                dot.push_str(&format!(
+                    "  {} -- {} [weight={count}];\n",
+                    quote(document),
+                    quote(passage)
+                ));
+            }
+        }
+        for ((left, right), shared) in self.co_citations.iter() {
+            dot.push_str(&format!(
+                "  {} -- {} [style=dashed, weight={shared}];\n",
+                quote(left),
+                quote(right)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> Value {
+        let documents = self
+            .citations
+            .keys()
+            .map(|document| json!({ "id": document, "type": "document" }));
+        let passages = self
+            .passages()
+            .into_iter()
+            .map(|passage| json!({ "id": passage, "type": "passage" }));
+        let citations = self.citations.iter().flat_map(|(document, cited)| {
+            cited.iter().map(move |(passage, count)| {
+                json!({ "source": document, "target": passage, "type": "cites", "weight": count })
+            })
+        });
+        let co_citations = self.co_citations.iter().map(|((left, right), shared)| {
+            json!({ "source": left, "target": right, "type": "coCited", "weight": shared })
+        });
+        json!({
+            "nodes": documents.chain(passages).collect::<Vec<_>>(),
+            "edges": citations.chain(co_citations).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// DOT ids with spaces and colons have to be quoted
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
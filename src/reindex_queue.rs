@@ -0,0 +1,119 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use tower_lsp::lsp_types::Url;
+
+/// - More changes than this at once is basically a new workspace (like switching branches)
+/// - Past it the queue is dropped and the whole workspace is scanned instead
+pub const CAPACITY: usize = 5_000;
+
+/// Files re-indexed between progress reports and yields back to the runtime
+pub const BATCH_SIZE: usize = 25;
+
+#[derive(Debug, Default)]
+struct QueueState {
+    pending: VecDeque<Url>,
+    queued: BTreeSet<Url>,
+    overflowed: bool,
+}
+
+/**
+Files changed on disk that still need to be re-indexed
+
+- Open documents never go in here, they are re-indexed right away from what the editor has
+- Only one worker drains it at a time, see [`ReindexQueue::start`]
+*/
+#[derive(Debug, Default)]
+pub struct ReindexQueue {
+    state: Mutex<QueueState>,
+    running: AtomicBool,
+}
+
+impl ReindexQueue {
+    /// Duplicates are ignored, since the file is read when it is processed anyway
+    pub fn push(&self, uri: Url) {
+        let mut state = self.state.lock().unwrap();
+        if state.overflowed || state.queued.contains(&uri) {
+            return;
+        }
+        if state.pending.len() >= CAPACITY {
+            state.pending.clear();
+            state.queued.clear();
+            state.overflowed = true;
+            return;
+        }
+        state.queued.insert(uri.clone());
+        state.pending.push_back(uri);
+    }
+
+    pub fn next_batch(&self) -> Vec<Url> {
+        let mut state = self.state.lock().unwrap();
+        let count = state.pending.len().min(BATCH_SIZE);
+        let batch: Vec<Url> = state.pending.drain(..count).collect();
+        for uri in batch.iter() {
+            state.queued.remove(uri);
+        }
+        batch
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the queue overflowed since the last call, meaning a full scan is needed
+    pub fn take_overflow(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().overflowed)
+    }
+
+    /// Returns `false` if a worker is already draining the queue
+    pub fn start(&self) -> bool {
+        self.running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(idx: usize) -> Url {
+        Url::parse(&format!("file:///notes/{idx}.md")).unwrap()
+    }
+
+    #[test]
+    fn batches_are_deduplicated_and_bounded() {
+        let queue = ReindexQueue::default();
+        for idx in 0..BATCH_SIZE + 5 {
+            queue.push(uri(idx));
+            queue.push(uri(idx));
+        }
+        assert_eq!(queue.len(), BATCH_SIZE + 5);
+        assert_eq!(queue.next_batch().len(), BATCH_SIZE);
+        assert_eq!(
+            queue.next_batch(),
+            (BATCH_SIZE..BATCH_SIZE + 5).map(uri).collect::<Vec<_>>()
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn overflow_falls_back_to_full_scan() {
+        let queue = ReindexQueue::default();
+        for idx in 0..=CAPACITY {
+            queue.push(uri(idx));
+        }
+        assert!(queue.is_empty());
+        assert!(queue.take_overflow());
+        assert!(!queue.take_overflow());
+    }
+}
@@ -0,0 +1,619 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cached::proc_macro::cached;
+use regex::Regex;
+use serde_json::Value;
+use tower_lsp::lsp_types::Url;
+
+use crate::bible_api::{BibleAPI, BibleContents};
+use crate::bible_json::{JSONBible, JSONBook, JSONTranslation};
+use crate::chapter_cache::ChapterCache;
+use crate::config::{LicensedApi, RemoteConfig};
+use crate::status::RemoteStatus;
+use crate::trace;
+
+/// Translation abbreviation (upper case), book id, and chapter
+type ChapterKey = (String, usize, usize);
+
+/// The verses of one chapter, `None` until it has been fetched
+type ChapterSlot = Arc<Mutex<Option<Arc<Vec<String>>>>>;
+
+/// How long a `dailyLimit` lasts before it starts over
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// - The highest verse number taken from a response, well past Psalm 119's 176
+/// - So a response can't make a chapter take up more memory than any real one
+const MAX_VERSE: usize = 200;
+
+const ESV_ENDPOINT: &str = "https://api.esv.org";
+/// Only the verses, with their numbers to split them by
+const ESV_OPTIONS: &str = "include-passage-references=false&include-verse-numbers=true&include-first-verse-numbers=true&include-footnotes=false&include-headings=false&include-short-copyright=false";
+
+const API_BIBLE_ENDPOINT: &str = "https://api.scripture.api.bible";
+/// Only the verses, with their numbers to split them by
+const API_BIBLE_OPTIONS: &str = "content-type=text&include-notes=false&include-titles=false&include-chapter-numbers=false&include-verse-numbers=true&include-verse-spans=false";
+
+/// Where a remote translation's chapters come from
+#[derive(Clone, Copy, Debug)]
+enum Source<'a> {
+    Template(&'a str),
+    Licensed(&'a LicensedApi),
+}
+
+#[derive(Debug, Default)]
+struct Quota {
+    /// when the current day started, at the first request of it
+    since: Option<Instant>,
+    requests: usize,
+    /// from the last response's `X-RateLimit-Remaining` header
+    remaining: Option<usize>,
+}
+
+impl Quota {
+    fn start_over_if_a_day_passed(&mut self) {
+        if self.since.is_some_and(|since| since.elapsed() >= DAY) {
+            *self = Self::default();
+        }
+    }
+}
+
+/**
+Verse text fetched from a web API, a whole chapter per request, see [`RemoteConfig`]
+
+- Chapters are kept once fetched, so hovering every verse of a chapter is one request
+- Requests for a chapter that is already being fetched wait for that one instead of sending their own
+- Once the quota is used up, nothing is sent until the day starts over
+- Chapters from licensed APIs are kept on disk too, see [`ChapterCache`]
+*/
+#[derive(Debug, Default)]
+pub struct RemoteSource {
+    config: RemoteConfig,
+    chapters: Mutex<HashMap<ChapterKey, ChapterSlot>>,
+    quota: Mutex<Quota>,
+    disk: ChapterCache,
+}
+
+impl RemoteSource {
+    pub fn new(config: RemoteConfig) -> Self {
+        let disk = ChapterCache::new(
+            crate::paths::cache_dir().join("chapters"),
+            config.disk_cache_chapters,
+        );
+        Self {
+            config,
+            disk,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &RemoteConfig {
+        &self.config
+    }
+
+    /// Every remote translation, from URL templates and licensed APIs
+    pub fn abbreviations(&self) -> impl Iterator<Item = &String> {
+        self.config
+            .translations
+            .keys()
+            .chain(self.config.licensed.keys())
+    }
+
+    /// The abbreviation of a remote translation as it was configured, matched in any case
+    pub fn serves(&self, abbreviation: &str) -> Option<&str> {
+        self.abbreviations()
+            .find(|known| known.eq_ignore_ascii_case(abbreviation))
+            .map(|known| known.as_str())
+    }
+
+    fn source(&self, abbreviation: &str) -> Option<Source<'_>> {
+        let known = self.serves(abbreviation)?;
+        match self.config.translations.get(known) {
+            Some(template) => Some(Source::Template(template)),
+            None => self.config.licensed.get(known).map(Source::Licensed),
+        }
+    }
+
+    /**
+    The verses of a chapter, fetching it if it hasn't been yet
+
+    - Blocks while it is fetched (or while another caller fetches it), so call it off the async
+      runtime
+    - Failed requests aren't kept, so the next call tries again
+    */
+    pub fn chapter(
+        &self,
+        translation: &str,
+        book_id: usize,
+        chapter: usize,
+    ) -> io::Result<Arc<Vec<String>>> {
+        let Some(source) = self.source(translation) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{translation} isn't a remote translation"),
+            ));
+        };
+        let slot = self
+            .chapters
+            .lock()
+            .unwrap()
+            .entry((translation.to_uppercase(), book_id, chapter))
+            .or_default()
+            .clone();
+        let mut slot = slot.lock().unwrap();
+        if let Some(verses) = slot.as_ref() {
+            return Ok(verses.clone());
+        }
+        let licensed = matches!(source, Source::Licensed(_));
+        if let Some(verses) = licensed
+            .then(|| self.disk.get(translation, book_id, chapter))
+            .flatten()
+        {
+            let verses = Arc::new(verses);
+            *slot = Some(verses.clone());
+            return Ok(verses);
+        }
+        self.take_request(translation)?;
+        let (verses, remaining) = match source {
+            Source::Template(template) => fetch(
+                &template
+                    .replace("{translation}", translation)
+                    .replace("{book}", &book_id.to_string())
+                    .replace("{chapter}", &chapter.to_string()),
+            )?,
+            Source::Licensed(api) => fetch_licensed(api, book_id, chapter)?,
+        };
+        if remaining.is_some() {
+            self.quota.lock().unwrap().remaining = remaining;
+        }
+        if licensed {
+            if let Err(err) = self.disk.put(translation, book_id, chapter, &verses) {
+                trace::log(trace::Level::Warn, || {
+                    format!("Couldn't keep {translation} {book_id}:{chapter} on disk: {err}")
+                });
+            }
+        }
+        let verses = Arc::new(verses);
+        *slot = Some(verses.clone());
+        Ok(verses)
+    }
+
+    /// Counts a request against the quota, or says it is used up
+    fn take_request(&self, translation: &str) -> io::Result<()> {
+        let mut quota = self.quota.lock().unwrap();
+        quota.start_over_if_a_day_passed();
+        let over_limit = self
+            .config
+            .daily_limit
+            .is_some_and(|limit| quota.requests >= limit);
+        if over_limit || quota.remaining == Some(0) {
+            return Err(io::Error::other(format!(
+                "The quota for {translation} is used up, so only chapters fetched before are shown"
+            )));
+        }
+        quota.since.get_or_insert_with(Instant::now);
+        quota.requests += 1;
+        Ok(())
+    }
+
+    /**
+    A translation with the chapters fetched so far, and empty verses everywhere else
+
+    - Shaped like `base`, so its references and verse counts are the default translation's
+    */
+    pub fn api(&self, base: &BibleAPI, abbreviation: &str) -> BibleAPI {
+        let key = abbreviation.to_uppercase();
+        let chapters = self.chapters.lock().unwrap();
+        let contents: BibleContents = base
+            .reference_array
+            .iter()
+            .enumerate()
+            .map(|(book_idx, verse_counts)| {
+                verse_counts
+                    .iter()
+                    .enumerate()
+                    .map(|(chapter_idx, verse_count)| {
+                        // one being fetched right now isn't there yet
+                        let fetched = chapters
+                            .get(&(key.clone(), book_idx + 1, chapter_idx + 1))
+                            .and_then(|slot| slot.try_lock().ok()?.clone());
+                        let mut verses = fetched.map(|verses| verses.to_vec()).unwrap_or_default();
+                        verses.resize(*verse_count, String::new());
+                        verses
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut api = base.clone();
+        api.translation = JSONTranslation {
+            name: abbreviation.to_string(),
+            language: base.translation.language.clone(),
+            abbreviation: abbreviation.to_string(),
+            attribution: None,
+        };
+        api.bible_contents = Arc::new(contents);
+        // they are the default translation's
+        api.footnotes = Default::default();
+        api.section_headings = Default::default();
+        api.words_of_christ = Default::default();
+        api.layouts = Default::default();
+        api
+    }
+
+    /// Whether the license of a remote translation allows keeping a copy, see
+    /// [`RemoteConfig::allow_export`]
+    pub fn exportable(&self, abbreviation: &str) -> bool {
+        self.config
+            .allow_export
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(abbreviation))
+    }
+
+    /**
+    Every chapter fetched so far, as a Bible JSON file that loads without the API
+
+    - Books are named like `base`, and chapters that weren't fetched are left empty
+    - `previous` is an earlier export of the same translation, whose chapters are kept unless
+      they were fetched again, so exporting now and then adds up
+    - Also returns how many chapters have text
+    */
+    pub fn export(
+        &self,
+        base: &BibleAPI,
+        abbreviation: &str,
+        previous: Option<JSONBible>,
+    ) -> (JSONBible, usize) {
+        let key = abbreviation.to_uppercase();
+        let translation = previous
+            .as_ref()
+            .map(|previous| previous.translation.clone())
+            .unwrap_or_else(|| self.api(base, abbreviation).translation);
+        let mut books: BTreeMap<usize, JSONBook> = previous
+            .into_iter()
+            .flat_map(|previous| previous.bible)
+            .map(|book| (book.id, book))
+            .collect();
+        for ((translation, book_id, chapter), slot) in self.chapters.lock().unwrap().iter() {
+            let Some(verses) = slot.try_lock().ok().and_then(|verses| verses.clone()) else {
+                continue;
+            };
+            if *translation != key {
+                continue;
+            }
+            let Some(name) = base.get_book_name(*book_id) else {
+                continue;
+            };
+            let book = books.entry(*book_id).or_insert_with(|| JSONBook {
+                id: *book_id,
+                book: name.to_string(),
+                abbreviations: base
+                    .abbreviations_to_book_id
+                    .iter()
+                    .filter(|(abbreviation, id)| {
+                        **id == *book_id && **abbreviation != name.to_lowercase()
+                    })
+                    .map(|(abbreviation, _)| abbreviation.clone())
+                    .collect(),
+                order: None,
+                content: vec![],
+                footnotes: Default::default(),
+                headings: Default::default(),
+                words_of_christ: Default::default(),
+                layout: Default::default(),
+            });
+            if book.content.len() < *chapter {
+                book.content.resize(*chapter, vec![]);
+            }
+            book.content[chapter - 1] = verses.to_vec();
+        }
+        let chapters = books
+            .values()
+            .flat_map(|book| &book.content)
+            .filter(|verses| !verses.is_empty())
+            .count();
+        let bible = JSONBible {
+            translation,
+            bible: books.into_values().collect(),
+        };
+        (bible, chapters)
+    }
+
+    /// For `bible/status`
+    pub fn status(&self) -> RemoteStatus {
+        let mut quota = self.quota.lock().unwrap();
+        quota.start_over_if_a_day_passed();
+        let left_today = self
+            .config
+            .daily_limit
+            .map(|limit| limit.saturating_sub(quota.requests));
+        let remaining = match (left_today, quota.remaining) {
+            (Some(left), Some(remaining)) => Some(left.min(remaining)),
+            (left, remaining) => left.or(remaining),
+        };
+        let cached_chapters = self
+            .chapters
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|slot| slot.try_lock().is_ok_and(|verses| verses.is_some()))
+            .count();
+        RemoteStatus {
+            translations: self.abbreviations().cloned().collect(),
+            requests_today: quota.requests,
+            daily_limit: self.config.daily_limit,
+            remaining,
+            cached_chapters,
+        }
+    }
+}
+
+/**
+The body at `url`, with what is left of the API's quota if it says
+
+- `headers` are sent with the request, like `("api-key", ...)`
+- `file://` URLs are read from disk, for translations mirrored locally
+- Errors name the URL but never the headers, since they hold API keys
+*/
+pub fn get(url: &str, headers: &[(&str, String)]) -> io::Result<(Vec<u8>, Option<usize>)> {
+    use std::io::Read;
+
+    if let Some(path) = Url::parse(url)
+        .ok()
+        .and_then(|url| crate::paths::url_to_path(&url))
+    {
+        let body = std::fs::read(&path)
+            .map_err(|err| io::Error::new(err.kind(), format!("Couldn't read {url}: {err}")))?;
+        return Ok((body, None));
+    }
+    let request = headers
+        .iter()
+        .fold(ureq::get(url), |request, (name, value)| {
+            request.set(name, value)
+        });
+    // ureq's errors start with the URL
+    let response = request
+        .call()
+        .map_err(|err| io::Error::other(format!("Couldn't fetch {err}")))?;
+    let remaining = response
+        .header("x-ratelimit-remaining")
+        .and_then(|value| value.trim().parse().ok());
+    let mut body = vec![];
+    response.into_reader().read_to_end(&mut body)?;
+    Ok((body, remaining))
+}
+
+/// [`get`] for a JSON response
+fn get_json(url: &str, headers: &[(&str, String)]) -> io::Result<(Value, Option<usize>)> {
+    let (body, remaining) = get(url, headers)?;
+    let body = serde_json::from_slice(&body).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{url} isn't JSON, expected the chapter's verses"),
+        )
+    })?;
+    Ok((body, remaining))
+}
+
+/**
+The verses at `url`, from a [`RemoteConfig::translations`] template
+
+- The response is a JSON array of verses, either strings or objects with a `text` (and a `verse`
+  number, for APIs that leave some out)
+*/
+fn fetch(url: &str) -> io::Result<(Vec<String>, Option<usize>)> {
+    let (body, remaining) = get_json(url, &[])?;
+    let invalid = |message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{url} {message}, expected a JSON array of verses"),
+        )
+    };
+    let Value::Array(items) = body else {
+        return Err(invalid("isn't an array"));
+    };
+    let mut verses: Vec<String> = vec![];
+    for (idx, item) in items.iter().enumerate() {
+        let (verse, text) = match item {
+            Value::String(text) => (idx + 1, text.as_str()),
+            Value::Object(item) => (
+                item.get("verse")
+                    .and_then(|verse| verse.as_u64())
+                    .map(|verse| verse as usize)
+                    .unwrap_or(idx + 1),
+                item.get("text")
+                    .and_then(|text| text.as_str())
+                    .ok_or_else(|| invalid("has a verse without any text"))?,
+            ),
+            _ => return Err(invalid("has something other than verses in it")),
+        };
+        if verse == 0 {
+            continue;
+        }
+        if verse > MAX_VERSE {
+            return Err(invalid(&format!("has a verse numbered {verse}")));
+        }
+        if verses.len() < verse {
+            verses.resize(verse, String::new());
+        }
+        verses[verse - 1] = text.to_string();
+    }
+    Ok((verses, remaining))
+}
+
+/// The verses of a chapter from an API that needs a key, see [`RemoteConfig::licensed`]
+fn fetch_licensed(
+    api: &LicensedApi,
+    book_id: usize,
+    chapter: usize,
+) -> io::Result<(Vec<String>, Option<usize>)> {
+    let Some((_, usfm, _, name)) = crate::convert::BOOKS.iter().find(|(id, ..)| *id == book_id)
+    else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No book {book_id} to fetch"),
+        ));
+    };
+    let endpoint = |endpoint: &Option<String>, default: &str| {
+        endpoint
+            .as_deref()
+            .unwrap_or(default)
+            .trim_end_matches('/')
+            .to_string()
+    };
+    // the key goes in a header, so it isn't in any error message with the URL
+    let (url, header, text_at) = match api {
+        LicensedApi::Esv {
+            api_key,
+            endpoint: custom,
+        } => (
+            format!(
+                "{}/v3/passage/text/?q={}+{chapter}&{ESV_OPTIONS}",
+                endpoint(custom, ESV_ENDPOINT),
+                name.replace(' ', "+")
+            ),
+            ("Authorization", format!("Token {api_key}")),
+            "/passages/0",
+        ),
+        LicensedApi::ApiBible {
+            api_key,
+            bible_id,
+            endpoint: custom,
+        } => (
+            format!(
+                "{}/v1/bibles/{bible_id}/chapters/{usfm}.{chapter}?{API_BIBLE_OPTIONS}",
+                endpoint(custom, API_BIBLE_ENDPOINT)
+            ),
+            ("api-key", api_key.clone()),
+            "/data/content",
+        ),
+    };
+    let (body, remaining) = get_json(&url, &[header])?;
+    let text = body
+        .pointer(text_at)
+        .and_then(|text| text.as_str())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{url} doesn't have the passage text"),
+            )
+        })?;
+    Ok((numbered_verses(text), remaining))
+}
+
+#[cached(size = 1)]
+fn verse_numbers() -> Regex {
+    Regex::new(r"\[(\d+)\]").unwrap()
+}
+
+/// Text with each verse after its number, like `[1] In the beginning ... [2] The earth ...`, which
+/// is how both licensed APIs send it
+fn numbered_verses(text: &str) -> Vec<String> {
+    // a number past any real verse is left in the text
+    let numbers: Vec<regex::Captures> = verse_numbers()
+        .captures_iter(text)
+        .filter(|number| {
+            number[1]
+                .parse::<usize>()
+                .is_ok_and(|verse| verse <= MAX_VERSE)
+        })
+        .collect();
+    let mut verses: Vec<String> = vec![];
+    for (idx, number) in numbers.iter().enumerate() {
+        let Some(verse) = number[1].parse::<usize>().ok().filter(|verse| *verse > 0) else {
+            continue;
+        };
+        let start = number.get(0).unwrap().end();
+        let end = numbers
+            .get(idx + 1)
+            .map(|next| next.get(0).unwrap().start())
+            .unwrap_or(text.len());
+        if verses.len() < verse {
+            verses.resize(verse, String::new());
+        }
+        verses[verse - 1] = text[start..end]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    verses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verses_are_split_at_their_numbers() {
+        let esv = "\n  [1] In the beginning, God created the heavens and the earth.\n\n  [2] The earth was\n    without form and void, [3] And God said,";
+        assert_eq!(
+            numbered_verses(esv),
+            [
+                "In the beginning, God created the heavens and the earth.",
+                "The earth was without form and void,",
+                "And God said,"
+            ]
+        );
+        // a verse some translations leave out
+        assert_eq!(
+            numbered_verses("[3] Three [5] Five"),
+            ["", "", "Three", "", "Five"]
+        );
+        assert_eq!(
+            numbered_verses("[1] One [4000000000] Two"),
+            ["One [4000000000] Two"]
+        );
+    }
+
+    #[test]
+    fn verse_numbers_past_any_chapter_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chapter.json");
+        let url = format!("file://{}", path.display());
+        std::fs::write(
+            &path,
+            r#"[{ "verse": 1, "text": "One" }, { "verse": 3, "text": "Three" }]"#,
+        )
+        .unwrap();
+        assert_eq!(fetch(&url).unwrap().0, ["One", "", "Three"]);
+        std::fs::write(&path, r#"[{ "verse": 4000000000, "text": "Huge" }]"#).unwrap();
+        let err = fetch(&url).unwrap_err();
+        assert!(err.to_string().contains("4000000000"), "{err}");
+    }
+
+    #[test]
+    fn responses_carry_the_quota_left() {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/43/3.json", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            for line in BufReader::new(&stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                request.push(line);
+            }
+            let body = r#"["One", "Two"]"#;
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nX-RateLimit-Remaining: 41\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            request
+        });
+        let (body, remaining) = get_json(&url, &[("api-key", String::from("secret"))]).unwrap();
+        assert_eq!(body, serde_json::json!(["One", "Two"]));
+        assert_eq!(remaining, Some(41));
+        let request = server.join().unwrap();
+        assert!(
+            request.contains(&String::from("api-key: secret")),
+            "{request:?}"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The config file `bible_lsp init` writes at the root of a vault
+pub const CONFIG_FILE: &str = ".bible-lsp.toml";
+
+/// Every setting is commented out, so the file documents the defaults without pinning them
+const CONFIG: &str = r#"# bible_lsp settings for this vault
+# Keys are the same as the `initializationOptions` your editor sends, which win over this file
+
+# translation = "{translation}"
+
+# Wrap inserted quotes in comments so `bible.refreshQuotes` can update them later
+# quoteMarkers = false
+
+# The order of the formatter code actions, the first one is preferred
+# formatterOrder = ["callout", "insert", "replace"]
+
+# How Psalms are numbered in citations: "hebrew" or "greek"
+# psalmNumbering = "hebrew"
+
+# Workspace files to leave out of the index, on top of .gitignore
+# ignoreGlobs = ["private/", "*.draft.md"]
+
+# [limits]
+# maxDocumentSize = 2000000
+# maxReferences = 5000
+"#;
+
+/// Example formatter templates, see [`crate::templates::parse_template`]
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "sermon.tmpl",
+        "verse = <sup>{verse}</sup> {content}\njoinVerses = \\n>\ncodeActions = insert, replace\n---\n> **{reference}** ({translation_abbrev})\n> {segments}\n",
+    ),
+    (
+        "study.tmpl",
+        "verse = **{chapter}:{verse}** {content}\njoinVerses = \\n\nchapterHeading = #### Chapter {chapter}\n---\n### {reference}\n\n{segments}\n",
+    ),
+];
+
+/// What `bible_lsp init` did with each file
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scaffolded {
+    Created(PathBuf),
+    /// already there, so left alone
+    Skipped(PathBuf),
+}
+
+fn write_new(path: PathBuf, contents: &str) -> io::Result<Scaffolded> {
+    if path.exists() {
+        return Ok(Scaffolded::Skipped(path));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, contents)?;
+    Ok(Scaffolded::Created(path))
+}
+
+/**
+Sets up a vault for writing with references
+
+- `.bible-lsp.toml` at the root of `vault`, with the translation filled in when there is one
+- Example templates in `templates_dir` (usually [`crate::templates::templates_dir`], which is
+  where the server loads them from)
+- Existing files are never overwritten, so running it twice is harmless
+*/
+pub fn init(
+    vault: &Path,
+    templates_dir: &Path,
+    translation: Option<&Path>,
+) -> io::Result<Vec<Scaffolded>> {
+    let config = match translation {
+        Some(path) => CONFIG.replace(
+            "# translation = \"{translation}\"",
+            &format!("translation = {:?}", path.display().to_string()),
+        ),
+        None => CONFIG.replace("{translation}", "/path/to/translation.json"),
+    };
+    let mut scaffolded = vec![write_new(vault.join(CONFIG_FILE), &config)?];
+    for (name, template) in TEMPLATES {
+        scaffolded.push(write_new(templates_dir.join(name), template)?);
+    }
+    Ok(scaffolded)
+}
+
+/**
+Downloads a translation file into `dir`, named after the last part of the URL
+
+- Fetched with [`crate::remote_source::get`], so `file://` URLs work too
+- An existing file with the same name is reused rather than downloaded again
+*/
+#[cfg(feature = "remote")]
+pub fn download_translation(url: &str, dir: &Path) -> io::Result<PathBuf> {
+    let name = url
+        .rsplit('/')
+        .find(|part| !part.is_empty())
+        .map(crate::paths::sanitize_file_name)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("No file name in {url}"),
+            )
+        })?;
+    let path = dir.join(name);
+    if path.exists() {
+        return Ok(path);
+    }
+    let (body, _) = crate::remote_source::get(url, &[])?;
+    fs::create_dir_all(dir)?;
+    fs::write(&path, body)?;
+    Ok(path)
+}
+
+/// Where `bible.downloadTranslation` downloads from unless `downloadSource` is set
+pub const DEFAULT_DOWNLOAD_SOURCE: &str =
+    "https://bolls.life/static/translations/{translation}.zip";
+
+/**
+Downloads a translation by its abbreviation and converts it to a Bible JSON file in `dir`, like
+`dir/kjv.json`
+
+- `source` is a URL with `{translation}` in it, and what it returns can be a Bible JSON file, a
+  list of verses (see [`crate::convert::Verses`]), or a `.zip` of either
+- A translation downloaded before is reused, so this only goes online once per translation
+- The translation is named after its abbreviation unless the file says otherwise
+*/
+#[cfg(feature = "remote")]
+pub fn install_translation(source: &str, abbreviation: &str, dir: &Path) -> io::Result<PathBuf> {
+    use crate::convert::{self, Reader};
+
+    let name = crate::paths::sanitize_file_name(&abbreviation.to_lowercase());
+    if name.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{abbreviation} isn't a translation abbreviation"),
+        ));
+    }
+    let path = dir.join(format!("{name}.json"));
+    if path.exists() {
+        return Ok(path);
+    }
+    let url = source.replace("{translation}", abbreviation);
+    let downloads = tempfile::tempdir()?;
+    let downloaded = download_translation(&url, downloads.path())?;
+    let is_zip = downloaded
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+    let text = match is_zip {
+        true => unzip(&downloaded)?,
+        false => fs::read_to_string(&downloaded)?,
+    };
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let bible = match convert::Json.read(&text) {
+        Ok(bible) => bible,
+        Err(_) => convert::Verses
+            .read(&text)
+            .map_err(|err| invalid(format!("{url} isn't a translation: {err}")))?,
+    };
+    let bible = convert::merge(vec![bible], convert::fallback_translation(&path));
+    if bible.bible.is_empty() {
+        return Err(invalid(format!("{url} doesn't have any verses")));
+    }
+    if let Some(problem) = crate::bible_json::problems(&bible)
+        .into_iter()
+        .find(|problem| problem.fatal)
+    {
+        return Err(invalid(format!(
+            "{url}: {}: {}",
+            problem.at, problem.message
+        )));
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(&path, serde_json::to_string(&bible)?)?;
+    Ok(path)
+}
+
+/// The files in a zip archive, one after the other (translations come as one file each)
+#[cfg(feature = "remote")]
+fn unzip(path: &Path) -> io::Result<String> {
+    use std::io::Read;
+
+    let invalid = |err: zip::result::ZipError| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Couldn't unzip {}: {err}", path.display()),
+        )
+    };
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?).map_err(invalid)?;
+    let mut text = String::new();
+    for idx in 0..archive.len() {
+        archive
+            .by_index(idx)
+            .map_err(invalid)?
+            .read_to_string(&mut text)?;
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn translations_are_converted_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(
+            source.join("TST.json"),
+            r#"[{"pk": 1, "translation": "TST", "book": 43, "chapter": 1, "verse": 1, "text": "In the beginning<br/>was the <S>3056</S>Word"}]"#,
+        )
+        .unwrap();
+        let template = format!("file://{}/{{translation}}.json", source.display());
+        let translations = dir.path().join("translations");
+
+        let path = install_translation(&template, "TST", &translations).unwrap();
+        assert_eq!(path, translations.join("tst.json"));
+        let api = crate::bible_api::BibleAPI::load(&path).unwrap();
+        assert_eq!(api.translation.abbreviation, "TST");
+        assert_eq!(
+            api.bible_contents[42][0][0],
+            "In the beginning was the Word"
+        );
+
+        // the converted file is used from then on
+        fs::remove_file(source.join("TST.json")).unwrap();
+        assert_eq!(
+            install_translation(&template, "tst", &translations).unwrap(),
+            path
+        );
+        assert!(install_translation(&template, "ABC", &translations).is_err());
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn zipped_translations_are_unzipped() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut zipped = zip::ZipWriter::new(fs::File::create(dir.path().join("ZIP.zip")).unwrap());
+        zipped
+            .start_file("ZIP.json", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zipped
+            .write_all(br#"[{"pk": 1, "translation": "ZIP", "book": 1, "chapter": 1, "verse": 1, "text": "In the beginning"}]"#)
+            .unwrap();
+        zipped.finish().unwrap();
+        let template = format!("file://{}/{{translation}}.zip", dir.path().display());
+
+        let path = install_translation(&template, "ZIP", &dir.path().join("translations")).unwrap();
+        let api = crate::bible_api::BibleAPI::load(&path).unwrap();
+        assert_eq!(api.bible_contents[0][0][0], "In the beginning");
+    }
+
+    #[test]
+    fn init_skips_existing_files() {
+        let vault = tempfile::tempdir().unwrap();
+        let templates = vault.path().join("templates");
+        fs::write(templates.with_file_name(CONFIG_FILE), "# mine\n").unwrap();
+        let scaffolded = init(vault.path(), &templates, None).unwrap();
+        assert_eq!(
+            scaffolded[0],
+            Scaffolded::Skipped(vault.path().join(CONFIG_FILE))
+        );
+        assert!(matches!(scaffolded[1], Scaffolded::Created(_)));
+        for (name, _) in TEMPLATES {
+            let source = fs::read_to_string(templates.join(name)).unwrap();
+            let formatter = crate::templates::parse_template(&source).unwrap();
+            assert!(formatter.lint().is_empty(), "{name}");
+        }
+
+        let other = tempfile::tempdir().unwrap();
+        init(other.path(), &templates, Some(Path::new("/data/esv.json"))).unwrap();
+        let config = fs::read_to_string(other.path().join(CONFIG_FILE)).unwrap();
+        assert!(config.contains("\ntranslation = \"/data/esv.json\"\n"));
+        let settings = crate::config_file::load(&other.path().join(CONFIG_FILE)).unwrap();
+        assert_eq!(settings.unwrap()["translation"], "/data/esv.json");
+    }
+}
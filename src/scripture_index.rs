@@ -0,0 +1,116 @@
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{bible_api::BibleAPI, book_reference::BookReference, document::LineIndex};
+
+/// The index is wrapped in these so it can be found and updated later
+pub const START_MARKER: &str = "<!-- bible_lsp:scripture-index -->";
+pub const END_MARKER: &str = "<!-- /bible_lsp:scripture-index -->";
+
+/// Where the index is in `text`, from the start of its opening marker to the end of its closing one
+pub fn find(text: &str) -> Option<std::ops::Range<usize>> {
+    let start = text.find(START_MARKER)?;
+    let end = text[start..]
+        .find(END_MARKER)
+        .map(|end| start + end + END_MARKER.len())
+        .unwrap_or(text.len());
+    Some(start..end)
+}
+
+/// Whether `range` is inside the index (by line), since what is listed there isn't a citation
+pub fn contains(text: &str, line_index: &LineIndex, range: Range) -> bool {
+    find(text).is_some_and(|span| {
+        let start = line_index.position(text, span.start);
+        let end = line_index.position(text, span.end);
+        start <= range.start && range.end <= end
+    })
+}
+
+/**
+Every passage cited, one line per book in canon order
+
+```text
+## Scripture Index
+
+- Genesis 1:1; 2:3-5
+- Romans 8:28
+```
+
+- Passages cited more than once are listed once, and sorted by where they start
+*/
+pub fn index_block<'a>(
+    api: &BibleAPI,
+    references: impl IntoIterator<Item = &'a BookReference>,
+) -> String {
+    let mut references: Vec<&BookReference> = references.into_iter().collect();
+    references.sort_by_key(|book_ref| {
+        let first = book_ref.segments.first();
+        (
+            api.canon_position(book_ref.book_id),
+            first.map(|segment| segment.get_starting_chapter()),
+            first.map(|segment| segment.get_starting_verse()),
+        )
+    });
+    let mut lines: Vec<(usize, Vec<String>)> = vec![];
+    for book_ref in references {
+        let label = book_ref.segments.label();
+        match lines.last_mut() {
+            Some((book_id, labels)) if *book_id == book_ref.book_id => {
+                if !labels.contains(&label) {
+                    labels.push(label);
+                }
+            }
+            _ => lines.push((book_ref.book_id, vec![label])),
+        }
+    }
+    let lines: Vec<String> = lines
+        .into_iter()
+        .filter_map(|(book_id, labels)| {
+            Some(format!(
+                "- {} {}",
+                api.get_book_name(book_id)?,
+                labels.join("; ")
+            ))
+        })
+        .collect();
+    format!(
+        "{START_MARKER}\n## Scripture Index\n\n{}\n{END_MARKER}",
+        lines.join("\n")
+    )
+}
+
+/**
+- If the document already has an index, it is replaced
+- Otherwise it is appended to the end of the document, unless `insert` is false (like when saving,
+  which only keeps an existing one current)
+
+Returns `None` when there is nothing to change
+*/
+pub fn index_edit(text: &str, block: String, insert: bool) -> Option<TextEdit> {
+    let line_index = LineIndex::new(text);
+    if let Some(span) = find(text) {
+        if text[span.clone()] == block {
+            return None;
+        }
+        return Some(TextEdit {
+            range: Range {
+                start: line_index.position(text, span.start),
+                end: line_index.position(text, span.end),
+            },
+            new_text: block,
+        });
+    }
+    if !insert {
+        return None;
+    }
+    let end: Position = line_index.position(text, text.len());
+    let separator = match text {
+        "" => "",
+        text if text.ends_with("\n\n") => "",
+        text if text.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    Some(TextEdit {
+        range: Range { start: end, end },
+        new_text: format!("{separator}{block}\n"),
+    })
+}
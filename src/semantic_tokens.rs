@@ -0,0 +1,75 @@
+use tower_lsp::lsp_types::{
+    Range, SemanticToken, SemanticTokenType, SemanticTokensLegend, SemanticTokensOptions,
+};
+
+use crate::{book_reference::BookReference, document::DocumentSnapshot};
+
+/// The book name, like `Eph` in `Eph 1:1-4`
+const BOOK: u32 = 0;
+/// A chapter and verse segment, like `1:1-4`
+const SEGMENT: u32 = 1;
+
+pub fn options() -> SemanticTokensOptions {
+    SemanticTokensOptions {
+        legend: SemanticTokensLegend {
+            // indexed by `BOOK` and `SEGMENT`
+            token_types: vec![SemanticTokenType::NAMESPACE, SemanticTokenType::NUMBER],
+            token_modifiers: vec![],
+        },
+        range: Some(true),
+        full: None,
+        work_done_progress_options: Default::default(),
+    }
+}
+
+/**
+Semantic tokens for the references in `refs`, which are in document order
+
+- The book name and each segment are tokens of their own
+- A reference without known segment ranges is one book token
+- References written across lines are left out, since not every editor reads multiline tokens
+*/
+pub fn tokens(snapshot: &DocumentSnapshot, refs: &[BookReference]) -> Vec<SemanticToken> {
+    let mut spans: Vec<(Range, u32)> = vec![];
+    for book_ref in refs {
+        if book_ref.range.start.line != book_ref.range.end.line {
+            continue;
+        }
+        let Some(first) = book_ref.segment_ranges.first() else {
+            spans.push((book_ref.range, BOOK));
+            continue;
+        };
+        let book = Range::new(book_ref.range.start, first.start);
+        let written = snapshot.text_at(book).unwrap_or_default();
+        let mut book_end = book.end;
+        book_end.character -= written[written.trim_end().len()..].encode_utf16().count() as u32;
+        spans.push((Range::new(book.start, book_end), BOOK));
+        spans.extend(
+            book_ref
+                .segment_ranges
+                .iter()
+                .map(|range| (*range, SEGMENT)),
+        );
+    }
+
+    // every token is relative to the one before it
+    let (mut line, mut character) = (0, 0);
+    spans
+        .into_iter()
+        .filter(|(range, _)| range.end.character > range.start.character)
+        .map(|(range, token_type)| {
+            if range.start.line != line {
+                character = 0;
+            }
+            let token = SemanticToken {
+                delta_line: range.start.line - line,
+                delta_start: range.start.character - character,
+                length: range.end.character - range.start.character,
+                token_type,
+                token_modifiers_bitset: 0,
+            };
+            (line, character) = (range.start.line, range.start.character);
+            token
+        })
+        .collect()
+}
@@ -0,0 +1,3377 @@
+use serde_json::Value;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, ClientSocket, LanguageServer, LspService};
+
+use crate::bible_api::BibleAPI;
+use crate::bible_lsp::{append_log, BibleLSP};
+use crate::book_reference::BookReference;
+use crate::book_reference_segment::LabelSeparators;
+use crate::config::{self, Config};
+#[cfg(feature = "commentary")]
+use crate::cross_references;
+use crate::deadline::Deadline;
+use crate::document::{DocumentSnapshot, DocumentStore};
+use crate::document_filter::DocumentFilter;
+use crate::memory_budget::{MemoryBudget, MemoryUsage};
+#[cfg(feature = "remote")]
+use crate::remote_source;
+#[cfg(any(not(feature = "search"), not(feature = "remote")))]
+use crate::status;
+use crate::status::Status;
+use crate::virtual_document::VirtualDocument;
+use crate::{
+    alias_packs, attribution, batch_edits, bible_formatter, book_overview, book_tags, commands,
+    completion_ranking, config_file, daemon, error, folding, front_matter, headings, hover_cache,
+    ignored_phrases, paths, quote_limits, quote_markers, reading_queue, scripture_index,
+    semantic_tokens, spelling, templates, trace, transliterate, verse_id, verse_navigation,
+    versification,
+};
+#[cfg(feature = "search")]
+use crate::{backlinks, coverage, progress, reference_graph, reindex_queue, workspace_index};
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range};
+
+/// Only advertise what is both implemented and enabled
+fn server_capabilities(config: &Config) -> ServerCapabilities {
+    let features = &config.features;
+    ServerCapabilities {
+        text_document_sync: Some(match config.autocorrect_on_save || config.refresh_on_save {
+            true => TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                will_save_wait_until: Some(true),
+                ..Default::default()
+            }),
+            false => TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL),
+        }),
+        hover_provider: features
+            .hover
+            .then_some(HoverProviderCapability::Simple(true)),
+        definition_provider: features.definition.then_some(OneOf::Left(true)),
+        // links only show up in the chapter documents that goto-definition opens
+        document_link_provider: features.definition.then(|| DocumentLinkOptions {
+            resolve_provider: Some(false),
+            work_done_progress_options: Default::default(),
+        }),
+        completion_provider: features.completion.then(|| CompletionOptions {
+            trigger_characters: Some(config.completion.trigger_characters.clone()),
+            completion_item: Some(CompletionOptionsCompletionItem {
+                label_details_support: Some(true),
+            }),
+            ..CompletionOptions::default()
+        }),
+        diagnostic_provider: features.diagnostics.then(|| {
+            DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                identifier: Some(String::from("bible_lsp")),
+                workspace_diagnostics: true,
+                ..Default::default()
+            })
+        }),
+        code_action_provider: features
+            .code_actions
+            .then_some(CodeActionProviderCapability::Simple(true)),
+        inlay_hint_provider: (features.inlay_hints || features.citation_counts).then(|| {
+            OneOf::Right(InlayHintServerCapabilities::Options(InlayHintOptions {
+                // the documents behind a citation count are only listed when asked for
+                resolve_provider: Some(features.citation_counts),
+                work_done_progress_options: Default::default(),
+            }))
+        }),
+        document_symbol_provider: features.document_symbols.then_some(OneOf::Left(true)),
+        semantic_tokens_provider: features.semantic_tokens.then(|| {
+            SemanticTokensServerCapabilities::SemanticTokensOptions(semantic_tokens::options())
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: commands::ALL
+                .iter()
+                .map(|command| command.to_string())
+                .collect(),
+            work_done_progress_options: Default::default(),
+        }),
+        folding_range_provider: features
+            .definition
+            .then_some(FoldingRangeProviderCapability::Simple(true)),
+        ..Default::default()
+    }
+}
+
+#[derive(Debug)]
+pub struct Backend {
+    client: Client,
+    /// - Swapped out whole when settings that change detection (like alias packs) change
+    /// - Handlers grab their own `Arc` with [`Backend::lsp`] so a swap never happens mid-request
+    lsp: RwLock<Arc<BibleLSP>>,
+    /// - The translation as loaded, before [`Backend::apply_api_settings`] adds to it
+    /// - Kept so settings can be applied again from scratch, like when an alias pack is turned off
+    translation: RwLock<Arc<BibleLSP>>,
+    /// - The abbreviation of the translation loaded before the last switch
+    /// - What [`commands::UPDATE_QUOTED_TRANSLATION`] updates from by default
+    previous_translation: RwLock<Option<String>>,
+    /// see [`Config::compare_translation`]
+    compare: RwLock<Option<Arc<BibleLSP>>>,
+    /// see [`Config::translations`]
+    translations: RwLock<BTreeMap<String, Arc<BibleAPI>>>,
+    /// see [`Config::cross_references_file`]
+    #[cfg(feature = "commentary")]
+    cross_references: RwLock<Option<Arc<cross_references::CrossReferences>>>,
+    /// see [`Config::remote`]
+    #[cfg(feature = "remote")]
+    remote: RwLock<Arc<remote_source::RemoteSource>>,
+    documents: DocumentStore,
+    config: RwLock<Config>,
+    /// workspace folders, to find their config files again when settings change
+    roots: RwLock<Vec<PathBuf>>,
+    client_capabilities: RwLock<ClientCapabilities>,
+    recent_books: completion_ranking::RecentBooks,
+    templates: Arc<templates::TemplateStore>,
+    hover_cache: Arc<hover_cache::HoverCache>,
+    memory_budget: RwLock<MemoryBudget>,
+    /// - Swapped for the daemon's shared index of the same workspace and settings whenever they
+    ///   change, see [`Backend::share_index`]
+    #[cfg(feature = "search")]
+    index: RwLock<Arc<workspace_index::WorkspaceIndex>>,
+    /// - What every client of `bible_lsp --daemon` shares, `None` over stdio
+    shared: Option<Arc<daemon::Shared>>,
+    #[cfg(feature = "search")]
+    reindex_queue: Arc<reindex_queue::ReindexQueue>,
+}
+
+impl Backend {
+    fn lsp(&self) -> Arc<BibleLSP> {
+        self.lsp.read().unwrap().clone()
+    }
+
+    #[cfg(feature = "search")]
+    fn index(&self) -> Arc<workspace_index::WorkspaceIndex> {
+        self.index.read().unwrap().clone()
+    }
+
+    /**
+    A fingerprint of what the workspace index depends on besides its roots
+
+    - The translation and every setting that changes which references are found, or how many are
+      kept
+    - The daemon only shares an index between clients where it's the same
+    */
+    #[cfg(feature = "search")]
+    fn index_settings(&self, config: &Config) -> u64 {
+        let lsp = self.lsp();
+        let settings = format!(
+            "{:?}",
+            (
+                &lsp.api.translation,
+                lsp.translations.keys().collect::<Vec<_>>(),
+                &lsp.ignored_phrases,
+                &config.alias_packs,
+                &config.aliases,
+                config.psalm_numbering,
+                &config.contexts,
+                &config.index.extensions,
+                &config.ignore_globs,
+                &config.front_matter_fields,
+                config.memory_budget_mb,
+            )
+        );
+        crate::bible_cache::fnv1a(settings.as_bytes())
+    }
+
+    /**
+    Swaps in the daemon's index for these roots and settings
+
+    - So a client's settings never change an index that clients with others are using
+    - Returns whether it's another index than before, which then doesn't have to be cleared
+    - Always `false` over stdio, where the client has the only one
+    */
+    #[cfg(feature = "search")]
+    fn share_index(&self, config: &Config) -> bool {
+        let Some(shared) = &self.shared else {
+            return false;
+        };
+        let current = self.index();
+        let roots = current.roots();
+        let index = shared.index(&roots, self.index_settings(config));
+        if Arc::ptr_eq(&index, &current) {
+            return false;
+        }
+        index.set_roots(roots);
+        *self.index.write().unwrap() = index;
+        true
+    }
+
+    /**
+    Loads the translation from the settings, or the default one if the server started without any
+
+    - Problems are shown to the user, since nothing is detected without a translation
+    - A translation the server was built with (like in the tests) is kept unless the settings
+      name another one
+    */
+    async fn load_translation(&self, config: &Config, previous: Option<&Config>) -> bool {
+        let loaded = self.translation.read().unwrap().clone();
+        let path = match &config.translation {
+            Some(path)
+                if previous.is_some_and(|previous| previous.translation.as_ref() == Some(path)) =>
+            {
+                return false
+            }
+            Some(path) => path.clone(),
+            // unsetting it keeps the one that is loaded, since there is nothing to go back to
+            None if !loaded.api.is_empty() => return false,
+            None => match config::default_translation() {
+                Some(path) => path,
+                None => {
+                    self.client
+                        .show_message(
+                            MessageType::ERROR,
+                            format!(
+                                "No translation to load, set `translation` in initializationOptions to a Bible JSON file or put one in {}",
+                                paths::translations_dir().display()
+                            ),
+                        )
+                        .await;
+                    return false;
+                }
+            },
+        };
+        match self.read_translation(&path).await {
+            Ok(lsp) => {
+                self.switch_translation(lsp).await;
+                true
+            }
+            Err(err) => {
+                self.client
+                    .show_message(
+                        MessageType::ERROR,
+                        format!(
+                            "{}, so no references will be detected",
+                            error::summary(&err)
+                        ),
+                    )
+                    .await;
+                false
+            }
+        }
+    }
+
+    /// - Replaces the loaded translation, remembering the one it replaced
+    /// - Points to [`commands::UPDATE_QUOTED_TRANSLATION`] when it is another translation, since
+    ///   quotes already in the workspace are still in the old one
+    async fn switch_translation(&self, lsp: BibleLSP) {
+        let to = lsp.api.translation.abbreviation.clone();
+        let replaced = std::mem::replace(&mut *self.translation.write().unwrap(), Arc::new(lsp));
+        let from = &replaced.api.translation.abbreviation;
+        if replaced.api.is_empty() || from.eq_ignore_ascii_case(&to) {
+            return;
+        }
+        *self.previous_translation.write().unwrap() = Some(from.clone());
+        self.client
+            .show_message(
+                MessageType::INFO,
+                format!(
+                    "Switched from {from} to {to}, run {} to update the quotes and attributions in the workspace",
+                    commands::UPDATE_QUOTED_TRANSLATION
+                ),
+            )
+            .await;
+    }
+
+    /// Through the daemon when there is one, so every client shares the text
+    async fn read_translation(&self, path: &std::path::Path) -> error::Result<BibleLSP> {
+        match &self.shared {
+            Some(shared) => shared.translation(path).await,
+            None => BibleLSP::load(path),
+        }
+    }
+
+    /// Loads [`Config::compare_translation`] when it changes
+    async fn load_compare(&self, config: &Config, previous: Option<&Config>) {
+        if previous
+            .is_some_and(|previous| previous.compare_translation == config.compare_translation)
+        {
+            return;
+        }
+        let Some(path) = &config.compare_translation else {
+            *self.compare.write().unwrap() = None;
+            return;
+        };
+        let compare = match self.read_translation(path).await {
+            Ok(lsp) => Some(Arc::new(lsp)),
+            Err(err) => {
+                self.client
+                    .show_message(
+                        MessageType::WARNING,
+                        format!("{}, so hovers won't be compared", error::summary(&err)),
+                    )
+                    .await;
+                None
+            }
+        };
+        *self.compare.write().unwrap() = compare;
+    }
+
+    /// - Loads [`Config::cross_references_file`] when it changes
+    /// - A missing default file just means no cross references, but a configured one is a problem
+    #[cfg(feature = "commentary")]
+    async fn load_cross_references(&self, config: &Config, previous: Option<&Config>) {
+        if previous
+            .is_some_and(|previous| previous.cross_references_file == config.cross_references_file)
+        {
+            return;
+        }
+        let path = config
+            .cross_references_file
+            .clone()
+            .unwrap_or_else(cross_references::default_path);
+        let loaded = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || cross_references::CrossReferences::load(&path)
+        })
+        .await
+        .map_err(|err| std::io::Error::other(err.to_string()))
+        .and_then(|loaded| loaded);
+        let cross_references = match loaded {
+            Ok(loaded) => Some(Arc::new(loaded)),
+            Err(err)
+                if err.kind() == std::io::ErrorKind::NotFound
+                    && config.cross_references_file.is_none() =>
+            {
+                None
+            }
+            Err(err) => {
+                self.client
+                    .show_message(
+                        MessageType::WARNING,
+                        format!(
+                            "Couldn't read {}: {err}, so hovers won't have cross references",
+                            path.display()
+                        ),
+                    )
+                    .await;
+                None
+            }
+        };
+        *self.cross_references.write().unwrap() = cross_references;
+    }
+
+    /// - Loads [`Config::translations`] when they change
+    /// - Ones that can't be loaded are skipped, since the default one still works
+    async fn load_translations(&self, config: &Config, previous: Option<&Config>) {
+        if previous.is_some_and(|previous| previous.translations == config.translations) {
+            return;
+        }
+        let mut translations = BTreeMap::new();
+        for path in config.translations.iter() {
+            match self.read_translation(path).await {
+                Ok(lsp) => {
+                    let abbreviation = lsp.api.translation.abbreviation.to_uppercase();
+                    translations.insert(abbreviation, Arc::new(lsp.api));
+                }
+                Err(err) => {
+                    self.client
+                        .show_message(
+                            MessageType::WARNING,
+                            format!("{}, skipping it", error::summary(&err)),
+                        )
+                        .await
+                }
+            }
+        }
+        *self.translations.write().unwrap() = translations;
+    }
+
+    /// - Rebuilds the API with the aliases of every enabled pack, the user's own aliases, and the
+    ///   Psalm numbering, and sets the detection contexts
+    /// - Returns the pack names that don't exist, and the user's aliases for books it doesn't have
+    fn apply_api_settings(&self, config: &Config) -> (Vec<String>, Vec<String>) {
+        let mut lsp = BibleLSP::clone(&self.translation.read().unwrap());
+        let (aliases, unknown_packs) = alias_packs::aliases(&lsp.api, &config.alias_packs);
+        // resolved before the packs are added, so `Gn = "Genesis"` can't point at another alias
+        let (user_aliases, unknown_books) = alias_packs::user_aliases(&lsp.api, &config.aliases);
+        lsp.api.add_aliases(aliases);
+        lsp.api.replace_aliases(user_aliases);
+        lsp.api.psalm_numbering = config.psalm_numbering;
+        lsp.contexts = config.contexts.clone();
+        let ignored_path = self.ignored_phrases_path(config);
+        lsp.ignored_phrases = match ignored_phrases::IgnoredPhrases::load(&ignored_path) {
+            Ok(ignored) => ignored.phrases,
+            Err(err) => {
+                trace::log(trace::Level::Warn, || {
+                    format!("Couldn't read {}: {err}", ignored_path.display())
+                });
+                vec![]
+            }
+        };
+        lsp.translations = self.translations.read().unwrap().clone();
+        // the default one is already there
+        lsp.translations
+            .remove(&lsp.api.translation.abbreviation.to_uppercase());
+        #[cfg(feature = "remote")]
+        {
+            let remote = self.remote.read().unwrap().clone();
+            for abbreviation in remote.abbreviations() {
+                // a file of the same translation wins
+                if lsp.translation(abbreviation).is_none() {
+                    lsp.add_translation(remote.api(&lsp.api, abbreviation));
+                }
+            }
+        }
+        *self.lsp.write().unwrap() = Arc::new(lsp);
+        self.hover_cache.clear();
+        (unknown_packs, unknown_books)
+    }
+
+    /**
+    Puts settings into effect, from `initialize` or when they change
+
+    - Every cache that depends on them is cleared, including the regexes (see
+      [`crate::bible_api::BibleAPI::alias_generation`]) and rendered hovers
+    - Returns whether another translation was loaded
+    */
+    async fn apply_config(&self, config: Config, previous: Option<&Config>) -> bool {
+        let loaded = self.load_translation(&config, previous).await;
+        self.load_compare(&config, previous).await;
+        self.load_translations(&config, previous).await;
+        #[cfg(feature = "commentary")]
+        self.load_cross_references(&config, previous).await;
+        #[cfg(feature = "remote")]
+        if self.remote.read().unwrap().config() != &config.remote {
+            // a new one, since the chapters fetched so far might be from other URLs
+            *self.remote.write().unwrap() =
+                Arc::new(remote_source::RemoteSource::new(config.remote.clone()));
+        }
+        let (unknown_packs, unknown_books) = self.apply_api_settings(&config);
+        if !unknown_books.is_empty() {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!(
+                        "Unknown books in bible.aliases, skipping {}",
+                        unknown_books.join(", ")
+                    ),
+                )
+                .await;
+        }
+        if !unknown_packs.is_empty() {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!(
+                        "Unknown alias packs {}, expected one of {}",
+                        unknown_packs.join(", "),
+                        alias_packs::PACKS.join(", ")
+                    ),
+                )
+                .await;
+        }
+        trace::set_level(config.log_level);
+        self.documents.set_limits(config.limits.clone());
+        let (filter, bad_globs) = DocumentFilter::new(&config.documents);
+        for err in bad_globs {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("Invalid glob in bible.documents, skipping it: {err}"),
+                )
+                .await;
+        }
+        self.documents.set_filter(filter);
+        // before the settings are put on it, since they're the same for everyone sharing it
+        #[cfg(feature = "search")]
+        self.share_index(&config);
+        #[cfg(feature = "search")]
+        self.index().set_ignore_globs(config.ignore_globs.clone());
+        #[cfg(feature = "search")]
+        self.index()
+            .set_front_matter_fields(config.front_matter_fields.clone());
+        let budget = MemoryBudget::from_mb(config.memory_budget_mb);
+        *self.memory_budget.write().unwrap() = budget;
+        self.hover_cache.set_budget(budget.hover_cache());
+        #[cfg(feature = "search")]
+        self.index().set_budget(budget.index());
+        *self.config.write().unwrap() = config;
+        self.hover_cache.clear();
+        loaded
+    }
+
+    /// Asks the client to pull diagnostics, inlay hints, and code lenses again, if it can
+    async fn refresh_client(&self) {
+        let capabilities = self.client_capabilities.read().unwrap().clone();
+        let workspace = capabilities.workspace.as_ref();
+        if workspace
+            .and_then(|workspace| workspace.diagnostic.as_ref()?.refresh_support)
+            .unwrap_or(false)
+        {
+            _ = self.client.workspace_diagnostic_refresh().await;
+        }
+        if workspace
+            .and_then(|workspace| workspace.inlay_hint.as_ref()?.refresh_support)
+            .unwrap_or(false)
+        {
+            _ = self.client.inlay_hint_refresh().await;
+        }
+        if workspace
+            .and_then(|workspace| workspace.code_lens.as_ref()?.refresh_support)
+            .unwrap_or(false)
+        {
+            _ = self.client.code_lens_refresh().await;
+        }
+    }
+
+    /**
+    Loads another translation for `bible.setTranslation`, keeping every other setting
+
+    - Open documents are parsed again and the index is rebuilt, since both have the old book names
+    - A file that can't be loaded is an error, and the current translation is kept
+    */
+    async fn set_translation(&self, path: PathBuf) -> Result<Value> {
+        let lsp = self
+            .read_translation(&path)
+            .await
+            .map_err(|err| tower_lsp::jsonrpc::Error::invalid_params(error::summary(&err)))?;
+        let translation = lsp.api.translation.clone();
+        self.switch_translation(lsp).await;
+        let config = self.config.read().unwrap().clone();
+        // already reported when the settings were applied
+        _ = self.apply_api_settings(&config);
+        self.redetect().await;
+        Ok(serde_json::json!({
+            "name": translation.name,
+            "language": translation.language,
+            "abbreviation": translation.abbreviation,
+        }))
+    }
+
+    /// Finds the references in open documents and the workspace again, once what counts as one
+    /// has changed
+    async fn redetect(&self) {
+        self.documents.reparse();
+        #[cfg(feature = "search")]
+        {
+            let config = self.config.read().unwrap().clone();
+            // a new one from the daemon has nothing indexed the old way
+            if !self.share_index(&config) {
+                self.index().clear();
+            }
+            self.spawn_scan();
+        }
+        self.refresh_client().await;
+    }
+
+    /// See [`Config::ignored_phrases_file`]
+    fn ignored_phrases_path(&self, config: &Config) -> PathBuf {
+        config
+            .ignored_phrases_file
+            .clone()
+            .unwrap_or_else(|| ignored_phrases::default_path(&self.roots.read().unwrap()))
+    }
+
+    /// - Changes the ignored phrases and writes them back, returning what `change` returned
+    /// - Everything is detected again, so the change shows right away
+    async fn change_ignored_phrases<T>(
+        &self,
+        change: impl FnOnce(&mut ignored_phrases::IgnoredPhrases) -> T,
+    ) -> Result<T> {
+        let config = self.config.read().unwrap().clone();
+        let path = self.ignored_phrases_path(&config);
+        let invalid = |err: std::io::Error| {
+            tower_lsp::jsonrpc::Error::invalid_params(format!("{}: {err}", path.display()))
+        };
+        let mut ignored = ignored_phrases::IgnoredPhrases::load(&path).map_err(invalid)?;
+        let changed = change(&mut ignored);
+        ignored.save(&path).map_err(invalid)?;
+        // already reported when the settings were applied
+        _ = self.apply_api_settings(&config);
+        self.redetect().await;
+        Ok(changed)
+    }
+
+    /// See [`Config::reading_queue_file`]
+    fn reading_queue_path(&self) -> PathBuf {
+        self.config
+            .read()
+            .unwrap()
+            .reading_queue_file
+            .clone()
+            .unwrap_or_else(reading_queue::default_path)
+    }
+
+    /// - Changes the reading queue and writes it back, returning what `change` returned
+    /// - The queue's document is rewritten too, in case it is open
+    fn change_reading_queue<T>(
+        &self,
+        change: impl FnOnce(&mut reading_queue::ReadingQueue) -> T,
+    ) -> Result<T> {
+        let path = self.reading_queue_path();
+        let invalid = |err: std::io::Error| {
+            tower_lsp::jsonrpc::Error::invalid_params(format!("{}: {err}", path.display()))
+        };
+        let mut queue = reading_queue::ReadingQueue::load(&path).map_err(invalid)?;
+        let changed = change(&mut queue);
+        queue.save(&path).map_err(invalid)?;
+        _ = queue.materialize();
+        Ok(changed)
+    }
+
+    /// See [`commands::EXPORT_CACHE`]
+    #[cfg(feature = "remote")]
+    fn export_cache(&self, translation: &str, path: &std::path::Path) -> Result<Value> {
+        let invalid = tower_lsp::jsonrpc::Error::invalid_params;
+        let remote = self.remote.read().unwrap().clone();
+        let Some(abbreviation) = remote.serves(translation) else {
+            return Err(invalid(format!("{translation} isn't a remote translation")));
+        };
+        if !remote.exportable(abbreviation) {
+            return Err(invalid(format!(
+                "{abbreviation} isn't in remote.allowExport, so its license may not allow keeping a copy"
+            )));
+        }
+        let previous = path
+            .exists()
+            .then(|| crate::bible_json::read(path))
+            .transpose()
+            .map_err(|err| invalid(error::summary(&err)))?;
+        if let Some(previous) = &previous {
+            if !previous
+                .translation
+                .abbreviation
+                .eq_ignore_ascii_case(abbreviation)
+            {
+                return Err(invalid(format!(
+                    "{} is already {}, not {abbreviation}",
+                    path.display(),
+                    previous.translation.abbreviation
+                )));
+            }
+        }
+        let limit = self
+            .config
+            .read()
+            .unwrap()
+            .quote_limit(abbreviation)
+            .cloned();
+        if limit.as_ref().is_some_and(|limit| !limit.allow_full_insert) {
+            return Err(invalid(format!(
+                "The {abbreviation} can only be cited, not exported (see `quoteLimits`)"
+            )));
+        }
+        let (bible, chapters) = remote.export(&self.lsp().api, abbreviation, previous);
+        if let Some(max_insert_verses) = limit.and_then(|limit| limit.max_insert_verses) {
+            let verses = bible
+                .bible
+                .iter()
+                .flat_map(|book| book.content.iter().flatten())
+                .filter(|verse| !verse.is_empty())
+                .count();
+            if verses > max_insert_verses {
+                return Err(invalid(format!(
+                    "The export would be {verses} verses, but at most {max_insert_verses} of the {abbreviation} can be copied (see `quoteLimits`)"
+                )));
+            }
+        }
+        let json = serde_json::to_string(&bible).map_err(|err| invalid(err.to_string()))?;
+        std::fs::write(path, json)
+            .map_err(|err| invalid(format!("Couldn't write {}: {err}", path.display())))?;
+        Ok(serde_json::json!({ "path": path, "chapters": chapters }))
+    }
+
+    /// Formatters from the settings come first, then template files, then the built-in ones
+    fn formatter(&self, name: &str) -> Option<bible_formatter::PassageFormatter> {
+        self.config
+            .read()
+            .unwrap()
+            .formatters
+            .get(name)
+            .cloned()
+            .or_else(|| self.templates.get(name))
+            .or_else(|| bible_formatter::builtin(name))
+    }
+
+    /// Every formatter name, in the order their code actions are shown
+    fn formatter_names(&self) -> Vec<String> {
+        let config = self.config.read().unwrap();
+        let mut rest: Vec<String> = bible_formatter::BUILTIN_NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .chain(config.formatters.keys().cloned())
+            .chain(self.templates.names())
+            .filter(|name| !config.formatter_order.contains(name))
+            .collect();
+        rest.sort();
+        rest.dedup();
+        let mut names: Vec<String> = config.formatter_order.clone();
+        names.dedup();
+        names.extend(rest);
+        names
+    }
+
+    /// Says in the log that a handler ran out of time, since partial results look like any others
+    async fn log_timeout(&self, handler: &str, deadline: &Deadline, left_out: &str) {
+        self.client
+            .log_message(
+                MessageType::LOG,
+                format!(
+                    "{handler} stopped after {}ms, leaving out {left_out}",
+                    deadline.elapsed().as_millis()
+                ),
+            )
+            .await;
+    }
+
+    /**
+    Every diagnostic for an open document
+
+    - Also returns whether the list is complete, which it isn't when the diagnostics timeout was
+      hit
+    */
+    async fn document_diagnostics(&self, snapshot: &DocumentSnapshot) -> (Vec<Diagnostic>, bool) {
+        if !snapshot.detected {
+            return (vec![], true);
+        }
+        let lsp = self.lsp();
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        let config = self.config.read().unwrap().clone();
+        let deadline = Deadline::after_millis(config.timeouts.diagnostics);
+        let (references, parsed) = snapshot.references_before(&lsp, &deadline);
+        let mut complete = parsed;
+
+        let settings = &config.diagnostics;
+        // whole chapters in front matter aren't found like other references, so they are added
+        let passages = match snapshot.is_large() {
+            true => vec![],
+            false => front_matter::passages(&lsp, &snapshot.text, &config.front_matter_fields),
+        };
+        let whole_chapters = passages
+            .iter()
+            .filter(|passage| passage.whole_chapter)
+            .flat_map(|passage| passage.references.iter());
+        for book_ref in references
+            .iter()
+            .chain(whole_chapters)
+            .filter(|_| settings.enabled)
+        {
+            let api = lsp.api_for(book_ref.translation.as_deref());
+            let Some(message) = book_ref.format_diagnostic(api, settings.preview_length) else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range: book_ref.range,
+                severity: Some(settings.severity.into()),
+                // severity: Some(DiagnosticSeverity::HINT),
+                message,
+                code: Some(NumberOrString::String(book_ref.full_ref_label(&lsp.api))),
+                // code_description: Some(CodeDescription { href: () }),
+                // source: todo!(),
+                // related_information: Some(vec![
+                //     DiagnosticRelatedInformation
+                // ]),
+                // tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                // data: todo!(),
+                ..Default::default()
+            });
+        }
+
+        // the value of a passage field is always meant to be one
+        diagnostics.extend(
+            passages
+                .iter()
+                .filter(|passage| passage.references.is_empty())
+                .map(|passage| Diagnostic {
+                    range: passage.field.range,
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    message: format!(
+                        "`{}` in `{}` isn't a passage",
+                        passage.field.value, passage.field.key
+                    ),
+                    ..Default::default()
+                }),
+        );
+
+        // the whole text is scanned for misspellings too
+        if deadline.expired() {
+            complete = false;
+        } else if !snapshot.is_large() {
+            diagnostics.extend(
+                spelling::find_misspellings(&lsp.api, &snapshot.text, &snapshot.line_index)
+                    .iter()
+                    .map(spelling::Misspelling::diagnostic),
+            );
+        }
+
+        // only once everything was parsed, since it would parse everything
+        if let Some(message) = parsed.then(|| snapshot.truncation_warning(&lsp)).flatten() {
+            diagnostics.push(Diagnostic {
+                range: Range::default(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                message,
+                ..Default::default()
+            });
+        }
+
+        if !config.quote_limits.is_empty() {
+            let elsewhere = self.quoted_elsewhere(&lsp, &config, &snapshot.uri);
+            let quoted = quote_limits::quoted_references(&lsp, &snapshot.text, &references);
+            diagnostics.extend(quote_limits::quote_limit_diagnostics(
+                &lsp, &config, &quoted, &elsewhere,
+            ));
+        }
+
+        // re-rendering every marked quote is the slowest part, so it goes last
+        if deadline.expired() {
+            complete = false;
+        } else if !snapshot.is_large() {
+            let uri = &snapshot.uri;
+            diagnostics.extend(
+                quote_markers::changed_quotes(&lsp, snapshot, |book_ref, formatter| {
+                    self.render_quote(&lsp, uri, book_ref, formatter, None)
+                })
+                .iter()
+                .map(quote_markers::StaleQuote::diagnostic),
+            );
+        }
+
+        if !complete {
+            let left_out = match parsed {
+                true => "some spelling and quote checks",
+                false => "references past what was parsed",
+            };
+            self.log_timeout("diagnostics", &deadline, left_out).await;
+        }
+        (diagnostics, complete)
+    }
+
+    /// - The verses quoted in every indexed document but `uri`, for the `quoteLimits` that count
+    ///   the whole workspace, see [`quote_limits::quoted_references`]
+    /// - Nothing when none of them do, or without the `search` feature
+    fn quoted_elsewhere(
+        &self,
+        lsp: &BibleLSP,
+        config: &Config,
+        uri: &Url,
+    ) -> quote_limits::QuotedVerses {
+        #[cfg(feature = "search")]
+        if config.quote_limits.values().any(|limit| limit.workspace) {
+            let mut quoted = quote_limits::QuotedVerses::new();
+            let index = self.index();
+            for (file, references) in index.files().iter() {
+                if file == uri {
+                    continue;
+                }
+                for reference in references.iter().filter(|reference| reference.quoted) {
+                    let api = lsp.api_for(reference.translation.as_deref());
+                    let verses = quoted
+                        .entry(api.translation.abbreviation.clone())
+                        .or_default();
+                    for (chapter, verse) in reference.verses(api) {
+                        verses.insert((reference.book_id, chapter, verse));
+                    }
+                }
+            }
+            return quoted;
+        }
+        #[cfg(not(feature = "search"))]
+        let _ = (lsp, config, uri);
+        quote_limits::QuotedVerses::new()
+    }
+
+    /**
+    Identifies what a document's diagnostics were computed from
+
+    - Diagnostics only depend on the text and the settings, so the same id means they are the same
+    - Texts are hashed too, since versions start over when a document is reopened
+    */
+    fn diagnostic_result_id(&self, snapshot: &DocumentSnapshot) -> String {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        snapshot.text.hash(&mut hasher);
+        format!(
+            "{}:{}:{:x}",
+            snapshot.version,
+            self.hover_cache.generation(),
+            hasher.finish()
+        )
+    }
+
+    /// - Skips computing diagnostics the client already has, going by `previous_result_id`
+    /// - Partial results don't get an id, so they are always computed again, and neither do
+    ///   ones with `quoteLimits` counted across the workspace
+    async fn diagnostic_report(
+        &self,
+        snapshot: &DocumentSnapshot,
+        previous_result_id: Option<&str>,
+    ) -> DocumentDiagnosticReport {
+        let result_id = self.diagnostic_result_id(snapshot);
+        if previous_result_id == Some(result_id.as_str()) {
+            return DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id,
+                },
+            });
+        }
+        let (items, complete) = self.document_diagnostics(snapshot).await;
+        // quotes in other documents change them without this one changing
+        let complete = complete
+            && !self
+                .config
+                .read()
+                .unwrap()
+                .quote_limits
+                .values()
+                .any(|limit| limit.workspace);
+        DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+            related_documents: None,
+            full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                result_id: complete.then_some(result_id),
+                items,
+            },
+        })
+    }
+
+    /// - A reference formatted with the named formatter, for the document at `uri`
+    /// - In `translation` when it is loaded, and the default one otherwise
+    /// - `None` if there is no formatter by that name
+    fn render_quote(
+        &self,
+        lsp: &BibleLSP,
+        uri: &Url,
+        book_ref: &BookReference,
+        formatter: &str,
+        translation: Option<&str>,
+    ) -> Option<String> {
+        let formatter = self.formatter(formatter)?;
+        // `untitled:Untitled-1` has no path, so the URI itself is the best name there is
+        let file_name = paths::url_to_path(uri)
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| uri.path().to_string());
+        let api = lsp.api_for(translation);
+        let context = bible_formatter::FormatContext::new(api, file_name);
+        Some(formatter.format(api, book_ref, &context))
+    }
+
+    /// The text the insert code actions produce, wrapped in markers if they are enabled
+    fn quote_text(
+        &self,
+        lsp: &BibleLSP,
+        uri: &Url,
+        book_ref: &BookReference,
+        formatter: &str,
+        translation: Option<&str>,
+    ) -> String {
+        let quote = self
+            .render_quote(lsp, uri, book_ref, formatter, translation)
+            .unwrap_or_default();
+        let abbreviation = &lsp.api_for(translation).translation.abbreviation;
+        match self.config.read().unwrap().quote_markers {
+            true => quote_markers::wrap(lsp, abbreviation, book_ref, formatter, &quote),
+            false => quote,
+        }
+    }
+
+    /// Why `book_ref` can't be inserted in `translation`, see [`quote_limits::insert_refusal`]
+    fn insert_refusal(
+        &self,
+        lsp: &BibleLSP,
+        book_ref: &BookReference,
+        translation: Option<&str>,
+    ) -> Option<String> {
+        let api = lsp.api_for(translation);
+        let config = self.config.read().unwrap();
+        let limit = config.quote_limit(&api.translation.abbreviation)?;
+        quote_limits::insert_refusal(api, limit, book_ref)
+    }
+
+    /**
+    The abbreviation and attribution of each translation quoted in the document, see
+    [`attribution::quoted_translations`]
+
+    - Marked quotes in `requoted` count as the default translation, since they are about to be
+      quoted again in it
+    */
+    fn attributions(
+        &self,
+        lsp: &BibleLSP,
+        snapshot: &DocumentSnapshot,
+        requoted: Option<&str>,
+    ) -> Vec<(String, String)> {
+        let references = snapshot.references(lsp);
+        let quotes = quote_markers::find_quotes(snapshot);
+        let cited = (references
+            .iter()
+            .map(|book_ref| book_ref.translation.as_deref()))
+        .chain(quotes.iter().map(|quote| {
+            let translation = quote.translation.as_str();
+            (!requoted.is_some_and(|from| from.eq_ignore_ascii_case(translation)))
+                .then_some(translation)
+        }));
+        let config = self.config.read().unwrap();
+        attribution::quoted_translations(lsp, cited)
+            .into_iter()
+            .map(|api| {
+                (
+                    api.translation.abbreviation.clone(),
+                    attribution::attribution_text(api, &config),
+                )
+            })
+            .collect()
+    }
+
+    /// - The abbreviations attributed, and the edit
+    /// - `None` when there is nothing to attribute or the block is already current
+    fn attribution_edit(&self, snapshot: &DocumentSnapshot) -> Option<(String, WorkspaceEdit)> {
+        let lsp = self.lsp();
+        let (abbreviations, attributions): (Vec<String>, Vec<String>) =
+            self.attributions(&lsp, snapshot, None).into_iter().unzip();
+        if attributions.is_empty() {
+            return None;
+        }
+        let edit = attribution::attribution_edit(&snapshot.text, &attributions)?;
+        Some((
+            abbreviations.join(", "),
+            commands::document_edit(snapshot.uri.clone(), vec![edit]),
+        ))
+    }
+
+    /// `None` when the document's book tags are already current, see [`book_tags::tags_edit`]
+    fn book_tags_edit(&self, snapshot: &DocumentSnapshot) -> Option<WorkspaceEdit> {
+        let lsp = self.lsp();
+        let config = self.config.read().unwrap().clone();
+        // whole chapters in front matter count too, and aren't in `references`
+        let passages = front_matter::passages(&lsp, &snapshot.text, &config.front_matter_fields);
+        let whole_chapters = passages
+            .iter()
+            .filter(|passage| passage.whole_chapter)
+            .flat_map(|passage| passage.references.iter());
+        let books = book_tags::book_names(
+            &lsp.api,
+            snapshot.references(&lsp).iter().chain(whole_chapters),
+        );
+        let edit = book_tags::tags_edit(&snapshot.text, &config.book_tags.field, &books)?;
+        Some(commands::document_edit(snapshot.uri.clone(), vec![edit]))
+    }
+
+    /// - See [`scripture_index::index_edit`]
+    /// - What is listed in the index itself doesn't count, or it would never go away
+    fn scripture_index_edit(&self, snapshot: &DocumentSnapshot, insert: bool) -> Option<TextEdit> {
+        let lsp = self.lsp();
+        let references = snapshot.references(&lsp).iter().filter(|book_ref| {
+            !scripture_index::contains(&snapshot.text, &snapshot.line_index, book_ref.range)
+        });
+        let block = scripture_index::index_block(&lsp.api, references);
+        scripture_index::index_edit(&snapshot.text, block, insert)
+    }
+
+    /// Keeps the workspace index in sync with what is open in the editor
+    #[cfg(feature = "search")]
+    fn reindex(&self, snapshot: &DocumentSnapshot) {
+        let lsp = self.lsp();
+        if self.index().contains_path(&snapshot.uri)
+            && VirtualDocument::from_uri(&lsp.api, &snapshot.uri).is_none()
+        {
+            self.index().index_references(
+                &lsp,
+                snapshot.uri.clone(),
+                &snapshot.text,
+                snapshot.references(&lsp),
+            );
+        }
+    }
+
+    /// Scans the workspace in the background, see [`workspace_index::WorkspaceIndex::scan`]
+    #[cfg(feature = "search")]
+    fn spawn_scan(&self) {
+        let index = self.index();
+        let lsp = self.lsp();
+        let extensions = self.config.read().unwrap().index.extensions.clone();
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let scanned = tokio::task::spawn_blocking(move || {
+                let summary = index.scan(&lsp, &extensions);
+                (summary, index.save_cache(&lsp))
+            })
+            .await;
+            if let Ok((summary, saved)) = scanned {
+                client
+                    .log_message(
+                        MessageType::INFO,
+                        format!(
+                            "Indexed {} files ({} unchanged since last session)",
+                            summary.files, summary.cached
+                        ),
+                    )
+                    .await;
+                if let Err(err) = saved {
+                    client
+                        .log_message(
+                            MessageType::WARNING,
+                            format!("Failed to save the workspace index: {err}"),
+                        )
+                        .await;
+                }
+            }
+        });
+    }
+
+    /**
+    Drains the re-index queue in the background
+
+    - Batches run on the blocking pool and report progress in between, so a big `git checkout`
+      doesn't stall hovers and completions
+    - If the queue overflowed, the whole workspace is rescanned instead
+    */
+    #[cfg(feature = "search")]
+    fn spawn_reindex(&self) {
+        if !self.reindex_queue.start() {
+            return;
+        }
+        let index = self.index();
+        let lsp = self.lsp();
+        let queue = self.reindex_queue.clone();
+        let client = self.client.clone();
+        let extensions = self.config.read().unwrap().index.extensions.clone();
+        tokio::spawn(async move {
+            let progress = match queue.len() > reindex_queue::BATCH_SIZE {
+                true => {
+                    progress::Progress::begin(&client, "bible_lsp/reindex", "Indexing references")
+                        .await
+                }
+                false => None,
+            };
+            let mut done = 0;
+            loop {
+                let batch = queue.next_batch();
+                if batch.is_empty() {
+                    queue.finish();
+                    // something could have been pushed right before finishing
+                    if queue.is_empty() || !queue.start() {
+                        break;
+                    }
+                    continue;
+                }
+                done += batch.len();
+                let (index, lsp) = (index.clone(), lsp.clone());
+                _ = tokio::task::spawn_blocking(move || {
+                    for uri in batch {
+                        index.index_file(&lsp, uri);
+                    }
+                })
+                .await;
+                if let Some(progress) = &progress {
+                    let total = done + queue.len();
+                    progress
+                        .report(format!("{done}/{total} files"), done, total)
+                        .await;
+                }
+            }
+            if queue.take_overflow() {
+                let (index, lsp) = (index.clone(), lsp.clone());
+                if let Ok(summary) =
+                    tokio::task::spawn_blocking(move || index.scan(&lsp, &extensions)).await
+                {
+                    done = summary.files;
+                }
+            }
+            let (index, lsp) = (index.clone(), lsp.clone());
+            _ = tokio::task::spawn_blocking(move || index.save_cache(&lsp)).await;
+            if let Some(progress) = progress {
+                progress.end(format!("Indexed {done} files")).await;
+            }
+        });
+    }
+
+    /// - Handles `bible.nextVerse` and `bible.prevVerse`
+    /// - Returns the verse that was found, so clients can show it however they like
+    async fn navigate_verse(
+        &self,
+        arguments: &[Value],
+        direction: verse_navigation::Direction,
+    ) -> Result<Option<Value>> {
+        let uri: Url = commands::argument(arguments, 0)?;
+        let position: Position = commands::argument(arguments, 1)?;
+        let mode: Option<verse_navigation::NavigationMode> = commands::argument(arguments, 2)?;
+        let lsp = self.lsp();
+        let Some(snapshot) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        let Some(book_ref) = verse_navigation::reference_at(snapshot.references(&lsp), position)
+        else {
+            return Ok(None);
+        };
+        let Some((chapter, verse)) =
+            verse_navigation::target_verse(&lsp.api, &snapshot, book_ref, direction)
+        else {
+            return Ok(None);
+        };
+        let book_name = lsp.api.get_book_name(book_ref.book_id).unwrap_or_default();
+        let content = lsp
+            .api
+            .get_bible_contents(book_ref.book_id, chapter, verse)
+            .unwrap_or_default();
+        match mode.unwrap_or_default() {
+            verse_navigation::NavigationMode::Insert => {
+                if let Some(edit) = verse_navigation::insert_edit(
+                    &lsp.api,
+                    &snapshot,
+                    book_ref,
+                    (chapter, verse),
+                    direction,
+                ) {
+                    self.client
+                        .apply_edit(commands::document_edit(uri, vec![edit]))
+                        .await?;
+                }
+            }
+            verse_navigation::NavigationMode::Preview => {
+                self.client
+                    .show_message(
+                        MessageType::INFO,
+                        format!("{book_name} {chapter}:{verse} {content}"),
+                    )
+                    .await;
+            }
+        }
+        Ok(Some(serde_json::json!({
+            "reference": format!("{book_name} {chapter}:{verse}"),
+            "content": content,
+        })))
+    }
+
+    /**
+    `lsp` with the chapters `refs` cite in remote translations fetched, see
+    [`remote_source::RemoteSource`]
+
+    - Every chapter is fetched before anything is rendered, so the renderers stay synchronous
+    - The same `lsp` when none of them are remote
+    */
+    #[cfg(feature = "remote")]
+    async fn with_remote(
+        &self,
+        lsp: Arc<BibleLSP>,
+        refs: &[BookReference],
+        translation: Option<&str>,
+    ) -> Result<Arc<BibleLSP>> {
+        let remote = self.remote.read().unwrap().clone();
+        let loaded = self.translations.read().unwrap().clone();
+        let mut chapters: BTreeSet<(String, usize, usize)> = BTreeSet::new();
+        for book_ref in refs {
+            let Some(abbreviation) = book_ref
+                .translation
+                .as_deref()
+                .or(translation)
+                .and_then(|translation| remote.serves(translation))
+                .filter(|abbreviation| {
+                    !loaded.contains_key(&abbreviation.to_uppercase())
+                        && !abbreviation.eq_ignore_ascii_case(&lsp.api.translation.abbreviation)
+                })
+            else {
+                continue;
+            };
+            for (chapter, _) in book_ref.verses(&lsp.api) {
+                chapters.insert((abbreviation.to_string(), book_ref.book_id, chapter));
+            }
+        }
+        if chapters.is_empty() {
+            return Ok(lsp);
+        }
+        let fetching = remote.clone();
+        let wanted = chapters.clone();
+        let internal = |message: String| tower_lsp::jsonrpc::Error {
+            code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+            message: message.into(),
+            data: None,
+        };
+        tokio::task::spawn_blocking(move || {
+            wanted
+                .into_iter()
+                .try_for_each(|(translation, book_id, chapter)| {
+                    fetching.chapter(&translation, book_id, chapter).map(|_| ())
+                })
+        })
+        .await
+        .map_err(|err| internal(err.to_string()))?
+        .map_err(|err| internal(err.to_string()))?;
+        let mut updated = BibleLSP::clone(&lsp);
+        let abbreviations: BTreeSet<&String> = chapters
+            .iter()
+            .map(|(abbreviation, ..)| abbreviation)
+            .collect();
+        for abbreviation in abbreviations {
+            updated.add_translation(remote.api(&lsp.api, abbreviation));
+        }
+        Ok(Arc::new(updated))
+    }
+
+    /**
+    `textDocument/hover`, with the passages' text in `translation`
+
+    - References are found the same way whatever the translation, only their text changes
+    - The default translation for `None`, or one that isn't loaded, see [`BibleLSP::api_for`]
+    */
+    async fn hover_in(
+        &self,
+        params: HoverParams,
+        translation: Option<&str>,
+    ) -> Result<Option<Hover>> {
+        let lsp = self.lsp();
+        let doc = params.text_document_position_params.text_document;
+        let Some(snapshot) = self.documents.get(&doc.uri) else {
+            return Ok(None);
+        };
+        let pos = params.text_document_position_params.position;
+        let mut refs = snapshot.references_on_line(&lsp, pos.line);
+        if refs.is_empty() && snapshot.detected {
+            // whole chapters in front matter, like `passage: John 15`
+            let fields = self.config.read().unwrap().front_matter_fields.clone();
+            refs = front_matter::passages(&lsp, &snapshot.text, &fields)
+                .into_iter()
+                .filter(|passage| {
+                    let range = passage.field.range;
+                    range.start.line == pos.line
+                        && (range.start.character..=range.end.character).contains(&pos.character)
+                })
+                .flat_map(|passage| passage.references)
+                .collect();
+        }
+        if refs.is_empty() && snapshot.detected && self.config.read().unwrap().hover.books {
+            if let Some(hover) = self.book_hover(&lsp, &snapshot, pos, translation) {
+                return Ok(Some(hover));
+            }
+        }
+        // just `2:2-3:4` when that is what's under the cursor in `Eph 1:1-4,2:2-3:4`
+        if let Some(segment) = refs.iter().find_map(|book_ref| book_ref.segment_at(pos)) {
+            refs = vec![segment];
+        }
+        #[cfg(feature = "remote")]
+        let lsp = self.with_remote(lsp, &refs, translation).await?;
+
+        let (profile, options) = {
+            let config = self.config.read().unwrap();
+            (config.hover_formatter.clone(), config.hover.clone())
+        };
+        let compare = self.compare.read().unwrap().clone();
+        let render = |book_ref: &BookReference| {
+            // one written after the reference wins
+            let translation = book_ref.translation.as_deref().or(translation);
+            let api = lsp.api_for(translation);
+            let key = hover_cache::HoverKey {
+                translation: api.translation.abbreviation.clone(),
+                reference: book_ref.full_ref_label(&lsp.api) + &book_ref.translation_suffix(),
+                profile: match &compare {
+                    Some(compare) => format!("compare:{}", compare.api.translation.abbreviation),
+                    None => profile.clone().unwrap_or_default(),
+                },
+            };
+            self.hover_cache.get_or_render(key, || {
+                if let Some(compare) = &compare {
+                    return book_ref.format_compared(api, &compare.api);
+                }
+                profile
+                    .as_deref()
+                    .and_then(|name| self.render_quote(&lsp, &doc.uri, book_ref, name, translation))
+                    .unwrap_or_else(|| book_ref.format_with(api, &options))
+            })
+        };
+
+        if refs.len() == 1 {
+            let book_ref = refs.first().unwrap();
+            #[allow(unused_mut)]
+            let mut hover_contents = render(book_ref);
+            #[cfg(feature = "commentary")]
+            if let Some(see_also) = self.see_also(
+                lsp.api_for(book_ref.translation.as_deref().or(translation)),
+                book_ref,
+                &options,
+            ) {
+                hover_contents = format!("{hover_contents}\n\n---\n{see_also}");
+            }
+            return Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::from_markdown(hover_contents)),
+                range: Some(book_ref.range),
+            }));
+        }
+
+        // i could just use the one under the cursor, but i dont want to do that right now
+        let deadline = Deadline::after_millis(self.config.read().unwrap().timeouts.hover);
+        let mut passages = vec![];
+        for book_ref in refs.iter() {
+            // always at least one, or the hover would be empty
+            if !passages.is_empty() && deadline.expired() {
+                break;
+            }
+            passages.push(render(book_ref));
+        }
+        let left_out = refs.len() - passages.len();
+        if left_out > 0 {
+            passages.push(format!("*{left_out} more not shown*"));
+            self.log_timeout("hover", &deadline, &format!("{left_out} passages"))
+                .await;
+        }
+        let hover_contents = passages.join("\n\n---\n");
+        Ok(Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::from_markdown(hover_contents)),
+            range: None,
+        }))
+    }
+
+    /// - The cross references of a single verse, see [`cross_references::see_also`]
+    /// - Added after the hover is rendered, so cached hovers don't keep a stale list
+    #[cfg(feature = "commentary")]
+    fn see_also(
+        &self,
+        api: &BibleAPI,
+        book_ref: &BookReference,
+        options: &crate::book_reference::FormatOptions,
+    ) -> Option<String> {
+        let [segment] = book_ref.segments.as_slice() else {
+            return None;
+        };
+        let (chapter, verse) = (segment.get_starting_chapter(), segment.get_starting_verse());
+        if !options.cross_references
+            || (chapter, verse) != (segment.get_ending_chapter(), segment.get_ending_verse())
+        {
+            return None;
+        }
+        let cross_references = self.cross_references.read().unwrap().clone()?;
+        cross_references::see_also(
+            api,
+            cross_references.of(book_ref.book_id, chapter, verse),
+            options.max_cross_references,
+        )
+    }
+
+    /// - The section headings from [`Config::headings_file`], for book introductions
+    /// - The translation's own when it has none or can't be read, since an introduction is still
+    ///   useful without an outline
+    fn headings(&self, lsp: &BibleLSP) -> headings::Headings {
+        let path = self
+            .config
+            .read()
+            .unwrap()
+            .headings_file
+            .clone()
+            .unwrap_or_else(headings::default_path);
+        match headings::Headings::load(lsp, &path) {
+            Ok(headings) if !headings.is_empty() => headings,
+            _ => headings::Headings::of_translation(&lsp.api),
+        }
+    }
+
+    /// A markdown link that opens a book's introduction, see [`commands::OPEN_BOOK_INTRO`]
+    fn intro_link(api: &BibleAPI, book_id: usize) -> Option<String> {
+        Some(format!(
+            "[About {}]({})",
+            api.get_book_name(book_id)?,
+            commands::command_uri(commands::OPEN_BOOK_INTRO, &serde_json::json!([book_id]))
+        ))
+    }
+
+    /// - A book named without a chapter under the cursor, see [`book_overview::format`]
+    /// - Its chapters link to [`commands::OPEN_CHAPTER`], and it ends with a link to its
+    ///   introduction
+    fn book_hover(
+        &self,
+        lsp: &BibleLSP,
+        snapshot: &DocumentSnapshot,
+        pos: Position,
+        translation: Option<&str>,
+    ) -> Option<Hover> {
+        let text = &snapshot.text;
+        let line_start = snapshot
+            .line_index
+            .offset(text, Position::new(pos.line, 0))?;
+        let cursor = snapshot.line_index.offset(text, pos)? - line_start;
+        let (book_id, name) = book_overview::book_at(&lsp.api, snapshot.line(pos.line)?, cursor)?;
+        let api = lsp.api_for(translation);
+        let overview = book_overview::format(api, book_id, |chapter| {
+            commands::command_uri(
+                commands::OPEN_CHAPTER,
+                &serde_json::json!([book_id, chapter]),
+            )
+        })?;
+        let contents = format!("{overview}\n\n{}", Self::intro_link(api, book_id)?);
+        Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::from_markdown(contents)),
+            range: Some(Range {
+                start: snapshot.line_index.position(text, line_start + name.start),
+                end: snapshot.line_index.position(text, line_start + name.end),
+            }),
+        })
+    }
+
+    /// `textDocument/completion`, with verse previews in `translation`
+    async fn completion_in(
+        &self,
+        params: CompletionParams,
+        translation: Option<&str>,
+    ) -> Result<Option<CompletionResponse>> {
+        let lsp = self.lsp();
+        let doc = params.text_document_position.text_document;
+        let Some(snapshot) = self.documents.get(&doc.uri) else {
+            return Ok(None);
+        };
+        if !snapshot.detected {
+            return Ok(None);
+        }
+        let pos = params.text_document_position.position;
+        let text = &snapshot.text;
+        let line_start = snapshot
+            .line_index
+            .line_range(text, pos.line..pos.line + 1)
+            .start;
+        // the character is in UTF-16, and clients past the end of the line get the end of it
+        let Some(cursor) = snapshot.line_index.offset(text, pos) else {
+            return Ok(None);
+        };
+        let text_before_cursor = &text[line_start..cursor];
+        let max_items = self.config.read().unwrap().completion.max_items;
+        let (suggestions, truncated) = lsp.suggest_auto_completion(text_before_cursor, max_items);
+        // never past the cursor, so text after it is left alone
+        let edit_range = Range {
+            start: snapshot
+                .line_index
+                .position(text, line_start + lsp.completion_start(text_before_cursor)),
+            end: snapshot.line_index.position(text, cursor),
+        };
+        let ranking = completion_ranking::CompletionContext::new(
+            snapshot.references(&lsp),
+            self.recent_books.books(),
+        );
+        let completion_items: Vec<CompletionItem> = suggestions
+            .into_iter()
+            .map(|item| {
+                let label = item.label(&lsp.api);
+                // append_log(format!("{:#?}", label));
+                // append_log(format!("{:#?}\n", item));
+                let text_edit = Some(CompletionTextEdit::Edit(TextEdit {
+                    range: edit_range,
+                    new_text: label.clone(),
+                }));
+
+                // match item {
+                //
+                // };
+                let mut doc_content = item.lsp_preview(lsp.api_for(translation));
+                if let crate::autocompletion::BibleCompletion::BookName(_) = item {
+                    if let Some(link) = Self::intro_link(lsp.api_for(translation), item.book_id()) {
+                        doc_content = format!("{doc_content}\n\n{link}");
+                    }
+                }
+                let sort_text = ranking.sort_text(&lsp.api, &item);
+                CompletionItem {
+                    label,
+                    documentation: Some(Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: doc_content,
+                    })),
+                    text_edit,
+                    kind: Some(CompletionItemKind::REFERENCE),
+                    sort_text: Some(sort_text),
+                    command: Some(Command {
+                        title: String::new(),
+                        command: commands::COMPLETION_ACCEPTED.to_string(),
+                        arguments: Some(vec![Value::from(item.book_id())]),
+                    }),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        if !truncated {
+            return Ok(Some(CompletionResponse::Array(completion_items)));
+        }
+        // incomplete lists are asked for again as more is typed, which narrows them
+        Ok(Some(CompletionResponse::List(CompletionList {
+            is_incomplete: true,
+            items: completion_items,
+        })))
+    }
+
+    /// `textDocument/definition`, going to the chapter in `translation`
+    async fn goto_definition_in(
+        &self,
+        params: GotoDefinitionParams,
+        translation: Option<&str>,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let lsp = self.lsp();
+        let doc = params.text_document_position_params.text_document;
+        let Some(snapshot) = self.documents.get(&doc.uri) else {
+            return Ok(None);
+        };
+        let pos = params.text_document_position_params.position;
+        let cursor = params.text_document_position_params.position.character;
+        // let book_ref = if refs.first().is_some_and(|found| found.range) {
+        //
+        // } else {};
+        let Some(book_ref) = snapshot
+            .references_on_line(&lsp, pos.line)
+            .into_iter()
+            .find(|r| r.range.start.character <= cursor && cursor <= r.range.end.character)
+        else {
+            return Ok(None);
+        };
+        let Some((chapter, verse)) = book_ref
+            .segments
+            .first()
+            .map(|seg| (seg.get_starting_chapter(), seg.get_starting_verse()))
+        else {
+            return Ok(None);
+        };
+        let api = lsp.api_for(book_ref.translation.as_deref().or(translation));
+        let document = VirtualDocument::chapter(api, book_ref.book_id, chapter);
+        let Some(file_contents) = document.render(api, &headings::Headings::default()) else {
+            return Ok(None);
+        };
+        // this would have to change when i change templating
+        // let the_match = format!("[{}:{}]", chapter, verse).as_str();
+        let Some(the_match) = file_contents.find(format!("[{}:{}]", chapter, verse).as_str())
+        else {
+            return Ok(None);
+        };
+        let line_number = file_contents[..=the_match]
+            .chars()
+            .filter(|c| *c == '\n')
+            .count();
+
+        match document.materialize(api, &headings::Headings::default()) {
+            Ok(uri) => Ok(Some(GotoDefinitionResponse::Scalar(Location {
+                uri,
+                range: Range {
+                    start: Position {
+                        line: line_number as u32,
+                        character: 0,
+                    },
+                    end: Position {
+                        line: line_number as u32,
+                        character: 0,
+                    },
+                },
+            }))),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// `textDocument/codeAction`, inserting quotes in `translation`
+    async fn code_action_in(
+        &self,
+        params: CodeActionParams,
+        translation: Option<&str>,
+    ) -> Result<Option<CodeActionResponse>> {
+        let lsp = self.lsp();
+        // params.text_document.uri
+        let doc = params.text_document;
+        let uri = doc.uri.clone();
+        let Some(snapshot) = self.documents.get(&doc.uri) else {
+            return Ok(None);
+        };
+        // every reference in the selection, not just on the line it starts on
+        let lines = params.range.start.line..params.range.end.line.saturating_add(1);
+        let refs = snapshot.references_in_lines(&lsp, lines);
+        #[cfg(feature = "remote")]
+        let lsp = self.with_remote(lsp, &refs, translation).await?;
+        // append_log(format!("{:#?}", refs));
+        let mut res = CodeActionResponse::new();
+        let formatters = self.formatter_names();
+        let separators = self.config.read().unwrap().label_separators.clone();
+        // the segment at the cursor can be inserted on its own, but replacing the line with it
+        // would lose the rest of the reference
+        let segment = refs
+            .iter()
+            .find_map(|book_ref| book_ref.segment_at(params.range.start));
+        let targets = (refs.iter().map(|book_ref| (book_ref, true)))
+            .chain(segment.iter().map(|segment| (segment, false)));
+        for (each, whole) in targets {
+            let label = separators.label(&lsp.api, each, snapshot.text_at(each.range));
+            // the reference's own line, since a selection can have references on several
+            let pos = each.range.end;
+            let refusal =
+                self.insert_refusal(&lsp, each, each.translation.as_deref().or(translation));
+            for name in formatters.iter() {
+                let Some(formatter) = self.formatter(name) else {
+                    continue;
+                };
+                for action in formatter.code_actions() {
+                    let title = match action {
+                        "insert" => format!("Insert {label} as {name}"),
+                        "replace" if whole => format!("Replace {label} with {name}"),
+                        _ => continue,
+                    };
+                    // still listed, so the user sees why it can't be done
+                    if let Some(reason) = &refusal {
+                        res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                            title,
+                            disabled: Some(CodeActionDisabled {
+                                reason: reason.clone(),
+                            }),
+                            ..Default::default()
+                        }));
+                        continue;
+                    }
+                    let (range, new_text) = match action {
+                        // prefix inserted content with \n so that way it works when
+                        // i try inserting on the next line when i am on the last line
+                        "insert" => (
+                            Range {
+                                start: Position {
+                                    line: pos.line,
+                                    character: u32::MAX,
+                                },
+                                end: Position {
+                                    line: pos.line,
+                                    character: u32::MAX,
+                                },
+                            },
+                            format!(
+                                "\n{}",
+                                self.quote_text(
+                                    &lsp,
+                                    &uri,
+                                    each,
+                                    name,
+                                    each.translation.as_deref().or(translation)
+                                )
+                            ),
+                        ),
+                        // this doesn't work if i am on last line
+                        _ => (
+                            Range {
+                                start: Position {
+                                    line: each.range.start.line,
+                                    character: 0,
+                                },
+                                end: Position {
+                                    line: pos.line,
+                                    character: u32::MAX,
+                                },
+                            },
+                            self.quote_text(
+                                &lsp,
+                                &uri,
+                                each,
+                                name,
+                                each.translation.as_deref().or(translation),
+                            ),
+                        ),
+                    };
+                    res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                        title,
+                        edit: Some(commands::document_edit(
+                            uri.clone(),
+                            vec![TextEdit { range, new_text }],
+                        )),
+                        is_preferred: res.is_empty().then_some(true),
+                        ..Default::default()
+                    }));
+                }
+            }
+        }
+
+        // a single action for the whole selection, with each passage after its own line
+        if refs.len() > 1 {
+            for name in formatters.iter() {
+                let Some(formatter) = self.formatter(name) else {
+                    continue;
+                };
+                if !formatter.code_actions().contains(&"insert") {
+                    continue;
+                }
+                let title = format!("Insert all {} passages as {name}", refs.len());
+                let refusal = refs.iter().find_map(|book_ref| {
+                    self.insert_refusal(
+                        &lsp,
+                        book_ref,
+                        book_ref.translation.as_deref().or(translation),
+                    )
+                });
+                if let Some(reason) = refusal {
+                    res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                        title,
+                        disabled: Some(CodeActionDisabled { reason }),
+                        ..Default::default()
+                    }));
+                    continue;
+                }
+                let edits = batch_edits::insert_after_lines(refs.iter().map(|book_ref| {
+                    (
+                        book_ref.range.end.line,
+                        self.quote_text(
+                            &lsp,
+                            &uri,
+                            book_ref,
+                            name,
+                            book_ref.translation.as_deref().or(translation),
+                        ),
+                    )
+                }));
+                res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    edit: Some(commands::document_edit(uri.clone(), edits)),
+                    ..Default::default()
+                }));
+            }
+        }
+
+        for book_ref in refs.iter() {
+            for direction in [
+                verse_navigation::Direction::Next,
+                verse_navigation::Direction::Previous,
+            ] {
+                let Some((title, edits)) =
+                    verse_navigation::extend_edits(&lsp.api, &snapshot, book_ref, direction)
+                else {
+                    continue;
+                };
+                res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                    edit: Some(commands::document_edit(uri.clone(), edits)),
+                    ..Default::default()
+                }));
+            }
+        }
+
+        for book_ref in refs.iter() {
+            // read again when it's queued, so in the numbering it was cited in
+            let label = LabelSeparators::default().label(&lsp.api, book_ref, None);
+            let title = format!("Add {label} to the reading queue");
+            res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: title.clone(),
+                command: Some(Command {
+                    title,
+                    command: commands::QUEUE_PASSAGE.to_string(),
+                    arguments: Some(vec![serde_json::json!(label), serde_json::json!(uri)]),
+                }),
+                ..Default::default()
+            }));
+        }
+
+        for book_ref in refs.iter() {
+            let Some(written) = snapshot.text_at(book_ref.range) else {
+                continue;
+            };
+            let title = format!("Ignore \"{written}\" as a reference");
+            res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: title.clone(),
+                command: Some(Command {
+                    title,
+                    command: commands::IGNORE_REFERENCE.to_string(),
+                    arguments: Some(vec![serde_json::json!(written)]),
+                }),
+                ..Default::default()
+            }));
+        }
+
+        let selection = (params.range.start != params.range.end)
+            .then(|| snapshot.text_at(params.range))
+            .flatten()
+            .filter(|selected| transliterate::has_original_language(selected));
+        if let Some(selected) = selection {
+            let title = String::from("Transliterate selection");
+            res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: title.clone(),
+                command: Some(Command {
+                    title,
+                    command: commands::TRANSLITERATE.to_string(),
+                    arguments: Some(vec![
+                        serde_json::json!(selected),
+                        serde_json::json!(uri),
+                        serde_json::json!(params.range),
+                    ]),
+                }),
+                ..Default::default()
+            }));
+        }
+
+        for diagnostic in params.context.diagnostics.iter() {
+            let Some(misspelling) = spelling::Misspelling::from_diagnostic(diagnostic) else {
+                continue;
+            };
+            res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!(
+                    "Change \"{}\" to \"{}\"",
+                    misspelling.found, misspelling.suggestion
+                ),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(commands::document_edit(
+                    uri.clone(),
+                    vec![TextEdit {
+                        range: misspelling.range,
+                        new_text: misspelling.suggestion.to_string(),
+                    }],
+                )),
+                is_preferred: Some(true),
+                ..Default::default()
+            }));
+        }
+
+        for diagnostic in params.context.diagnostics.iter() {
+            let Some(stale) = quote_markers::StaleQuote::from_diagnostic(diagnostic) else {
+                continue;
+            };
+            res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Refresh the quote of {}", stale.label),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(commands::document_edit(uri.clone(), vec![stale.edit])),
+                is_preferred: Some(true),
+                ..Default::default()
+            }));
+        }
+
+        if let Some((abbreviations, edit)) = self.attribution_edit(&snapshot) {
+            let verb = match snapshot.text.contains(attribution::START_MARKER) {
+                true => "Update",
+                false => "Insert",
+            };
+            res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("{verb} {abbreviations} attribution"),
+                kind: Some(CodeActionKind::SOURCE),
+                edit: Some(edit),
+                ..Default::default()
+            }));
+        }
+
+        let tag_books = self.config.read().unwrap().book_tags.enabled;
+        if let Some(edit) = tag_books.then(|| self.book_tags_edit(&snapshot)).flatten() {
+            res.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: String::from("Update the cited books in the front matter"),
+                kind: Some(book_tags::code_action_kind()),
+                edit: Some(edit),
+                ..Default::default()
+            }));
+        }
+
+        Ok(Some(res))
+        // Ok(None)
+    }
+
+    /// What `bible.dumpState` returns, see [`commands::DUMP_STATE`]
+    async fn dump_state(&self) -> Value {
+        let lsp = self.lsp();
+        let documents: Vec<Value> = self
+            .documents
+            .snapshots()
+            .iter()
+            .map(|snapshot| {
+                serde_json::json!({
+                    "uri": snapshot.uri,
+                    "version": snapshot.version,
+                    "languageId": snapshot.language_id,
+                    "detected": snapshot.detected,
+                    "bytes": snapshot.text.len(),
+                    "large": snapshot.is_large(),
+                    "references": snapshot.references(&lsp).len(),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "status": self.status().await.ok(),
+            "logLevel": trace::level(),
+            "logFile": paths::log_file(),
+            "roots": *self.roots.read().unwrap(),
+            "documents": documents,
+        })
+    }
+
+    /// Handles the custom `bible/status` request
+    async fn status(&self) -> Result<Status> {
+        let (hover_cache_entries, hover_cache_bytes) = self.hover_cache.usage();
+        #[cfg(feature = "search")]
+        let (index_files, index_bytes, evicted_files) = self.index().usage();
+        #[cfg(not(feature = "search"))]
+        let (index_files, index_bytes, evicted_files) = (0, 0, 0);
+        let memory = MemoryUsage {
+            budget_bytes: self.memory_budget.read().unwrap().bytes(),
+            hover_cache_entries,
+            hover_cache_bytes,
+            index_files,
+            index_bytes,
+            evicted_files,
+        };
+        #[cfg(feature = "remote")]
+        let remote = Some(self.remote.read().unwrap().clone())
+            .filter(|remote| remote.abbreviations().next().is_some())
+            .map(|remote| remote.status());
+        #[cfg(not(feature = "remote"))]
+        let remote = None;
+        Ok(Status {
+            remote,
+            ..Status::new(self.lsp().translation_names(), self.documents.len(), memory)
+        })
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        #[allow(deprecated)]
+        let roots = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        let roots = roots
+            .iter()
+            .filter_map(paths::url_to_path)
+            .collect::<Vec<_>>();
+        *self.roots.write().unwrap() = roots.clone();
+        *self.client_capabilities.write().unwrap() = params.capabilities;
+        let (settings, problems) = config_file::layered(&roots, params.initialization_options);
+        for problem in problems {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("{}, skipping it", error::summary(&problem)),
+                )
+                .await;
+        }
+        let config = match Config::from_value(settings) {
+            Ok(config) => config,
+            Err(err) => {
+                self.client
+                    .show_message(
+                        MessageType::WARNING,
+                        format!("Invalid initializationOptions, using defaults: {err}"),
+                    )
+                    .await;
+                Config::default()
+            }
+        };
+        let capabilities = server_capabilities(&config);
+
+        // swapped for the daemon's shared one in `apply_config`, in case another window on the
+        // same vault already has it indexed
+        #[cfg(feature = "search")]
+        self.index().set_roots(roots);
+        self.apply_config(config, None).await;
+
+        Ok(InitializeResult {
+            capabilities,
+            server_info: Some(ServerInfo {
+                name: String::from("Bible LSP"),
+                version: Some(String::from("0.0.1α")),
+            }),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.client
+            .log_message(MessageType::INFO, "server initialized!")
+            .await;
+
+        // picks up template files as they are saved, so formats can be tweaked without a restart
+        // - stops once the client is gone, which matters when the daemon outlives it
+        let templates = Arc::downgrade(&self.templates);
+        let hover_cache = self.hover_cache.clone();
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let dir = templates::templates_dir();
+            while let Some(templates) = templates.upgrade() {
+                if let Some(problems) = templates.reload(&dir) {
+                    hover_cache.clear();
+                    client
+                        .log_message(
+                            MessageType::INFO,
+                            format!(
+                                "Loaded templates from {}: {}",
+                                dir.display(),
+                                templates.names().join(", ")
+                            ),
+                        )
+                        .await;
+                    for problem in problems {
+                        client.log_message(MessageType::WARNING, problem).await;
+                    }
+                }
+                tokio::time::sleep(templates::POLL_INTERVAL).await;
+            }
+        });
+
+        // scanning a large vault takes a while, so it shouldn't hold up anything else
+        #[cfg(feature = "search")]
+        {
+            // so bulk changes on disk (like `git checkout`) are picked up
+            let watchers = self
+                .config
+                .read()
+                .unwrap()
+                .index
+                .extensions
+                .iter()
+                .map(|extension| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/*.{extension}")),
+                    kind: None,
+                })
+                .collect();
+            let registration = Registration {
+                id: String::from("bible_lsp/watchedFiles"),
+                method: String::from("workspace/didChangeWatchedFiles"),
+                register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                    watchers,
+                })
+                .ok(),
+            };
+            if let Err(err) = self.client.register_capability(vec![registration]).await {
+                self.client
+                    .log_message(
+                        MessageType::INFO,
+                        format!("File watching unavailable, the index won't see changes made outside the editor: {err}"),
+                    )
+                    .await;
+            }
+
+            self.spawn_scan();
+        }
+    }
+
+    /**
+    Applies changed settings without a restart
+
+    - The files from [`config_file::layered`] are read again too, so edits to them are picked up
+      when the editor sends any change
+    - Clients that send `null` (the pull model) are asked for the `bible` section
+    - Features and completion trigger characters can't be changed this way, since capabilities
+      are only sent once
+    */
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let mut settings = params.settings;
+        let can_pull = self
+            .client_capabilities
+            .read()
+            .unwrap()
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        if settings.is_null() && can_pull {
+            let item = ConfigurationItem {
+                scope_uri: None,
+                section: Some(String::from("bible")),
+            };
+            if let Ok(mut values) = self.client.configuration(vec![item]).await {
+                if !values.is_empty() {
+                    // already the section, so it's wrapped again for `Config::from_value`
+                    settings = serde_json::json!({ "bible": values.swap_remove(0) });
+                }
+            }
+        }
+        let roots = self.roots.read().unwrap().clone();
+        let (settings, problems) = config_file::layered(&roots, Some(settings));
+        for problem in problems {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("{}, skipping it", error::summary(&problem)),
+                )
+                .await;
+        }
+        let config = match Config::from_value(settings) {
+            Ok(config) => config,
+            Err(err) => {
+                self.client
+                    .show_message(
+                        MessageType::WARNING,
+                        format!("Invalid settings, keeping the current ones: {err}"),
+                    )
+                    .await;
+                return;
+            }
+        };
+        let previous = self.config.read().unwrap().clone();
+        if config.features != previous.features
+            || config.completion.trigger_characters != previous.completion.trigger_characters
+        {
+            self.client
+                .show_message(
+                    MessageType::INFO,
+                    "Restart the server to turn features on or off or change completion trigger characters",
+                )
+                .await;
+        }
+        #[cfg(feature = "search")]
+        let fields_changed = config.front_matter_fields != previous.front_matter_fields;
+        #[cfg(feature = "search")]
+        let rescan = config.index.extensions != previous.index.extensions
+            || config.ignore_globs != previous.ignore_globs
+            || fields_changed;
+        #[cfg(feature = "search")]
+        let before = self.index();
+        let loaded = self.apply_config(config, Some(&previous)).await;
+        #[cfg(feature = "search")]
+        let switched = !Arc::ptr_eq(&before, &self.index());
+        #[cfg(feature = "search")]
+        if loaded || rescan || switched {
+            // labels depend on the translation's book names, and which references are front
+            // matter on the fields, so nothing indexed can be kept, unless the daemon swapped in
+            // another index for the new settings
+            if (loaded || fields_changed) && !switched {
+                self.index().clear();
+            }
+            self.spawn_scan();
+        }
+        #[cfg(not(feature = "search"))]
+        let _ = loaded;
+        self.refresh_client().await;
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let TextDocumentItem {
+            text,
+            uri,
+            version,
+            language_id,
+        } = params.text_document;
+        self.documents.open(uri.clone(), language_id, version, text);
+        #[cfg(feature = "search")]
+        if let Some(snapshot) = self
+            .documents
+            .get(&uri)
+            .filter(|snapshot| snapshot.detected)
+        {
+            self.reindex(&snapshot);
+        }
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let VersionedTextDocumentIdentifier { uri, version } = params.text_document;
+        // full sync, so only the last change matters
+        if let Some(change) = params.content_changes.into_iter().last() {
+            self.documents.update(uri.clone(), version, change.text);
+        }
+        #[cfg(feature = "search")]
+        if let Some(snapshot) = self
+            .documents
+            .get(&uri)
+            .filter(|snapshot| snapshot.detected)
+        {
+            self.reindex(&snapshot);
+        }
+    }
+
+    /// - Refreshes generated sections, see [`Config::refresh_on_save`]
+    /// - Rewrites references, see [`Config::autocorrect_on_save`]
+    async fn will_save_wait_until(
+        &self,
+        params: WillSaveTextDocumentParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let (autocorrect, refresh, separators) = {
+            let config = self.config.read().unwrap();
+            if params.reason != TextDocumentSaveReason::MANUAL {
+                return Ok(None);
+            }
+            (
+                config.autocorrect_on_save,
+                config.refresh_on_save,
+                config.label_separators.clone(),
+            )
+        };
+        if !autocorrect && !refresh {
+            return Ok(None);
+        }
+        let lsp = self.lsp();
+        let uri = params.text_document.uri;
+        let Some(snapshot) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        let mut edits = vec![];
+        if refresh {
+            edits.extend(self.scripture_index_edit(&snapshot, false));
+            let quotes = quote_markers::changed_quotes(&lsp, &snapshot, |book_ref, formatter| {
+                self.render_quote(&lsp, &uri, book_ref, formatter, None)
+            });
+            edits.extend(quotes.into_iter().map(|stale| stale.edit));
+        }
+        if !autocorrect {
+            return Ok(Some(edits));
+        }
+        // references in what was just regenerated, or in the index, are already written out
+        let regenerated: Vec<Range> = edits.iter().map(|edit| edit.range).collect();
+        let corrections: Vec<TextEdit> = snapshot
+            .references(&lsp)
+            .iter()
+            .filter(|book_ref| {
+                !regenerated.iter().any(|range| {
+                    range.start <= book_ref.range.end && book_ref.range.start <= range.end
+                }) && !scripture_index::contains(
+                    &snapshot.text,
+                    &snapshot.line_index,
+                    book_ref.range,
+                )
+            })
+            .filter_map(|book_ref| {
+                let written = snapshot.text_at(book_ref.range)?;
+                let label = separators.label(&lsp.api, book_ref, Some(written));
+                (label != written).then_some(TextEdit {
+                    range: book_ref.range,
+                    new_text: label,
+                })
+            })
+            .collect();
+        edits.extend(corrections);
+        Ok(Some(edits))
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.close(&uri);
+        // unsaved edits are gone, so go back to what is on disk
+        #[cfg(feature = "search")]
+        if self.index().contains_path(&uri) {
+            self.index().index_file(&self.lsp(), uri);
+        }
+    }
+
+    /// - Open documents are re-indexed right away from what the editor has
+    /// - Everything else goes on the queue
+    #[cfg(feature = "search")]
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        for FileEvent { uri, typ } in params.changes {
+            if !self.index().contains_path(&uri) {
+                continue;
+            }
+            if let Some(snapshot) = self.documents.get(&uri) {
+                self.reindex(&snapshot);
+            } else if typ == FileChangeType::DELETED {
+                self.index().remove(&uri);
+            } else {
+                self.reindex_queue.push(uri);
+            }
+        }
+        self.spawn_reindex();
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        self.hover_in(params, None).await
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        self.completion_in(params, None).await
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let doc = params.text_document;
+        let Some(snapshot) = self.documents.get(&doc.uri) else {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "{} is not open",
+                doc.uri
+            )));
+        };
+        let report = self
+            .diagnostic_report(&snapshot, params.previous_result_id.as_deref())
+            .await;
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    /**
+    Diagnostics for every open document, for clients that pull them for the whole workspace
+
+    - Closed files are left out, since reading the whole workspace on every pull would cost more
+      than the diagnostics are worth
+    - Documents whose previous result id still matches are reported unchanged
+    */
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let mut items = vec![];
+        for snapshot in self.documents.snapshots() {
+            let previous = params
+                .previous_result_ids
+                .iter()
+                .find(|previous| previous.uri == snapshot.uri)
+                .map(|previous| previous.value.as_str());
+            let uri = snapshot.uri.clone();
+            let version = Some(snapshot.version as i64);
+            items.push(match self.diagnostic_report(&snapshot, previous).await {
+                DocumentDiagnosticReport::Full(report) => {
+                    WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                        uri,
+                        version,
+                        full_document_diagnostic_report: report.full_document_diagnostic_report,
+                    })
+                }
+                DocumentDiagnosticReport::Unchanged(report) => {
+                    WorkspaceDocumentDiagnosticReport::Unchanged(
+                        WorkspaceUnchangedDocumentDiagnosticReport {
+                            uri,
+                            version,
+                            unchanged_document_diagnostic_report: report
+                                .unchanged_document_diagnostic_report,
+                        },
+                    )
+                }
+            });
+        }
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    // see /home/dgmastertemple/Development/rust/scripture_lsp/src/main.rs line 233
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        self.goto_definition_in(params, None).await
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let lsp = self.lsp();
+        let uri = params.text_document.uri;
+        let Some(document) = VirtualDocument::from_uri(&lsp.api, &uri) else {
+            return Ok(None);
+        };
+        let Some(snapshot) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        Ok(Some(document.document_links(&lsp.api, &snapshot.text)))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        self.code_action_in(params, None).await
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let lsp = self.lsp();
+        let features = self.config.read().unwrap().features.clone();
+        if !features.inlay_hints && !features.citation_counts {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+        let Some(snapshot) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        let visible_lines = params.range.start.line..params.range.end.line.saturating_add(1);
+        let deadline = Deadline::after_millis(self.config.read().unwrap().timeouts.inlay_hints);
+        let mode = self.config.read().unwrap().inlay_hints.mode;
+        let references = snapshot.references_in_lines(&lsp, visible_lines);
+        let mut hints = vec![];
+        for (idx, book_ref) in references.iter().enumerate() {
+            if deadline.expired() {
+                let left_out = format!("hints for {} references", references.len() - idx);
+                self.log_timeout("inlayHint", &deadline, &left_out).await;
+                break;
+            }
+            if features.inlay_hints && mode == config::InlayHintMode::Canonical {
+                let canonical = LabelSeparators::canonical().label(&lsp.api, book_ref, None);
+                if snapshot.text_at(book_ref.range) != Some(canonical.as_str()) {
+                    hints.push(InlayHint {
+                        position: book_ref.range.end,
+                        label: InlayHintLabel::String(canonical.clone()),
+                        kind: None,
+                        text_edits: Some(vec![TextEdit {
+                            range: book_ref.range,
+                            new_text: canonical,
+                        }]),
+                        tooltip: Some(InlayHintTooltip::MarkupContent(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: book_ref.format(&lsp.api),
+                        })),
+                        padding_left: Some(true),
+                        padding_right: Some(true),
+                        data: None,
+                    });
+                }
+            } else if features.inlay_hints {
+                if let Some(label) = book_ref.format_diagnostic(&lsp.api, None) {
+                    hints.push(InlayHint {
+                        position: book_ref.range.end,
+                        label: InlayHintLabel::String(label),
+                        kind: None,
+                        text_edits: None,
+                        tooltip: Some(InlayHintTooltip::MarkupContent(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: book_ref.format(&lsp.api),
+                        })),
+                        padding_left: Some(true),
+                        padding_right: Some(true),
+                        data: None,
+                    });
+                }
+            }
+            #[cfg(feature = "search")]
+            if features.citation_counts {
+                let indexed = workspace_index::IndexedReference::new(&lsp, book_ref);
+                let count = backlinks::citing(&self.index().files(), &uri, &indexed).len();
+                if count > 0 {
+                    hints.push(InlayHint {
+                        position: book_ref.range.end,
+                        label: InlayHintLabel::String(format!("cited {count}× in workspace")),
+                        kind: None,
+                        text_edits: None,
+                        tooltip: None,
+                        padding_left: Some(true),
+                        padding_right: Some(true),
+                        // for listing the documents in `inlay_hint_resolve`
+                        data: serde_json::to_value((&uri, &indexed)).ok(),
+                    });
+                }
+            }
+        }
+        Ok(Some(hints))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        if !self.config.read().unwrap().features.semantic_tokens {
+            return Ok(None);
+        }
+        let Some(snapshot) = self.documents.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        let lines = params.range.start.line..params.range.end.line.saturating_add(1);
+        let references = snapshot.references_in_lines(&self.lsp(), lines);
+        Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: semantic_tokens::tokens(&snapshot, &references),
+        })))
+    }
+
+    /// Lists the places behind a citation count, which is only worth doing for hovered hints
+    async fn inlay_hint_resolve(&self, hint: InlayHint) -> Result<InlayHint> {
+        #[cfg(feature = "search")]
+        let hint = {
+            let mut hint = hint;
+            let data = hint.data.clone().and_then(|data| {
+                serde_json::from_value::<(Url, workspace_index::IndexedReference)>(data).ok()
+            });
+            if let Some((uri, indexed)) = data {
+                let places: Vec<String> = backlinks::citing(&self.index().files(), &uri, &indexed)
+                    .into_iter()
+                    .map(|(other_uri, other)| {
+                        format!(
+                            "- {}: {}",
+                            self.index().display_name(&other_uri),
+                            other.label
+                        )
+                    })
+                    .collect();
+                hint.tooltip = Some(InlayHintTooltip::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: places.join("\n"),
+                }));
+            }
+            hint
+        };
+        Ok(hint)
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let uri = params.text_document.uri;
+        let Some(snapshot) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        if let Some(document) = VirtualDocument::from_uri(&self.lsp().api, &uri) {
+            return Ok(Some(document.folding_ranges(&snapshot.text)));
+        }
+        // the prefixes of every formatter that writes a verse per line
+        let prefixes: Vec<String> = self
+            .formatter_names()
+            .iter()
+            .filter_map(|name| self.formatter(name)?.verse_per_line)
+            .collect();
+        Ok(Some(folding::folding_ranges(&snapshot, &prefixes)))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let lsp = self.lsp();
+        let doc = params.text_document;
+        let Some(snapshot) = self.documents.get(&doc.uri) else {
+            return Ok(None);
+        };
+
+        // generated chapters get an outline of their verses instead of references
+        if let Some(document) = VirtualDocument::from_uri(&lsp.api, &doc.uri) {
+            return Ok(Some(DocumentSymbolResponse::Nested(
+                document.document_symbols(&lsp.api, &snapshot.text),
+            )));
+        }
+
+        // let mut symbols: Vec<Diagnostic> = Vec::new();
+        let timeout = self.config.read().unwrap().timeouts.document_symbols;
+        let deadline = Deadline::after_millis(timeout);
+        let (references, complete) = snapshot.references_before(&lsp, &deadline);
+        let symbols = references
+            .iter()
+            .map(|book_ref| SymbolInformation {
+                name: book_ref.full_ref_label(&lsp.api),
+                kind: SymbolKind::KEY,
+                location: Location {
+                    uri: doc.uri.clone(),
+                    range: book_ref.range,
+                },
+                tags: None,
+                deprecated: None,
+                container_name: None,
+            })
+            .collect::<Vec<_>>();
+        if !complete {
+            self.log_timeout(
+                "documentSymbol",
+                &deadline,
+                "references past what was parsed",
+            )
+            .await;
+        }
+        Ok(Some(DocumentSymbolResponse::Flat(symbols)))
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        match params.command.as_str() {
+            commands::INSERT_ATTRIBUTION => {
+                let uri: Url = commands::argument(&params.arguments, 0)?;
+                let Some(snapshot) = self.documents.get(&uri) else {
+                    return Ok(None);
+                };
+                if let Some((_, edit)) = self.attribution_edit(&snapshot) {
+                    self.client.apply_edit(edit).await?;
+                }
+                Ok(None)
+            }
+            commands::UPDATE_BOOK_TAGS => {
+                let uri: Url = commands::argument(&params.arguments, 0)?;
+                let Some(snapshot) = self.documents.get_or_read(&uri) else {
+                    return Ok(None);
+                };
+                if let Some(edit) = self.book_tags_edit(&snapshot) {
+                    self.client.apply_edit(edit).await?;
+                }
+                Ok(None)
+            }
+            commands::INSERT_SCRIPTURE_INDEX => {
+                let uri: Url = commands::argument(&params.arguments, 0)?;
+                let Some(snapshot) = self.documents.get_or_read(&uri) else {
+                    return Ok(None);
+                };
+                if let Some(edit) = self.scripture_index_edit(&snapshot, true) {
+                    self.client
+                        .apply_edit(commands::document_edit(uri, vec![edit]))
+                        .await?;
+                }
+                Ok(None)
+            }
+            commands::OPEN_CHAPTER => {
+                let book_id: usize = commands::argument(&params.arguments, 0)?;
+                let chapter: usize = commands::argument(&params.arguments, 1)?;
+                let lsp = self.lsp();
+                let document = VirtualDocument::chapter(&lsp.api, book_id, chapter);
+                let uri = document
+                    .materialize(&lsp.api, &headings::Headings::default())
+                    .map_err(|err| tower_lsp::jsonrpc::Error::invalid_params(err.to_string()))?;
+                self.client
+                    .show_document(ShowDocumentParams {
+                        uri: uri.clone(),
+                        external: None,
+                        take_focus: Some(true),
+                        selection: None,
+                    })
+                    .await?;
+                Ok(Some(serde_json::json!(uri)))
+            }
+            commands::OPEN_BOOK_INTRO => {
+                let book_id: usize = commands::argument(&params.arguments, 0)?;
+                let lsp = self.lsp();
+                let document = VirtualDocument::intro(&lsp.api, book_id);
+                let uri = document
+                    .materialize(&lsp.api, &self.headings(&lsp))
+                    .map_err(|err| tower_lsp::jsonrpc::Error::invalid_params(err.to_string()))?;
+                self.client
+                    .show_document(ShowDocumentParams {
+                        uri: uri.clone(),
+                        external: None,
+                        take_focus: Some(true),
+                        selection: None,
+                    })
+                    .await?;
+                Ok(Some(serde_json::json!(uri)))
+            }
+            commands::DUMP_STATE => {
+                let state = self.dump_state().await;
+                append_log(format!("{}: {state:#}", commands::DUMP_STATE));
+                Ok(Some(state))
+            }
+            commands::SET_TRANSLATION => {
+                let path: PathBuf = commands::argument(&params.arguments, 0)?;
+                self.set_translation(path).await.map(Some)
+            }
+            commands::QUEUE_PASSAGE => {
+                let label: String = commands::argument(&params.arguments, 0)?;
+                let source: Option<Url> = commands::argument(&params.arguments, 1)?;
+                let added = self.change_reading_queue(|queue| {
+                    queue.push(reading_queue::QueuedPassage { label, source })
+                })?;
+                Ok(Some(serde_json::json!(added)))
+            }
+            commands::IGNORE_REFERENCE => {
+                let phrase: String = commands::argument(&params.arguments, 0)?;
+                let added = self
+                    .change_ignored_phrases(|ignored| ignored.add(&phrase))
+                    .await?;
+                Ok(Some(serde_json::json!(added)))
+            }
+            commands::IGNORED_REFERENCES => {
+                let config = self.config.read().unwrap().clone();
+                let path = self.ignored_phrases_path(&config);
+                let ignored = ignored_phrases::IgnoredPhrases::load(&path)
+                    .map_err(|err| tower_lsp::jsonrpc::Error::invalid_params(err.to_string()))?;
+                Ok(Some(
+                    serde_json::json!({ "path": path, "phrases": ignored.phrases }),
+                ))
+            }
+            commands::UNIGNORE_REFERENCES => {
+                let phrase: Option<String> = commands::argument(&params.arguments, 0)?;
+                let removed = self
+                    .change_ignored_phrases(|ignored| ignored.remove(phrase.as_deref()))
+                    .await?;
+                Ok(Some(serde_json::json!(removed)))
+            }
+            commands::READING_QUEUE => {
+                let queue = reading_queue::ReadingQueue::load(&self.reading_queue_path())
+                    .map_err(|err| tower_lsp::jsonrpc::Error::invalid_params(err.to_string()))?;
+                let uri = queue
+                    .materialize()
+                    .map_err(|err| tower_lsp::jsonrpc::Error::invalid_params(err.to_string()))?;
+                Ok(Some(
+                    serde_json::json!({ "uri": uri, "passages": queue.passages }),
+                ))
+            }
+            commands::POP_READING_QUEUE => {
+                let popped = self.change_reading_queue(|queue| queue.pop())?;
+                Ok(Some(serde_json::json!(popped)))
+            }
+            commands::OUTLINE_PASSAGE => {
+                let passage: String = commands::argument(&params.arguments, 0)?;
+                let uri: Option<Url> = commands::argument(&params.arguments, 1)?;
+                let position: Option<Position> = commands::argument(&params.arguments, 2)?;
+                let lsp = self.lsp();
+                let Some((book_id, start, end)) = headings::passage_span(&lsp, &passage) else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                        "No passage in {passage}"
+                    )));
+                };
+                let path = self
+                    .config
+                    .read()
+                    .unwrap()
+                    .headings_file
+                    .clone()
+                    .unwrap_or_else(headings::default_path);
+                let mut dataset = headings::Headings::load(&lsp, &path)
+                    .map_err(|err| tower_lsp::jsonrpc::Error::invalid_params(err.to_string()))?;
+                if dataset.is_empty() {
+                    dataset = headings::Headings::of_translation(&lsp.api);
+                }
+                if dataset.is_empty() {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                        "No section headings in {} or in {}",
+                        path.display(),
+                        lsp.api.translation.abbreviation
+                    )));
+                }
+                let sections = dataset.sections(&lsp.api, book_id, start, end);
+                let Some(text) = headings::outline(&lsp.api, &sections) else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                        "No section headings cover {passage}"
+                    )));
+                };
+                if let (Some(uri), Some(position)) = (uri, position) {
+                    let edit = TextEdit {
+                        range: Range {
+                            start: position,
+                            end: position,
+                        },
+                        new_text: format!("{text}\n"),
+                    };
+                    self.client
+                        .apply_edit(commands::document_edit(uri, vec![edit]))
+                        .await?;
+                }
+                let sections: Vec<serde_json::Value> = sections
+                    .iter()
+                    .map(|(heading, book_ref)| {
+                        serde_json::json!({
+                            "heading": heading,
+                            "label": book_ref.full_ref_label(&lsp.api),
+                        })
+                    })
+                    .collect();
+                Ok(Some(serde_json::json!({
+                    "label": headings::span_reference(book_id, start, end).full_ref_label(&lsp.api),
+                    "sections": sections,
+                    "text": text,
+                })))
+            }
+            commands::TRANSLITERATE => {
+                let text: String = commands::argument(&params.arguments, 0)?;
+                let uri: Option<Url> = commands::argument(&params.arguments, 1)?;
+                let range: Option<Range> = commands::argument(&params.arguments, 2)?;
+                let lsp = self.lsp();
+                let original = match transliterate::has_original_language(&text) {
+                    true => text.clone(),
+                    // references, in whichever translation they name
+                    false => lsp
+                        .find_book_references(&text)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|book_ref| {
+                            book_ref.format_content(lsp.api_for(book_ref.translation.as_deref()))
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                };
+                if !transliterate::has_original_language(&original) {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                        "No Greek or Hebrew in {text}"
+                    )));
+                }
+                let transliterated = transliterate::transliterate(&original);
+                if let (Some(uri), Some(range)) = (uri, range) {
+                    let edit = TextEdit {
+                        range,
+                        new_text: transliterated.clone(),
+                    };
+                    self.client
+                        .apply_edit(commands::document_edit(uri, vec![edit]))
+                        .await?;
+                }
+                Ok(Some(serde_json::json!(transliterated)))
+            }
+            commands::COPY_PASSAGE => {
+                let passage: String = commands::argument(&params.arguments, 0)?;
+                let name: Option<String> = commands::argument(&params.arguments, 1)?;
+                let name = name.unwrap_or_else(|| String::from("insert"));
+                let Some(formatter) = self.formatter(&name) else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                        "No formatter named {name}"
+                    )));
+                };
+                let lsp = self.lsp();
+                let refs = lsp.find_book_references(&passage).unwrap_or_default();
+                if refs.is_empty() {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                        "No passage in {passage}"
+                    )));
+                }
+                #[cfg(feature = "remote")]
+                let lsp = self.with_remote(lsp, &refs, None).await?;
+                let refusal = refs.iter().find_map(|book_ref| {
+                    self.insert_refusal(&lsp, book_ref, book_ref.translation.as_deref())
+                });
+                if let Some(reason) = refusal {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(reason));
+                }
+                let (labels, texts): (Vec<String>, Vec<String>) = refs
+                    .iter()
+                    .map(|book_ref| {
+                        // one written after the reference wins
+                        let api = lsp.api_for(book_ref.translation.as_deref());
+                        let context = bible_formatter::FormatContext::new(api, "");
+                        (
+                            formatter.label.label(api, book_ref),
+                            formatter.format(api, book_ref, &context),
+                        )
+                    })
+                    .unzip();
+                Ok(Some(serde_json::json!({
+                    "label": labels.join("; "),
+                    "text": texts.join("\n\n"),
+                })))
+            }
+            commands::DOWNLOAD_TRANSLATION => {
+                let abbreviation: String = commands::argument(&params.arguments, 0)?;
+                #[cfg(feature = "remote")]
+                {
+                    let source = self.config.read().unwrap().download_source.clone();
+                    let path = tokio::task::spawn_blocking(move || {
+                        crate::scaffold::install_translation(
+                            &source,
+                            &abbreviation,
+                            &paths::translations_dir(),
+                        )
+                    })
+                    .await
+                    .map_err(|err| err.to_string())
+                    .and_then(|installed| installed.map_err(|err| err.to_string()))
+                    .map_err(|message| tower_lsp::jsonrpc::Error {
+                        code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+                        message: message.into(),
+                        data: None,
+                    })?;
+                    self.set_translation(path).await.map(Some)
+                }
+                #[cfg(not(feature = "remote"))]
+                {
+                    let _ = abbreviation;
+                    Err(status::feature_disabled("remote"))
+                }
+            }
+            commands::EXPORT_CACHE => {
+                let translation: String = commands::argument(&params.arguments, 0)?;
+                let path: PathBuf = commands::argument(&params.arguments, 1)?;
+                #[cfg(feature = "remote")]
+                {
+                    self.export_cache(&translation, &path).map(Some)
+                }
+                #[cfg(not(feature = "remote"))]
+                {
+                    let _ = (translation, path);
+                    Err(status::feature_disabled("remote"))
+                }
+            }
+            commands::COMPLETION_ACCEPTED => {
+                let book_id: usize = commands::argument(&params.arguments, 0)?;
+                self.recent_books.record(book_id);
+                Ok(None)
+            }
+            commands::NEXT_VERSE => {
+                self.navigate_verse(&params.arguments, verse_navigation::Direction::Next)
+                    .await
+            }
+            commands::PREV_VERSE => {
+                self.navigate_verse(&params.arguments, verse_navigation::Direction::Previous)
+                    .await
+            }
+            commands::REFRESH_QUOTES => {
+                let uri: Url = commands::argument(&params.arguments, 0)?;
+                let Some(snapshot) = self.documents.get(&uri) else {
+                    return Ok(None);
+                };
+                let lsp = self.lsp();
+                let edits = quote_markers::refresh_edits(&lsp, &snapshot, |book_ref, formatter| {
+                    self.render_quote(&lsp, &uri, book_ref, formatter, None)
+                });
+                let refreshed = edits.len();
+                if !edits.is_empty() {
+                    self.client
+                        .apply_edit(commands::document_edit(uri, edits))
+                        .await?;
+                }
+                Ok(Some(Value::from(refreshed)))
+            }
+            commands::RETARGET_REFERENCES => {
+                let uri: Url = commands::argument(&params.arguments, 0)?;
+                let to: versification::PsalmNumbering = commands::argument(&params.arguments, 1)?;
+                let from: Option<versification::PsalmNumbering> =
+                    commands::argument(&params.arguments, 2)?;
+                let from = from.unwrap_or(self.config.read().unwrap().psalm_numbering);
+                let Some(snapshot) = self.documents.get(&uri) else {
+                    return Ok(None);
+                };
+                let edits = versification::retarget_edits(&self.lsp(), &snapshot, from, to);
+                let retargeted = edits.len();
+                if !edits.is_empty() {
+                    let support = self
+                        .client_capabilities
+                        .read()
+                        .unwrap()
+                        .workspace
+                        .as_ref()
+                        .and_then(|workspace| workspace.workspace_edit.clone())
+                        .unwrap_or_default();
+                    let annotated = support.change_annotation_support.is_some();
+                    let edit = if !support.document_changes.unwrap_or(false) {
+                        // a plain map for clients that only take that, without the version check
+                        WorkspaceEdit {
+                            changes: Some([(uri, edits)].into()),
+                            ..Default::default()
+                        }
+                    } else {
+                        let annotation = String::from("retarget");
+                        WorkspaceEdit {
+                            changes: None,
+                            document_changes: Some(DocumentChanges::Edits(vec![
+                                TextDocumentEdit {
+                                    text_document: OptionalVersionedTextDocumentIdentifier {
+                                        uri,
+                                        version: Some(snapshot.version),
+                                    },
+                                    edits: edits
+                                        .into_iter()
+                                        .map(|text_edit| match annotated {
+                                            true => OneOf::Right(AnnotatedTextEdit {
+                                                text_edit,
+                                                annotation_id: annotation.clone(),
+                                            }),
+                                            false => OneOf::Left(text_edit),
+                                        })
+                                        .collect(),
+                                },
+                            ])),
+                            // so the user confirms renumbering, where the client can ask
+                            change_annotations: annotated.then(|| {
+                                [(
+                                    annotation,
+                                    ChangeAnnotation {
+                                        label: format!("Renumber {retargeted} Psalm citations"),
+                                        needs_confirmation: Some(true),
+                                        description: Some(format!("{from:?} to {to:?} numbering")),
+                                    },
+                                )]
+                                .into()
+                            }),
+                        }
+                    };
+                    self.client.apply_edit(edit).await?;
+                }
+                Ok(Some(Value::from(retargeted)))
+            }
+            commands::CHECK_QUOTES => {
+                let apply: Option<bool> = commands::argument(&params.arguments, 0)?;
+                #[cfg(feature = "search")]
+                {
+                    let lsp = self.lsp();
+                    let uris: Vec<Url> = self.index().files().keys().cloned().collect();
+                    let (mut checked, mut skipped) = (0, 0);
+                    let mut changed = vec![];
+                    let mut edits = vec![];
+                    for uri in uris {
+                        let Some(snapshot) = self.documents.get_or_read(&uri) else {
+                            continue;
+                        };
+                        for quote in quote_markers::find_quotes(&snapshot) {
+                            match quote
+                                .translation
+                                .eq_ignore_ascii_case(&lsp.api.translation.abbreviation)
+                            {
+                                true => checked += 1,
+                                false => skipped += 1,
+                            }
+                        }
+                        let stale = quote_markers::changed_quotes(
+                            &lsp,
+                            &snapshot,
+                            |book_ref, formatter| {
+                                self.render_quote(&lsp, &uri, book_ref, formatter, None)
+                            },
+                        );
+                        if stale.is_empty() {
+                            continue;
+                        }
+                        let name = self.index().display_name(&uri);
+                        changed.extend(stale.iter().map(|stale| {
+                            serde_json::json!({
+                                "uri": uri,
+                                "name": name,
+                                "label": stale.label,
+                                "translation": stale.translation,
+                                "range": stale.edit.range,
+                            })
+                        }));
+                        edits.push((uri, stale.into_iter().map(|stale| stale.edit).collect()));
+                    }
+                    let edit = commands::documents_edit(edits);
+                    if apply.unwrap_or(false) && !changed.is_empty() {
+                        self.client.apply_edit(edit.clone()).await?;
+                    }
+                    Ok(Some(serde_json::json!({
+                        "checked": checked,
+                        "skipped": skipped,
+                        "changed": changed,
+                        "edit": edit,
+                    })))
+                }
+                #[cfg(not(feature = "search"))]
+                {
+                    let _ = apply;
+                    Err(status::feature_disabled("search"))
+                }
+            }
+            commands::UPDATE_QUOTED_TRANSLATION => {
+                let from: Option<String> = commands::argument(&params.arguments, 0)?;
+                let apply: Option<bool> = commands::argument(&params.arguments, 1)?;
+                let Some(from) = from.or_else(|| self.previous_translation.read().unwrap().clone())
+                else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                        "The translation hasn't been switched, so say which one to update from",
+                    ));
+                };
+                let lsp = self.lsp();
+                // open documents too, since switching translations indexes the workspace again
+                let uris: BTreeSet<Url> = self
+                    .documents
+                    .snapshots()
+                    .iter()
+                    .map(|snapshot| snapshot.uri.clone())
+                    .collect();
+                #[cfg(feature = "search")]
+                let uris = {
+                    let mut uris = uris;
+                    uris.extend(self.index().files().keys().cloned());
+                    uris
+                };
+                let mut documents = vec![];
+                let mut edits = vec![];
+                for uri in uris {
+                    let Some(snapshot) = self.documents.get_or_read(&uri) else {
+                        continue;
+                    };
+                    let mut document_edits: Vec<TextEdit> =
+                        quote_markers::stale_quotes(&lsp, &snapshot, |book_ref, formatter| {
+                            self.render_quote(&lsp, &uri, book_ref, formatter, None)
+                        })
+                        .into_iter()
+                        .filter(|stale| stale.translation.eq_ignore_ascii_case(&from))
+                        .map(|stale| stale.edit)
+                        .collect();
+                    let quotes = document_edits.len();
+                    // only blocks that are already there, this isn't the place to add them
+                    let attribution = snapshot
+                        .text
+                        .contains(attribution::START_MARKER)
+                        .then(|| {
+                            let attributions: Vec<String> = self
+                                .attributions(&lsp, &snapshot, Some(&from))
+                                .into_iter()
+                                .map(|(_, attribution)| attribution)
+                                .collect();
+                            attribution::attribution_edit(&snapshot.text, &attributions)
+                        })
+                        .flatten();
+                    let attributed = attribution.is_some();
+                    document_edits.extend(attribution);
+                    if document_edits.is_empty() {
+                        continue;
+                    }
+                    #[cfg(feature = "search")]
+                    let name = self.index().display_name(&uri);
+                    #[cfg(not(feature = "search"))]
+                    let name = match crate::paths::url_to_path(&uri) {
+                        Some(path) => path.to_string_lossy().replace('\\', "/"),
+                        None => uri.to_string(),
+                    };
+                    documents.push(serde_json::json!({
+                        "uri": uri,
+                        "name": name,
+                        "quotes": quotes,
+                        "attribution": attributed,
+                    }));
+                    edits.push((uri, document_edits));
+                }
+                let edit = commands::documents_edit(edits);
+                if apply.unwrap_or(true) && !documents.is_empty() {
+                    self.client.apply_edit(edit.clone()).await?;
+                }
+                Ok(Some(serde_json::json!({
+                    "from": from,
+                    "to": lsp.api.translation.abbreviation,
+                    "documents": documents,
+                    "edit": edit,
+                })))
+            }
+            commands::LINT_TEMPLATE => {
+                let formatter: Option<Value> = commands::argument(&params.arguments, 0)?;
+                let formatters: Vec<(String, bible_formatter::PassageFormatter)> = match formatter {
+                    None => {
+                        let mut names: Vec<String> = self
+                            .config
+                            .read()
+                            .unwrap()
+                            .formatters
+                            .keys()
+                            .cloned()
+                            .collect();
+                        names.extend(self.templates.names());
+                        names.sort();
+                        names.dedup();
+                        names
+                            .into_iter()
+                            .filter_map(|name| Some((name.clone(), self.formatter(&name)?)))
+                            .collect()
+                    }
+                    Some(Value::String(name)) => match self.formatter(&name) {
+                        Some(formatter) => vec![(name, formatter)],
+                        None => {
+                            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                                "Unknown formatter {name}"
+                            )))
+                        }
+                    },
+                    Some(_) => vec![(
+                        String::from("formatter"),
+                        commands::argument(&params.arguments, 0)?,
+                    )],
+                };
+                let problems: serde_json::Map<String, Value> = formatters
+                    .into_iter()
+                    .map(|(name, formatter)| (name, formatter.lint()))
+                    .filter(|(_, problems)| !problems.is_empty())
+                    .map(|(name, problems)| (name, Value::from(problems)))
+                    .collect();
+                Ok(Some(Value::Object(problems)))
+            }
+            commands::RESOLVE_ID => {
+                let text: String = commands::argument(&params.arguments, 0)?;
+                let lsp = self.lsp();
+                let resolved = |id: String, book_ref: &BookReference| serde_json::json!({ "id": id, "reference": book_ref.full_ref_label(&lsp.api) });
+                let results: Vec<Value> = match text.parse::<verse_id::VerseId>() {
+                    Ok(id) => id
+                        .to_book_reference(&lsp.api)
+                        .map(|book_ref| resolved(id.to_string(), &book_ref))
+                        .into_iter()
+                        .collect(),
+                    Err(_) => lsp
+                        .find_book_references(&text)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|book_ref| {
+                            let id = verse_id::VerseId::new(&lsp.api, book_ref);
+                            resolved(id.to_string(), book_ref)
+                        })
+                        .collect(),
+                };
+                Ok(Some(Value::from(results)))
+            }
+            commands::BACKLINKS => {
+                let uri: Url = commands::argument(&params.arguments, 0)?;
+                #[cfg(feature = "search")]
+                {
+                    let backlinks = backlinks::backlinks(&self.index(), &uri);
+                    Ok(Some(serde_json::to_value(backlinks).unwrap_or_default()))
+                }
+                #[cfg(not(feature = "search"))]
+                {
+                    let _ = uri;
+                    Err(status::feature_disabled("search"))
+                }
+            }
+            commands::NOTES_BY_PASSAGE => {
+                let text: String = commands::argument(&params.arguments, 0)?;
+                let field: Option<String> = commands::argument(&params.arguments, 1)?;
+                #[cfg(feature = "search")]
+                {
+                    let lsp = self.lsp();
+                    let (references, _) = front_matter::value_references(&lsp, &text);
+                    if references.is_empty() {
+                        return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                            "No passage in {text}"
+                        )));
+                    }
+                    let passages: Vec<workspace_index::IndexedReference> = references
+                        .iter()
+                        .map(|book_ref| workspace_index::IndexedReference::new(&lsp, book_ref))
+                        .collect();
+                    let notes =
+                        backlinks::notes_by_passage(&self.index(), &passages, field.as_deref());
+                    Ok(Some(serde_json::to_value(notes).unwrap_or_default()))
+                }
+                #[cfg(not(feature = "search"))]
+                {
+                    let _ = (text, field);
+                    Err(status::feature_disabled("search"))
+                }
+            }
+            commands::EXPORT_GRAPH => {
+                let format: Option<String> = commands::argument(&params.arguments, 0)?;
+                #[cfg(feature = "search")]
+                {
+                    let format = match format.as_deref() {
+                        None | Some("json") => reference_graph::GraphFormat::Json,
+                        Some("dot") => reference_graph::GraphFormat::Dot,
+                        Some(other) => {
+                            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                                "Unknown graph format {other}, expected \"dot\" or \"json\""
+                            )))
+                        }
+                    };
+                    let graph = reference_graph::ReferenceGraph::build(&self.index());
+                    Ok(Some(graph.export(format)))
+                }
+                #[cfg(not(feature = "search"))]
+                {
+                    let _ = format;
+                    Err(status::feature_disabled("search"))
+                }
+            }
+            commands::COVERAGE => {
+                let format: Option<String> = commands::argument(&params.arguments, 0)?;
+                #[cfg(feature = "search")]
+                {
+                    let format = match format.as_deref() {
+                        None | Some("json") => coverage::CoverageFormat::Json,
+                        Some("csv") => coverage::CoverageFormat::Csv,
+                        Some(other) => {
+                            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                                "Unknown coverage format {other}, expected \"json\" or \"csv\""
+                            )))
+                        }
+                    };
+                    let coverage = coverage::Coverage::build(&self.lsp().api, &self.index());
+                    Ok(Some(coverage.export(format)))
+                }
+                #[cfg(not(feature = "search"))]
+                {
+                    let _ = format;
+                    Err(status::feature_disabled("search"))
+                }
+            }
+            command => Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Unknown command {command}"
+            ))),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        #[cfg(feature = "search")]
+        if let Err(err) = self.index().save_cache(&self.lsp()) {
+            trace::log(trace::Level::Error, || {
+                format!("Failed to save the workspace index: {err}")
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The server, ready to be served over stdio (or driven directly, like the integration tests do)
+pub fn build_service(lsp: BibleLSP) -> (LspService<Backend>, ClientSocket) {
+    build(lsp, None)
+}
+
+/// A server for one client of `bible_lsp --daemon`, see [`daemon::serve`]
+pub fn build_shared_service(
+    lsp: BibleLSP,
+    shared: Arc<daemon::Shared>,
+) -> (LspService<Backend>, ClientSocket) {
+    build(lsp, Some(shared))
+}
+
+fn build(
+    lsp: BibleLSP,
+    shared: Option<Arc<daemon::Shared>>,
+) -> (LspService<Backend>, ClientSocket) {
+    let lsp = Arc::new(lsp);
+    LspService::build(|client| Backend {
+        client,
+        lsp: RwLock::new(lsp.clone()),
+        translation: RwLock::new(lsp),
+        compare: Default::default(),
+        translations: Default::default(),
+        #[cfg(feature = "commentary")]
+        cross_references: Default::default(),
+        #[cfg(feature = "remote")]
+        remote: Default::default(),
+        documents: DocumentStore::default(),
+        config: RwLock::new(Config::default()),
+        roots: Default::default(),
+        client_capabilities: Default::default(),
+        recent_books: Default::default(),
+        previous_translation: Default::default(),
+        templates: Default::default(),
+        hover_cache: Default::default(),
+        memory_budget: Default::default(),
+        #[cfg(feature = "search")]
+        index: Default::default(),
+        shared,
+        #[cfg(feature = "search")]
+        reindex_queue: Default::default(),
+    })
+    .custom_method("bible/status", Backend::status)
+    .finish()
+}
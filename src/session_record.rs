@@ -0,0 +1,334 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tower::{Service, ServiceExt};
+use tower_lsp::jsonrpc::{Request, Response};
+
+use crate::{bible_lsp::BibleLSP, paths, server};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// editor to server
+    In,
+    /// server to editor
+    Out,
+}
+
+/// One line of a recording
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub direction: Direction,
+    pub message: Value,
+}
+
+/// Collects bytes until there is a whole `Content-Length` framed message
+#[derive(Debug, Default)]
+struct Frames {
+    buffer: Vec<u8>,
+}
+
+impl Frames {
+    /// The bodies of every message completed by `bytes`
+    fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut bodies = vec![];
+        while let Some(header_end) = self
+            .buffer
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        {
+            let header = String::from_utf8_lossy(&self.buffer[..header_end]);
+            let length = header.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            });
+            let body_start = header_end + 4;
+            let Some(length) = length else {
+                // not a header we understand, so skip past it rather than getting stuck
+                self.buffer.drain(..body_start);
+                continue;
+            };
+            if self.buffer.len() < body_start + length {
+                break;
+            }
+            bodies.push(self.buffer[body_start..body_start + length].to_vec());
+            self.buffer.drain(..body_start + length);
+        }
+        bodies
+    }
+}
+
+/**
+Writes every message going through stdin/stdout to a file, one JSON object per line
+
+- Meant to be attached to bug reports, then run with [`replay`]
+- Messages are sanitized first, see [`sanitize`]
+*/
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<File>,
+    incoming: Mutex<Frames>,
+    outgoing: Mutex<Frames>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Arc<Self>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Arc::new(Self {
+            file: Mutex::new(File::create(path)?),
+            incoming: Default::default(),
+            outgoing: Default::default(),
+        }))
+    }
+
+    fn record(&self, direction: Direction, bytes: &[u8]) {
+        let frames = match direction {
+            Direction::In => &self.incoming,
+            Direction::Out => &self.outgoing,
+        };
+        for body in frames.lock().unwrap().push(bytes) {
+            let Ok(mut message) = serde_json::from_slice::<Value>(&body) else {
+                continue;
+            };
+            sanitize(&mut message);
+            let Ok(line) = serde_json::to_string(&RecordedMessage { direction, message }) else {
+                continue;
+            };
+            // a recording missing a line is better than a server that stops responding
+            _ = writeln!(self.file.lock().unwrap(), "{line}");
+        }
+    }
+}
+
+/**
+Removes what identifies the user but doesn't matter for reproducing a bug
+
+- `processId` and `clientInfo` are dropped
+- API keys (`apiKey`, see [`crate::config::LicensedApi`]) are replaced with `<redacted>`
+- The home directory is replaced with `~` everywhere (URIs, paths, and text)
+- Document text is kept, since that is usually what the bug is about
+*/
+pub fn sanitize(message: &mut Value) {
+    let home = paths::home_dir().map(|home| home.to_string_lossy().into_owned());
+    sanitize_value(message, home.as_deref().filter(|home| home.len() > 1));
+}
+
+fn sanitize_value(value: &mut Value, home: Option<&str>) {
+    match value {
+        Value::Object(map) => {
+            map.remove("processId");
+            map.remove("clientInfo");
+            if let Some(key) = map.get_mut("apiKey").filter(|key| key.is_string()) {
+                *key = Value::String(String::from("<redacted>"));
+            }
+            map.values_mut()
+                .for_each(|value| sanitize_value(value, home));
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| sanitize_value(value, home)),
+        Value::String(text) => {
+            if let Some(home) = home.filter(|home| text.contains(home)) {
+                *text = text.replace(home, "~");
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Stdin, recording what the editor sends
+pub struct RecordingReader<R> {
+    inner: R,
+    recorder: Arc<Recorder>,
+}
+
+impl<R> RecordingReader<R> {
+    pub fn new(inner: R, recorder: Arc<Recorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for RecordingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.recorder.record(Direction::In, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+/// Stdout, recording what the server sends
+pub struct RecordingWriter<W> {
+    inner: W,
+    recorder: Arc<Recorder>,
+}
+
+impl<W> RecordingWriter<W> {
+    pub fn new(inner: W, recorder: Arc<Recorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for RecordingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.recorder.record(Direction::Out, &buf[..written]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A request whose response isn't what was recorded
+#[derive(Clone, Debug)]
+pub struct ReplayDifference {
+    pub method: String,
+    pub id: Value,
+    pub recorded: Value,
+    pub replayed: Value,
+}
+
+/**
+Sends every recorded editor message to a fresh server and compares the responses
+
+- Requests the server sends to the editor (like registering file watchers) are answered with
+  `null`, rather than with what the editor said back then
+- `exit` is skipped so the replay can finish
+*/
+pub async fn replay(path: &Path, lsp: BibleLSP) -> io::Result<Vec<ReplayDifference>> {
+    let mut recorded_requests = vec![];
+    let mut recorded_responses = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recorded: RecordedMessage = serde_json::from_str(&line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        match recorded.direction {
+            // responses to the server's own requests have no method
+            Direction::In if recorded.message.get("method").is_some() => {
+                recorded_requests.push(recorded.message)
+            }
+            Direction::Out if recorded.message.get("method").is_none() => {
+                recorded_responses.push(recorded.message)
+            }
+            _ => {}
+        }
+    }
+
+    let (mut service, socket) = server::build_service(lsp);
+    let (mut requests, mut responses) = socket.split();
+    tokio::spawn(async move {
+        while let Some(request) = requests.next().await {
+            if let Some(id) = request.id().cloned() {
+                _ = responses.send(Response::from_ok(id, Value::Null)).await;
+            }
+        }
+    });
+
+    let mut differences = vec![];
+    for message in recorded_requests {
+        let method = message["method"].as_str().unwrap_or_default().to_string();
+        if method == "exit" {
+            continue;
+        }
+        let request: Request = serde_json::from_value(message)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let Ok(service) = service.ready().await else {
+            break;
+        };
+        let Ok(Some(response)) = service.call(request).await else {
+            continue;
+        };
+        let mut replayed = serde_json::to_value(&response).unwrap_or_default();
+        sanitize(&mut replayed);
+        // recordings can start partway through, or end before the response was sent
+        let Some(recorded) = recorded_responses
+            .iter()
+            .find(|recorded| recorded.get("id") == replayed.get("id"))
+            .cloned()
+        else {
+            continue;
+        };
+        if recorded != replayed {
+            differences.push(ReplayDifference {
+                method,
+                id: replayed.get("id").cloned().unwrap_or_default(),
+                recorded,
+                replayed,
+            });
+        }
+    }
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_split_across_reads() {
+        let mut frames = Frames::default();
+        let first = br#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+        let message = format!(
+            "Content-Length: {}\r\n\r\n{}Content-Length: 2\r\n\r\n{{}}",
+            first.len(),
+            String::from_utf8_lossy(first)
+        );
+        let (start, end) = message.as_bytes().split_at(30);
+        assert!(frames.push(start).is_empty());
+        assert_eq!(frames.push(end), vec![first.to_vec(), b"{}".to_vec()]);
+    }
+
+    #[test]
+    fn sanitizing_drops_client_details() {
+        let mut message = serde_json::json!({
+            "params": { "processId": 42, "clientInfo": { "name": "nvim" }, "rootUri": "file:///home/me/notes" }
+        });
+        sanitize_value(&mut message, Some("/home/me"));
+        assert_eq!(
+            message,
+            serde_json::json!({ "params": { "rootUri": "file://~/notes" } })
+        );
+
+        let mut message = serde_json::json!({
+            "params": { "settings": { "remote": { "licensed": { "ESV": { "api": "esv", "apiKey": "secret" } } } } }
+        });
+        sanitize_value(&mut message, None);
+        assert_eq!(
+            message["params"]["settings"]["remote"]["licensed"]["ESV"],
+            serde_json::json!({ "api": "esv", "apiKey": "<redacted>" })
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{bible_api::BibleAPI, book_reference_segment::BookReferenceSegment, versification};
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+/// A number in English words, like `one hundred nineteen` or `twenty-three`
+pub fn number(number: usize) -> String {
+    match number {
+        0..=19 => ONES[number].to_string(),
+        20..=99 => match number % 10 {
+            0 => TENS[number / 10].to_string(),
+            ones => format!("{}-{}", TENS[number / 10], ONES[ones]),
+        },
+        100..=999 => match number % 100 {
+            0 => format!("{} hundred", ONES[number / 100]),
+            rest => format!("{} hundred {}", ONES[number / 100], self::number(rest)),
+        },
+        _ => match number % 1000 {
+            0 => format!("{} thousand", self::number(number / 1000)),
+            rest => format!(
+                "{} thousand {}",
+                self::number(number / 1000),
+                self::number(rest)
+            ),
+        },
+    }
+}
+
+/// `1 John` as `First John`, the way numbered books are read out
+pub fn book_name(name: &str) -> String {
+    let Some((digits, rest)) = name.split_once(' ') else {
+        return name.to_string();
+    };
+    let ordinal = match digits {
+        "1" => "First",
+        "2" => "Second",
+        "3" => "Third",
+        "4" => "Fourth",
+        _ => return name.to_string(),
+    };
+    format!("{ordinal} {rest}")
+}
+
+/**
+A reference as it would be read aloud, for sermon scripts and teleprompters
+
+```text
+Ephesians chapter one, verses one through four
+John chapter three, verses one through five, and verse sixteen
+Psalm twenty-three
+First John chapter four, verse nine, through chapter five, verse one
+```
+
+- Whole chapters are just the chapter, and Psalms are read by number (`Psalm twenty-three`)
+  rather than as chapters of a book, when the translation calls them that
+- English only, since the words around the numbers are
+*/
+pub fn citation(api: &BibleAPI, book_id: usize, segments: &[BookReferenceSegment]) -> String {
+    let name = api
+        .get_book_name(book_id)
+        .map(|name| name.to_string())
+        .unwrap_or_default();
+    let psalms = book_id == versification::PSALMS && ["Psalm", "Psalms"].contains(&name.as_str());
+    let spoken = spoken_segments(api, book_id, segments, psalms);
+    match psalms {
+        true => spoken,
+        false => format!("{} {spoken}", book_name(&name)),
+    }
+}
+
+/// The chapters and verses of [`citation`] without the book, like `chapter one, verse three`
+pub fn segments(api: &BibleAPI, book_id: usize, segments: &[BookReferenceSegment]) -> String {
+    spoken_segments(api, book_id, segments, false)
+}
+
+fn spoken_segments(
+    api: &BibleAPI,
+    book_id: usize,
+    segments: &[BookReferenceSegment],
+    psalms: bool,
+) -> String {
+    let (chapter_word, chapters_word) = match psalms {
+        true => ("Psalm", "Psalms"),
+        false => ("chapter", "chapters"),
+    };
+    let whole_chapter =
+        |chapter: usize, verse: usize| api.get_chapter_verse_count(book_id, chapter) == Some(verse);
+    let mut previous_chapter: Option<usize> = None;
+    let mut parts: Vec<String> = vec![];
+    for seg in segments {
+        let (start_chapter, start_verse) = (seg.get_starting_chapter(), seg.get_starting_verse());
+        let (end_chapter, end_verse) = (seg.get_ending_chapter(), seg.get_ending_verse());
+        let chapter = match previous_chapter == Some(start_chapter) {
+            true => String::new(),
+            false => format!("{chapter_word} {}, ", number(start_chapter)),
+        };
+        let part = if start_verse <= 1 && whole_chapter(end_chapter, end_verse) {
+            match start_chapter == end_chapter {
+                true => format!("{chapter_word} {}", number(start_chapter)),
+                false => format!(
+                    "{chapters_word} {} through {}",
+                    number(start_chapter),
+                    number(end_chapter)
+                ),
+            }
+        } else if start_chapter != end_chapter {
+            format!(
+                "{chapter}verse {}, through {chapter_word} {}, verse {}",
+                number(start_verse),
+                number(end_chapter),
+                number(end_verse)
+            )
+        } else if start_verse == end_verse {
+            format!("{chapter}verse {}", number(start_verse))
+        } else {
+            format!(
+                "{chapter}verses {} through {}",
+                number(start_verse),
+                number(end_verse)
+            )
+        };
+        parts.push(part);
+        previous_chapter = Some(end_chapter);
+    }
+    match parts.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{}, and {last}", rest.join(", ")),
+        _ => parts.concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bible_lsp::BibleLSP;
+
+    #[test]
+    fn references_are_read_out() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let spoken = |text: &str| {
+            let book_ref = lsp.find_book_references_in(text, &[]).unwrap().remove(0);
+            citation(&lsp.api, book_ref.book_id, &book_ref.segments)
+        };
+        assert_eq!(
+            spoken("Ephesians 1:1-4"),
+            "Ephesians chapter one, verses one through four"
+        );
+        assert_eq!(
+            spoken("John 3:1-4, 5"),
+            "John chapter three, verses one through four, and verse five"
+        );
+        assert_eq!(
+            spoken("1 John 1:2-2:1"),
+            "First John chapter one, verse two, through chapter two, verse one"
+        );
+        assert_eq!(spoken("Psalm 2:1"), "Psalm two, verse one");
+        assert_eq!(number(119), "one hundred nineteen");
+        assert_eq!(number(176), "one hundred seventy-six");
+        assert_eq!(number(2040), "two thousand forty");
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use cached::proc_macro::cached;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range};
+
+use crate::{bible_api::BibleAPI, document::LineIndex};
+
+/// Diagnostic code of misspelled book names, so the quick fix can find them again
+pub const MISSPELLED_BOOK: &str = "misspelled-book";
+
+/// - A word (optionally numbered like `1 Corinthans`) followed by `chapter:verse`
+/// - Known books are matched by [`BibleAPI::book_abbreviation_regex`], so whatever this finds
+///   that isn't a known book is probably a typo
+#[cached(size = 1)]
+fn book_like_reference() -> Regex {
+    Regex::new(r"(?i)\b((?:[1-3] ?)?[a-z]+)\.? *\d+:\d+").unwrap()
+}
+
+/// A book name that is close to, but not quite, a real one
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Misspelling {
+    pub range: Range,
+    /// what was written, ex: `Galations`
+    pub found: String,
+    pub book_id: usize,
+    /// the book name to replace it with, ex: `Galatians`
+    pub suggestion: Arc<str>,
+}
+
+impl Misspelling {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            range: self.range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(MISSPELLED_BOOK.to_string())),
+            source: Some(String::from("bible_lsp")),
+            message: format!(
+                "Unknown book \"{}\", did you mean \"{}\"?",
+                self.found, self.suggestion
+            ),
+            data: serde_json::to_value(self).ok(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_diagnostic(diagnostic: &Diagnostic) -> Option<Self> {
+        if diagnostic.code != Some(NumberOrString::String(MISSPELLED_BOOK.to_string())) {
+            return None;
+        }
+        serde_json::from_value(diagnostic.data.clone().unwrap_or(Value::Null)).ok()
+    }
+}
+
+/// - Finds every near-miss book name followed by a chapter and verse
+/// - Ex: `Galations 2:20`, `Revelations 3:16`, `1 Corinthans 13:4`
+pub fn find_misspellings(api: &BibleAPI, text: &str, line_index: &LineIndex) -> Vec<Misspelling> {
+    book_like_reference()
+        .captures_iter(text)
+        .filter_map(|cap| {
+            let token = cap.get(1)?;
+            if api.get_book_id(token.as_str()).is_some() {
+                return None;
+            }
+            let book_id = closest_book(api, token.as_str())?;
+            Some(Misspelling {
+                range: Range {
+                    start: line_index.position(text, token.start()),
+                    end: line_index.position(text, token.end()),
+                },
+                found: token.as_str().to_string(),
+                book_id,
+                suggestion: api.get_book_name(book_id)?,
+            })
+        })
+        .collect()
+}
+
+/**
+- The book whose name or abbreviation is closest to `token`
+- Short words need to be nearly exact, otherwise every 4 letter word would be a typo of some book
+- Ties go to the first book, which is usually the better known one
+*/
+pub fn closest_book(api: &BibleAPI, token: &str) -> Option<usize> {
+    let token = token.to_lowercase().replace("  ", " ");
+    let letters = token.chars().filter(|ch| ch.is_alphabetic()).count();
+    let max_distance = match letters {
+        0..=3 => return None,
+        4..=5 => 1,
+        6..=9 => 2,
+        _ => 3,
+    };
+    api.abbreviations_to_book_id
+        .iter()
+        .map(|(alias, book_id)| (levenshtein(&token, alias), *book_id))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, book_id)| book_id)
+}
+
+/// Edit distance counting inserts, deletes, and substitutions of characters
+pub fn levenshtein(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+    for (row, left_char) in left.chars().enumerate() {
+        let mut current = vec![row + 1];
+        for (col, right_char) in right.iter().enumerate() {
+            let substitution = previous[col] + usize::from(left_char != *right_char);
+            current.push(
+                substitution
+                    .min(previous[col + 1] + 1)
+                    .min(current[col] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[right.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    #[test]
+    fn levenshtein_distances() {
+        assert_eq!(levenshtein("galations", "galatians"), 1);
+        assert_eq!(levenshtein("revelations", "revelation"), 1);
+        assert_eq!(levenshtein("", "john"), 4);
+        assert_eq!(levenshtein("psalm", "psalm"), 0);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn near_misses_of_book_names_are_found() {
+        let lsp = crate::bible_lsp::BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let text = "See Galations 2:20\nand 1 Corinthans 13:4, not Galatians 2:21 or Page 3:4";
+        let misspellings = find_misspellings(&lsp.api, text, &LineIndex::new(text));
+        let found: Vec<(&str, &str)> = misspellings
+            .iter()
+            .map(|misspelling| (misspelling.found.as_str(), &*misspelling.suggestion))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Galations", "Galatians"),
+                ("1 Corinthans", "1 Corinthians")
+            ]
+        );
+        assert_eq!(
+            misspellings[0].range,
+            Range::new(Position::new(0, 4), Position::new(0, 13))
+        );
+        // the suggestion is the translation's own name, not a copy of it
+        let galatians = lsp.api.get_book_name(48).unwrap();
+        assert!(Arc::ptr_eq(&misspellings[0].suggestion, &galatians));
+
+        let diagnostic = misspellings[1].diagnostic();
+        assert_eq!(
+            diagnostic.message,
+            "Unknown book \"1 Corinthans\", did you mean \"1 Corinthians\"?"
+        );
+        assert_eq!(
+            Misspelling::from_diagnostic(&diagnostic).as_ref(),
+            Some(&misspellings[1])
+        );
+        let other = Diagnostic {
+            code: None,
+            ..diagnostic
+        };
+        assert_eq!(Misspelling::from_diagnostic(&other), None);
+    }
+}
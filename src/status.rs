@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tower_lsp::jsonrpc;
+
+use crate::memory_budget::MemoryUsage;
+
+/// Cargo features that can be left out of a build, and whether this build has them
+pub const FEATURES: [(&str, bool); 6] = [
+    ("search", cfg!(feature = "search")),
+    ("remote", cfg!(feature = "remote")),
+    ("usfm", cfg!(feature = "usfm")),
+    ("mybible", cfg!(feature = "mybible")),
+    ("commentary", cfg!(feature = "commentary")),
+    ("tui", cfg!(feature = "tui")),
+];
+
+/// - Subsystems no build has yet, always reported as off so clients can check for them the same way
+/// - `sword`: reading SWORD modules, which SWORD's `mod2osis` and `bible_lsp convert` can turn into a
+///   Bible JSON file in the meantime
+pub const UNAVAILABLE: [&str; 1] = ["sword"];
+
+/// Response of the custom `bible/status` request
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub version: &'static str,
+    pub translation: String,
+    /// every loaded translation, the default one first
+    pub translations: Vec<String>,
+    pub features: BTreeMap<&'static str, bool>,
+    pub open_documents: usize,
+    pub memory: MemoryUsage,
+    /// only when there are remote translations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteStatus>,
+}
+
+/// How much of a web API's quota is used, see [`crate::config::RemoteConfig`]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteStatus {
+    pub translations: Vec<String>,
+    pub requests_today: usize,
+    pub daily_limit: Option<usize>,
+    /// - Requests left today, the fewer of what `dailyLimit` leaves and what the API last said
+    /// - `null` when neither says
+    pub remaining: Option<usize>,
+    pub cached_chapters: usize,
+}
+
+impl Status {
+    pub fn new(translations: Vec<String>, open_documents: usize, memory: MemoryUsage) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            translation: translations.first().cloned().unwrap_or_default(),
+            translations,
+            features: FEATURES
+                .into_iter()
+                .chain(UNAVAILABLE.map(|feature| (feature, false)))
+                .collect(),
+            open_documents,
+            memory,
+            remote: None,
+        }
+    }
+}
+
+/// The error returned by commands that need a feature this build was compiled without
+pub fn feature_disabled(feature: &str) -> jsonrpc::Error {
+    jsonrpc::Error {
+        code: jsonrpc::ErrorCode::InvalidRequest,
+        message: format!("This build of bible_lsp was compiled without the \"{feature}\" feature")
+            .into(),
+        data: None,
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+use crate::{bible_formatter::PassageFormatter, paths};
+
+/// How often the templates directory is checked for changes
+pub const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// `~/.config/bible_lsp/templates` on Linux, see [`paths::config_dir`]
+pub fn templates_dir() -> PathBuf {
+    paths::config_dir().join("templates")
+}
+
+/**
+Parses a `.tmpl` file into a formatter
+
+```text
+verse = <sup>{verse}</sup>{content}
+joinVerses = \n
+---
+> [!note] {reference}
+> {segments}
+```
+
+- Everything after the `---` line is the `text` template, and the lines before it set the other
+  fields (`verse`, `joinVerses`, `segment`, `joinSegment`, `chapterHeading`, `codeActions`, and
+  the label's `pad`, `digits`, `direction`, and `dualPsalms`), with `\n` for line breaks and
+  commas between code actions
+- Without a `---` line the whole file is the `text` template
+- Fields that aren't set come from the default formatter, except `chapterHeading`, which is empty
+  like in configured formatters
+*/
+pub fn parse_template(source: &str) -> Result<PassageFormatter, String> {
+    let source = source.replace("\r\n", "\n");
+    let mut formatter = PassageFormatter {
+        chapter_heading: String::new(),
+        ..Default::default()
+    };
+    let (header, text) = match source.split_once("\n---\n") {
+        Some((header, text)) => (header, text),
+        None => match source.strip_prefix("---\n") {
+            Some(text) => ("", text),
+            None => ("", source.as_str()),
+        },
+    };
+    for line in header.lines().filter(|line| !line.trim().is_empty()) {
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Expected `key = value`, found `{line}`"));
+        };
+        let value = value.trim().replace("\\n", "\n");
+        match key.trim() {
+            "verse" => formatter.verse = value,
+            "joinVerses" => formatter.join_verses = value,
+            "segment" => formatter.segment = value,
+            "joinSegment" => formatter.join_segment = value,
+            "chapterHeading" => formatter.chapter_heading = value,
+            "pad" => {
+                formatter.label.pad = value
+                    .parse()
+                    .map_err(|_| format!("Expected a number for `pad`, found `{value}`"))?
+            }
+            "digits" => {
+                formatter.label.digits = serde_json::from_value(Value::String(value.clone()))
+                    .map_err(|_| format!("Unknown digits `{value}`"))?
+            }
+            "direction" => {
+                formatter.label.direction = serde_json::from_value(Value::String(value.clone()))
+                    .map_err(|_| format!("Unknown direction `{value}`"))?
+            }
+            "dualPsalms" => {
+                formatter.label.dual_psalms = value.parse().map_err(|_| {
+                    format!("Expected true or false for `dualPsalms`, found `{value}`")
+                })?
+            }
+            "codeActions" => {
+                formatter.code_actions = value
+                    .split(',')
+                    .map(|action| action.trim().to_string())
+                    .filter(|action| !action.is_empty())
+                    .collect()
+            }
+            other => return Err(format!("Unknown field `{other}`")),
+        }
+    }
+    formatter.text = text.strip_suffix('\n').unwrap_or(text).to_string();
+    Ok(formatter)
+}
+
+/// Whether anything in the directory changed, without reading every file
+type DirStamp = BTreeMap<PathBuf, (SystemTime, u64)>;
+
+fn dir_stamp(dir: &Path) -> DirStamp {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return DirStamp::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tmpl"))
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            Some((path, (metadata.modified().ok()?, metadata.len())))
+        })
+        .collect()
+}
+
+/**
+Formatters loaded from `*.tmpl` files, named after the file (`slide.tmpl` is `slide`)
+
+- The directory is polled rather than watched, since it is outside the workspace and editors only
+  watch files in the workspace
+- Templates that fail to parse are left out and reported by [`TemplateStore::reload`]
+*/
+#[derive(Debug, Default)]
+pub struct TemplateStore {
+    templates: RwLock<BTreeMap<String, PassageFormatter>>,
+    stamp: RwLock<DirStamp>,
+}
+
+impl TemplateStore {
+    pub fn get(&self, name: &str) -> Option<PassageFormatter> {
+        self.templates.read().unwrap().get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.templates.read().unwrap().keys().cloned().collect()
+    }
+
+    /// - Reloads every template if anything in `dir` changed since the last call
+    /// - Returns `None` if nothing changed, otherwise the problems found, like
+    ///   `slide.tmpl: unknown variable {contnet}`
+    pub fn reload(&self, dir: &Path) -> Option<Vec<String>> {
+        let stamp = dir_stamp(dir);
+        if *self.stamp.read().unwrap() == stamp {
+            return None;
+        }
+        let mut templates = BTreeMap::new();
+        let mut problems = vec![];
+        for path in stamp.keys() {
+            let Some(name) = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            let file_name = format!("{name}.tmpl");
+            let parsed = fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|source| parse_template(&source));
+            match parsed {
+                Ok(formatter) => {
+                    problems.extend(
+                        formatter
+                            .lint()
+                            .into_iter()
+                            .map(|problem| format!("{file_name}: {problem}")),
+                    );
+                    templates.insert(name, formatter);
+                }
+                Err(err) => problems.push(format!("{file_name}: {err}")),
+            }
+        }
+        *self.templates.write().unwrap() = templates;
+        *self.stamp.write().unwrap() = stamp;
+        Some(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_parse_header_and_text() {
+        let formatter = parse_template(
+            "verse = {verse} {content}\njoinVerses = \\n\n---\n# {reference}\n\n{segments}\n",
+        )
+        .unwrap();
+        assert_eq!(formatter.verse, "{verse} {content}");
+        assert_eq!(formatter.join_verses, "\n");
+        assert_eq!(formatter.text, "# {reference}\n\n{segments}");
+        assert_eq!(formatter.segment, PassageFormatter::default().segment);
+
+        assert_eq!(parse_template("{segments}").unwrap().text, "{segments}");
+        assert!(parse_template("colour = red\n---\n{segments}").is_err());
+    }
+
+    #[test]
+    fn reload_only_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TemplateStore::default();
+        fs::write(dir.path().join("plain.tmpl"), "{segments}").unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+        assert_eq!(store.reload(dir.path()), Some(vec![]));
+        assert_eq!(store.names(), vec!["plain"]);
+        assert_eq!(store.reload(dir.path()), None);
+
+        fs::write(dir.path().join("slide.tmpl"), "{segmnets}").unwrap();
+        assert_eq!(
+            store.reload(dir.path()),
+            Some(vec![String::from(
+                "slide.tmpl: text: unknown variable {segmnets}"
+            )])
+        );
+        assert_eq!(store.names(), vec!["plain", "slide"]);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bible_lsp::append_log;
+
+/**
+How much goes into the log file, from the `logLevel` setting
+
+- `warn` by default, so nothing is written while typing unless something goes wrong
+- `debug` and `trace` are for tracking down a problem, and are sampled (see [`sampled`]) since
+  they are written from handlers that run on every keystroke
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Level {
+    Off,
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Every handler shares one level, since there is one log file
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Off,
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// - Writes a line to the log file if `level` is enabled
+/// - `content` is only formatted then, so large debug structs cost nothing otherwise
+pub fn log(level: Level, content: impl FnOnce() -> String) {
+    if enabled(level) {
+        append_log(content());
+    }
+}
+
+/// Per call site: when it last wrote, and how many lines it skipped since
+static SAMPLES: Mutex<Option<HashMap<&'static str, (Instant, usize)>>> = Mutex::new(None);
+
+/// How often each call site of [`sampled`] can write
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+[`log`] for hot paths, writing at most one line per [`SAMPLE_INTERVAL`] for each `site`
+
+- Ex: `trace::sampled("completion", Level::Trace, || format!("{state:#?}"))`
+- The line says how many were skipped since the last one, so the log still shows how busy it was
+*/
+pub fn sampled(site: &'static str, level: Level, content: impl FnOnce() -> String) {
+    if !enabled(level) {
+        return;
+    }
+    let skipped = {
+        let mut samples = SAMPLES.lock().unwrap();
+        let samples = samples.get_or_insert_with(HashMap::new);
+        match samples.get_mut(site) {
+            Some((last, skipped)) if last.elapsed() < SAMPLE_INTERVAL => {
+                *skipped += 1;
+                return;
+            }
+            Some((last, skipped)) => {
+                *last = Instant::now();
+                std::mem::take(skipped)
+            }
+            None => {
+                samples.insert(site, (Instant::now(), 0));
+                0
+            }
+        }
+    };
+    match skipped {
+        0 => append_log(content()),
+        skipped => append_log(format!("{} ({skipped} skipped)", content())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_include_the_ones_before_them() {
+        set_level(Level::Info);
+        assert!(enabled(Level::Error));
+        assert!(enabled(Level::Info));
+        assert!(!enabled(Level::Debug));
+        set_level(Level::Off);
+        assert!(!enabled(Level::Error));
+        assert!(!enabled(Level::Off));
+        set_level(Level::default());
+    }
+}
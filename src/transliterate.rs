@@ -0,0 +1,580 @@
+/**
+Greek and Hebrew written in Latin letters, the way the SBL Handbook of Style's academic style
+does
+
+- `Ἐν ἀρχῇ ἦν ὁ λόγος` is `En archę̄ ēn ho logos`, and `בְּרֵאשִׁית` is `bərēʾšîṯ`
+- Accents and cantillation marks are dropped, and anything that isn't Greek or Hebrew is kept as
+  it is, so it works on a whole selection of mixed text
+- Works with both precomposed (`ἦ`) and decomposed (`η` + `̓` + `͂`) text
+*/
+pub fn transliterate(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let chars: Vec<char> = text.chars().collect();
+    let mut idx = 0;
+    while idx < chars.len() {
+        let end = run_end(&chars, idx, is_greek);
+        if end > idx {
+            output.push_str(&greek(&chars[idx..end]));
+            idx = end;
+            continue;
+        }
+        let end = run_end(&chars, idx, is_hebrew);
+        if end > idx {
+            output.push_str(&hebrew(&chars[idx..end]));
+            idx = end;
+            continue;
+        }
+        // the Greek question mark and raised dot
+        output.push(match chars[idx] {
+            '\u{37e}' => '?',
+            '\u{387}' => ';',
+            c => c,
+        });
+        idx += 1;
+    }
+    output
+}
+
+/// Whether `text` has any Greek or Hebrew letters to transliterate
+pub fn has_original_language(text: &str) -> bool {
+    text.chars()
+        .any(|c| greek_letter(c).is_some() || hebrew_consonant(c).is_some())
+}
+
+/// Where a run of characters (and the marks on them) from one script ends
+fn run_end(chars: &[char], start: usize, script: fn(char) -> bool) -> usize {
+    let mut end = start;
+    while end < chars.len() && (script(chars[end]) || (end > start && is_mark(chars[end]))) {
+        end += 1;
+    }
+    end
+}
+
+/// Combining marks either script can put on a letter
+fn is_mark(c: char) -> bool {
+    matches!(c, '\u{300}'..='\u{36f}')
+}
+
+fn is_greek(c: char) -> bool {
+    greek_letter(c).is_some()
+}
+
+/// Letters, points, and punctuation like the maqqef
+fn is_hebrew(c: char) -> bool {
+    matches!(c, '\u{591}'..='\u{5f4}' | '\u{fb2a}')
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct GreekLetter {
+    /// lowercase, without any marks
+    base: char,
+    upper: bool,
+    rough: bool,
+    iota_subscript: bool,
+    diaeresis: bool,
+}
+
+/**
+A Greek letter and the marks that matter here, from its precomposed form
+
+- The Greek Extended block is laid out in rows of 8, where odd columns have a rough breathing
+*/
+fn greek_letter(c: char) -> Option<GreekLetter> {
+    let letter = |base: char, upper: bool| GreekLetter {
+        base,
+        upper,
+        ..Default::default()
+    };
+    let code = c as u32;
+    let mut found = match c {
+        'α'..='ω' => letter(c, false),
+        'Α'..='Ω' => letter(char::from_u32(code + 0x20)?, true),
+        'ά' => letter('α', false),
+        'έ' => letter('ε', false),
+        'ή' => letter('η', false),
+        'ί' => letter('ι', false),
+        'ό' => letter('ο', false),
+        'ύ' => letter('υ', false),
+        'ώ' => letter('ω', false),
+        'Ά' => letter('α', true),
+        'Έ' => letter('ε', true),
+        'Ή' => letter('η', true),
+        'Ί' => letter('ι', true),
+        'Ό' => letter('ο', true),
+        'Ύ' => letter('υ', true),
+        'Ώ' => letter('ω', true),
+        'ϊ' | 'ΐ' => GreekLetter {
+            diaeresis: true,
+            ..letter('ι', false)
+        },
+        'ϋ' | 'ΰ' => GreekLetter {
+            diaeresis: true,
+            ..letter('υ', false)
+        },
+        '\u{1f00}'..='\u{1f6f}' => {
+            let row = ((code - 0x1f00) / 8) as usize;
+            let vowels = ['α', 'ε', 'η', 'ι', 'ο', 'υ', 'ω'];
+            GreekLetter {
+                rough: code % 2 == 1,
+                ..letter(vowels[row / 2], row % 2 == 1)
+            }
+        }
+        '\u{1f70}'..='\u{1f7d}' => {
+            let vowels = ['α', 'ε', 'η', 'ι', 'ο', 'υ', 'ω'];
+            letter(vowels[((code - 0x1f70) / 2) as usize], false)
+        }
+        '\u{1f80}'..='\u{1faf}' => {
+            let row = ((code - 0x1f80) / 8) as usize;
+            GreekLetter {
+                rough: code % 2 == 1,
+                iota_subscript: true,
+                ..letter(['α', 'η', 'ω'][row / 2], row % 2 == 1)
+            }
+        }
+        '\u{1fb0}'..='\u{1fbc}' => GreekLetter {
+            iota_subscript: matches!(c, 'ᾲ' | 'ᾳ' | 'ᾴ' | 'ᾷ' | 'ᾼ'),
+            ..letter('α', code >= 0x1fb8)
+        },
+        '\u{1fc2}'..='\u{1fcc}' => GreekLetter {
+            iota_subscript: matches!(c, 'ῂ' | 'ῃ' | 'ῄ' | 'ῇ' | 'ῌ'),
+            ..letter(
+                if matches!(c, 'Ὲ' | 'Έ') {
+                    'ε'
+                } else {
+                    'η'
+                },
+                code >= 0x1fc8,
+            )
+        },
+        '\u{1fd0}'..='\u{1fdb}' => GreekLetter {
+            diaeresis: matches!(c, 'ῒ' | 'ΐ' | 'ῗ'),
+            ..letter('ι', code >= 0x1fd8)
+        },
+        'ῤ' => letter('ρ', false),
+        'ῥ' => GreekLetter {
+            rough: true,
+            ..letter('ρ', false)
+        },
+        'Ῥ' => GreekLetter {
+            rough: true,
+            ..letter('ρ', true)
+        },
+        '\u{1fe0}'..='\u{1feb}' => GreekLetter {
+            diaeresis: matches!(c, 'ῢ' | 'ΰ' | 'ῧ'),
+            ..letter('υ', code >= 0x1fe8)
+        },
+        '\u{1ff2}'..='\u{1ffc}' => GreekLetter {
+            iota_subscript: matches!(c, 'ῲ' | 'ῳ' | 'ῴ' | 'ῷ' | 'ῼ'),
+            ..letter(
+                if matches!(c, 'Ὸ' | 'Ό') {
+                    'ο'
+                } else {
+                    'ω'
+                },
+                code >= 0x1ff8,
+            )
+        },
+        _ => return None,
+    };
+    if found.base == 'ς' {
+        found.base = 'σ';
+    }
+    Some(found)
+}
+
+/// Latin letters for a Greek one without breathings, see [`transliterate`]
+fn greek_latin(base: char) -> &'static str {
+    match base {
+        'α' => "a",
+        'β' => "b",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' => "e",
+        'ζ' => "z",
+        'η' => "ē",
+        'θ' => "th",
+        'ι' => "i",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' => "s",
+        'τ' => "t",
+        'υ' => "y",
+        'φ' => "ph",
+        'χ' => "ch",
+        'ψ' => "ps",
+        'ω' => "ō",
+        _ => "",
+    }
+}
+
+fn is_greek_vowel(base: char) -> bool {
+    matches!(base, 'α' | 'ε' | 'η' | 'ι' | 'ο' | 'υ' | 'ω')
+}
+
+/// `αι`, `ει`, `οι`, `υι`, `αυ`, `ευ`, `ηυ`, and `ου`, unless the second has a diaeresis
+fn is_diphthong(first: &GreekLetter, second: &GreekLetter) -> bool {
+    !second.diaeresis
+        && !first.iota_subscript
+        && match second.base {
+            'ι' => matches!(first.base, 'α' | 'ε' | 'ο' | 'υ'),
+            'υ' => matches!(first.base, 'α' | 'ε' | 'η' | 'ο'),
+            _ => false,
+        }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn greek(chars: &[char]) -> String {
+    // marks written after their letter are folded into it
+    let mut letters: Vec<GreekLetter> = vec![];
+    for c in chars.iter().copied() {
+        match (c, letters.last_mut()) {
+            ('\u{314}', Some(last)) => last.rough = true,
+            ('\u{345}', Some(last)) => last.iota_subscript = true,
+            ('\u{308}', Some(last)) => last.diaeresis = true,
+            (c, _) if is_mark(c) => {}
+            (c, _) => letters.extend(greek_letter(c)),
+        }
+    }
+
+    let mut output = String::new();
+    let mut idx = 0;
+    while idx < letters.len() {
+        let letter = letters[idx];
+        let next = letters.get(idx + 1);
+        let mut latin = match (letter.base, next.map(|next| next.base)) {
+            // a nasal gamma
+            ('γ', Some('γ' | 'κ' | 'ξ' | 'χ')) => String::from("n"),
+            ('ρ', _) if letter.rough => String::from("rh"),
+            (base, _) if letter.iota_subscript => match base {
+                'α' => String::from("ą"),
+                'η' => String::from("ę̄"),
+                _ => String::from("ǭ"),
+            },
+            ('ι', _) if letter.diaeresis => String::from("ï"),
+            ('υ', _) if letter.diaeresis => String::from("ü"),
+            (base, _) => greek_latin(base).to_string(),
+        };
+        let mut rough = letter.rough && letter.base != 'ρ';
+        let mut width = 1;
+        if let Some(next) = next.filter(|next| is_diphthong(&letter, next)) {
+            // `υ` is `u` in a diphthong, and the breathing is on the second vowel
+            latin = match letter.base {
+                'υ' => String::from("ui"),
+                base => format!("{}{}", greek_latin(base), greek_latin(next.base)),
+            }
+            .replace("ay", "au")
+            .replace("ey", "eu")
+            .replace("ēy", "ēu")
+            .replace("oy", "ou");
+            rough |= next.rough;
+            width = 2;
+        }
+        if rough && is_greek_vowel(letter.base) {
+            latin.insert(0, 'h');
+        }
+        match letter.upper {
+            true => output.push_str(&capitalize(&latin)),
+            false => output.push_str(&latin),
+        }
+        idx += width;
+    }
+    output
+}
+
+fn hebrew_consonant(c: char) -> Option<&'static str> {
+    Some(match c {
+        'א' => "ʾ",
+        'ב' => "ḇ",
+        'ג' => "ḡ",
+        'ד' => "ḏ",
+        'ה' => "h",
+        'ו' => "w",
+        'ז' => "z",
+        'ח' => "ḥ",
+        'ט' => "ṭ",
+        'י' => "y",
+        'כ' | 'ך' => "ḵ",
+        'ל' => "l",
+        'מ' | 'ם' => "m",
+        'נ' | 'ן' => "n",
+        'ס' => "s",
+        'ע' => "ʿ",
+        'פ' | 'ף' => "p̄",
+        'צ' | 'ץ' => "ṣ",
+        'ק' => "q",
+        'ר' => "r",
+        'ש' => "š",
+        'ת' => "ṯ",
+        _ => return None,
+    })
+}
+
+/// The hard sound of `בגדכפת` with a dagesh
+fn hard(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ב' => "b",
+        'ג' => "g",
+        'ד' => "d",
+        'כ' | 'ך' => "k",
+        'פ' | 'ף' => "p",
+        'ת' => "t",
+        _ => return None,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Vowel {
+    Shewa,
+    HatefSegol,
+    HatefPatah,
+    HatefQamats,
+    Hiriq,
+    Tsere,
+    Segol,
+    Patah,
+    Qamats,
+    Holam,
+    Qubuts,
+}
+
+impl Vowel {
+    fn from_point(c: char) -> Option<Self> {
+        Some(match c {
+            '\u{5b0}' => Vowel::Shewa,
+            '\u{5b1}' => Vowel::HatefSegol,
+            '\u{5b2}' => Vowel::HatefPatah,
+            '\u{5b3}' => Vowel::HatefQamats,
+            '\u{5b4}' => Vowel::Hiriq,
+            '\u{5b5}' => Vowel::Tsere,
+            '\u{5b6}' => Vowel::Segol,
+            '\u{5b7}' => Vowel::Patah,
+            '\u{5b8}' | '\u{5c7}' => Vowel::Qamats,
+            '\u{5b9}' | '\u{5ba}' => Vowel::Holam,
+            '\u{5bb}' => Vowel::Qubuts,
+            _ => return None,
+        })
+    }
+
+    fn latin(self) -> &'static str {
+        match self {
+            Vowel::Shewa => "ə",
+            Vowel::HatefSegol => "ĕ",
+            Vowel::HatefPatah => "ă",
+            Vowel::HatefQamats => "ŏ",
+            Vowel::Hiriq => "i",
+            Vowel::Tsere => "ē",
+            Vowel::Segol => "e",
+            Vowel::Patah => "a",
+            Vowel::Qamats => "ā",
+            Vowel::Holam => "ō",
+            Vowel::Qubuts => "u",
+        }
+    }
+
+    /// After these a shewa starts a syllable rather than closing one
+    fn is_long(self) -> bool {
+        matches!(self, Vowel::Tsere | Vowel::Qamats | Vowel::Holam)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct HebrewLetter {
+    consonant: char,
+    dagesh: bool,
+    sin: bool,
+    vowel: Option<Vowel>,
+}
+
+/// Words joined by a maqqef are joined by a hyphen, and other punctuation is dropped
+fn hebrew(chars: &[char]) -> String {
+    chars
+        .split(|c| *c == '\u{5be}')
+        .map(hebrew_word)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn hebrew_word(chars: &[char]) -> String {
+    let mut letters: Vec<HebrewLetter> = vec![];
+    for c in chars.iter().copied() {
+        if c == '\u{fb2a}' {
+            letters.push(HebrewLetter {
+                consonant: 'ש',
+                dagesh: false,
+                sin: false,
+                vowel: None,
+            });
+            continue;
+        }
+        if hebrew_consonant(c).is_some() {
+            letters.push(HebrewLetter {
+                consonant: c,
+                dagesh: false,
+                sin: false,
+                vowel: None,
+            });
+            continue;
+        }
+        let Some(last) = letters.last_mut() else {
+            continue;
+        };
+        match c {
+            '\u{5bc}' => last.dagesh = true,
+            '\u{5c2}' => last.sin = true,
+            c => {
+                if let Some(vowel) = Vowel::from_point(c) {
+                    last.vowel = Some(vowel);
+                }
+            }
+        }
+    }
+
+    let mut output = String::new();
+    for (idx, letter) in letters.iter().enumerate() {
+        let previous = idx.checked_sub(1).map(|idx| letters[idx]);
+        let next = letters.get(idx + 1);
+        let last = idx + 1 == letters.len();
+        let after_vowel = previous
+            .and_then(|previous| previous.vowel)
+            .is_some_and(|vowel| vowel != Vowel::Shewa);
+
+        // vowel letters, which lengthen the vowel before them rather than being read
+        let previous_vowel = previous.and_then(|previous| previous.vowel);
+        if letter.consonant == 'ו' && previous.is_some() && previous_vowel.is_none() {
+            match (letter.dagesh, letter.vowel) {
+                (true, None) => {
+                    output.push('û');
+                    continue;
+                }
+                (false, Some(Vowel::Holam)) => {
+                    output.push('ô');
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        if letter.consonant == 'ו' && idx == 0 && letter.dagesh && letter.vowel.is_none() {
+            output.push('û');
+            continue;
+        }
+        if letter.vowel.is_none() && !letter.dagesh {
+            let lengthened = match (letter.consonant, previous_vowel) {
+                ('י', Some(Vowel::Hiriq)) => Some('î'),
+                ('י', Some(Vowel::Tsere | Vowel::Segol)) => Some('ê'),
+                ('ה', Some(Vowel::Qamats)) if last => Some('â'),
+                ('ה', Some(Vowel::Segol | Vowel::Tsere)) if last => Some('ê'),
+                ('ה', Some(Vowel::Holam)) if last => Some('ô'),
+                _ => None,
+            };
+            // `ָיו` is still `āyw`
+            let before_waw = next.is_some_and(|next| next.consonant == 'ו');
+            if let Some(lengthened) = lengthened.filter(|_| !before_waw) {
+                output.pop();
+                output.push(lengthened);
+                continue;
+            }
+        }
+
+        let consonant = match letter.consonant {
+            'ש' if letter.sin => "ś",
+            c if letter.dagesh => hard(c).unwrap_or_else(|| hebrew_consonant(c).unwrap_or("")),
+            c => hebrew_consonant(c).unwrap_or(""),
+        };
+        // a dagesh after a vowel doubles the consonant, except a mappiq in a final `ה`
+        let doubled = letter.dagesh && after_vowel && !(last && letter.consonant == 'ה');
+        // a patah under a final guttural is said before it
+        let furtive = last
+            && letter.vowel == Some(Vowel::Patah)
+            && matches!(letter.consonant, 'ח' | 'ע' | 'ה');
+        if furtive {
+            output.push('a');
+        }
+        if doubled {
+            output.push_str(consonant);
+        }
+        output.push_str(consonant);
+        match letter.vowel {
+            Some(_) if furtive => {}
+            Some(Vowel::Shewa) => {
+                let vocal = !last
+                    && (idx == 0
+                        || doubled
+                        || previous_vowel == Some(Vowel::Shewa)
+                        || previous_vowel.is_some_and(Vowel::is_long));
+                if vocal {
+                    output.push_str(Vowel::Shewa.latin());
+                }
+            }
+            Some(vowel) => output.push_str(vowel.latin()),
+            None => {}
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greek_follows_the_sbl_table() {
+        assert_eq!(
+            transliterate("Ἐν ἀρχῇ ἦν ὁ λόγος,"),
+            "En archę̄ ēn ho logos,"
+        );
+        for (greek, latin) in [
+            ("ἄγγελος", "angelos"),
+            ("εὐαγγέλιον", "euangelion"),
+            ("υἱός", "huios"),
+            ("Ἰησοῦς", "Iēsous"),
+            ("ῥῆμα", "rhēma"),
+            ("Χριστός", "Christos"),
+            ("Αἰγύπτου", "Aigyptou"),
+            ("Ἁγίου", "Hagiou"),
+            ("ψυχή", "psychē"),
+            ("πρωΐ", "prōï"),
+        ] {
+            assert_eq!(transliterate(greek), latin, "{greek}");
+        }
+        // the same word with its marks as separate characters
+        assert_eq!(transliterate("υ\u{314}\u{301}ι\u{314}ος"), "huios");
+    }
+
+    #[test]
+    fn hebrew_follows_the_sbl_academic_table() {
+        for (hebrew, latin) in [
+            ("בְּרֵאשִׁית", "bərēʾšîṯ"),
+            ("אֱלֹהִים", "ʾĕlōhîm"),
+            ("שָׁלוֹם", "šālôm"),
+            ("תּוֹרָה", "tôrâ"),
+            ("דָּוִד", "dāwiḏ"),
+            ("רוּחַ", "rûaḥ"),
+            ("מֶלֶךְ", "meleḵ"),
+            ("הַמֶּלֶךְ", "hammeleḵ"),
+            ("יִשְׂרָאֵל", "yiśrāʾēl"),
+        ] {
+            assert_eq!(transliterate(hebrew), latin, "{hebrew}");
+        }
+        assert_eq!(transliterate("בֵּית־לֶחֶם הָאָרֶץ׃"), "bêṯ-leḥem hāʾāreṣ");
+    }
+
+    #[test]
+    fn other_text_is_kept() {
+        assert_eq!(transliterate("John 1:1 (λόγος)"), "John 1:1 (logos)");
+        assert!(has_original_language("see λόγος"));
+        assert!(!has_original_language("see logos"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use tower_lsp::lsp_types::Range;
+
+use crate::{
+    bible_api::BibleAPI,
+    book_reference::BookReference,
+    book_reference_segment::{BookReferenceSegment, BookReferenceSegments},
+};
+
+/**
+A stable id for a passage, like `ESV:49.1.3` for Ephesians 1:3
+
+- `translation:book.chapter.verse`, with the book as its id, so ids still resolve after book names
+  or label styles change
+- Ranges end with `-chapter.verse` (`ESV:49.1.3-1.14`), and segments are separated by commas
+  (`ESV:49.1.3-1.14,2.8`)
+- The translation is optional (`49.1.3`), for ids that aren't tied to one
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerseId {
+    pub translation: Option<String>,
+    pub book_id: usize,
+    /// the first and last `(chapter, verse)` of every segment
+    pub spans: Vec<[(usize, usize); 2]>,
+}
+
+impl VerseId {
+    pub fn new(api: &BibleAPI, book_ref: &BookReference) -> Self {
+        Self {
+            translation: Some(api.translation.abbreviation.clone()),
+            book_id: book_ref.book_id,
+            spans: book_ref
+                .segments
+                .iter()
+                .map(|seg| {
+                    [
+                        (seg.get_starting_chapter(), seg.get_starting_verse()),
+                        (seg.get_ending_chapter(), seg.get_ending_verse()),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// - The reference the id points to, with an empty range
+    /// - `None` if the translation doesn't have the book or the first verse
+    pub fn to_book_reference(&self, api: &BibleAPI) -> Option<BookReference> {
+        api.get_book_name(self.book_id)?;
+        let (chapter, verse) = self.spans.first()?[0];
+        if !api.is_valid_reference(self.book_id, chapter, verse) {
+            return None;
+        }
+        Some(BookReference {
+            range: Range::default(),
+            book_id: self.book_id,
+            segments: BookReferenceSegments(
+                self.spans
+                    .iter()
+                    .map(|[start, end]| BookReferenceSegment::from_span(*start, *end))
+                    .collect(),
+            ),
+            translation: None,
+            segment_ranges: vec![],
+        })
+    }
+}
+
+impl fmt::Display for VerseId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(translation) = &self.translation {
+            write!(f, "{translation}:")?;
+        }
+        write!(f, "{}.", self.book_id)?;
+        for (idx, [(start_chapter, start_verse), end]) in self.spans.iter().enumerate() {
+            if idx > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{start_chapter}.{start_verse}")?;
+            if (*start_chapter, *start_verse) != *end {
+                write!(f, "-{}.{}", end.0, end.1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn chapter_verse(text: &str) -> Option<(usize, usize)> {
+    let (chapter, verse) = text.split_once('.')?;
+    Some((chapter.parse().ok()?, verse.parse().ok()?))
+}
+
+impl FromStr for VerseId {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{id}` isn't a verse id like `ESV:49.1.3`");
+        let (translation, rest) = match id.trim().split_once(':') {
+            Some((translation, rest)) => (Some(translation.to_string()), rest),
+            None => (None, id.trim()),
+        };
+        let (book_id, spans) = rest.split_once('.').ok_or_else(invalid)?;
+        let book_id = book_id.parse().map_err(|_| invalid())?;
+        let spans = spans
+            .split(',')
+            .map(|span| match span.split_once('-') {
+                Some((start, end)) => Some([chapter_verse(start)?, chapter_verse(end)?]),
+                None => chapter_verse(span).map(|start| [start, start]),
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            translation: translation.filter(|translation| !translation.is_empty()),
+            book_id,
+            spans,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_round_trip() {
+        for id in ["ESV:49.1.3", "ESV:49.1.3-1.14,2.8", "43.3.16-3.18"] {
+            assert_eq!(id.parse::<VerseId>().unwrap().to_string(), id);
+        }
+        let id: VerseId = "KJV:19.23.1-24.2".parse().unwrap();
+        assert_eq!(id.translation.as_deref(), Some("KJV"));
+        assert_eq!(id.spans, vec![[(23, 1), (24, 2)]]);
+        assert!("Ephesians 1:3".parse::<VerseId>().is_err());
+        assert!("ESV:49.1".parse::<VerseId>().is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use cached::proc_macro::cached;
+use regex::Regex;
+use serde::Deserialize;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{
+    bible_api::BibleAPI, book_reference::BookReference,
+    book_reference_segment::BookReferenceSegment, document::DocumentSnapshot,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Next,
+    Previous,
+}
+
+/// What `bible.nextVerse`/`bible.prevVerse` do with the verse they find
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NavigationMode {
+    /// adds the verse under the reference, after any verses already inserted there
+    #[default]
+    Insert,
+    /// only shows the verse
+    Preview,
+}
+
+/// Matches verse lines inserted by the insert code action, like `[2:8] For by grace...`
+#[cached(size = 1)]
+fn inserted_verse() -> Regex {
+    Regex::new(r"^\[(\d+):(\d+)\]").unwrap()
+}
+
+/// - The verse before or after `(chapter, verse)`, crossing into the next or previous chapter
+/// - Stops at the start and end of the book
+pub fn adjacent_verse(
+    api: &BibleAPI,
+    book_id: usize,
+    (chapter, verse): (usize, usize),
+    direction: Direction,
+) -> Option<(usize, usize)> {
+    match direction {
+        Direction::Next => {
+            if api.is_valid_reference(book_id, chapter, verse + 1) {
+                Some((chapter, verse + 1))
+            } else if api.is_valid_reference(book_id, chapter + 1, 1) {
+                Some((chapter + 1, 1))
+            } else {
+                None
+            }
+        }
+        Direction::Previous => {
+            if verse > 1 {
+                Some((chapter, verse - 1))
+            } else if chapter > 1 {
+                let last = api.get_chapter_verse_count(book_id, chapter - 1)?;
+                Some((chapter - 1, last))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// - The reference under the cursor, or else the closest one before it
+/// - So running the command from the quote text below a reference still works
+pub fn reference_at(refs: &[BookReference], position: Position) -> Option<&BookReference> {
+    refs.iter()
+        .find(|book_ref| book_ref.range.start <= position && position <= book_ref.range.end)
+        .or_else(|| {
+            refs.iter()
+                .rev()
+                .find(|book_ref| book_ref.range.start <= position)
+        })
+}
+
+/// - The verse that comes after everything already cited (or inserted under the reference)
+/// - For [`Direction::Previous`], the verse before the first one
+pub fn target_verse(
+    api: &BibleAPI,
+    snapshot: &DocumentSnapshot,
+    book_ref: &BookReference,
+    direction: Direction,
+) -> Option<(usize, usize)> {
+    let mut verses = book_ref.verses(api);
+    verses.extend(inserted_lines(snapshot, book_ref).map(|(_, verse)| verse));
+    let from = match direction {
+        Direction::Next => verses.into_iter().max()?,
+        Direction::Previous => verses.into_iter().min()?,
+    };
+    adjacent_verse(api, book_ref.book_id, from, direction)
+}
+
+/// - Inserts `[chapter:verse] content` right below the reference
+/// - Next verses go after the verse lines already there, previous verses go before them
+pub fn insert_edit(
+    api: &BibleAPI,
+    snapshot: &DocumentSnapshot,
+    book_ref: &BookReference,
+    (chapter, verse): (usize, usize),
+    direction: Direction,
+) -> Option<TextEdit> {
+    let content = api.get_bible_contents(book_ref.book_id, chapter, verse)?;
+    let after_line = match direction {
+        Direction::Next => inserted_lines(snapshot, book_ref)
+            .last()
+            .map(|(line, _)| line)
+            .unwrap_or(book_ref.range.end.line),
+        Direction::Previous => book_ref.range.end.line,
+    };
+    let end = Position {
+        line: after_line,
+        character: snapshot
+            .line(after_line)
+            .map(|text| text.encode_utf16().count() as u32)
+            .unwrap_or(0),
+    };
+    Some(TextEdit {
+        range: Range { start: end, end },
+        new_text: format!("\n[{chapter}:{verse}] {content}"),
+    })
+}
+
+/**
+Extends the reference by one verse, returning the action title and edits
+
+- Forward extends the last segment, backward extends the first
+- If the quote was inserted below the reference (the `[chapter:verse]` lines), the new verse is
+  added to it too so the two stay in sync
+*/
+pub fn extend_edits(
+    api: &BibleAPI,
+    snapshot: &DocumentSnapshot,
+    book_ref: &BookReference,
+    direction: Direction,
+) -> Option<(String, Vec<TextEdit>)> {
+    let mut segments = book_ref.segments.clone();
+    let (title, target) = match direction {
+        Direction::Next => {
+            let last = segments.last_mut()?;
+            let end = (last.get_ending_chapter(), last.get_ending_verse());
+            let target = adjacent_verse(api, book_ref.book_id, end, direction)?;
+            *last = BookReferenceSegment::from_span(
+                (last.get_starting_chapter(), last.get_starting_verse()),
+                target,
+            );
+            ("to", target)
+        }
+        Direction::Previous => {
+            let first = segments.first_mut()?;
+            let start = (first.get_starting_chapter(), first.get_starting_verse());
+            let target = adjacent_verse(api, book_ref.book_id, start, direction)?;
+            *first = BookReferenceSegment::from_span(
+                target,
+                (first.get_ending_chapter(), first.get_ending_verse()),
+            );
+            ("back to", target)
+        }
+    };
+    let extended = BookReference {
+        segments,
+        segment_ranges: vec![],
+        ..book_ref.clone()
+    };
+    let title = format!(
+        "Extend {} {title} {}:{}",
+        book_ref.full_ref_label(api),
+        target.0,
+        target.1
+    );
+    let mut edits = vec![TextEdit {
+        range: book_ref.range,
+        new_text: extended.full_ref_label(api) + &book_ref.translation_suffix(),
+    }];
+    if inserted_lines(snapshot, book_ref).next().is_some() {
+        edits.extend(insert_edit(api, snapshot, book_ref, target, direction));
+    }
+    Some((title, edits))
+}
+
+/// The `[chapter:verse]` lines directly below the reference
+fn inserted_lines<'a>(
+    snapshot: &'a DocumentSnapshot,
+    book_ref: &BookReference,
+) -> impl Iterator<Item = (u32, (usize, usize))> + 'a {
+    (book_ref.range.end.line + 1..).map_while(|line| {
+        let cap = inserted_verse().captures(snapshot.line(line)?)?;
+        Some((line, (cap[1].parse().ok()?, cap[2].parse().ok()?)))
+    })
+}
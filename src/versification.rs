@@ -0,0 +1,237 @@
+use serde::Deserialize;
+use tower_lsp::lsp_types::TextEdit;
+
+use crate::{
+    bible_lsp::BibleLSP,
+    book_reference_segment::{BookReferenceSegment, BookReferenceSegments},
+    document::DocumentSnapshot,
+};
+
+/// Book id of Psalms
+pub const PSALMS: usize = 19;
+
+/**
+Which numbering a Psalm citation uses
+
+- Most Protestant translations (and the data files) follow the Hebrew (Masoretic) numbering
+- Catholic and Orthodox sources often follow the Greek (Septuagint/Vulgate) numbering, which is
+  one lower for most of the book, so `Psalm 50` there is `Psalm 51` here
+- Only chapters (and the verses of the split Psalms) are mapped, not the verse shifts some
+  translations have from numbering superscriptions
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PsalmNumbering {
+    #[default]
+    Hebrew,
+    Greek,
+}
+
+/// A Greek-numbered Psalm `(chapter, verse)` in the Hebrew numbering
+pub fn greek_to_hebrew(chapter: usize, verse: usize) -> (usize, usize) {
+    match chapter {
+        // Hebrew 9 and 10 are one Psalm in the Greek
+        9 if verse > 21 => (10, verse - 21),
+        10..=112 => (chapter + 1, verse),
+        // Hebrew 114 and 115 are one Psalm in the Greek
+        113 if verse > 8 => (115, verse - 8),
+        113 => (114, verse),
+        // and Hebrew 116 and 147 are two
+        114 => (116, verse),
+        115 => (116, verse + 9),
+        116..=145 => (chapter + 1, verse),
+        146 => (147, verse),
+        147 => (147, verse + 11),
+        _ => (chapter, verse),
+    }
+}
+
+/// The opposite of [`greek_to_hebrew`]
+pub fn hebrew_to_greek(chapter: usize, verse: usize) -> (usize, usize) {
+    match chapter {
+        10 => (9, verse + 21),
+        11..=113 => (chapter - 1, verse),
+        114 => (113, verse),
+        115 => (113, verse + 8),
+        116 if verse > 9 => (115, verse - 9),
+        116 => (114, verse),
+        117..=146 => (chapter - 1, verse),
+        147 if verse > 11 => (147, verse - 11),
+        147 => (146, verse),
+        _ => (chapter, verse),
+    }
+}
+
+fn map_segments(
+    segments: &BookReferenceSegments,
+    map: fn(usize, usize) -> (usize, usize),
+) -> BookReferenceSegments {
+    BookReferenceSegments(
+        segments
+            .iter()
+            .map(|seg| {
+                BookReferenceSegment::from_span(
+                    map(seg.get_starting_chapter(), seg.get_starting_verse()),
+                    map(seg.get_ending_chapter(), seg.get_ending_verse()),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Segments cited with Greek Psalm numbers, in the Hebrew numbering the data uses
+pub fn segments_to_hebrew(segments: &BookReferenceSegments) -> BookReferenceSegments {
+    map_segments(segments, greek_to_hebrew)
+}
+
+/// The opposite of [`segments_to_hebrew`]
+pub fn segments_to_greek(segments: &BookReferenceSegments) -> BookReferenceSegments {
+    map_segments(segments, hebrew_to_greek)
+}
+
+/**
+- Replaces a dual-numbered chapter at the start of `text` (` 51(50):3`) with just the Hebrew one
+  (` 51:3`), so it parses like any other citation
+- Whichever numbering is primary comes first, and the other is in parentheses
+- Returns the new text and how many bytes shorter it is, or `None` if there is no dual number
+*/
+pub fn strip_dual_chapter(text: &str, numbering: PsalmNumbering) -> Option<(String, usize)> {
+    let caps = crate::re::dual_psalm_chapter().captures(text)?;
+    let whole = caps.get(0)?;
+    let hebrew = match numbering {
+        PsalmNumbering::Hebrew => &caps[2],
+        PsalmNumbering::Greek => &caps[3],
+    };
+    let stripped = format!("{}{hebrew}{}", &caps[1], &text[whole.end()..]);
+    let removed = text.len() - stripped.len();
+    Some((stripped, removed))
+}
+
+/**
+A segment label (`51:1-3`) with both numberings, like `51(50):1-3`
+
+- The label's chapters are Hebrew, and the primary numbering comes first
+- Every chapter in the label gets the other number, so `116:1-117:2` is `116(114):1-117(116):2`
+*/
+pub fn dual_label(label: &str, numbering: PsalmNumbering) -> String {
+    crate::re::label_chapter()
+        .replace_all(label, |caps: &regex::Captures| {
+            let hebrew: usize = caps[1].parse().unwrap_or_default();
+            let greek = hebrew_to_greek(hebrew, 1).0;
+            match numbering {
+                PsalmNumbering::Hebrew => format!("{hebrew}({greek}):"),
+                PsalmNumbering::Greek => format!("{greek}({hebrew}):"),
+            }
+        })
+        .into_owned()
+}
+
+/**
+Edits rewriting every Psalm citation in a document from one numbering to the other, like after
+switching to a translation that numbers them differently
+
+- Citations are read in the `from` numbering, whatever the `psalmNumbering` setting is, so this
+  works before and after changing it
+- Dual-numbered citations (`Psalm 51(50):3`) keep both numbers, with `to` put first
+- The book is left as it was written, so `Ps 50:3` becomes `Ps 51:3`
+*/
+pub fn retarget_edits(
+    lsp: &BibleLSP,
+    snapshot: &DocumentSnapshot,
+    from: PsalmNumbering,
+    to: PsalmNumbering,
+) -> Vec<TextEdit> {
+    if from == to {
+        return vec![];
+    }
+    snapshot
+        .references(lsp)
+        .iter()
+        .filter(|book_ref| book_ref.book_id == PSALMS)
+        .filter_map(|book_ref| {
+            let start = snapshot
+                .line_index
+                .offset(&snapshot.text, book_ref.range.start)?;
+            let end = snapshot
+                .line_index
+                .offset(&snapshot.text, book_ref.range.end)?;
+            let written = &snapshot.text[start..end];
+            let book = lsp.api.book_abbreviation_regex().find(written)?;
+            let after_book = &written[book.end()..];
+            let separator = &after_book[..after_book.find(|c: char| c.is_ascii_digit())?];
+            let label = match crate::re::dual_psalm_chapter().is_match(after_book) {
+                // the parser already picked the Hebrew numbers out of these
+                true => dual_label(&book_ref.segments.label(), to),
+                false => {
+                    // back to what was written, then into `to`
+                    let as_written = match lsp.api.psalm_numbering {
+                        PsalmNumbering::Hebrew => book_ref.segments.clone(),
+                        PsalmNumbering::Greek => segments_to_greek(&book_ref.segments),
+                    };
+                    match from {
+                        PsalmNumbering::Hebrew => segments_to_greek(&as_written),
+                        PsalmNumbering::Greek => segments_to_hebrew(&as_written),
+                    }
+                    .label()
+                }
+            };
+            let new_text = format!(
+                "{}{separator}{label}{}",
+                book.as_str(),
+                book_ref.translation_suffix()
+            );
+            (new_text != written).then_some(TextEdit {
+                range: book_ref.range,
+                new_text,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn psalm_numbering_round_trips() {
+        assert_eq!(greek_to_hebrew(50, 3), (51, 3));
+        assert_eq!(greek_to_hebrew(9, 22), (10, 1));
+        assert_eq!(greek_to_hebrew(115, 1), (116, 10));
+        assert_eq!(greek_to_hebrew(150, 6), (150, 6));
+        for (chapter, verse) in [(9, 21), (10, 18), (23, 1), (114, 8), (115, 18), (116, 19)] {
+            let greek = hebrew_to_greek(chapter, verse);
+            assert_eq!(greek_to_hebrew(greek.0, greek.1), (chapter, verse));
+        }
+        assert_eq!(dual_label("51:1-3", PsalmNumbering::Hebrew), "51(50):1-3");
+        assert_eq!(
+            strip_dual_chapter(" 50(51):1", PsalmNumbering::Greek),
+            Some((String::from(" 51:1"), 4))
+        );
+    }
+
+    #[test]
+    fn psalms_are_retargeted_as_written() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let text = String::from("Ps 51:3-5, Psalm 51(50):1, and Gen 1:1");
+        let uri = tower_lsp::lsp_types::Url::parse("file:///notes/psalms.md").unwrap();
+        let snapshot = DocumentSnapshot::new(uri, 1, text, Default::default());
+        let edits = retarget_edits(
+            &lsp,
+            &snapshot,
+            PsalmNumbering::Hebrew,
+            PsalmNumbering::Greek,
+        );
+        let new_texts: Vec<&str> = edits.iter().map(|edit| edit.new_text.as_str()).collect();
+        assert_eq!(new_texts, vec!["Ps 50:3-5", "Psalm 50(51):1"]);
+        assert!(retarget_edits(
+            &lsp,
+            &snapshot,
+            PsalmNumbering::Greek,
+            PsalmNumbering::Greek
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,469 @@
+use std::fs;
+use std::path::PathBuf;
+
+use cached::proc_macro::cached;
+use regex::Regex;
+use tower_lsp::lsp_types::{
+    DocumentLink, DocumentSymbol, FoldingRange, FoldingRangeKind, Position, Range, SymbolKind, Url,
+};
+
+use crate::{bible_api::BibleAPI, book_overview, document::LineIndex, headings::Headings, paths};
+
+/**
+A document generated by the server, like a chapter opened by goto-definition
+
+- Each one has a logical `bible://` URI (`bible://ESV/Ephesians/2`, or `bible://ESV/Ephesians/intro`
+  for a book's introduction)
+- Editors can't open custom schemes without an extension, so they are materialized as markdown
+  files under [`paths::temp_dir`] and served as `file://` URIs
+- [`VirtualDocument::from_uri`] accepts either form, so the server can recognize its own
+  documents when the editor opens them
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VirtualDocument {
+    Chapter {
+        translation: String,
+        book_id: usize,
+        chapter: usize,
+    },
+    /// see [`book_overview::intro`]
+    Intro { translation: String, book_id: usize },
+}
+
+/// Matches markdown links to sibling chapter documents like `[Ephesians 3 →](3.md)`
+#[cached(size = 1)]
+fn chapter_link() -> Regex {
+    Regex::new(r"\[([^\]]+)\]\((\d+)\.md\)").unwrap()
+}
+
+/// Matches the `[2:1]` verse markers at the start of each line of a chapter
+#[cached(size = 1)]
+fn verse_marker() -> Regex {
+    Regex::new(r"^\[(\d+):(\d+)\] ?").unwrap()
+}
+
+/// A whole line as an LSP range
+fn line_range(line: usize, line_text: &str) -> Range {
+    Range {
+        start: Position {
+            line: line as u32,
+            character: 0,
+        },
+        end: Position {
+            line: line as u32,
+            character: line_text.encode_utf16().count() as u32,
+        },
+    }
+}
+
+impl VirtualDocument {
+    pub fn chapter(api: &BibleAPI, book_id: usize, chapter: usize) -> Self {
+        Self::Chapter {
+            translation: api.translation.abbreviation.clone(),
+            book_id,
+            chapter,
+        }
+    }
+
+    pub fn intro(api: &BibleAPI, book_id: usize) -> Self {
+        Self::Intro {
+            translation: api.translation.abbreviation.clone(),
+            book_id,
+        }
+    }
+
+    /// `(translation, book_id, page)`, where the page is a chapter number or `intro`
+    fn parts(&self) -> (&str, usize, String) {
+        match self {
+            VirtualDocument::Chapter {
+                translation,
+                book_id,
+                chapter,
+            } => (translation, *book_id, chapter.to_string()),
+            VirtualDocument::Intro {
+                translation,
+                book_id,
+            } => (translation, *book_id, String::from("intro")),
+        }
+    }
+
+    /// Ex: `bible://ESV/Ephesians/2`
+    pub fn bible_uri(&self, api: &BibleAPI) -> Option<Url> {
+        let (translation, book_id, page) = self.parts();
+        let mut url = Url::parse(&format!("bible://{translation}/")).ok()?;
+        url.path_segments_mut()
+            .ok()?
+            .pop_if_empty()
+            .push(&api.get_book_name(book_id)?)
+            .push(&page);
+        Some(url)
+    }
+
+    /// Ex: `/tmp/bible_lsp-<user>/documents/ESV/Ephesians/2.md`
+    pub fn path(&self, api: &BibleAPI) -> Option<PathBuf> {
+        let (translation, book_id, page) = self.parts();
+        let book_name = api.get_book_name(book_id)?;
+        Some(
+            paths::temp_dir()
+                .join(paths::sanitize_file_name(translation))
+                .join(paths::sanitize_file_name(&book_name))
+                .join(format!("{page}.md")),
+        )
+    }
+
+    /// Recognizes both `bible://` URIs and the files they are materialized to
+    pub fn from_uri(api: &BibleAPI, uri: &Url) -> Option<Self> {
+        let parts: Vec<String> = match uri.scheme() {
+            "bible" => std::iter::once(uri.host_str()?.to_string())
+                .chain(
+                    uri.path_segments()?
+                        .map(|seg| percent_decode(seg).trim_end_matches(".md").to_string()),
+                )
+                .collect(),
+            "file" => {
+                let path = paths::url_to_path(uri)?;
+                let relative = path.strip_prefix(paths::temp_dir()).ok()?;
+                relative
+                    .iter()
+                    .map(|part| part.to_string_lossy().trim_end_matches(".md").to_string())
+                    .collect()
+            }
+            _ => return None,
+        };
+        match parts.as_slice() {
+            [translation, book, page] => {
+                // `bible://ESV/49/2` is the same chapter, by book id like a verse id
+                let book_id = match book.parse::<usize>() {
+                    Ok(book_id) => book_id,
+                    Err(_) => api.get_book_id(book)?,
+                };
+                if page == "intro" {
+                    api.get_book_chapter_count(book_id)?;
+                    return Some(Self::Intro {
+                        translation: translation.clone(),
+                        book_id,
+                    });
+                }
+                let chapter = page.parse().ok()?;
+                api.get_chapter_verse_count(book_id, chapter)?;
+                Some(Self::Chapter {
+                    translation: translation.clone(),
+                    book_id,
+                    chapter,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /**
+    Renders a chapter like the following:
+
+    ```text
+    # Ephesians
+
+    [1](1.md) · **2** · [3](3.md) · [4](4.md) · [5](5.md) · [6](6.md)
+
+    ## Ephesians 2
+
+    [2:1] And you were dead in the trespasses and sins
+    ...
+
+    ---
+
+    [← Ephesians 1](1.md) | [Ephesians 3 →](3.md)
+    ```
+
+    - Introductions are rendered by [`book_overview::intro`], with an outline from `headings`
+    */
+    pub fn render(&self, api: &BibleAPI, headings: &Headings) -> Option<String> {
+        match self {
+            VirtualDocument::Intro { book_id, .. } => book_overview::intro(api, *book_id, headings),
+            VirtualDocument::Chapter {
+                book_id, chapter, ..
+            } => {
+                let book_id = *book_id;
+                let chapter = *chapter;
+                let book_name = api.get_book_name(book_id)?;
+                let chapter_count = api.get_book_chapter_count(book_id)?;
+
+                let table_of_contents = (1..=chapter_count)
+                    .map(|other| match other == chapter {
+                        true => format!("**{other}**"),
+                        false => format!("[{other}]({other}.md)"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" · ");
+
+                let content = api
+                    .get_all_verses(book_id, chapter)?
+                    .filter_map(|verse| {
+                        api.get_bible_contents(book_id, chapter, verse)
+                            .map(|content| format!("[{}:{}] {}", chapter, verse, content))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                let mut navigation = vec![];
+                if chapter > 1 {
+                    let previous = chapter - 1;
+                    navigation.push(format!("[← {book_name} {previous}]({previous}.md)"));
+                }
+                if chapter < chapter_count {
+                    let next = chapter + 1;
+                    navigation.push(format!("[{book_name} {next} →]({next}.md)"));
+                }
+                let navigation = navigation.join(" | ");
+
+                Some(format!(
+                    "# {book_name}\n\n{table_of_contents}\n\n## {book_name} {chapter}\n\n{content}\n\n---\n\n{navigation}\n"
+                ))
+            }
+        }
+    }
+
+    /// - Writes the document to disk and returns its `file://` URI
+    /// - The file is only rewritten when its contents changed, so editors that already have it
+    ///   open don't get told it changed on disk
+    pub fn materialize(&self, api: &BibleAPI, headings: &Headings) -> std::io::Result<Url> {
+        let path = self
+            .path(api)
+            .ok_or_else(|| std::io::Error::other("Invalid book"))?;
+        let contents = self
+            .render(api, headings)
+            .ok_or_else(|| std::io::Error::other("Invalid book or chapter"))?;
+        paths::create_temp_dir()?;
+        if fs::read_to_string(&path).ok().as_ref() != Some(&contents) {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, contents)?;
+        }
+        paths::path_to_url(&path)
+            .ok_or_else(|| std::io::Error::other("Failed to convert path to URI"))
+    }
+
+    /// - Links for the table of contents and the previous/next chapter footer, or an
+    ///   introduction's list of chapters
+    /// - Targets are materialized here so following a link always opens a real file
+    pub fn document_links(&self, api: &BibleAPI, text: &str) -> Vec<DocumentLink> {
+        let (translation, book_id, _) = self.parts();
+        let line_index = LineIndex::new(text);
+        (0..line_index.line_count())
+            .filter_map(|line| Some((line, line_index.line(text, line)?)))
+            .flat_map(|(line, line_text)| {
+                chapter_link()
+                    .captures_iter(line_text)
+                    .filter_map(|cap| {
+                        let whole = cap.get(0)?;
+                        let chapter = cap.get(2)?.as_str().parse().ok()?;
+                        let target = VirtualDocument::Chapter {
+                            translation: translation.to_string(),
+                            book_id,
+                            chapter,
+                        }
+                        .materialize(api, &Headings::default())
+                        .ok()?;
+                        Some(DocumentLink {
+                            range: Range {
+                                start: Position {
+                                    line: line as u32,
+                                    character: line_text[..whole.start()].encode_utf16().count()
+                                        as u32,
+                                },
+                                end: Position {
+                                    line: line as u32,
+                                    character: line_text[..whole.end()].encode_utf16().count()
+                                        as u32,
+                                },
+                            },
+                            target: Some(target),
+                            tooltip: Some(format!("Open {}", cap.get(1)?.as_str())),
+                            data: None,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// - One symbol per verse, nested under the chapter heading, so the outline works as a
+    ///   table of contents while reading
+    /// - The verse lines are found in the text the editor has open (rather than re-rendering) so
+    ///   the ranges are always right
+    /// - Introductions have one symbol per section instead
+    pub fn document_symbols(&self, api: &BibleAPI, text: &str) -> Vec<DocumentSymbol> {
+        let (book_id, chapter) = match self {
+            VirtualDocument::Chapter {
+                book_id, chapter, ..
+            } => (book_id, chapter),
+            VirtualDocument::Intro { .. } => return section_symbols(text),
+        };
+        let Some(book_name) = api.get_book_name(*book_id) else {
+            return vec![];
+        };
+        let line_index = LineIndex::new(text);
+        let lines = (0..line_index.line_count())
+            .filter_map(|line| Some((line, line_index.line(text, line)?)))
+            .collect::<Vec<_>>();
+
+        #[allow(deprecated)]
+        let verses = lines
+            .iter()
+            .filter_map(|(line, line_text)| {
+                let cap = verse_marker().captures(line_text)?;
+                let verse = cap.get(2)?.as_str();
+                let content = &line_text[cap.get(0)?.end()..];
+                let range = line_range(*line, line_text);
+                Some(DocumentSymbol {
+                    name: format!("{book_name} {chapter}:{verse}"),
+                    detail: Some(content.chars().take(60).collect()),
+                    kind: SymbolKind::KEY,
+                    tags: None,
+                    deprecated: None,
+                    range,
+                    selection_range: range,
+                    children: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let heading = format!("## {book_name} {chapter}");
+        let Some((heading_line, heading_text)) =
+            lines.iter().find(|(_, line_text)| *line_text == heading)
+        else {
+            return verses;
+        };
+        let mut range = line_range(*heading_line, heading_text);
+        if let Some(last) = verses.last() {
+            range.end = last.range.end;
+        }
+        #[allow(deprecated)]
+        let chapter_symbol = DocumentSymbol {
+            name: format!("{book_name} {chapter}"),
+            detail: Some(format!("{} verses", verses.len())),
+            kind: SymbolKind::MODULE,
+            tags: None,
+            deprecated: None,
+            range,
+            selection_range: line_range(*heading_line, heading_text),
+            children: Some(verses),
+        };
+        vec![chapter_symbol]
+    }
+
+    /// - Folds the table of contents and the chapter body (heading through the last verse)
+    /// - Folds each section of an introduction
+    pub fn folding_ranges(&self, text: &str) -> Vec<FoldingRange> {
+        let line_index = LineIndex::new(text);
+        let lines = (0..line_index.line_count())
+            .filter_map(|line| Some((line as u32, line_index.line(text, line)?)))
+            .collect::<Vec<_>>();
+        if let VirtualDocument::Intro { .. } = self {
+            return sections(&lines)
+                .into_iter()
+                .map(|(start, end)| FoldingRange {
+                    start_line: start,
+                    end_line: end,
+                    kind: Some(FoldingRangeKind::Region),
+                    ..Default::default()
+                })
+                .collect();
+        }
+        let mut ranges = vec![];
+
+        let heading = lines
+            .iter()
+            .find(|(_, line_text)| line_text.starts_with("## "))
+            .map(|(line, _)| *line);
+        let last_verse = lines
+            .iter()
+            .rev()
+            .find(|(_, line_text)| verse_marker().is_match(line_text))
+            .map(|(line, _)| *line);
+
+        // everything between the book title and the chapter heading
+        if let Some(heading) = heading.filter(|heading| *heading > 1) {
+            ranges.push(FoldingRange {
+                start_line: 0,
+                end_line: heading - 1,
+                kind: Some(FoldingRangeKind::Region),
+                collapsed_text: Some(String::from("Contents")),
+                ..Default::default()
+            });
+        }
+        if let (Some(heading), Some(last_verse)) = (heading, last_verse) {
+            ranges.push(FoldingRange {
+                start_line: heading,
+                end_line: last_verse,
+                kind: Some(FoldingRangeKind::Region),
+                ..Default::default()
+            });
+        }
+        ranges
+    }
+}
+
+/// `(heading, last line)` of each `## ` section, without the blank lines after it
+fn sections(lines: &[(u32, &str)]) -> Vec<(u32, u32)> {
+    let mut sections: Vec<(u32, u32)> = vec![];
+    for (line, line_text) in lines {
+        if line_text.starts_with("## ") {
+            sections.push((*line, *line));
+        } else if let Some(section) = sections.last_mut().filter(|_| !line_text.trim().is_empty()) {
+            section.1 = *line;
+        }
+    }
+    sections
+}
+
+/// One symbol per `## ` section of an introduction
+fn section_symbols(text: &str) -> Vec<DocumentSymbol> {
+    let line_index = LineIndex::new(text);
+    let lines = (0..line_index.line_count())
+        .filter_map(|line| Some((line as u32, line_index.line(text, line)?)))
+        .collect::<Vec<_>>();
+    #[allow(deprecated)]
+    sections(&lines)
+        .into_iter()
+        .map(|(start, end)| {
+            let heading = lines[start as usize].1;
+            let selection_range = line_range(start as usize, heading);
+            DocumentSymbol {
+                name: heading.trim_start_matches("## ").to_string(),
+                detail: None,
+                kind: SymbolKind::NAMESPACE,
+                tags: None,
+                deprecated: None,
+                range: Range {
+                    start: selection_range.start,
+                    end: line_range(end as usize, lines[end as usize].1).end,
+                },
+                selection_range,
+                children: None,
+            }
+        })
+        .collect()
+}
+
+/// Just enough percent decoding for book names with spaces and accents
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            if let Some(Ok(byte)) = input
+                .get(idx + 1..idx + 3)
+                .map(|hex| u8::from_str_radix(hex, 16))
+            {
+                decoded.push(byte);
+                idx += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,142 @@
+/// - Past this many word pairs, the verses are treated as entirely different
+/// - The table is `ours × theirs`, and no verse comes close, so this only guards against data
+///   files with a whole chapter in one verse
+const MAX_CELLS: usize = 1_000_000;
+
+/// A run of words that both texts share, or a spot where they differ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    Same(Vec<&'a str>),
+    /// Either side can be empty, for words only one of the texts has
+    Changed {
+        ours: Vec<&'a str>,
+        theirs: Vec<&'a str>,
+    },
+}
+
+/// - What words are compared by, so `world,` and `World` are the same word
+/// - Only punctuation around the word is dropped, so `Lord's` and `Lords` still differ
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/**
+The words of `ours`, split into what `theirs` also has and what it words differently
+
+- The longest common subsequence of words, so an inserted word doesn't make the rest of the verse
+  look changed
+- Whitespace is not kept, since only the words are shown
+*/
+pub fn words<'a>(ours: &'a str, theirs: &'a str) -> Vec<Chunk<'a>> {
+    let ours: Vec<&str> = ours.split_whitespace().collect();
+    let theirs: Vec<&str> = theirs.split_whitespace().collect();
+    if ours.len().saturating_mul(theirs.len()) > MAX_CELLS {
+        return vec![Chunk::Changed { ours, theirs }];
+    }
+    let (our_keys, their_keys): (Vec<String>, Vec<String>) = (
+        ours.iter().map(|word| normalize(word)).collect(),
+        theirs.iter().map(|word| normalize(word)).collect(),
+    );
+    // lengths[i][j] is the longest common run of ours[i..] and theirs[j..]
+    let mut lengths = vec![vec![0usize; theirs.len() + 1]; ours.len() + 1];
+    for i in (0..ours.len()).rev() {
+        for j in (0..theirs.len()).rev() {
+            lengths[i][j] = if our_keys[i] == their_keys[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut chunks = vec![];
+    let (mut same, mut ours_changed, mut theirs_changed) = (vec![], vec![], vec![]);
+    let flush_changed =
+        |chunks: &mut Vec<Chunk<'a>>, ours: &mut Vec<&'a str>, theirs: &mut Vec<&'a str>| {
+            if !ours.is_empty() || !theirs.is_empty() {
+                chunks.push(Chunk::Changed {
+                    ours: std::mem::take(ours),
+                    theirs: std::mem::take(theirs),
+                });
+            }
+        };
+    let (mut i, mut j) = (0, 0);
+    while i < ours.len() || j < theirs.len() {
+        if i < ours.len() && j < theirs.len() && our_keys[i] == their_keys[j] {
+            flush_changed(&mut chunks, &mut ours_changed, &mut theirs_changed);
+            same.push(ours[i]);
+            i += 1;
+            j += 1;
+            continue;
+        }
+        if !same.is_empty() {
+            chunks.push(Chunk::Same(std::mem::take(&mut same)));
+        }
+        if j == theirs.len() || (i < ours.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            ours_changed.push(ours[i]);
+            i += 1;
+        } else {
+            theirs_changed.push(theirs[j]);
+            j += 1;
+        }
+    }
+    flush_changed(&mut chunks, &mut ours_changed, &mut theirs_changed);
+    if !same.is_empty() {
+        chunks.push(Chunk::Same(same));
+    }
+    chunks
+}
+
+/**
+`ours` in Markdown, with the words `theirs` doesn't share in bold
+
+- What `theirs` has instead follows in italics, labeled with `label` (like `KJV: `)
+- Ex: `For God so **loved** *(KJV: cared for)* the world`
+*/
+pub fn markdown(ours: &str, theirs: &str, label: &str) -> String {
+    words(ours, theirs)
+        .into_iter()
+        .filter_map(|chunk| match chunk {
+            Chunk::Same(words) => Some(words.join(" ")),
+            Chunk::Changed { ours, theirs } => {
+                let ours = (!ours.is_empty()).then(|| format!("**{}**", ours.join(" ")));
+                let theirs =
+                    (!theirs.is_empty()).then(|| format!("*({label}{})*", theirs.join(" ")));
+                match (ours, theirs) {
+                    (Some(ours), Some(theirs)) => Some(format!("{ours} {theirs}")),
+                    (ours, theirs) => ours.or(theirs),
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_differing_words_are_marked() {
+        assert_eq!(
+            markdown(
+                "For God so loved the world, that he gave his only Son,",
+                "For God so loved the world, that he gave his only begotten Son,",
+                "KJV: "
+            ),
+            "For God so loved the world, that he gave his only *(KJV: begotten)* Son,"
+        );
+        assert_eq!(
+            words("In the beginning God", "In the beginning was the Word"),
+            vec![
+                Chunk::Same(vec!["In", "the", "beginning"]),
+                Chunk::Changed {
+                    ours: vec!["God"],
+                    theirs: vec!["was", "the", "Word"]
+                },
+            ]
+        );
+        assert_eq!(markdown("Jesus wept.", "Jesus wept", ""), "Jesus wept.");
+    }
+}
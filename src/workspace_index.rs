@@ -0,0 +1,632 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard};
+
+use ignore::gitignore::GitignoreBuilder;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{Match, WalkBuilder};
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Range, Url};
+
+use crate::{
+    bible_api::BibleAPI,
+    bible_lsp::BibleLSP,
+    book_reference::BookReference,
+    front_matter,
+    index_cache::{FileStamp, IndexCache},
+    paths, quote_limits,
+    verse_id::VerseId,
+};
+
+/// A reference found in a workspace file, stripped down to what queries need
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexedReference {
+    pub range: Range,
+    pub book_id: usize,
+    /// Ex: `Ephesians 1:1-4`
+    pub label: String,
+    /// - Ex: `ESV:49.1.1-1.4`, see [`VerseId`]
+    /// - For anything storing references outside the index, since labels change with book names
+    pub id: String,
+    /// the first and last `(chapter, verse)` of every segment
+    pub spans: Vec<[(usize, usize); 2]>,
+    /// - The front matter field it is the value of, like `passage`
+    /// - `None` for references in the rest of the text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// the translation it was cited in, like `KJV` in `John 3:16 KJV`, see
+    /// [`BookReference::translation`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+    /// whether its text is in the file, see [`quote_limits::quoted_references`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quoted: bool,
+}
+
+impl IndexedReference {
+    pub fn new(lsp: &BibleLSP, book_ref: &BookReference) -> Self {
+        Self {
+            range: book_ref.range,
+            book_id: book_ref.book_id,
+            label: book_ref.full_ref_label(&lsp.api),
+            id: VerseId::new(&lsp.api, book_ref).to_string(),
+            spans: book_ref
+                .segments
+                .iter()
+                .map(|seg| {
+                    [
+                        (seg.get_starting_chapter(), seg.get_starting_verse()),
+                        (seg.get_ending_chapter(), seg.get_ending_verse()),
+                    ]
+                })
+                .collect(),
+            field: None,
+            translation: book_ref.translation.clone(),
+            quoted: false,
+        }
+    }
+
+    /// Every `(chapter, verse)` cited, expanded the same way as `BookReferenceSegment::verses`
+    pub fn verses(&self, api: &BibleAPI) -> Vec<(usize, usize)> {
+        let mut verses = vec![];
+        for [(start_chapter, start_verse), (end_chapter, end_verse)] in self.spans.iter().copied() {
+            for chapter in start_chapter..=end_chapter {
+                let Some(verse_count) = api.get_chapter_verse_count(self.book_id, chapter) else {
+                    continue;
+                };
+                let first = match chapter == start_chapter {
+                    true => start_verse,
+                    false => 1,
+                };
+                let last = match chapter == end_chapter {
+                    true => end_verse.min(verse_count),
+                    false => verse_count,
+                };
+                verses.extend((first..=last).map(|verse| (chapter, verse)));
+            }
+        }
+        verses
+    }
+
+    /// Roughly how many bytes this takes, for [`WorkspaceIndex::set_budget`]
+    pub fn approximate_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.label.len()
+            + self.id.len()
+            + std::mem::size_of_val(self.spans.as_slice())
+    }
+
+    /// Whether any verse is cited by both references
+    pub fn overlaps(&self, other: &IndexedReference) -> bool {
+        self.book_id == other.book_id
+            && self.spans.iter().any(|[start, end]| {
+                other
+                    .spans
+                    .iter()
+                    .any(|[other_start, other_end]| start <= other_end && other_start <= end)
+            })
+    }
+}
+
+/**
+Every reference in every text file of the workspace
+
+- Built by walking the workspace folders once at startup, reusing whatever the [`IndexCache`]
+  from last time still has right
+- Open documents are re-indexed as they change, so queries see unsaved edits
+- Can be held to a memory budget, see [`WorkspaceIndex::set_budget`]
+*/
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    roots: RwLock<Vec<PathBuf>>,
+    files: RwLock<BTreeMap<Url, Vec<IndexedReference>>>,
+    /// - The on-disk version of each file that was indexed
+    /// - Files indexed from unsaved edits have no stamp, so they aren't cached
+    stamps: RwLock<BTreeMap<Url, FileStamp>>,
+    /// see [`crate::config::Config::ignore_globs`]
+    ignore_globs: RwLock<Vec<String>>,
+    /// see [`crate::config::Config::front_matter_fields`]
+    front_matter_fields: RwLock<Vec<String>>,
+    /// roughly how many bytes each file's references take, and when it was last indexed
+    usage: RwLock<BTreeMap<Url, FileUsage>>,
+    /// in bytes
+    budget: RwLock<Option<usize>>,
+    /// files dropped to stay in the budget
+    evicted: RwLock<BTreeSet<Url>>,
+    /// bumped for every file indexed, so the least recently indexed can be found
+    clock: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FileUsage {
+    bytes: usize,
+    indexed_at: u64,
+}
+
+/// What [`WorkspaceIndex::scan`] did
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanSummary {
+    pub files: usize,
+    /// files that were unchanged since the last session
+    pub cached: usize,
+}
+
+impl WorkspaceIndex {
+    pub fn set_roots(&self, roots: Vec<PathBuf>) {
+        *self.roots.write().unwrap() = roots;
+    }
+
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots.read().unwrap().clone()
+    }
+
+    pub fn set_ignore_globs(&self, ignore_globs: Vec<String>) {
+        *self.ignore_globs.write().unwrap() = ignore_globs;
+    }
+
+    pub fn set_front_matter_fields(&self, front_matter_fields: Vec<String>) {
+        *self.front_matter_fields.write().unwrap() = front_matter_fields;
+    }
+
+    pub fn front_matter_fields(&self) -> Vec<String> {
+        self.front_matter_fields.read().unwrap().clone()
+    }
+
+    /// Only files inside a workspace folder, and not ignored by the same rules as [`walk`], belong
+    /// in the index
+    pub fn contains_path(&self, uri: &Url) -> bool {
+        let Some(path) = paths::url_to_path(uri) else {
+            return false;
+        };
+        let ignore_globs = self.ignore_globs.read().unwrap();
+        self.roots
+            .read()
+            .unwrap()
+            .iter()
+            .any(|root| path.starts_with(root) && !is_ignored(root, &path, &ignore_globs))
+    }
+
+    /// - Indexes every file with one of the extensions
+    /// - Files that haven't changed since the last session are taken from the cache instead of
+    ///   being read again
+    pub fn scan(&self, lsp: &BibleLSP, extensions: &[String]) -> ScanSummary {
+        let roots = self.roots();
+        let mut cache = IndexCache::load(
+            &IndexCache::path(&roots),
+            &lsp.api.translation.abbreviation,
+            &self.front_matter_fields(),
+            &lsp.ignored_phrases,
+        );
+        // rescans (after a big `git checkout`) can reuse what is already in memory too
+        for (uri, stamp) in self.stamps.read().unwrap().iter() {
+            if let Some(references) = self.files.read().unwrap().get(uri) {
+                cache.insert(uri.clone(), *stamp, references.clone());
+            }
+        }
+        let mut files = vec![];
+        let ignore_globs = self.ignore_globs.read().unwrap().clone();
+        for root in roots.iter() {
+            walk(root, extensions, &ignore_globs, &mut files);
+        }
+        let mut summary = ScanSummary::default();
+        let mut seen = BTreeSet::new();
+        for path in files {
+            let Some(uri) = paths::path_to_url(&path) else {
+                continue;
+            };
+            seen.insert(uri.clone());
+            // open documents were already indexed from what the editor has
+            if self.is_open(&uri) {
+                summary.files += 1;
+                continue;
+            }
+            let cached =
+                FileStamp::of(&path).and_then(|stamp| Some((stamp, cache.get(&uri, stamp)?)));
+            match cached {
+                Some((stamp, references)) => {
+                    self.insert(uri.clone(), references.clone());
+                    self.stamps.write().unwrap().insert(uri, stamp);
+                    summary.cached += 1;
+                }
+                None if self.index_file(lsp, uri) => {}
+                None => continue,
+            }
+            summary.files += 1;
+        }
+        // files deleted since they were indexed
+        let deleted: Vec<Url> = self
+            .stamps
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|uri| !seen.contains(*uri))
+            .cloned()
+            .collect();
+        for uri in deleted {
+            self.remove(&uri);
+        }
+        self.trim();
+        summary
+    }
+
+    /// Indexed from an open document rather than from disk
+    fn is_open(&self, uri: &Url) -> bool {
+        self.files.read().unwrap().contains_key(uri)
+            && !self.stamps.read().unwrap().contains_key(uri)
+    }
+
+    /// - Indexes the file as it is on disk, or removes it if it can't be read anymore
+    /// - Returns whether it was indexed
+    pub fn index_file(&self, lsp: &BibleLSP, uri: Url) -> bool {
+        let Some(path) = paths::url_to_path(&uri) else {
+            return false;
+        };
+        let stamp = FileStamp::of(&path);
+        let Ok(text) = fs::read_to_string(&path) else {
+            self.remove(&uri);
+            return false;
+        };
+        let refs = lsp.find_book_references(&text).unwrap_or_default();
+        self.index_references(lsp, uri.clone(), &text, &refs);
+        if let Some(stamp) = stamp {
+            self.stamps.write().unwrap().insert(uri, stamp);
+        }
+        self.trim();
+        true
+    }
+
+    /**
+    Indexes references from an open document, which may not match what is on disk
+
+    - References in the values of [`Self::front_matter_fields`] are marked with the field, and
+      whole chapters there (which `refs` never has) are added
+    */
+    pub fn index_references(&self, lsp: &BibleLSP, uri: Url, text: &str, refs: &[BookReference]) {
+        let mut indexed: Vec<IndexedReference> = refs
+            .iter()
+            .map(|book_ref| IndexedReference::new(lsp, book_ref))
+            .collect();
+        let fields = self.front_matter_fields();
+        for passage in front_matter::passages(lsp, text, &fields) {
+            for book_ref in passage.references.iter() {
+                let field = Some(passage.field.key.clone());
+                match indexed
+                    .iter_mut()
+                    .find(|other| other.range == book_ref.range)
+                {
+                    Some(existing) => existing.field = field,
+                    None => indexed.push(IndexedReference {
+                        field,
+                        ..IndexedReference::new(lsp, book_ref)
+                    }),
+                }
+            }
+        }
+        for book_ref in quote_limits::quoted_references(lsp, text, refs) {
+            match indexed
+                .iter_mut()
+                .find(|other| other.range == book_ref.range)
+            {
+                Some(existing) => {
+                    existing.quoted = true;
+                    existing.translation = book_ref.translation;
+                }
+                None => indexed.push(IndexedReference {
+                    quoted: true,
+                    ..IndexedReference::new(lsp, &book_ref)
+                }),
+            }
+        }
+        self.stamps.write().unwrap().remove(&uri);
+        self.insert(uri, indexed);
+    }
+
+    fn insert(&self, uri: Url, references: Vec<IndexedReference>) {
+        self.evicted.write().unwrap().remove(&uri);
+        let usage = FileUsage {
+            bytes: uri.as_str().len()
+                + references
+                    .iter()
+                    .map(IndexedReference::approximate_size)
+                    .sum::<usize>(),
+            indexed_at: self.clock.fetch_add(1, Ordering::Relaxed),
+        };
+        self.usage.write().unwrap().insert(uri.clone(), usage);
+        self.files.write().unwrap().insert(uri, references);
+    }
+
+    pub fn remove(&self, uri: &Url) {
+        self.stamps.write().unwrap().remove(uri);
+        self.files.write().unwrap().remove(uri);
+        self.usage.write().unwrap().remove(uri);
+        self.evicted.write().unwrap().remove(uri);
+    }
+
+    /// Forgets every file, like before the first scan
+    pub fn clear(&self) {
+        self.files.write().unwrap().clear();
+        self.stamps.write().unwrap().clear();
+        self.usage.write().unwrap().clear();
+        self.evicted.write().unwrap().clear();
+    }
+
+    /**
+    Keeps the references in memory under `bytes`, or lifts the limit with `None`
+
+    - The least recently indexed files are dropped first, and they are indexed again when they
+      change or on the next scan
+    - Only files that match what is on disk are dropped, never open documents
+    - Until then, backlinks and coverage leave them out
+    */
+    pub fn set_budget(&self, bytes: Option<usize>) {
+        *self.budget.write().unwrap() = bytes;
+        self.trim();
+    }
+
+    fn trim(&self) {
+        let Some(budget) = *self.budget.read().unwrap() else {
+            return;
+        };
+        let mut usage = self.usage.write().unwrap();
+        let mut total: usize = usage.values().map(|usage| usage.bytes).sum();
+        if total <= budget {
+            return;
+        }
+        let mut oldest: Vec<(u64, Url)> = {
+            let stamps = self.stamps.read().unwrap();
+            usage
+                .iter()
+                .filter(|(uri, _)| stamps.contains_key(*uri))
+                .map(|(uri, usage)| (usage.indexed_at, uri.clone()))
+                .collect()
+        };
+        oldest.sort();
+        let mut files = self.files.write().unwrap();
+        let mut evicted = self.evicted.write().unwrap();
+        for (_, uri) in oldest {
+            if total <= budget {
+                break;
+            }
+            if let Some(dropped) = usage.remove(&uri) {
+                total -= dropped.bytes;
+            }
+            files.remove(&uri);
+            evicted.insert(uri);
+        }
+    }
+
+    /// Files in memory, roughly how many bytes they take, and how many were dropped to stay in
+    /// the budget
+    pub fn usage(&self) -> (usize, usize, usize) {
+        (
+            self.files.read().unwrap().len(),
+            self.usage
+                .read()
+                .unwrap()
+                .values()
+                .map(|usage| usage.bytes)
+                .sum(),
+            self.evicted.read().unwrap().len(),
+        )
+    }
+
+    /// Writes every file that matches what is on disk to the cache for the next session
+    pub fn save_cache(&self, lsp: &BibleLSP) -> std::io::Result<()> {
+        let mut cache = IndexCache::new(
+            &lsp.api.translation.abbreviation,
+            &self.front_matter_fields(),
+            &lsp.ignored_phrases,
+        );
+        let files = self.files.read().unwrap();
+        for (uri, stamp) in self.stamps.read().unwrap().iter() {
+            if let Some(references) = files.get(uri) {
+                cache.insert(uri.clone(), *stamp, references.clone());
+            }
+        }
+        cache.save(&IndexCache::path(&self.roots()))
+    }
+
+    pub fn files(&self) -> RwLockReadGuard<'_, BTreeMap<Url, Vec<IndexedReference>>> {
+        self.files.read().unwrap()
+    }
+
+    /// The path relative to its workspace folder, which is much easier to read than the URI
+    pub fn display_name(&self, uri: &Url) -> String {
+        let Some(path) = paths::url_to_path(uri) else {
+            return uri.to_string();
+        };
+        self.roots
+            .read()
+            .unwrap()
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+}
+
+/// `ignore_globs` as overrides, which are a whitelist unless negated
+fn overrides(root: &Path, ignore_globs: &[String]) -> Override {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in ignore_globs {
+        // a typo in one glob shouldn't stop the others from working
+        _ = builder.add(&format!("!{glob}"));
+    }
+    builder.build().unwrap_or_else(|_| Override::empty())
+}
+
+/**
+Recursively collects files with a matching extension
+
+- Skips hidden files and folders like `.git`
+- Honors `.gitignore` and `.ignore` files, nested ones too, even outside a git repository, so
+  `node_modules` and build output aren't scanned
+- `ignore_globs` are gitignore-style patterns relative to `dir`, like `private/` or `*.draft.md`
+*/
+pub(crate) fn walk(
+    dir: &Path,
+    extensions: &[String],
+    ignore_globs: &[String],
+    files: &mut Vec<PathBuf>,
+) {
+    let walker = WalkBuilder::new(dir)
+        .require_git(false)
+        .overrides(overrides(dir, ignore_globs))
+        .build();
+    for entry in walker.flatten() {
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        let matches = entry.path().extension().is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|wanted| ext.eq_ignore_ascii_case(wanted.as_str()))
+        });
+        if matches {
+            files.push(entry.into_path());
+        }
+    }
+}
+
+/// - Whether [`walk`] would skip `path`, for files that show up after the scan
+/// - Ignore files are read every time, so edits to them apply right away
+fn is_ignored(root: &Path, path: &Path, ignore_globs: &[String]) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    let hidden = relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+    // globs like `private/` only match the folder, which `walk` never goes into
+    let overrides = overrides(root, ignore_globs);
+    let overridden = path
+        .ancestors()
+        .take_while(|ancestor| *ancestor != root)
+        .enumerate()
+        .any(|(idx, ancestor)| overrides.matched(ancestor, idx > 0).is_ignore());
+    if hidden || overridden {
+        return true;
+    }
+    // the closest ignore file with a matching rule wins, like it does for git
+    for dir in path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+    {
+        let mut builder = GitignoreBuilder::new(dir);
+        // `.ignore` is added last so its rules take precedence
+        for name in [".gitignore", ".ignore"] {
+            let file = dir.join(name);
+            if file.is_file() {
+                builder.add(file);
+            }
+        }
+        let Ok(gitignore) = builder.build() else {
+            continue;
+        };
+        match gitignore.matched_path_or_any_parents(path, false) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_ignore_files_are_honored() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let write = |path: &str, text: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+        write(".gitignore", "node_modules/\n*.draft.md\n");
+        write("notes/.ignore", "scratch/\n!keep.draft.md\n");
+        write("notes/john.md", "John 3:16");
+        write("notes/keep.draft.md", "John 3:17");
+        write("notes/todo.draft.md", "John 3:18");
+        write("notes/scratch/eph.md", "Eph 1:1");
+        write("notes/private/journal.md", "Ps 23:1");
+        write("node_modules/pkg/readme.md", "Gen 1:1");
+        write(".obsidian/cache.md", "Gen 1:2");
+
+        let ignore_globs = vec![String::from("private/")];
+        let mut files = vec![];
+        walk(root, &[String::from("md")], &ignore_globs, &mut files);
+        let mut found: Vec<String> = files
+            .iter()
+            .map(|path| {
+                path.strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        found.sort();
+        assert_eq!(found, vec!["notes/john.md", "notes/keep.draft.md"]);
+
+        for path in [
+            "notes/todo.draft.md",
+            "notes/scratch/eph.md",
+            "notes/private/journal.md",
+            "node_modules/pkg/readme.md",
+            ".obsidian/cache.md",
+        ] {
+            assert!(is_ignored(root, &root.join(path), &ignore_globs), "{path}");
+        }
+        assert!(!is_ignored(
+            root,
+            &root.join("notes/keep.draft.md"),
+            &ignore_globs
+        ));
+    }
+
+    #[test]
+    fn budget_drops_the_least_recently_indexed_files() {
+        let lsp = BibleLSP::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/bible.json"
+        ));
+        let dir = tempfile::tempdir().unwrap();
+        let index = WorkspaceIndex::default();
+        index.set_roots(vec![dir.path().to_path_buf()]);
+        let mut uris = vec![];
+        for name in ["a.md", "b.md", "c.md"] {
+            let path = dir.path().join(name);
+            fs::write(&path, "Gen 1:1, Exod 2:3 and John 3:4").unwrap();
+            let uri = paths::path_to_url(&path).unwrap();
+            assert!(index.index_file(&lsp, uri.clone()));
+            uris.push(uri);
+        }
+        // open in the editor, so only in memory
+        let open = Url::parse("file:///unsaved.md").unwrap();
+        let refs = lsp.find_book_references("Gen 1:1").unwrap();
+        index.index_references(&lsp, open.clone(), "Gen 1:1", &refs);
+
+        let (_, bytes, _) = index.usage();
+        index.set_budget(Some(bytes - 1));
+        assert!(!index.files().contains_key(&uris[0]));
+        assert_eq!(index.usage().0, 3);
+
+        index.set_budget(Some(0));
+        assert_eq!(index.files().keys().collect::<Vec<_>>(), vec![&open]);
+        assert_eq!(index.usage().2, 3);
+
+        index.set_budget(None);
+        assert!(index.index_file(&lsp, uris[0].clone()));
+        let (files, _, evicted) = index.usage();
+        assert_eq!((files, evicted), (2, 2));
+    }
+}
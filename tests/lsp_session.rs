@@ -0,0 +1,3048 @@
+//! Drives the server in-process the way an editor would, so protocol regressions show up while
+//! refactoring handlers
+
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tower::{Service, ServiceExt};
+use tower_lsp::jsonrpc::{Request, Response};
+use tower_lsp::LspService;
+
+use bible_lsp::{bible_lsp::BibleLSP, server};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bible.json");
+const URI: &str = "file:///notes/sermon.md";
+
+/// A fake editor connected to the server
+struct Session {
+    service: LspService<server::Backend>,
+    next_id: i64,
+    /// what the server showed with `window/showMessage`
+    messages: Arc<Mutex<Vec<String>>>,
+    /// what the server asked the editor to apply with `workspace/applyEdit`
+    edits: Arc<Mutex<Vec<Value>>>,
+    /// what the server answered `initialize` with
+    capabilities: Value,
+}
+
+impl Session {
+    /// Starts the server and goes through `initialize`/`initialized`
+    async fn start() -> Self {
+        Self::start_with(BibleLSP::new(FIXTURE), Value::Null).await
+    }
+
+    /// [`Session::start`] with a different translation and `initializationOptions`
+    async fn start_with(lsp: BibleLSP, options: Value) -> Self {
+        Self::initialize(
+            lsp,
+            json!({ "capabilities": {}, "initializationOptions": options }),
+        )
+        .await
+    }
+
+    /// [`Session::start`] for an editor with these client capabilities
+    async fn start_as(capabilities: Value) -> Self {
+        Self::initialize(
+            BibleLSP::new(FIXTURE),
+            json!({ "capabilities": capabilities }),
+        )
+        .await
+    }
+
+    /// [`Session::start_with`] with `root` as the workspace, for what needs the workspace index
+    #[cfg(feature = "search")]
+    async fn start_in(root: &std::path::Path, options: Value) -> Self {
+        let root_uri = tower_lsp::lsp_types::Url::from_directory_path(root).unwrap();
+        Self::initialize(
+            BibleLSP::new(FIXTURE),
+            json!({ "capabilities": {}, "initializationOptions": options, "rootUri": root_uri }),
+        )
+        .await
+    }
+
+    async fn initialize(lsp: BibleLSP, params: Value) -> Self {
+        let (service, socket) = server::build_service(lsp);
+        // the server asks the editor for things too (file watchers, progress), and waits for
+        // an answer, so every request gets one (empty, unless it has to be something)
+        let (mut requests, mut responses) = socket.split();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let shown = messages.clone();
+        let edits = Arc::new(Mutex::new(Vec::new()));
+        let applied = edits.clone();
+        tokio::spawn(async move {
+            while let Some(request) = requests.next().await {
+                if request.method() == "window/showMessage" {
+                    let message = request
+                        .params()
+                        .and_then(|params| params["message"].as_str());
+                    shown.lock().unwrap().extend(message.map(String::from));
+                }
+                if request.method() == "workspace/applyEdit" {
+                    let edit = request.params().map(|params| params["edit"].clone());
+                    applied.lock().unwrap().extend(edit);
+                }
+                let result = match request.method() {
+                    "workspace/applyEdit" => json!({ "applied": true }),
+                    "window/showDocument" => json!({ "success": true }),
+                    _ => Value::Null,
+                };
+                if let Some(id) = request.id().cloned() {
+                    _ = responses.send(Response::from_ok(id, result)).await;
+                }
+            }
+        });
+        let mut session = Self {
+            service,
+            next_id: 0,
+            messages,
+            edits,
+            capabilities: Value::Null,
+        };
+        let result = session
+            .request("initialize", params)
+            .await
+            .expect("initialize succeeds");
+        assert!(result["capabilities"]["hoverProvider"].as_bool().unwrap());
+        session.capabilities = result["capabilities"].clone();
+        session.notify("initialized", json!({})).await;
+        session
+    }
+
+    async fn call(&mut self, request: Request) -> Option<Response> {
+        self.service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+    }
+
+    async fn request(&mut self, method: &'static str, params: Value) -> Result<Value, String> {
+        self.next_id += 1;
+        let request = Request::build(method)
+            .params(params)
+            .id(self.next_id)
+            .finish();
+        let response = self.call(request).await.expect("requests get a response");
+        let (_, result) = response.into_parts();
+        result.map_err(|err| err.message.to_string())
+    }
+
+    async fn notify(&mut self, method: &'static str, params: Value) {
+        let notification = Request::build(method).params(params).finish();
+        assert!(self.call(notification).await.is_none());
+    }
+
+    async fn open(&mut self, text: &str) {
+        self.open_uri(URI, text).await;
+    }
+
+    async fn open_uri(&mut self, uri: &str, text: &str) {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "markdown", "version": 1, "text": text }
+            }),
+        )
+        .await;
+    }
+}
+
+fn position(line: u32, character: u32) -> Value {
+    position_in(URI, line, character)
+}
+
+fn position_in(uri: &str, line: u32, character: u32) -> Value {
+    json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+}
+
+#[tokio::test]
+async fn hover_shows_verse_text() {
+    let mut session = Session::start().await;
+    session.open("# Notes\n\nSee John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(2, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("John 3:16"), "{contents}");
+    assert!(contents.contains("Text of John 3:16."), "{contents}");
+    assert_eq!(
+        hover["range"]["start"],
+        json!({ "line": 2, "character": 4 })
+    );
+}
+
+#[tokio::test]
+async fn hover_without_references_is_empty() {
+    let mut session = Session::start().await;
+    session.open("nothing to see here").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 3))
+        .await
+        .unwrap();
+    assert_eq!(hover["contents"], json!(""));
+}
+
+#[tokio::test]
+async fn book_names_show_their_chapters() {
+    let mut session = Session::start().await;
+    session.open("Read Genesis, or genesis\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 7))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.starts_with("### Genesis\n\n"), "{contents}");
+    assert!(
+        contents.contains("[1](command:bible.openChapter?%5B1%2C1%5D) 5"),
+        "{contents}"
+    );
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 0, "character": 5 }, "end": { "line": 0, "character": 12 } })
+    );
+    // a word, not a name
+    let hover = session
+        .request("textDocument/hover", position(0, 20))
+        .await
+        .unwrap();
+    assert_eq!(hover["contents"], json!(""));
+
+    let uri = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.openChapter", "arguments": [1, 1] }),
+        )
+        .await
+        .unwrap();
+    assert!(uri.as_str().unwrap().ends_with("/Genesis/1.md"), "{uri}");
+}
+
+#[tokio::test]
+async fn books_have_introductions() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("headings.json");
+    std::fs::write(
+        &path,
+        r#"{ "Jude 1:1": "Greeting", "Jude 1:3": "Contend" }"#,
+    )
+    .unwrap();
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "headingsFile": path })).await;
+    session.open("Read Jude\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.ends_with("[About Jude](command:bible.openBookIntro?%5B65%5D)"),
+        "{contents}"
+    );
+
+    let uri = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.openBookIntro", "arguments": [65] }),
+        )
+        .await
+        .unwrap();
+    let uri = uri.as_str().unwrap();
+    assert!(uri.ends_with("/Jude/intro.md"), "{uri}");
+    let file = tower_lsp::lsp_types::Url::parse(uri)
+        .unwrap()
+        .to_file_path()
+        .unwrap();
+    let intro = std::fs::read_to_string(file).unwrap();
+    assert!(
+        intro.starts_with("# Jude\n\n- Book 65 of 66 in the "),
+        "{intro}"
+    );
+    assert!(
+        intro.contains("## Outline\n\n- Greeting (Jude 1:1-2)\n- Contend (Jude 1:3-5)"),
+        "{intro}"
+    );
+    assert!(intro.contains("- [Jude 1](1.md): 5 verses"), "{intro}");
+    assert!(intro.contains("- 1 chapter, 5 verses, 20 words"), "{intro}");
+
+    session.open_uri(uri, &intro).await;
+    let links = session
+        .request(
+            "textDocument/documentLink",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+        .unwrap();
+    assert!(links[0]["target"].as_str().unwrap().ends_with("/Jude/1.md"));
+    let symbols = session
+        .request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+        .unwrap();
+    let names: Vec<&str> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|symbol| symbol["name"].as_str())
+        .collect();
+    assert_eq!(names, ["Outline", "Chapters", "Statistics"]);
+}
+
+#[tokio::test]
+async fn completion_suggests_books() {
+    let mut session = Session::start().await;
+    session.open("eph").await;
+    let completions = session
+        .request("textDocument/completion", position(0, 3))
+        .await
+        .unwrap();
+    let labels: Vec<&str> = completions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|item| item["label"].as_str())
+        .collect();
+    assert!(labels.contains(&"Ephesians"), "{labels:?}");
+    let ephesians = completions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["label"] == "Ephesians")
+        .unwrap();
+    let documentation = ephesians["documentation"]["value"].as_str().unwrap();
+    assert!(
+        documentation.ends_with("[About Ephesians](command:bible.openBookIntro?%5B49%5D)"),
+        "{documentation}"
+    );
+}
+
+/// Neovim has sent columns past the end of the line, which used to panic when slicing
+#[tokio::test]
+async fn completion_past_end_of_line() {
+    let mut session = Session::start().await;
+    session.open("eph 1:").await;
+    let completions = session
+        .request("textDocument/completion", position(0, 400))
+        .await;
+    assert!(completions.is_ok(), "{completions:?}");
+    let past_last_line = session
+        .request("textDocument/completion", position(5, 0))
+        .await;
+    assert_eq!(past_last_line, Ok(Value::Null));
+}
+
+#[tokio::test]
+async fn unknown_commands_are_rejected() {
+    let mut session = Session::start().await;
+    let result = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.doesNotExist", "arguments": [] }),
+        )
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn verse_ids_resolve_both_ways() {
+    let mut session = Session::start().await;
+    let mut resolve = async |text: &str| {
+        session
+            .request(
+                "workspace/executeCommand",
+                json!({ "command": "bible.resolveId", "arguments": [text] }),
+            )
+            .await
+            .unwrap()
+    };
+    assert_eq!(
+        resolve("see eph 1:3-5").await,
+        json!([{ "id": "TST:49.1.3-1.5", "reference": "Ephesians 1:3-5" }])
+    );
+    assert_eq!(
+        resolve("TST:43.3.16").await,
+        json!([{ "id": "TST:43.3.16", "reference": "John 3:16" }])
+    );
+}
+
+#[tokio::test]
+async fn changed_quotes_are_flagged() {
+    let mut session = Session::start().await;
+    session
+        .open(concat!(
+            "<!-- bible:quote Genesis 1:1 | TST | insert -->\n",
+            "An older edition of Genesis 1:1.\n",
+            "<!-- /bible:quote -->\n",
+            "<!-- bible:quote Genesis 1:2 | KJV | insert -->\n",
+            "Another translation entirely.\n",
+            "<!-- /bible:quote -->\n",
+        ))
+        .await;
+    let report = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    let stale: Vec<&Value> = report["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|diagnostic| diagnostic["code"] == "stale-quote")
+        .collect();
+    assert_eq!(stale.len(), 1);
+    assert_eq!(stale[0]["range"]["start"]["line"], 0);
+    assert!(stale[0]["data"]["edit"]["newText"]
+        .as_str()
+        .unwrap()
+        .contains("Text of Genesis 1:1."));
+}
+
+#[tokio::test]
+async fn unchanged_diagnostics_are_not_resent() {
+    let mut session = Session::start().await;
+    session.open("See Gen 1:1 and John 3:16.").await;
+    let first = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(first["kind"], "full");
+    let result_id = first["resultId"].as_str().unwrap();
+    let second = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI }, "previousResultId": result_id }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        second,
+        json!({ "kind": "unchanged", "resultId": result_id })
+    );
+
+    let workspace = session
+        .request(
+            "workspace/diagnostic",
+            json!({ "previousResultIds": [{ "uri": URI, "value": "stale" }] }),
+        )
+        .await
+        .unwrap();
+    let items = workspace["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["kind"], "full");
+    assert_eq!(items[0]["resultId"], result_id);
+}
+
+/**
+Golden cases for how positions line up with what clients expect
+
+- Neither client is offered another encoding, so every `character` is in UTF-16 code units, which
+  is where byte offsets and UTF-16 columns drift apart after `“`, `é`, and emoji
+- Both clients only accept a completion edit on the cursor's line that ends at the cursor, so the
+  end is checked with text after the cursor too
+- Neovim can ask past the end of the line (the cursor in normal mode sits after it), which is
+  clamped to the end
+*/
+#[tokio::test]
+async fn positions_match_client_expectations() {
+    let mut session = Session::start().await;
+    // (client, line, cursor, expected completion edit start and end)
+    let completions = [
+        ("vscode", "see eph 1:", 10, (4, 10)),
+        ("vscode", "see eph 1: and more", 10, (4, 10)),
+        ("neovim", "“grace” eph 2:", 14, (8, 14)),
+        ("neovim", "café eph 2:", 11, (5, 11)),
+        ("neovim", "🙏 eph 2:", 9, (3, 9)),
+        ("neovim", "eph 2:", 40, (0, 6)),
+    ];
+    for (client, line, cursor, (start, end)) in completions {
+        session.open(line).await;
+        let items = session
+            .request("textDocument/completion", position(0, cursor))
+            .await
+            .unwrap();
+        let range = &items[0]["textEdit"]["range"];
+        assert_eq!(
+            (
+                range["start"]["character"].clone(),
+                range["end"]["character"].clone()
+            ),
+            (json!(start), json!(end)),
+            "{client}: {line}"
+        );
+        assert_eq!(range["start"]["line"], 0, "{client}: {line}");
+    }
+
+    // (line, hovered character, expected reference start and end)
+    let hovers = [
+        ("see Gen 1:1", 5, (4, 11)),
+        ("“grace” Gen 1:1", 9, (8, 15)),
+        ("café Gen 1:1", 6, (5, 12)),
+        ("🙏 Gen 1:1", 4, (3, 10)),
+    ];
+    for (line, character, (start, end)) in hovers {
+        session.open(&format!("# Notes\n{line}")).await;
+        let hover = session
+            .request("textDocument/hover", position(1, character))
+            .await
+            .unwrap();
+        assert_eq!(
+            hover["range"],
+            json!({
+                "start": { "line": 1, "character": start },
+                "end": { "line": 1, "character": end },
+            }),
+            "{line}"
+        );
+    }
+}
+
+/// Completion edits replace the reference being typed, from its book up to the cursor, whatever
+/// the reference ends with
+#[tokio::test]
+async fn completions_replace_the_typed_reference() {
+    let mut session = Session::start().await;
+    // (line, cursor, where the edit starts)
+    let cases = [
+        // `AutocompletionEndingOperator::None`
+        ("see Eph 1:1,2 today", 13, 4),
+        // `AutocompletionEndingOperator::Chapter`
+        ("see Eph 1:1,2: today", 14, 4),
+        // `AutocompletionEndingOperator::Break`
+        ("see Eph 1:1, today", 12, 4),
+        // `AutocompletionEndingOperator::Through`
+        ("see Eph 1:1- today", 12, 4),
+        // a new book after a finished reference leaves the reference alone
+        ("see Eph 1:1 and ge", 18, 16),
+        // and with nothing typed yet, the book is inserted at the cursor
+        ("see Eph 1:1 and ", 16, 16),
+    ];
+    for (line, cursor, start) in cases {
+        session.open(line).await;
+        let items = session
+            .request("textDocument/completion", position(0, cursor))
+            .await
+            .unwrap();
+        let edit = &items[0]["textEdit"];
+        assert_eq!(
+            edit["range"],
+            json!({
+                "start": { "line": 0, "character": start },
+                "end": { "line": 0, "character": cursor },
+            }),
+            "{line}"
+        );
+        if start == 4 {
+            let new_text = edit["newText"].as_str().unwrap();
+            assert!(new_text.starts_with("Ephesians 1:1"), "{line}: {new_text}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn translation_comes_from_initialization_options() {
+    let mut session =
+        Session::start_with(BibleLSP::empty(), json!({ "translation": FIXTURE })).await;
+    session.open("See John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of John 3:16."), "{contents}");
+    assert!(session.messages.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn missing_translation_is_shown() {
+    let mut session = Session::start_with(
+        BibleLSP::empty(),
+        json!({ "translation": "/missing/esv.json" }),
+    )
+    .await;
+    let messages = session.messages.lock().unwrap().clone();
+    assert_eq!(messages.len(), 1, "{messages:?}");
+    assert!(
+        messages[0].starts_with("Couldn't read /missing/esv.json: "),
+        "{messages:?}"
+    );
+    // nothing is detected, but nothing breaks either
+    session.open("See John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    assert_eq!(hover["contents"], json!(""));
+}
+
+#[tokio::test]
+async fn translation_switches_at_runtime() {
+    let mut session = Session::start_with(BibleLSP::empty(), Value::Null).await;
+    session.open("See John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    assert_eq!(hover["contents"], json!(""));
+
+    let missing = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.setTranslation", "arguments": ["/missing/esv.json"] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        missing.starts_with("Couldn't read /missing/esv.json"),
+        "{missing}"
+    );
+
+    let loaded = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.setTranslation", "arguments": [FIXTURE] }),
+        )
+        .await
+        .unwrap();
+    assert!(loaded["abbreviation"].is_string(), "{loaded}");
+    // the document was already parsed without any books
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of John 3:16."), "{contents}");
+}
+
+#[tokio::test]
+async fn quotes_follow_a_switch_of_translation() {
+    let dir = tempfile::tempdir().unwrap();
+    let new = dir.path().join("new.json");
+    let fixture = std::fs::read_to_string(FIXTURE).unwrap();
+    std::fs::write(
+        &new,
+        fixture.replacen(r#""abbreviation": "TST""#, r#""abbreviation": "NEW""#, 1),
+    )
+    .unwrap();
+    let mut session = Session::start().await;
+    session
+        .open(concat!(
+            "See John 3:16\n",
+            "<!-- bible:quote John 3:16 | TST | insert -->\n",
+            "Text of John 3:16.\n",
+            "<!-- /bible:quote -->\n",
+            "<!-- bible:quote John 3:17 | KJV | insert -->\n",
+            "For God sent not his Son\n",
+            "<!-- /bible:quote -->\n",
+            "\n",
+            "<!-- bible_lsp:attribution -->\n",
+            "Scripture quotations are from the Test Bible (TST).\n",
+            "<!-- /bible_lsp:attribution -->\n",
+        ))
+        .await;
+    let update =
+        |from: Value| json!({ "command": "bible.updateQuotedTranslation", "arguments": [from] });
+    let not_switched = session
+        .request("workspace/executeCommand", update(Value::Null))
+        .await
+        .unwrap_err();
+    assert!(
+        not_switched.contains("hasn't been switched"),
+        "{not_switched}"
+    );
+
+    session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.setTranslation", "arguments": [new] }),
+        )
+        .await
+        .unwrap();
+    let updated = session
+        .request("workspace/executeCommand", update(Value::Null))
+        .await
+        .unwrap();
+    assert_eq!(updated["from"], "TST");
+    assert_eq!(updated["to"], "NEW");
+    assert_eq!(
+        updated["documents"],
+        json!([{ "uri": URI, "name": "/notes/sermon.md", "quotes": 1, "attribution": true }])
+    );
+    let edits = &updated["edit"]["documentChanges"][0]["edits"];
+    assert!(
+        edits[0]["newText"]
+            .as_str()
+            .unwrap()
+            .starts_with("<!-- bible:quote John 3:16 | NEW | insert -->"),
+        "{edits}"
+    );
+    assert!(
+        edits[1]["newText"]
+            .as_str()
+            .unwrap()
+            .contains("Test Bible (NEW)"),
+        "{edits}"
+    );
+    let messages = session.messages.lock().unwrap().clone();
+    assert!(
+        messages[0].starts_with("Switched from TST to NEW"),
+        "{messages:?}"
+    );
+}
+
+#[tokio::test]
+async fn state_is_dumped_on_demand() {
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "logLevel": "debug" })).await;
+    session.open("See John 3:16 and Rom 8:28\n").await;
+    let state = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.dumpState", "arguments": [] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(state["logLevel"], "debug");
+    assert_eq!(state["status"]["openDocuments"], 1);
+    assert_eq!(state["documents"][0]["uri"], URI);
+    assert_eq!(state["documents"][0]["references"], 2);
+    assert_eq!(state["documents"][0]["detected"], true);
+}
+
+#[tokio::test]
+async fn status_reports_the_memory_budget() {
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "memoryBudgetMb": 1 })).await;
+    session.open("See John 3:16 today\n").await;
+    session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    // no params at all, which `request` always sends
+    let response = session
+        .call(Request::build("bible/status").id(100).finish())
+        .await
+        .unwrap();
+    let status = response.result().unwrap();
+    assert_eq!(status["memory"]["budgetBytes"], json!(1024 * 1024));
+    assert_eq!(status["memory"]["hoverCacheEntries"], json!(1));
+    assert!(status["memory"]["hoverCacheBytes"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn status_says_which_features_were_left_out() {
+    let mut session = Session::start().await;
+    let response = session
+        .call(Request::build("bible/status").id(100).finish())
+        .await
+        .unwrap();
+    let features = &response.result().unwrap()["features"];
+    assert_eq!(features["search"], cfg!(feature = "search"));
+    assert_eq!(features["remote"], cfg!(feature = "remote"));
+    assert_eq!(features["usfm"], cfg!(feature = "usfm"));
+    assert_eq!(features["mybible"], cfg!(feature = "mybible"));
+    assert_eq!(features["commentary"], cfg!(feature = "commentary"));
+    assert_eq!(features["tui"], cfg!(feature = "tui"));
+    // no build reads SWORD modules yet
+    assert_eq!(features["sword"], false);
+
+    // and commands that need a feature this build doesn't have say which
+    let export = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.exportCache", "arguments": ["REM", "/tmp/rem.json"] }),
+        )
+        .await;
+    if !cfg!(feature = "remote") {
+        let err = export.unwrap_err();
+        assert!(err.contains("without the \"remote\" feature"), "{err}");
+    }
+}
+
+#[tokio::test]
+async fn settings_change_without_a_restart() {
+    let mut session = Session::start_with(BibleLSP::empty(), json!({})).await;
+    session
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "bible": { "translation": FIXTURE, "hoverFormatter": "blockquote" } } }),
+        )
+        .await;
+    session.open("See John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.starts_with("> "), "{hover}");
+    assert!(contents.contains("Text of John 3:16."), "{hover}");
+}
+
+#[tokio::test]
+async fn excluded_languages_have_no_references() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "documents": { "include": ["plaintext"] } }),
+    )
+    .await;
+    // `open` says it's markdown
+    session.open("See John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    assert_eq!(hover["contents"], json!(""));
+    let completion = session
+        .request("textDocument/completion", position(0, 8))
+        .await;
+    assert_eq!(completion, Ok(Value::Null));
+
+    session
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "documents": { "include": ["markdown"] } } }),
+        )
+        .await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    assert!(hover["contents"]
+        .as_str()
+        .unwrap()
+        .contains("Text of John 3:16."));
+}
+
+#[tokio::test]
+async fn hovers_mark_wording_another_translation_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let other = dir.path().join("other.json");
+    let text = std::fs::read_to_string(FIXTURE)
+        .unwrap()
+        .replace("\"TST\"", "\"OTH\"")
+        .replace("Text of John 3:16.", "Words of John 3:16.");
+    std::fs::write(&other, text).unwrap();
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "compareTranslation": other }),
+    )
+    .await;
+    session.open("See John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.contains("[3:16] **Text** *(OTH: Words)* of John 3:16."),
+        "{contents}"
+    );
+}
+
+#[tokio::test]
+async fn several_translations_are_loaded() {
+    let dir = tempfile::tempdir().unwrap();
+    let other = dir.path().join("other.json");
+    let text = std::fs::read_to_string(FIXTURE)
+        .unwrap()
+        .replace("\"TST\"", "\"OTH\"")
+        .replace("Text of John 3:16.", "Words of John 3:16.");
+    std::fs::write(&other, text).unwrap();
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "translations": [other, "/missing/kjv.json"] }),
+    )
+    .await;
+    let messages = session.messages.lock().unwrap().clone();
+    assert_eq!(messages.len(), 1, "{messages:?}");
+    assert!(
+        messages[0].starts_with("Couldn't read /missing/kjv.json"),
+        "{messages:?}"
+    );
+    let response = session
+        .call(Request::build("bible/status").id(100).finish())
+        .await
+        .unwrap();
+    let status = response.result().unwrap();
+    assert_eq!(status["translation"], "TST");
+    assert_eq!(status["translations"], json!(["TST", "OTH"]));
+
+    // the first one is still the default
+    session.open("See John 3:16 today\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of John 3:16."), "{contents}");
+}
+
+#[tokio::test]
+async fn references_can_name_their_translation() {
+    let dir = tempfile::tempdir().unwrap();
+    let other = dir.path().join("other.json");
+    let text = std::fs::read_to_string(FIXTURE)
+        .unwrap()
+        .replace("\"TST\"", "\"OTH\"")
+        .replace("Text of John 3:16.", "Words of John 3:16.");
+    std::fs::write(&other, text).unwrap();
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "translations": [other] })).await;
+    session
+        .open("See John 3:16 OTH today\nJohn 3:16 oth\n")
+        .await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("### John 3:16 OTH"), "{contents}");
+    assert!(contents.contains("Words of John 3:16."), "{contents}");
+    assert_eq!(hover["range"]["end"], json!({ "line": 0, "character": 17 }));
+
+    // not an abbreviation, so it stays a word
+    let hover = session
+        .request("textDocument/hover", position(1, 2))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of John 3:16."), "{contents}");
+    assert_eq!(hover["range"]["end"], json!({ "line": 1, "character": 9 }));
+}
+
+#[tokio::test]
+async fn canonical_hints_only_follow_messy_references() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "features": { "inlayHints": true }, "inlayHints": { "mode": "canonical" } }),
+    )
+    .await;
+    session.open("See eph. 1:1 - 4 and Ephesians 1:1–4\n").await;
+    let hints = session
+        .request(
+            "textDocument/inlayHint",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 0 } }
+            }),
+        )
+        .await
+        .unwrap();
+    let hints = hints.as_array().unwrap();
+    assert_eq!(hints.len(), 1, "{hints:?}");
+    assert_eq!(hints[0]["label"], "Ephesians 1:1–4");
+    assert_eq!(hints[0]["position"], json!({ "line": 0, "character": 16 }));
+    assert_eq!(hints[0]["textEdits"][0]["newText"], "Ephesians 1:1–4");
+}
+
+#[tokio::test]
+async fn range_requests_only_parse_their_lines() {
+    // only the first reference is kept, so a whole-document parse never gets to the last line
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({
+            "features": { "semanticTokens": true },
+            "limits": { "maxReferences": 1 }
+        }),
+    )
+    .await;
+    let text = format!("John 3:16\n{}See Eph 1:1-4\n", "filler\n".repeat(20));
+    session.open(&text).await;
+    let range =
+        json!({ "start": { "line": 21, "character": 0 }, "end": { "line": 21, "character": 5 } });
+    let tokens = session
+        .request(
+            "textDocument/semanticTokens/range",
+            json!({ "textDocument": { "uri": URI }, "range": range }),
+        )
+        .await
+        .unwrap();
+    // `Eph` as a book, then `1:1-4` as a segment
+    assert_eq!(tokens["data"], json!([21, 4, 3, 0, 0, 0, 4, 5, 1, 0]));
+
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({ "textDocument": { "uri": URI }, "range": range, "context": { "diagnostics": [] } }),
+        )
+        .await
+        .unwrap();
+    let titles: Vec<&str> = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|action| action["title"].as_str())
+        .collect();
+    assert!(
+        titles.iter().any(|title| title.contains("Ephesians 1:1-4")),
+        "{titles:?}"
+    );
+    assert!(
+        !titles.iter().any(|title| title.contains("John")),
+        "{titles:?}"
+    );
+
+    // a range to the end of everything doesn't overflow
+    let tokens = session
+        .request(
+            "textDocument/semanticTokens/range",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": u32::MAX, "character": 0 } }
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(tokens["data"], json!([0, 0, 4, 0, 0, 0, 5, 4, 1, 0]));
+}
+
+#[tokio::test]
+async fn references_are_corrected_on_save() {
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "autocorrectOnSave": true })).await;
+    session.open("See eph 1:1 -4 and John 3:16\n").await;
+    // TextDocumentSaveReason::MANUAL
+    let edits = session
+        .request(
+            "textDocument/willSaveWaitUntil",
+            json!({ "textDocument": { "uri": URI }, "reason": 1 }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        edits,
+        json!([{
+            "range": {
+                "start": { "line": 0, "character": 4 },
+                "end": { "line": 0, "character": 14 }
+            },
+            "newText": "Ephesians 1:1-4"
+        }])
+    );
+    // autosaves are left alone
+    let edits = session
+        .request(
+            "textDocument/willSaveWaitUntil",
+            json!({ "textDocument": { "uri": URI }, "reason": 2 }),
+        )
+        .await
+        .unwrap();
+    assert!(edits.is_null(), "{edits}");
+}
+
+#[tokio::test]
+async fn greek_psalms_stay_put_on_save() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("psalms.json");
+    let chapters = vec![vec!["A verse."; 5]; 60];
+    let bible = json!({
+        "translation": { "name": "Psalter", "language": "English", "abbreviation": "PSL" },
+        "bible": [{ "id": 19, "book": "Psalms", "abbreviations": ["ps"], "content": chapters }]
+    });
+    std::fs::write(&path, bible.to_string()).unwrap();
+    let mut session = Session::start_with(
+        BibleLSP::new(path.to_str().unwrap()),
+        json!({
+            "psalmNumbering": "greek",
+            "autocorrectOnSave": true,
+            "features": { "inlayHints": true },
+            "inlayHints": { "mode": "canonical" }
+        }),
+    )
+    .await;
+    session.open("See ps 50:3 - 4\n").await;
+    let edits = session
+        .request(
+            "textDocument/willSaveWaitUntil",
+            json!({ "textDocument": { "uri": URI }, "reason": 1 }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(edits[0]["newText"], "Psalms 50:3-4");
+    let hints = session
+        .request(
+            "textDocument/inlayHint",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 0 } }
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(hints[0]["label"], "Psalms 50:3–4");
+
+    // saving what autocorrect wrote leaves it alone
+    session.open("See Psalms 50:3-4\n").await;
+    let edits = session
+        .request(
+            "textDocument/willSaveWaitUntil",
+            json!({ "textDocument": { "uri": URI }, "reason": 1 }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(edits, json!([]));
+}
+
+#[tokio::test]
+async fn scripture_index_is_kept_current_on_save() {
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "autocorrectOnSave": true })).await;
+    let index = "<!-- bible_lsp:scripture-index -->\n## Scripture Index\n\n- Genesis 2:1\n<!-- /bible_lsp:scripture-index -->";
+    session
+        .open(&format!("See rom 8:28 and Gen 1:1\n\n{index}\n"))
+        .await;
+    let edits = session
+        .request(
+            "textDocument/willSaveWaitUntil",
+            json!({ "textDocument": { "uri": URI }, "reason": 1 }),
+        )
+        .await
+        .unwrap();
+    let edits = edits.as_array().unwrap();
+    assert_eq!(edits.len(), 3, "{edits:?}");
+    assert_eq!(
+        edits[0]["newText"],
+        "<!-- bible_lsp:scripture-index -->\n## Scripture Index\n\n- Genesis 1:1\n- Romans 8:28\n<!-- /bible_lsp:scripture-index -->"
+    );
+    assert_eq!(
+        edits[0]["range"]["start"],
+        json!({ "line": 2, "character": 0 })
+    );
+    assert_eq!(edits[1]["newText"], "Romans 8:28");
+    assert_eq!(edits[2]["newText"], "Genesis 1:1");
+
+    // documents without one don't get one by saving
+    let uri = "file:///tmp/plain.md";
+    session.open_uri(uri, "See Genesis 1:1\n").await;
+    let edits = session
+        .request(
+            "textDocument/willSaveWaitUntil",
+            json!({ "textDocument": { "uri": uri }, "reason": 1 }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(edits, json!([]));
+}
+
+#[tokio::test]
+async fn verse_per_line_blocks_fold() {
+    let formatter = json!({ "verse": "[{chapter}:{verse}] {content}", "text": "{segments}", "versePerLine": "  " });
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "formatters": { "soft": formatter } }),
+    )
+    .await;
+    session
+        .open(concat!(
+            "See Gen 1:4-5\n",
+            "<!-- bible:quote Genesis 1:4-5 | TST | soft -->\n",
+            "  [1:4] Text of Genesis 1:4.\n",
+            "  [1:5] Text of Genesis 1:5.\n",
+            "<!-- /bible:quote -->\n",
+            "\n",
+            "  [1:1] Text of Genesis 1:1.\n",
+            "  [1:2] Text of Genesis 1:2.\n",
+            "\n",
+            "  [1:3] Text of Genesis 1:3.\n",
+        ))
+        .await;
+    let ranges = session
+        .request(
+            "textDocument/foldingRange",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    let ranges: Vec<(u64, u64)> = ranges
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|range| {
+            (
+                range["startLine"].as_u64().unwrap(),
+                range["endLine"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(ranges, [(1, 4), (6, 7)]);
+}
+
+#[cfg(feature = "remote")]
+#[tokio::test]
+async fn remote_chapters_are_fetched_once() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("43")).unwrap();
+    let verses: Vec<Value> = (1..=17)
+        .map(|verse| json!({ "verse": verse, "text": format!("Fetched John 3:{verse}.") }))
+        .collect();
+    std::fs::write(
+        dir.path().join("43/3.json"),
+        serde_json::to_string(&verses).unwrap(),
+    )
+    .unwrap();
+    let template = format!("file://{}/{{book}}/{{chapter}}.json", dir.path().display());
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "remote": { "translations": { "REM": template }, "dailyLimit": 1 } }),
+    )
+    .await;
+    session
+        .open("John 3:16 REM and John 3:17 REM\nRom 1:2 REM\n")
+        .await;
+    let hover = session
+        .request("textDocument/hover", position(0, 2))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Fetched John 3:16."), "{contents}");
+    assert!(contents.contains("Fetched John 3:17."), "{contents}");
+
+    let response = session
+        .call(Request::build("bible/status").id(100).finish())
+        .await
+        .unwrap();
+    let remote = &response.result().unwrap()["remote"];
+    assert_eq!(remote["requestsToday"], 1);
+    assert_eq!(remote["remaining"], 0);
+    assert_eq!(remote["cachedChapters"], 1);
+
+    // cached chapters are still shown once the quota is used up
+    let again = session.request("textDocument/hover", position(0, 2)).await;
+    assert!(again.is_ok(), "{again:?}");
+    let used_up = session
+        .request("textDocument/hover", position(1, 2))
+        .await
+        .unwrap_err();
+    assert!(used_up.contains("quota"), "{used_up}");
+}
+
+#[cfg(feature = "remote")]
+#[tokio::test]
+async fn licensed_apis_are_split_into_verses() {
+    let dir = tempfile::tempdir().unwrap();
+    let chapters = dir.path().join("v1/bibles/de4e12af7f28f599-01/chapters");
+    std::fs::create_dir_all(&chapters).unwrap();
+    std::fs::write(
+        chapters.join("JHN.3"),
+        json!({ "data": { "content": "  [15] that whoever believes\n  [16] For God so loved the world, [17] For God sent not" } })
+            .to_string(),
+    )
+    .unwrap();
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "remote": {
+            "licensed": { "KJVA": {
+                "api": "apiBible",
+                "apiKey": "secret",
+                "bibleId": "de4e12af7f28f599-01",
+                "endpoint": format!("file://{}/", dir.path().display()),
+            } },
+            "diskCacheChapters": 0,
+        } }),
+    )
+    .await;
+    session.open("John 3:16 KJVA\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 2))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.contains("For God so loved the world,"),
+        "{contents}"
+    );
+    assert!(!contents.contains("sent not"), "{contents}");
+}
+
+#[cfg(feature = "remote")]
+#[tokio::test]
+async fn remote_chapters_can_be_exported() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("43")).unwrap();
+    let verses: Vec<String> = (1..=17)
+        .map(|verse| format!("Fetched John 3:{verse}."))
+        .collect();
+    std::fs::write(
+        dir.path().join("43/3.json"),
+        serde_json::to_string(&verses).unwrap(),
+    )
+    .unwrap();
+    let template = format!("file://{}/{{book}}/{{chapter}}.json", dir.path().display());
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "remote": {
+            "translations": { "REM": template, "PAY": template, "LIM": template },
+            "allowExport": ["rem", "lim"],
+        },
+        "quoteLimits": { "LIM": { "maxInsertVerses": 10 } } }),
+    )
+    .await;
+    session.open("John 3:16 REM\nJohn 3:16 LIM\n").await;
+    for line in 0..2 {
+        session
+            .request("textDocument/hover", position(line, 2))
+            .await
+            .unwrap();
+    }
+
+    let path = dir.path().join("rem.json");
+    let export = |translation: &str| json!({ "command": "bible.exportCache", "arguments": [translation, path] });
+    let not_allowed = session
+        .request("workspace/executeCommand", export("PAY"))
+        .await
+        .unwrap_err();
+    assert!(not_allowed.contains("allowExport"), "{not_allowed}");
+    let limited = session
+        .request("workspace/executeCommand", export("LIM"))
+        .await
+        .unwrap_err();
+    assert!(limited.contains("17 verses"), "{limited}");
+    assert!(!path.exists());
+    let exported = session
+        .request("workspace/executeCommand", export("REM"))
+        .await
+        .unwrap();
+    assert_eq!(exported["chapters"], 1);
+
+    let bible: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(bible["translation"]["abbreviation"], "REM");
+    assert_eq!(bible["bible"][0]["book"], "John");
+    assert_eq!(bible["bible"][0]["content"][0], json!([]));
+    assert_eq!(bible["bible"][0]["content"][2][15], "Fetched John 3:16.");
+    // and it loads like any other translation
+    BibleLSP::new(path.to_str().unwrap());
+}
+
+#[tokio::test]
+async fn diagnostics_follow_their_settings() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "diagnostics": { "severity": "hint", "previewLength": 7 } }),
+    )
+    .await;
+    session.open("See John 3:16 today\n").await;
+    let diagnostics = |report: Value| report["items"].as_array().unwrap().clone();
+    let report = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    let items = diagnostics(report);
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["message"], "Text of…");
+    // DiagnosticSeverity::HINT
+    assert_eq!(items[0]["severity"], 4);
+
+    session
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "diagnostics": { "enabled": false } } }),
+        )
+        .await;
+    let report = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    assert!(diagnostics(report).is_empty());
+}
+
+#[tokio::test]
+async fn long_completion_lists_are_cut() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "completion": { "maxItems": 2 } }),
+    )
+    .await;
+    session.open("eph").await;
+    let completions = session
+        .request("textDocument/completion", position(0, 3))
+        .await
+        .unwrap();
+    assert_eq!(completions["isIncomplete"], true);
+    assert_eq!(completions["items"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn front_matter_passages_are_checked() {
+    let mut session = Session::start().await;
+    session
+        .open("---\ntitle: Abide\npassage: John 2\nscripture: \"Nothing here\"\n---\nSee John 3:16 today\n")
+        .await;
+    let report = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    let mut codes: Vec<Value> = report["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["code"].clone())
+        .collect();
+    codes.sort_by_key(Value::to_string);
+    assert_eq!(
+        codes,
+        [json!("John 2:1-5"), json!("John 3:16"), Value::Null]
+    );
+    let warning = report["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["code"].is_null())
+        .unwrap();
+    assert_eq!(
+        warning["message"],
+        "`Nothing here` in `scripture` isn't a passage"
+    );
+    assert_eq!(
+        warning["range"]["start"],
+        json!({ "line": 3, "character": 12 })
+    );
+
+    let hover = session
+        .request("textDocument/hover", position(2, 12))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of John 2:5."), "{contents}");
+}
+
+#[tokio::test]
+async fn user_aliases_are_detected() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "aliases": { "Yoh": "John", "Co": "1 Cor", "Hez": "Hezekiah" } }),
+    )
+    .await;
+    session.open("See Yoh 3:16 and Co 1:2\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of John 3:16."), "{contents}");
+    // `co` is Colossians in the translation, and the setting wins
+    let hover = session
+        .request("textDocument/hover", position(0, 18))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.contains("Text of 1 Corinthians 1:2."),
+        "{contents}"
+    );
+    let messages = session.messages.lock().unwrap().clone();
+    assert!(
+        messages.contains(&String::from(
+            "Unknown books in bible.aliases, skipping Hez (Hezekiah)"
+        )),
+        "{messages:?}"
+    );
+}
+
+#[tokio::test]
+async fn contexts_limit_where_references_are_found() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "contexts": ["wikilink", "quote"] }),
+    )
+    .await;
+    session
+        .open("Numbers 3:1 came up, see [[John 3:16]]:2\n> Rom 1:2\n")
+        .await;
+    let report = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    let codes: Vec<&str> = report["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(codes, ["John 3:16", "Romans 1:2"]);
+}
+
+#[tokio::test]
+async fn hovers_see_blocks_opened_on_earlier_lines() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "contexts": [{ "open": "{{", "close": "}}" }] }),
+    )
+    .await;
+    session
+        .open("Intro\n{{\nSee John 3:16\n}}\nNot Rom 1:2\nthe end\n")
+        .await;
+    // nothing has parsed the whole document yet
+    let hover = session
+        .request("textDocument/hover", position(2, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of John 3:16."), "{contents}");
+    let hover = session
+        .request("textDocument/hover", position(4, 9))
+        .await
+        .unwrap();
+    assert_eq!(hover["contents"], json!(""));
+}
+
+#[tokio::test]
+async fn cited_books_are_kept_in_the_front_matter() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "bookTags": { "enabled": true } }),
+    )
+    .await;
+    session
+        .open("---\ntitle: Grace\n---\nSee Eph 2:8, Rom 3:23 and Eph 1:1\n")
+        .await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+                "context": { "diagnostics": [], "only": ["source.bibleBooks"] }
+            }),
+        )
+        .await
+        .unwrap();
+    let action = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["kind"] == "source.bibleBooks")
+        .expect("the action is offered");
+    let edit = &action["edit"]["documentChanges"][0]["edits"][0];
+    assert_eq!(edit["newText"], "bible-books: [Romans, Ephesians]\n");
+    assert_eq!(edit["range"]["start"], json!({ "line": 2, "character": 0 }));
+}
+
+#[tokio::test]
+async fn passages_can_be_queued_for_later() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "readingQueueFile": dir.path().join("queue.json") }),
+    )
+    .await;
+    session.open("See jn 3:16-17 and Rom 1:2\n").await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let queue_actions: Vec<&Value> = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|action| action["command"]["command"] == "bible.queuePassage")
+        .collect();
+    let titles: Vec<&Value> = queue_actions
+        .iter()
+        .map(|action| &action["title"])
+        .collect();
+    assert_eq!(
+        titles,
+        [
+            "Add John 3:16-17 to the reading queue",
+            "Add Romans 1:2 to the reading queue"
+        ]
+    );
+    for action in queue_actions.iter().rev() {
+        let added = session
+            .request("workspace/executeCommand", action["command"].clone())
+            .await
+            .unwrap();
+        assert_eq!(added, true);
+    }
+    let again = session
+        .request(
+            "workspace/executeCommand",
+            queue_actions[0]["command"].clone(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(again, false);
+
+    let queue = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.readingQueue", "arguments": [] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(queue["passages"][0]["label"], "Romans 1:2");
+    assert_eq!(queue["passages"][1]["source"], URI);
+    let path = tower_lsp::lsp_types::Url::parse(queue["uri"].as_str().unwrap())
+        .unwrap()
+        .to_file_path()
+        .unwrap();
+    let document = std::fs::read_to_string(path).unwrap();
+    assert!(document.contains("2. John 3:16-17 (from ["), "{document}");
+
+    let pop = json!({ "command": "bible.popReadingQueue", "arguments": [] });
+    let popped = session
+        .request("workspace/executeCommand", pop.clone())
+        .await
+        .unwrap();
+    assert_eq!(popped["label"], "Romans 1:2");
+    session
+        .request("workspace/executeCommand", pop.clone())
+        .await
+        .unwrap();
+    let empty = session
+        .request("workspace/executeCommand", pop)
+        .await
+        .unwrap();
+    assert_eq!(empty, Value::Null);
+}
+
+#[tokio::test]
+async fn passages_are_outlined_from_headings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("headings.json");
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "headingsFile": path })).await;
+    let outline = json!({ "command": "bible.outlinePassage", "arguments": ["John 3:1-5"] });
+    let missing = session
+        .request("workspace/executeCommand", outline.clone())
+        .await
+        .unwrap_err();
+    assert!(missing.contains("No section headings"), "{missing}");
+
+    std::fs::write(
+        &path,
+        r#"{ "John 3:1": "Born Again", "jn 3:4": "Asked Twice", "John 2:1": "Cana" }"#,
+    )
+    .unwrap();
+    let result = session
+        .request("workspace/executeCommand", outline)
+        .await
+        .unwrap();
+    assert_eq!(result["label"], "John 3:1-5");
+    assert_eq!(
+        result["text"],
+        "- Born Again (John 3:1-3)\n- Asked Twice (John 3:4-5)"
+    );
+    assert_eq!(result["sections"][1]["heading"], "Asked Twice");
+
+    session.open("Outline\n").await;
+    let result = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.outlinePassage", "arguments": ["John 2-3", URI, { "line": 1, "character": 0 }] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["sections"].as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn selections_can_be_transliterated() {
+    let mut session = Session::start().await;
+    session.open("In John 1:1 ἐν ἀρχῇ ἦν ὁ λόγος\n").await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 12 }, "end": { "line": 0, "character": 30 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let action = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Transliterate selection")
+        .unwrap();
+    assert_eq!(action["command"]["arguments"][0], "ἐν ἀρχῇ ἦν ὁ λόγος");
+    let transliterated = session
+        .request("workspace/executeCommand", action["command"].clone())
+        .await
+        .unwrap();
+    assert_eq!(transliterated, "en archę̄ ēn ho logos");
+
+    // the fixture's verse text is English
+    let err = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.transliterate", "arguments": ["John 1:1"] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.contains("No Greek or Hebrew"), "{err}");
+}
+
+#[tokio::test]
+async fn untitled_buffers_work_like_files() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "bookTags": { "enabled": true }, "features": { "inlayHints": true } }),
+    )
+    .await;
+    let uri = "untitled:Untitled-1";
+    session
+        .open_uri(uri, "See John 3:16 today\nJohn 3:1\n")
+        .await;
+    let whole =
+        json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 8 } });
+
+    let hover = session
+        .request("textDocument/hover", position_in(uri, 0, 6))
+        .await
+        .unwrap();
+    assert!(hover["contents"]
+        .as_str()
+        .unwrap()
+        .contains("Text of John 3:16."));
+
+    let definition = session
+        .request("textDocument/definition", position_in(uri, 0, 6))
+        .await
+        .unwrap();
+    assert!(definition["uri"].as_str().unwrap().starts_with("file://"));
+
+    let completion = session
+        .request("textDocument/completion", position_in(uri, 1, 4))
+        .await
+        .unwrap();
+    assert!(!completion.is_null());
+
+    let diagnostics = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(diagnostics["kind"], "full");
+
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({ "textDocument": { "uri": uri }, "range": whole, "context": { "diagnostics": [] } }),
+        )
+        .await
+        .unwrap();
+    let kinds: Vec<&str> = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|action| action["kind"].as_str())
+        .collect();
+    assert!(kinds.contains(&"source.bibleBooks"), "{kinds:?}");
+
+    for method in ["textDocument/documentSymbol", "textDocument/foldingRange"] {
+        session
+            .request(method, json!({ "textDocument": { "uri": uri } }))
+            .await
+            .unwrap();
+    }
+    let hints = session
+        .request(
+            "textDocument/inlayHint",
+            json!({ "textDocument": { "uri": uri }, "range": whole }),
+        )
+        .await
+        .unwrap();
+    assert!(!hints.is_null());
+    session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.updateBookTags", "arguments": [uri] }),
+        )
+        .await
+        .unwrap();
+
+    session
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "Now Gen 1:1\n" }]
+            }),
+        )
+        .await;
+    let hover = session
+        .request("textDocument/hover", position_in(uri, 0, 6))
+        .await
+        .unwrap();
+    assert!(hover["contents"].as_str().unwrap().contains("Genesis 1:1"));
+
+    // closed without saving, so there is nothing left to read
+    session
+        .notify(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+    let hover = session
+        .request("textDocument/hover", position_in(uri, 0, 6))
+        .await
+        .unwrap();
+    assert!(hover.is_null());
+}
+
+#[cfg(feature = "commentary")]
+#[tokio::test]
+async fn single_verse_hovers_show_cross_references() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cross-references.tsv");
+    std::fs::write(
+        &path,
+        "From Verse\tTo Verse\tVotes\nJohn.3.16\tRom.5.8\t520\nJohn.3.16\t1John.4.9-1John.4.10\t600\nJohn.3.16\tGen.1.1\t2\n",
+    )
+    .unwrap();
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "crossReferencesFile": path, "hover": { "maxCrossReferences": 2 } }),
+    )
+    .await;
+    session.open("See John 3:16\nand John 3:16-17\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.ends_with("\n\n---\nSee also: 1 John 4:9-10; Romans 5:8"),
+        "{contents}"
+    );
+    let passage = session
+        .request("textDocument/hover", position(1, 6))
+        .await
+        .unwrap();
+    assert!(!passage["contents"].as_str().unwrap().contains("See also"));
+
+    session
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "bible": { "translation": FIXTURE, "crossReferencesFile": path, "hover": { "crossReferences": false } } } }),
+        )
+        .await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    assert!(!hover["contents"].as_str().unwrap().contains("See also"));
+}
+
+#[tokio::test]
+async fn misspelled_books_can_be_fixed() {
+    let mut session = Session::start().await;
+    session.open("Read Galations 2:20 today\n").await;
+    let report = session
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": URI } }),
+        )
+        .await
+        .unwrap();
+    let diagnostic = report["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["code"] == "misspelled-book")
+        .unwrap()
+        .clone();
+    assert_eq!(
+        diagnostic["message"],
+        "Unknown book \"Galations\", did you mean \"Galatians\"?"
+    );
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": diagnostic["range"],
+                "context": { "diagnostics": [diagnostic] }
+            }),
+        )
+        .await
+        .unwrap();
+    let action = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Change \"Galations\" to \"Galatians\"")
+        .unwrap();
+    assert_eq!(action["isPreferred"], true);
+    assert_eq!(
+        action["edit"]["documentChanges"][0]["edits"][0],
+        json!({
+            "range": { "start": { "line": 0, "character": 5 }, "end": { "line": 0, "character": 14 } },
+            "newText": "Galatians"
+        })
+    );
+}
+
+#[tokio::test]
+async fn passages_can_be_written_for_reading_aloud() {
+    let mut session = Session::start().await;
+    session.open("Read Ephesians 1:1-2 first\n").await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 6 }, "end": { "line": 0, "character": 6 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let action = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Insert Ephesians 1:1-2 as speech")
+        .unwrap();
+    let edit = &action["edit"]["documentChanges"][0]["edits"][0];
+    assert!(
+        edit["newText"].as_str().unwrap().starts_with(
+            "\nEphesians chapter one, verses one through two.\n\nText of Ephesians 1:1."
+        ),
+        "{edit}"
+    );
+
+    let copied = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.copyPassage", "arguments": ["1 John 1:3; Psalm 2:1-5", "speech"] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        copied["label"],
+        "First John chapter one, verse three; Psalm two"
+    );
+    assert!(copied["text"]
+        .as_str()
+        .unwrap()
+        .contains("verse three.\n\nText of 1 John 1:3.\n\nPsalm two.\n\nText of Psalms 2:1."));
+    let unknown = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.copyPassage", "arguments": ["John 3:16", "nope"] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(unknown.contains("No formatter named nope"), "{unknown}");
+}
+
+#[tokio::test]
+async fn footnotes_are_markdown_footnotes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.json");
+    std::fs::write(
+        &path,
+        r#"{"translation": {"name": "Noted", "language": "English", "abbreviation": "NTD"},
+            "bible": [{"id": 43, "book": "John", "abbreviations": ["jn"],
+                "content": [["In the beginning was the Word", "He was in the beginning with God."]],
+                "footnotes": {"1:1": ["Or *Message*", "Greek *logos*"]}}]}"#,
+    )
+    .unwrap();
+    let formatter = json!({ "verse": "{content}", "text": "{segments}", "footnotes": true });
+    let mut session = Session::start_with(
+        BibleLSP::new(path.to_str().unwrap()),
+        json!({ "hover": { "footnotes": true }, "formatters": { "noted": formatter } }),
+    )
+    .await;
+    session.open("Read John 1:1-2\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.ends_with(concat!(
+            "[1:1] In the beginning was the Word[^John.1.1a][^John.1.1b]\n",
+            "[1:2] He was in the beginning with God.\n\n",
+            "[^John.1.1a]: Or *Message*\n",
+            "[^John.1.1b]: Greek *logos*"
+        )),
+        "{contents}"
+    );
+
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 6 }, "end": { "line": 0, "character": 6 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let action = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Insert John 1:1-2 as noted")
+        .unwrap();
+    let edit = &action["edit"]["documentChanges"][0]["edits"][0];
+    assert!(
+        edit["newText"]
+            .as_str()
+            .unwrap()
+            .ends_with("God.\n\n[^John.1.1a]: Or *Message*\n[^John.1.1b]: Greek *logos*"),
+        "{edit}"
+    );
+    // the builtin formatters leave them out
+    let insert = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Insert John 1:1-2 as insert")
+        .unwrap();
+    assert!(!insert.to_string().contains("[^"), "{insert}");
+}
+
+#[tokio::test]
+async fn passages_show_the_sections_they_cross() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sections.json");
+    std::fs::write(
+        &path,
+        r#"{"translation": {"name": "Sections", "language": "English", "abbreviation": "SEC"},
+            "bible": [{"id": 40, "book": "Matthew", "abbreviations": ["mt"],
+                "content": [["Verse one.", "Verse two.", "Verse three."], ["Verse four."]],
+                "headings": {"1:1": "The Genealogy", "1:3": "The Birth", "2:1": "The Magi"}}]}"#,
+    )
+    .unwrap();
+    let mut session = Session::start_with(
+        BibleLSP::new(path.to_str().unwrap()),
+        json!({ "headingsFile": dir.path().join("none.json") }),
+    )
+    .await;
+    session.open("Read Matthew 1:1-2:1\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.ends_with(concat!(
+            "[1:1] Verse one.\n[1:2] Verse two.\n\n**The Birth**\n\n",
+            "[1:3] Verse three.\n\n**The Magi**\n\n[2:1] Verse four."
+        )),
+        "{contents}"
+    );
+
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 6 }, "end": { "line": 0, "character": 6 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let insert = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Insert Matthew 1:1-2:1 as insert")
+        .unwrap();
+    assert_eq!(
+        insert["edit"]["documentChanges"][0]["edits"][0]["newText"],
+        concat!(
+            "\n[1:1] Verse one.\n[1:2] Verse two.\n**The Birth**\n[1:3] Verse three.\n",
+            "— Chapter 2 —\n**The Magi**\n[2:1] Verse four."
+        )
+    );
+
+    // without a headings file, outlines use the translation's
+    let outline = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.outlinePassage", "arguments": ["Matthew"] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        outline["text"],
+        "- The Genealogy (Matthew 1:1-2)\n- The Birth (Matthew 1:3)\n- The Magi (Matthew 2:1)"
+    );
+}
+
+#[tokio::test]
+async fn inserts_stay_within_quote_limits() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "quoteLimits": { "tst": { "maxInsertVerses": 3 } } }),
+    )
+    .await;
+    session.open("John 3:1-2\nJohn 3:1-5\n").await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 0 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let action = |title: &str| {
+        actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|action| action["title"] == title)
+            .unwrap()
+            .clone()
+    };
+    assert!(action("Insert John 3:1-2 as insert")["edit"].is_object());
+    let refused = action("Insert John 3:1-5 as insert");
+    assert!(refused["edit"].is_null());
+    assert_eq!(
+        refused["disabled"]["reason"],
+        "John 3:1-5 is 5 verses, but at most 3 of the TST can be inserted at once"
+    );
+    assert!(action("Insert all 2 passages as insert")["disabled"].is_object());
+
+    let copied = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.copyPassage", "arguments": ["John 3:1-5"] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(copied.contains("at most 3"), "{copied}");
+
+    session
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "quoteLimits": { "TST": { "allowFullInsert": false } } } }),
+        )
+        .await;
+    let copied = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.copyPassage", "arguments": ["John 3:16"] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(copied.contains("The TST can only be cited"), "{copied}");
+}
+
+#[tokio::test]
+async fn quote_limits_only_count_quoted_text() {
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "quoteLimits": { "TST": { "maxVerses": 1 } } }),
+    )
+    .await;
+    let cited = "file:///tmp/cited.md";
+    let quoted = "file:///tmp/quoted.md";
+    session
+        .open_uri(cited, "See John 3:16 and John 3:17\n")
+        .await;
+    session
+        .open_uri(
+            quoted,
+            "John 3:16\n> Text of John 3:16.\n\nJohn 3:17\n> **Text of** John 3:17.\n",
+        )
+        .await;
+    let mut warnings = vec![];
+    for uri in [cited, quoted] {
+        let report = session
+            .request(
+                "textDocument/diagnostic",
+                json!({ "textDocument": { "uri": uri } }),
+            )
+            .await
+            .unwrap();
+        warnings.push(
+            report["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|item| item["code"] == "quote-limit")
+                .map(|item| item["message"].clone())
+                .collect::<Vec<_>>(),
+        );
+    }
+    assert!(warnings[0].is_empty(), "{:?}", warnings[0]);
+    assert_eq!(
+        warnings[1],
+        ["2 verses of the TST are quoted, but the limit is 1"]
+    );
+}
+
+#[tokio::test]
+async fn words_of_christ_can_be_red_letter() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("red.json");
+    std::fs::write(
+        &path,
+        r#"{"translation": {"name": "Red", "language": "English", "abbreviation": "RED"},
+            "bible": [{"id": 43, "book": "John", "abbreviations": ["jn"],
+                "content": [["Jesus said, Follow me. And they did.", "They followed."]],
+                "wordsOfChrist": {"1:1": [[11, 22]]}}]}"#,
+    )
+    .unwrap();
+    let formatter =
+        json!({ "verse": "{content}", "text": "{segments}", "redLetter": { "color": "#c00" } });
+    let mut session = Session::start_with(
+        BibleLSP::new(path.to_str().unwrap()),
+        json!({ "hover": { "redLetter": "bold" }, "formatters": { "red": formatter } }),
+    )
+    .await;
+    session.open("Read John 1:1-2\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.contains("[1:1] Jesus said, **Follow me.** And they did.\n[1:2] They followed."),
+        "{contents}"
+    );
+
+    let copied = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.copyPassage", "arguments": ["John 1:1", "red"] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        copied["text"],
+        "Jesus said, <span style=\"color: #c00\">Follow me.</span> And they did."
+    );
+}
+
+#[tokio::test]
+async fn segments_can_be_hovered_and_inserted_on_their_own() {
+    let mut session = Session::start().await;
+    session.open("Read Eph 1:1-4,2:2-3:1 first\n").await;
+    // on the book name, the whole reference
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    assert_eq!(hover["range"]["start"]["character"], 5);
+    assert_eq!(hover["range"]["end"]["character"], 22);
+
+    let hover = session
+        .request("textDocument/hover", position(0, 17))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Ephesians 2:2-3:1"), "{contents}");
+    assert!(!contents.contains("[1:1]"), "{contents}");
+    assert_eq!(hover["range"]["start"]["character"], 15);
+    assert_eq!(hover["range"]["end"]["character"], 22);
+
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 12 }, "end": { "line": 0, "character": 12 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let titles: Vec<&str> = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|action| action["title"].as_str())
+        .collect();
+    assert!(
+        titles.contains(&"Insert Ephesians 1:1-4; 2:2-3:1 as insert"),
+        "{titles:?}"
+    );
+    assert!(
+        titles.contains(&"Insert Ephesians 1:1-4 as insert"),
+        "{titles:?}"
+    );
+    assert!(
+        !titles.contains(&"Replace Ephesians 1:1-4 with insert"),
+        "{titles:?}"
+    );
+}
+
+#[tokio::test]
+async fn phrases_can_be_ignored_as_references() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("ignored.json");
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "ignoredPhrasesFile": file }),
+    )
+    .await;
+    session.open("Job 3:4 is at noon\n").await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let ignore = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Ignore \"Job 3:4\" as a reference")
+        .unwrap();
+    let command = &ignore["command"];
+    assert_eq!(command["command"], "bible.ignoreReference");
+    let added = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": command["command"], "arguments": command["arguments"] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(added, true);
+    let hover = session
+        .request("textDocument/hover", position(0, 1))
+        .await
+        .unwrap();
+    // just the book now
+    assert!(!hover.to_string().contains("Text of Job 3:4"), "{hover}");
+
+    let ignored = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.ignoredReferences", "arguments": [] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(ignored["phrases"], json!(["Job 3:4"]));
+    assert!(std::fs::read_to_string(&file).unwrap().contains("Job 3:4"));
+
+    let removed = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.unignoreReferences", "arguments": [] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(removed, 1);
+    let hover = session
+        .request("textDocument/hover", position(0, 1))
+        .await
+        .unwrap();
+    assert!(hover["contents"]
+        .as_str()
+        .unwrap()
+        .contains("Text of Job 3:4"));
+}
+
+#[tokio::test]
+async fn psalms_keep_their_lines_and_stanzas() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("poetry.json");
+    std::fs::write(
+        &path,
+        r#"{"translation": {"name": "Poetry", "language": "English", "abbreviation": "PTY"},
+            "bible": [{"id": 19, "book": "Psalms", "abbreviations": ["ps"],
+                "content": [["The LORD is my shepherd; I shall not want.",
+                    "He makes me lie down in green pastures.", "He restores my soul."]],
+                "wordsOfChrist": {"1:1": [[25, 42]]},
+                "layout": {"1:1": {"lines": [[0, 0], [25, 1]]},
+                    "1:2": {"stanza": true, "lines": [[0, 0], [21, 1]]},
+                    "1:3": {"paragraph": true}}}]}"#,
+    )
+    .unwrap();
+    let formatter = json!({
+        "verse": "{verse} {content}",
+        "joinVerses": "\n",
+        "text": "{segments}",
+        "poetry": { "indent": "    ", "paragraphBreaks": false }
+    });
+    let mut session = Session::start_with(
+        BibleLSP::new(path.to_str().unwrap()),
+        json!({ "hover": { "redLetter": "bold" }, "formatters": { "poem": formatter } }),
+    )
+    .await;
+    session.open("Read Ps 1:1-3\n").await;
+    let hover = session
+        .request("textDocument/hover", position(0, 6))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(
+        contents.contains(concat!(
+            "[1:1] The LORD is my shepherd;\n  **I shall not want.**\n\n",
+            "[1:2] He makes me lie down\n  in green pastures.\n\n",
+            "[1:3] He restores my soul."
+        )),
+        "{contents}"
+    );
+
+    let copy = |formatter: &str| json!({ "command": "bible.copyPassage", "arguments": ["Ps 1:1-3", formatter] });
+    let copied = session
+        .request("workspace/executeCommand", copy("poem"))
+        .await
+        .unwrap();
+    assert_eq!(
+        copied["text"],
+        concat!(
+            "1 The LORD is my shepherd;\n    I shall not want.\n\n",
+            "2 He makes me lie down\n    in green pastures.\n",
+            "3 He restores my soul."
+        )
+    );
+    let copied = session
+        .request("workspace/executeCommand", copy("replace"))
+        .await
+        .unwrap();
+    assert!(copied["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("> [1:1] The LORD is my shepherd; I shall not want. [1:2]"));
+}
+
+#[tokio::test]
+async fn changes_older_than_the_document_are_ignored() {
+    let mut session = Session::start().await;
+    session.open("See John 3:16\n").await;
+    let change = |version: i32, text: &str| {
+        json!({
+            "textDocument": { "uri": URI, "version": version },
+            "contentChanges": [{ "text": text }]
+        })
+    };
+    session
+        .notify("textDocument/didChange", change(3, "See Gen 1:1\n"))
+        .await;
+    // arrives late, after the newer text
+    session
+        .notify("textDocument/didChange", change(2, "See Exo 1:1\n"))
+        .await;
+    let hover = session
+        .request("textDocument/hover", position(0, 5))
+        .await
+        .unwrap();
+    let contents = hover["contents"].as_str().unwrap();
+    assert!(contents.contains("Text of Genesis 1:1."), "{contents}");
+}
+
+#[tokio::test]
+async fn only_enabled_features_are_advertised() {
+    let mut session = Session::start().await;
+    let capabilities = session.capabilities.clone();
+    assert!(capabilities["completionProvider"].is_object());
+    // off by default, and never answered with placeholders
+    for provider in [
+        "inlayHintProvider",
+        "codeLensProvider",
+        "inlineValueProvider",
+    ] {
+        assert!(capabilities[provider].is_null(), "{provider}");
+    }
+    session.open("See John 3:16\n").await;
+    let range =
+        json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 0 } });
+    let hints = session
+        .request(
+            "textDocument/inlayHint",
+            json!({ "textDocument": { "uri": URI }, "range": range }),
+        )
+        .await
+        .unwrap();
+    assert!(hints.is_null());
+
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "features": { "completion": false, "inlayHints": true } }),
+    )
+    .await;
+    assert!(session.capabilities["completionProvider"].is_null());
+    assert!(session.capabilities["inlayHintProvider"].is_object());
+    session.open("See John 3:16\n").await;
+    let hints = session
+        .request(
+            "textDocument/inlayHint",
+            json!({ "textDocument": { "uri": URI }, "range": range }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(hints.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn chapters_link_to_their_neighbors() {
+    let mut session = Session::start().await;
+    session.open("See Gen 2:3\n").await;
+    let definition = session
+        .request("textDocument/definition", position(0, 5))
+        .await
+        .unwrap();
+    let uri = definition["uri"].as_str().unwrap().to_string();
+    assert!(uri.ends_with("/Genesis/2.md"), "{uri}");
+    let path = tower_lsp::lsp_types::Url::parse(&uri)
+        .unwrap()
+        .to_file_path()
+        .unwrap();
+    let text = std::fs::read_to_string(path).unwrap();
+    assert!(
+        text.starts_with("# Genesis\n\n[1](1.md) · **2** · [3](3.md)\n\n## Genesis 2\n"),
+        "{text}"
+    );
+    assert!(
+        text.ends_with("---\n\n[← Genesis 1](1.md) | [Genesis 3 →](3.md)\n"),
+        "{text}"
+    );
+    // the line of `[2:3]`
+    assert_eq!(definition["range"]["start"]["line"], 8);
+
+    session.open_uri(&uri, &text).await;
+    let links = session
+        .request(
+            "textDocument/documentLink",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+        .unwrap();
+    let targets: Vec<&str> = links
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|link| link["target"].as_str().unwrap())
+        .collect();
+    assert_eq!(targets.len(), 4);
+    assert!(targets[0].ends_with("/Genesis/1.md"), "{targets:?}");
+    assert!(targets[3].ends_with("/Genesis/3.md"), "{targets:?}");
+}
+
+#[tokio::test]
+async fn chapters_have_verse_symbols_and_folds() {
+    let mut session = Session::start().await;
+    let uri = session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.openChapter", "arguments": [2, 1] }),
+        )
+        .await
+        .unwrap();
+    let uri = uri.as_str().unwrap().to_string();
+    let path = tower_lsp::lsp_types::Url::parse(&uri)
+        .unwrap()
+        .to_file_path()
+        .unwrap();
+    let text = std::fs::read_to_string(path).unwrap();
+    session.open_uri(&uri, &text).await;
+
+    let symbols = session
+        .request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+        .unwrap();
+    let chapter = &symbols[0];
+    assert_eq!(chapter["name"], "Exodus 1");
+    let verses = chapter["children"].as_array().unwrap();
+    assert_eq!(verses.len(), 5);
+    assert_eq!(verses[2]["name"], "Exodus 1:3");
+    assert_eq!(verses[2]["detail"], "Text of Exodus 1:3.");
+    assert_eq!(verses[2]["range"]["start"]["line"], 8);
+
+    let folds = session
+        .request(
+            "textDocument/foldingRange",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        folds,
+        json!([
+            { "startLine": 0, "endLine": 3, "kind": "region", "collapsedText": "Contents" },
+            { "startLine": 4, "endLine": 10, "kind": "region" }
+        ])
+    );
+}
+
+#[cfg(feature = "search")]
+#[tokio::test]
+async fn cited_passages_are_graphed() {
+    let dir = tempfile::tempdir().unwrap();
+    let notes = [("a.md", "Gen 1:1 and Exo 1:1\n"), ("b.md", "Gen 1:1\n")];
+    for (name, text) in notes {
+        std::fs::write(dir.path().join(name), text).unwrap();
+    }
+    let mut session = Session::start_in(dir.path(), Value::Null).await;
+    for (name, text) in notes {
+        let uri = tower_lsp::lsp_types::Url::from_file_path(dir.path().join(name)).unwrap();
+        session.open_uri(uri.as_str(), text).await;
+    }
+    let graph = |format: &str| json!({ "command": "bible.exportGraph", "arguments": [format] });
+    let json_graph = session
+        .request("workspace/executeCommand", graph("json"))
+        .await
+        .unwrap();
+    assert_eq!(
+        json_graph["nodes"],
+        json!([
+            { "id": "a.md", "type": "document" },
+            { "id": "b.md", "type": "document" },
+            { "id": "Exodus 1:1", "type": "passage" },
+            { "id": "Genesis 1:1", "type": "passage" }
+        ])
+    );
+    let edges = json_graph["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 4);
+    assert_eq!(
+        edges[3],
+        json!({ "source": "Exodus 1:1", "target": "Genesis 1:1", "type": "coCited", "weight": 1 })
+    );
+
+    let dot = session
+        .request("workspace/executeCommand", graph("dot"))
+        .await
+        .unwrap();
+    let dot = dot.as_str().unwrap();
+    assert!(dot.starts_with("graph bible_references {\n"), "{dot}");
+    assert!(
+        dot.contains("\"Exodus 1:1\" -- \"Genesis 1:1\" [style=dashed, weight=1];"),
+        "{dot}"
+    );
+    let unknown = session
+        .request("workspace/executeCommand", graph("svg"))
+        .await
+        .unwrap_err();
+    assert!(unknown.contains("Unknown graph format svg"), "{unknown}");
+}
+
+#[cfg(feature = "search")]
+#[tokio::test]
+async fn coverage_counts_cited_chapters() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.md");
+    let text = "Gen 1:1-2 and Gen 1:2\nExo 2:1\n";
+    std::fs::write(&path, text).unwrap();
+    let mut session = Session::start_in(dir.path(), Value::Null).await;
+    let uri = tower_lsp::lsp_types::Url::from_file_path(&path).unwrap();
+    session.open_uri(uri.as_str(), text).await;
+    let coverage = |format: &str| json!({ "command": "bible.coverage", "arguments": [format] });
+
+    let json_coverage = session
+        .request("workspace/executeCommand", coverage("json"))
+        .await
+        .unwrap();
+    let genesis = &json_coverage["books"][0];
+    assert_eq!(genesis["book"], "Genesis");
+    assert_eq!(
+        genesis["chapters"][0],
+        json!({ "chapter": 1, "citations": 2, "versesCited": 2, "verses": 5, "percent": 40.0 })
+    );
+    assert_eq!(genesis["chapters"][1]["citations"], 0);
+    assert_eq!(genesis["versesCited"], 2);
+    assert_eq!(genesis["percent"], 13.3);
+    assert_eq!(json_coverage["citations"], 3);
+
+    let csv = session
+        .request("workspace/executeCommand", coverage("csv"))
+        .await
+        .unwrap();
+    let csv = csv.as_str().unwrap();
+    assert!(
+        csv.starts_with("book,chapter,citations,verses_cited,verses,percent\nGenesis,1,2,2,5,40\n"),
+        "{csv}"
+    );
+    assert!(csv.contains("\nExodus,2,1,1,5,20\n"), "{csv}");
+}
+
+#[tokio::test]
+async fn alias_packs_can_be_switched() {
+    let mut session = Session::start().await;
+    session
+        .open("Canticles 1:1\nFirst John 1:1\nEsaias 1:1\n")
+        .await;
+    let hover_text = |hover: Value| hover["contents"].as_str().unwrap().to_string();
+    let hover = session
+        .request("textDocument/hover", position(0, 11))
+        .await
+        .unwrap();
+    assert!(hover_text(hover).contains("Text of Song of Solomon 1:1."));
+    let hover = session
+        .request("textDocument/hover", position(1, 12))
+        .await
+        .unwrap();
+    assert!(hover_text(hover).contains("Text of 1 John 1:1."));
+    // archaic names are off by default
+    let hover = session
+        .request("textDocument/hover", position(2, 8))
+        .await
+        .unwrap();
+    assert_eq!(hover_text(hover), "");
+
+    session
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "aliasPacks": ["archaic"] } }),
+        )
+        .await;
+    let hover = session
+        .request("textDocument/hover", position(2, 8))
+        .await
+        .unwrap();
+    assert!(hover_text(hover).contains("Text of Isaiah 1:1."));
+    let hover = session
+        .request("textDocument/hover", position(0, 11))
+        .await
+        .unwrap();
+    assert_eq!(hover_text(hover), "");
+}
+
+#[tokio::test]
+async fn completions_rank_cited_and_picked_books_first() {
+    let mut session = Session::start().await;
+    session.open("Romans 1:1\nr").await;
+    async fn ranked(session: &mut Session) -> Vec<String> {
+        let completions = session
+            .request("textDocument/completion", position(1, 1))
+            .await
+            .unwrap();
+        let mut items: Vec<(String, String)> = completions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                (
+                    item["sortText"].as_str().unwrap().to_string(),
+                    item["label"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        items.sort();
+        items.into_iter().take(3).map(|(_, label)| label).collect()
+    }
+    // then the rest in the translation's order
+    assert_eq!(ranked(&mut session).await, ["Romans", "Genesis", "Exodus"]);
+
+    session
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "bible.completionAccepted", "arguments": [66] }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        ranked(&mut session).await,
+        ["Romans", "Revelation", "Genesis"]
+    );
+}
+
+#[tokio::test]
+async fn verses_can_be_stepped_through() {
+    let mut session = Session::start().await;
+    session
+        .open("Gen 1:4\n[1:5] Text of Genesis 1:5.\n\nExo 2:1\n")
+        .await;
+    let navigate = |command: &str, line: u32, mode: &str| {
+        json!({
+            "command": command,
+            "arguments": [URI, { "line": line, "character": 2 }, mode]
+        })
+    };
+    // past the verse already inserted under it, into the next chapter
+    let next = session
+        .request(
+            "workspace/executeCommand",
+            navigate("bible.nextVerse", 0, "preview"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        next,
+        json!({ "reference": "Genesis 2:1", "content": "Text of Genesis 2:1." })
+    );
+    let previous = session
+        .request(
+            "workspace/executeCommand",
+            navigate("bible.prevVerse", 3, "insert"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(previous["reference"], "Exodus 1:5");
+    // only previews are shown
+    let messages = session.messages.lock().unwrap().clone();
+    assert_eq!(messages, ["Genesis 2:1 Text of Genesis 2:1."]);
+}
+
+#[tokio::test]
+async fn references_can_be_extended_a_verse() {
+    let mut session = Session::start().await;
+    session
+        .open("Gen 1:2-3\n[1:2] Text of Genesis 1:2.\n[1:3] Text of Genesis 1:3.\n")
+        .await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let edits = |title: &str| {
+        let action = actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|action| action["title"] == title)
+            .unwrap_or_else(|| panic!("{title} in {actions}"));
+        action["edit"]["documentChanges"][0]["edits"].clone()
+    };
+    // the inserted verses grow with the reference
+    assert_eq!(
+        edits("Extend Genesis 1:2-3 to 1:4"),
+        json!([
+            {
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 9 } },
+                "newText": "Genesis 1:2-4"
+            },
+            {
+                "range": { "start": { "line": 2, "character": 26 }, "end": { "line": 2, "character": 26 } },
+                "newText": "\n[1:4] Text of Genesis 1:4."
+            }
+        ])
+    );
+    let back = edits("Extend Genesis 1:2-3 back to 1:1");
+    assert_eq!(back[0]["newText"], "Genesis 1:1-3");
+    assert_eq!(back[1]["newText"], "\n[1:1] Text of Genesis 1:1.");
+    assert_eq!(back[1]["range"]["start"]["line"], 0);
+}
+
+#[tokio::test]
+async fn marked_quotes_can_be_refreshed() {
+    let mut session =
+        Session::start_with(BibleLSP::new(FIXTURE), json!({ "quoteMarkers": true })).await;
+    session.open("Gen 1:1\n").await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let insert = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Insert Genesis 1:1 as insert")
+        .unwrap();
+    let quote = insert["edit"]["documentChanges"][0]["edits"][0]["newText"]
+        .as_str()
+        .unwrap()
+        .trim()
+        .to_string();
+    assert!(
+        quote.starts_with("<!-- bible:quote Genesis 1:1 | TST | insert -->\n"),
+        "{quote}"
+    );
+    assert!(quote.contains("Text of Genesis 1:1."), "{quote}");
+    assert!(quote.ends_with("\n<!-- /bible:quote -->"), "{quote}");
+
+    let refresh = |uri: &str| json!({ "command": "bible.refreshQuotes", "arguments": [uri] });
+    let current = "file:///notes/current.md";
+    session.open_uri(current, &format!("{quote}\n")).await;
+    let refreshed = session
+        .request("workspace/executeCommand", refresh(current))
+        .await
+        .unwrap();
+    assert_eq!(refreshed, 0);
+    let stale = "file:///notes/stale.md";
+    let edited = quote.replace("Text of Genesis 1:1.", "An older edition.");
+    session.open_uri(stale, &format!("{edited}\n")).await;
+    let refreshed = session
+        .request("workspace/executeCommand", refresh(stale))
+        .await
+        .unwrap();
+    assert_eq!(refreshed, 1);
+}
+
+#[tokio::test]
+async fn every_formatter_has_its_own_code_actions() {
+    let plain = json!({ "verse": "{content}", "text": "{segments}", "codeActions": ["insert"] });
+    let mut session = Session::start_with(
+        BibleLSP::new(FIXTURE),
+        json!({ "formatters": { "plain": plain }, "formatterOrder": ["plain", "callout"] }),
+    )
+    .await;
+    session.open("Gen 1:1\n").await;
+    let actions = session
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": URI },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await
+        .unwrap();
+    let actions: Vec<&Value> = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|action| action["title"].as_str().unwrap().contains("Genesis 1:1 "))
+        .collect();
+    let titles: Vec<&str> = actions
+        .iter()
+        .map(|action| action["title"].as_str().unwrap())
+        .collect();
+    // in `formatterOrder`, then the rest by name
+    assert_eq!(
+        titles[..3],
+        [
+            "Insert Genesis 1:1 as plain",
+            "Replace Genesis 1:1 with callout",
+            "Insert Genesis 1:1 as blockquote"
+        ]
+    );
+    assert_eq!(actions[0]["isPreferred"], true);
+    assert!(actions[1]["isPreferred"].is_null());
+    assert_eq!(
+        actions[0]["edit"]["documentChanges"][0]["edits"][0]["newText"],
+        "\nText of Genesis 1:1."
+    );
+}
+
+#[cfg(feature = "search")]
+#[tokio::test]
+async fn citation_counts_list_the_other_places() {
+    let dir = tempfile::tempdir().unwrap();
+    let notes = [
+        ("a.md", "See Gen 1:1-3\n"),
+        ("b.md", "Gen 1:2\n"),
+        ("c.md", "Gen 1:3 and Exo 1:1\n"),
+    ];
+    for (name, text) in notes {
+        std::fs::write(dir.path().join(name), text).unwrap();
+    }
+    let mut session = Session::start_in(
+        dir.path(),
+        json!({ "features": { "citationCounts": true } }),
+    )
+    .await;
+    assert_eq!(
+        session.capabilities["inlayHintProvider"]["resolveProvider"],
+        true
+    );
+    let uri = |name: &str| {
+        tower_lsp::lsp_types::Url::from_file_path(dir.path().join(name))
+            .unwrap()
+            .to_string()
+    };
+    for (name, text) in notes {
+        session.open_uri(&uri(name), text).await;
+    }
+    let hints = session
+        .request(
+            "textDocument/inlayHint",
+            json!({
+                "textDocument": { "uri": uri("a.md") },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 0 } }
+            }),
+        )
+        .await
+        .unwrap();
+    let hints = hints.as_array().unwrap();
+    assert_eq!(hints.len(), 1, "{hints:?}");
+    assert_eq!(hints[0]["label"], "cited 2× in workspace");
+    assert_eq!(hints[0]["position"], json!({ "line": 0, "character": 13 }));
+    // the places are only listed once the hint is resolved
+    assert!(hints[0]["tooltip"].is_null());
+    let resolved = session
+        .request("inlayHint/resolve", hints[0].clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        resolved["tooltip"]["value"],
+        "- b.md: Genesis 1:2\n- c.md: Genesis 1:3"
+    );
+}
+
+#[tokio::test]
+async fn psalm_renumbering_is_confirmed_where_the_editor_can_ask() {
+    let retarget = json!({
+        "command": "bible.retargetReferences",
+        "arguments": [URI, "greek", "hebrew"]
+    });
+    let mut session = Session::start().await;
+    session.open("Ps 51:3\n").await;
+    let retargeted = session
+        .request("workspace/executeCommand", retarget.clone())
+        .await
+        .unwrap();
+    assert_eq!(retargeted, json!(1));
+    // an editor that didn't say it takes versioned or annotated edits
+    let edit = session.edits.lock().unwrap()[0].clone();
+    assert_eq!(edit["changes"][URI][0]["newText"], json!("Ps 50:3"));
+    assert!(edit["documentChanges"].is_null());
+
+    let mut session = Session::start_as(json!({
+        "workspace": {
+            "workspaceEdit": { "documentChanges": true, "changeAnnotationSupport": {} }
+        }
+    }))
+    .await;
+    session.open("Ps 51:3\n").await;
+    session
+        .request("workspace/executeCommand", retarget)
+        .await
+        .unwrap();
+    let edit = session.edits.lock().unwrap()[0].clone();
+    let document = &edit["documentChanges"][0];
+    assert_eq!(document["textDocument"]["version"], json!(1));
+    assert_eq!(document["edits"][0]["annotationId"], json!("retarget"));
+    assert_eq!(
+        edit["changeAnnotations"]["retarget"]["needsConfirmation"],
+        json!(true)
+    );
+}